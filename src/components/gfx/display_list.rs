@@ -15,15 +15,32 @@
 /// low-level drawing primitives.
 
 use color::Color;
-use geometry::Au;
+#[cfg(test)]
+use color::rgb;
+#[cfg(test)]
+use text::text_run::sendable_run_for_test;
+use azure::AzFloat;
+use geometry::{Au, to_frac_px};
 use render_context::RenderContext;
 use text::SendableTextRun;
 
 use std::cast::transmute_region;
+use std::hashmap::HashSet;
+use std::num;
+use std::ptr;
+use std::sys;
+use std::util;
+use std::vec;
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
 use servo_net::image::base::Image;
 use servo_util::range::Range;
+use servo_util::time::profile;
+use servo_util::time;
 use extra::arc::Arc;
+use extra::sort::tim_sort;
+use extra::json::{Boolean, Json, List, Number, Object, String, ToJson};
+use extra::treemap::TreeMap;
+use extra::url::Url;
 
 /// A list of rendering operations to be performed.
 pub struct DisplayList<E> {
@@ -51,10 +68,261 @@ impl<E> DisplayList<E> {
         for item in self.list.iter() {
             // FIXME(Issue #150): crashes
             //debug!("drawing %?", *item);
-            item.draw_into_context(render_context)
+            item.draw_clipped_into_context(render_context)
         }
         debug!("Ending display list.")
     }
+
+    /// Draws only the items that intersect `rect` into the given render context, looking them up
+    /// in `index`, which must have been built from this list. Stacking contexts draw only their
+    /// own items that intersect `rect`. Returns the number of items drawn and the number skipped,
+    /// counting the items of stacking contexts as well as the contexts themselves.
+    pub fn draw_intersecting_into_context(&self,
+                                          render_context: &RenderContext,
+                                          index: &DisplayListIndex,
+                                          rect: &Rect<Au>)
+                                          -> (uint, uint) {
+        let items = index.items_intersecting(self, rect);
+        debug!("Beginning display list (%u of %u items).", items.len(), self.list.len());
+        let mut drawn = items.len();
+        let mut culled = self.list.len() - items.len();
+        for &i in items.iter() {
+            match self.list[i] {
+                StackingContextDisplayItemClass(ref context) => {
+                    let context_index = match index.contexts.find(&i) {
+                        Some(context_index) => context_index,
+                        None => fail!("stacking context missing from the display list index"),
+                    };
+                    let opacity = context.opacity as AzFloat;
+                    do render_context.draw_with_opacity(opacity) |render_context| {
+                        let (context_drawn, context_culled) =
+                            context.list.draw_intersecting_into_context(render_context,
+                                                                        context_index,
+                                                                        rect);
+                        drawn += context_drawn;
+                        culled += context_culled;
+                    }
+                }
+                ref item => item.draw_clipped_into_context(render_context),
+            }
+        }
+        debug!("Ending display list.");
+        (drawn, culled)
+    }
+
+    /// Returns a readable description of the display list, one item per line, for debugging and
+    /// for tests that check layout output without rasterizing it. The items of stacking contexts
+    /// follow the context, indented.
+    pub fn dump(&self) -> ~str {
+        let mut lines = ~[];
+        self.dump_into(&mut lines, "");
+        lines.connect("\n")
+    }
+
+    fn dump_into(&self, lines: &mut ~[~str], indent: &str) {
+        for item in self.list.iter() {
+            lines.push(fmt!("%s%s", indent, item.debug_str()));
+            match *item {
+                StackingContextDisplayItemClass(ref context) => {
+                    let nested_indent = indent + "  ";
+                    context.list.dump_into(lines, nested_indent.as_slice())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the number of items in the display list. A stacking context counts as one item.
+    pub fn len(&self) -> uint {
+        self.list.len()
+    }
+
+    /// Returns the number of different fonts the text of this list, stacking contexts included,
+    /// is drawn with.
+    pub fn font_count(&self) -> uint {
+        let mut font_ids = HashSet::new();
+        self.collect_font_ids(&mut font_ids);
+        font_ids.len()
+    }
+
+    fn collect_font_ids(&self, font_ids: &mut HashSet<uint>) {
+        for item in self.list.iter() {
+            match *item {
+                TextDisplayItemClass(ref text) => {
+                    for font in text.text_run.get().fonts.iter() {
+                        font_ids.insert(font.get().id);
+                    }
+                }
+                StackingContextDisplayItemClass(ref context) => {
+                    context.list.collect_font_ids(font_ids)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Returns the smallest rect containing the bounds of all the items.
+    pub fn bounds(&self) -> Rect<Au> {
+        let mut bounds = Au::zero_rect();
+        for (i, item) in self.list.iter().enumerate() {
+            bounds = if i == 0 { item.bounds() } else { bounds.union(&item.bounds()) };
+        }
+        bounds
+    }
+
+    /// Puts the stacking contexts in this list into the painting order of CSS 2.1 Appendix E.
+    /// The first `background_len` items, which paint the background and borders of the element
+    /// forming the stacking context this list belongs to, stay first. They are followed by the
+    /// contexts with negative z-indices, then by the other items, then by the contexts with zero
+    /// or positive z-indices. Contexts with equal z-indices keep their order in the tree.
+    pub fn sort_stacking_contexts(&mut self, background_len: uint) {
+        let items = util::replace(&mut self.list, ~[]);
+        let mut negative = ~[];
+        let mut rest = ~[];
+        let mut positive = ~[];
+        for (i, item) in items.move_iter().enumerate() {
+            if i < background_len {
+                self.list.push(item);
+                loop
+            }
+
+            let z_index = match item {
+                StackingContextDisplayItemClass(ref context) => Some(context.z_index),
+                _ => None,
+            };
+            match z_index {
+                Some(z_index) if z_index < 0 => insert_by_z_index(&mut negative, z_index, item),
+                Some(z_index) => insert_by_z_index(&mut positive, z_index, item),
+                None => rest.push(item),
+            }
+        }
+
+        for (_, item) in negative.move_iter() {
+            self.list.push(item)
+        }
+        self.list.push_all_move(rest);
+        for (_, item) in positive.move_iter() {
+            self.list.push(item)
+        }
+    }
+
+    /// Returns the frontmost item drawn at the given point, looking inside stacking contexts.
+    pub fn item_at<'a>(&'a self, point: &Point2D<Au>) -> Option<&'a DisplayItem<E>> {
+        for item in self.list.rev_iter() {
+            match *item {
+                StackingContextDisplayItemClass(ref context) => {
+                    match context.list.item_at(point) {
+                        Some(item) => return Some(item),
+                        None => {}
+                    }
+                }
+                _ if item.base().contains_point(point) => return Some(item),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Returns an estimate of the memory owned by the display list, in bytes. Images are shared
+    /// with the image cache and are not counted. Text runs are counted once, however many items
+    /// draw parts of them.
+    pub fn memory_size(&self) -> uint {
+        let mut runs = HashSet::new();
+        self.memory_size_with_runs(&mut runs)
+    }
+
+    // `runs` holds the addresses of the text runs counted so far
+    fn memory_size_with_runs(&self, runs: &mut HashSet<uint>) -> uint {
+        let mut size = self.list.len() * sys::size_of::<DisplayItem<E>>();
+        for item in self.list.iter() {
+            size += item.memory_size(runs);
+        }
+        size
+    }
+}
+
+/// A uniform grid over the area covered by a display list, recording which items overlap each
+/// cell. Built once per display list so that rendering a tile only has to look at the items near
+/// it instead of the whole list.
+pub struct DisplayListIndex {
+    priv cell_size: Au,
+    priv columns: uint,
+    priv rows: uint,
+    /// For each cell in row-major order, the indices of the items overlapping it, in list order.
+    priv cells: ~[~[uint]],
+    /// The indices of the lists of the stacking contexts in the list, by the position of the
+    /// context in the list.
+    priv contexts: TreeMap<uint, DisplayListIndex>,
+}
+
+impl DisplayListIndex {
+    /// Indexes the items of `list`, which covers an area of the given size. Items extending
+    /// outside that area are filed under the cells on its edge. The lists of stacking contexts
+    /// are indexed too, over the same area.
+    pub fn new<E>(list: &DisplayList<E>, size: Size2D<Au>, cell_size: Au) -> DisplayListIndex {
+        let columns = num::max(1, ((*size.width + *cell_size - 1) / *cell_size) as int) as uint;
+        let rows = num::max(1, ((*size.height + *cell_size - 1) / *cell_size) as int) as uint;
+        let mut index = DisplayListIndex {
+            cell_size: cell_size,
+            columns: columns,
+            rows: rows,
+            cells: vec::from_fn(columns * rows, |_| ~[]),
+            contexts: TreeMap::new(),
+        };
+
+        for (i, item) in list.list.iter().enumerate() {
+            let (min_column, min_row, max_column, max_row) = index.cells_covering(&item.bounds());
+            for row in range(min_row, max_row + 1) {
+                for column in range(min_column, max_column + 1) {
+                    index.cells[row * columns + column].push(i);
+                }
+            }
+
+            match *item {
+                StackingContextDisplayItemClass(ref context) => {
+                    index.contexts.insert(i, DisplayListIndex::new(&context.list, size, cell_size));
+                }
+                _ => {}
+            }
+        }
+        index
+    }
+
+    // the first and last column and row of the cells that `rect` overlaps, clamped to the grid
+    fn cells_covering(&self, rect: &Rect<Au>) -> (uint, uint, uint, uint) {
+        let cell = |coordinate: Au, count: uint| -> uint {
+            num::min(num::max(0, *coordinate / *self.cell_size) as uint, count - 1)
+        };
+        (cell(rect.origin.x, self.columns),
+         cell(rect.origin.y, self.rows),
+         cell(rect.origin.x + rect.size.width, self.columns),
+         cell(rect.origin.y + rect.size.height, self.rows))
+    }
+
+    /// Returns the indices, in increasing order, of the items of `list` that intersect `rect`.
+    /// `list` must be the list this index was built from.
+    pub fn items_intersecting<E>(&self, list: &DisplayList<E>, rect: &Rect<Au>) -> ~[uint] {
+        let (min_column, min_row, max_column, max_row) = self.cells_covering(rect);
+        let mut items = ~[];
+        for row in range(min_row, max_row + 1) {
+            for column in range(min_column, max_column + 1) {
+                items.push_all(self.cells[row * self.columns + column]);
+            }
+        }
+        tim_sort(items);
+        items.dedup();
+        items.retain(|&i| list.list[i].bounds().intersects(rect));
+        items
+    }
+}
+
+impl<E> ToJson for DisplayList<E> {
+    fn to_json(&self) -> Json {
+        let mut items = do self.list.iter().map |item| {
+            item.to_json()
+        };
+        List(items.collect())
+    }
 }
 
 /// One drawing command in the list.
@@ -62,7 +330,12 @@ pub enum DisplayItem<E> {
     SolidColorDisplayItemClass(~SolidColorDisplayItem<E>),
     TextDisplayItemClass(~TextDisplayItem<E>),
     ImageDisplayItemClass(~ImageDisplayItem<E>),
+    BackgroundImageDisplayItemClass(~BackgroundImageDisplayItem<E>),
     BorderDisplayItemClass(~BorderDisplayItem<E>),
+    LinearGradientDisplayItemClass(~LinearGradientDisplayItem<E>),
+    RadialGradientDisplayItemClass(~RadialGradientDisplayItem<E>),
+    BoxShadowDisplayItemClass(~BoxShadowDisplayItem<E>),
+    StackingContextDisplayItemClass(~StackingContextDisplayItem<E>),
 }
 
 /// Information common to all display items.
@@ -72,28 +345,119 @@ pub struct BaseDisplayItem<E> {
     /// TODO: Which coordinate system should this use?
     bounds: Rect<Au>,
 
+    /// The region outside of which this item must not paint, in the same coordinate system as
+    /// `bounds`. Items that are not clipped use `Au::max_rect()`.
+    clip: Rect<Au>,
+
     /// Extra data: either the originating flow (for hit testing) or nothing (for rendering).
     extra: E,
 }
 
+impl<E> BaseDisplayItem<E> {
+    /// Returns true if the point lies within both the bounds and the clip rect of this item.
+    pub fn contains_point(&self, point: &Point2D<Au>) -> bool {
+        let (x, y) = (point.x, point.y);
+        x <= self.bounds.origin.x + self.bounds.size.width &&
+            self.bounds.origin.x <= x &&
+            y < self.bounds.origin.y + self.bounds.size.height &&
+            self.bounds.origin.y < y &&
+            self.clip.origin.x <= x && x < self.clip.origin.x + self.clip.size.width &&
+            self.clip.origin.y <= y && y < self.clip.origin.y + self.clip.size.height
+    }
+
+    /// Returns true if some of this item's bounds lie outside its clip rect, so that the clip has
+    /// to be applied when the item is drawn.
+    pub fn is_clipped(&self) -> bool {
+        self.bounds.origin.x < self.clip.origin.x ||
+            self.bounds.origin.y < self.clip.origin.y ||
+            self.bounds.origin.x + self.bounds.size.width >
+                self.clip.origin.x + self.clip.size.width ||
+            self.bounds.origin.y + self.bounds.size.height >
+                self.clip.origin.y + self.clip.size.height
+    }
+}
+
 /// Renders a solid color.
 pub struct SolidColorDisplayItem<E> {
     base: BaseDisplayItem<E>,
     color: Color,
+
+    /// The radii of the rounded corners the color is clipped to, if it paints a background.
+    radii: BorderRadii,
 }
 
 /// Renders text.
 pub struct TextDisplayItem<E> {
     base: BaseDisplayItem<E>,
-    text_run: ~SendableTextRun,
+    text_run: Arc<SendableTextRun>,
     range: Range,
     color: Color,
+    decorations: TextDecorations,
+
+    /// The shadows painted beneath the text, from the top one to the bottom one.
+    shadows: ~[TextShadow],
+}
+
+/// A copy of a run of text drawn beneath it, moved by an offset and blurred.
+#[deriving(Clone)]
+pub struct TextShadow {
+    offset: Point2D<Au>,
+
+    /// How far the shadow's edges are blurred. Zero gives sharp edges.
+    blur_radius: Au,
+
+    color: Color,
+}
+
+/// The lines drawn along a run of text. Each is drawn in the color of the element that asked for
+/// it, which need not be the color of the text.
+#[deriving(Clone)]
+pub struct TextDecorations {
+    underline: Option<Color>,
+    overline: Option<Color>,
+    line_through: Option<Color>,
+}
+
+impl TextDecorations {
+    pub fn none() -> TextDecorations {
+        TextDecorations {
+            underline: None,
+            overline: None,
+            line_through: None,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.underline.is_none() && self.overline.is_none() && self.line_through.is_none()
+    }
 }
 
 /// Renders an image.
 pub struct ImageDisplayItem<E> {
     base: BaseDisplayItem<E>,
     image: Arc<~Image>,
+
+    /// The URL the image was loaded from. Only used for debugging output.
+    url: Url,
+}
+
+/// Renders a background image, tiled across the bounds if it repeats.
+pub struct BackgroundImageDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+    image: Arc<~Image>,
+
+    /// The URL the image was loaded from. Only used for debugging output.
+    url: Url,
+
+    /// Where one copy of the image is drawn, at its used size. It may lie outside the bounds.
+    tile_bounds: Rect<Au>,
+
+    /// Whether copies of the image are laid out across and down the bounds.
+    repeat_x: bool,
+    repeat_y: bool,
+
+    /// The radii of the rounded corners the image is clipped to.
+    radii: BorderRadii,
 }
 
 /// Renders a border.
@@ -103,42 +467,300 @@ pub struct BorderDisplayItem<E> {
     /// The border widths
     border: SideOffsets2D<Au>,
 
-    /// The color of the border.
+    /// The color of each side of the border.
+    color: SideOffsets2D<Color>,
+
+    /// The style of each side of the border.
+    style: SideOffsets2D<BorderStyle>,
+
+    /// The radii of the outer edge of each corner of the border.
+    radii: BorderRadii,
+}
+
+/// How one side of a border is drawn.
+#[deriving(Clone, Eq)]
+pub enum BorderStyle {
+    BorderStyleNone,
+    BorderStyleHidden,
+    BorderStyleSolid,
+    BorderStyleDotted,
+    BorderStyleDashed,
+    BorderStyleDouble,
+    BorderStyleGroove,
+    BorderStyleRidge,
+    BorderStyleInset,
+    BorderStyleOutset,
+}
+
+impl BorderStyle {
+    /// Returns the name CSS uses for this style.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BorderStyleNone => "none",
+            BorderStyleHidden => "hidden",
+            BorderStyleSolid => "solid",
+            BorderStyleDotted => "dotted",
+            BorderStyleDashed => "dashed",
+            BorderStyleDouble => "double",
+            BorderStyleGroove => "groove",
+            BorderStyleRidge => "ridge",
+            BorderStyleInset => "inset",
+            BorderStyleOutset => "outset",
+        }
+    }
+}
+
+/// The horizontal and vertical radii of the four corners of a rounded rect. A corner with a zero
+/// radius in either direction is square.
+#[deriving(Clone, Eq)]
+pub struct BorderRadii {
+    top_left: Size2D<Au>,
+    top_right: Size2D<Au>,
+    bottom_right: Size2D<Au>,
+    bottom_left: Size2D<Au>,
+}
+
+impl BorderRadii {
+    /// Radii for a rect with square corners.
+    pub fn zero() -> BorderRadii {
+        BorderRadii {
+            top_left: Size2D(Au(0), Au(0)),
+            top_right: Size2D(Au(0), Au(0)),
+            bottom_right: Size2D(Au(0), Au(0)),
+            bottom_left: Size2D(Au(0), Au(0)),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        let corners = self.corners();
+        corners.iter().all(|corner| corner.width <= Au(0) || corner.height <= Au(0))
+    }
+
+    /// Returns the corners in clockwise order, starting at the top left.
+    pub fn corners(&self) -> [Size2D<Au>, ..4] {
+        [self.top_left, self.top_right, self.bottom_right, self.bottom_left]
+    }
+
+    /// Scales all the radii down by the same factor if necessary so that adjacent corners do not
+    /// overlap in a rect of the given size, as CSS 3 Backgrounds and Borders § 5.5 requires.
+    pub fn scaled_to_fit(&self, size: &Size2D<Au>) -> BorderRadii {
+        let ratio = |length: Au, first: Au, second: Au| -> float {
+            if first + second > length {
+                to_frac_px(length) / to_frac_px(first + second)
+            } else {
+                1.0
+            }
+        };
+        let ratios = [
+            ratio(size.width, self.top_left.width, self.top_right.width),
+            ratio(size.width, self.bottom_left.width, self.bottom_right.width),
+            ratio(size.height, self.top_left.height, self.bottom_left.height),
+            ratio(size.height, self.top_right.height, self.bottom_right.height),
+        ];
+        let factor = ratios.iter().fold(1.0f, |a, &b| num::min(a, b));
+        if factor >= 1.0 {
+            return *self
+        }
+
+        let scale = |corner: Size2D<Au>| -> Size2D<Au> {
+            Size2D(corner.width.scale_by(factor), corner.height.scale_by(factor))
+        };
+        BorderRadii {
+            top_left: scale(self.top_left),
+            top_right: scale(self.top_right),
+            bottom_right: scale(self.bottom_right),
+            bottom_left: scale(self.bottom_left),
+        }
+    }
+}
+
+/// A point along a gradient at which its color is given. Between stops the color is interpolated.
+#[deriving(Clone)]
+pub struct GradientStop {
+    /// The position of the stop along the gradient, from 0 to 1.
+    offset: f32,
+
     color: Color,
 }
 
+/// Renders a linear gradient filling the bounds. Beyond the ends of the gradient line the colors
+/// of the first and last stops are extended.
+pub struct LinearGradientDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    /// The radii of the rounded corners the gradient is clipped to.
+    radii: BorderRadii,
+
+    /// Where the gradient line starts, in the same coordinate system as the bounds.
+    start_point: Point2D<Au>,
+
+    /// Where the gradient line ends, in the same coordinate system as the bounds.
+    end_point: Point2D<Au>,
+
+    stops: ~[GradientStop],
+}
+
+/// Renders an elliptical gradient filling the bounds.
+pub struct RadialGradientDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    /// The radii of the rounded corners the gradient is clipped to.
+    radii: BorderRadii,
+
+    /// The center of the gradient, in the same coordinate system as the bounds.
+    center: Point2D<Au>,
+
+    /// The horizontal and vertical distances from the center at which the last stop is reached.
+    radius: Size2D<Au>,
+
+    stops: ~[GradientStop],
+}
+
+/// Renders the shadow cast by a box. The bounds cover everything the shadow paints: for an outer
+/// shadow that includes the blur beyond the shadow's edges, for an inset shadow it is the padding
+/// box.
+pub struct BoxShadowDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+
+    /// The border box of the box casting an outer shadow, or the padding box of the box casting
+    /// an inset shadow.
+    box_bounds: Rect<Au>,
+
+    /// How far the shadow is moved from the box.
+    offset: Point2D<Au>,
+
+    /// How far the shadow's edges are blurred. Zero gives sharp edges.
+    blur_radius: Au,
+
+    /// How far the shadow is grown (or, if negative, shrunk) on each side before blurring.
+    spread_radius: Au,
+
+    color: Color,
+
+    /// Whether the shadow is cast inside the box instead of outside it.
+    inset: bool,
+}
+
+/// A group of items that is painted as a unit, in the order given by its z-index among the other
+/// stacking contexts in the same list, and faded as a whole by its opacity. The bounds contain
+/// the bounds of all its items.
+pub struct StackingContextDisplayItem<E> {
+    base: BaseDisplayItem<E>,
+    z_index: int,
+
+    /// From 0 for invisible to 1 for opaque.
+    opacity: f32,
+
+    /// The items in the context, in painting order.
+    list: DisplayList<E>,
+}
+
+// Inserts a stacking context after those with a z-index less than or equal to its own.
+fn insert_by_z_index<E>(contexts: &mut ~[(int, DisplayItem<E>)],
+                        z_index: int,
+                        item: DisplayItem<E>) {
+    let position = match contexts.iter().position(|&(other, _)| other > z_index) {
+        Some(position) => position,
+        None => contexts.len(),
+    };
+    contexts.insert(position, (z_index, item))
+}
+
+// Grows a rect by the given amount on each side.
+fn inflate_rect(rect: &Rect<Au>, amount: Au) -> Rect<Au> {
+    Rect(Point2D(rect.origin.x - amount, rect.origin.y - amount),
+         Size2D(rect.size.width + amount.scale_by(2.0), rect.size.height + amount.scale_by(2.0)))
+}
+
 impl<E> DisplayItem<E> {
+    /// Renders this display item into the given render context, applying its clip rect if it
+    /// needs one.
+    fn draw_clipped_into_context(&self, render_context: &RenderContext) {
+        let base = self.base();
+        let clipped = base.is_clipped();
+        if clipped {
+            render_context.push_clip(&base.clip);
+        }
+        self.draw_into_context(render_context);
+        if clipped {
+            render_context.pop_clip();
+        }
+    }
+
     /// Renders this display item into the given render context.
     fn draw_into_context(&self, render_context: &RenderContext) {
         match *self {
             SolidColorDisplayItemClass(ref solid_color) => {
-                render_context.draw_solid_color(&solid_color.base.bounds, solid_color.color)
+                do render_context.with_rounded_clip(&solid_color.base.bounds,
+                                                    &solid_color.radii) {
+                    render_context.draw_solid_color(&solid_color.base.bounds, solid_color.color)
+                }
             }
 
             TextDisplayItemClass(ref text) => {
                 debug!("Drawing text at %?.", text.base.bounds);
 
-                // FIXME(pcwalton): Allocating? Why?
-                let new_run = @text.text_run.deserialize(render_context.font_ctx);
+                // The run is drawn from the glyphs layout shaped and the metrics of the fonts it
+                // shared. Only the platform fonts, which belong to a single task, come from this
+                // task's font context, by id. Parts of the run whose font cannot be loaded here are
+                // left out.
+                let run = text.text_run.get();
+                let font_ctx = render_context.font_ctx;
+                do profile(time::RenderingTextCategory, font_ctx.profiler_chan.clone()) {
+                    let metrics = &run.fonts[0].get().metrics;
+                    let origin = text.base.bounds.origin;
+                    let baseline_origin = Point2D(origin.x, origin.y + metrics.ascent);
 
-                let font = new_run.font;
-                let origin = text.base.bounds.origin;
-                let baseline_origin = Point2D(origin.x, origin.y + font.metrics.ascent);
+                    // Each direction of text is drawn in display order, and within it each part of
+                    // the run that falls back to another font is drawn with that font.
+                    let mut font_origin = baseline_origin;
+                    for &(ref level_range, rtl) in run.visual_ranges_for_range(&text.range).iter() {
+                        let mut font_ranges = run.font_ranges_for_range(level_range);
+                        if rtl {
+                            font_ranges.reverse();
+                        }
+                        for &(font_index, ref range) in font_ranges.iter() {
+                            let shared_font = run.fonts[font_index].get();
+                            let advance = run.advance_for_range(range);
+                            match font_ctx.get_shared_font(shared_font) {
+                                Ok(font) => {
+                                    font.draw_text_into_context(render_context,
+                                                                run,
+                                                                range,
+                                                                font_origin,
+                                                                rtl,
+                                                                text.color,
+                                                                text.shadows);
+                                }
+                                Err(()) => {
+                                    debug!("No font for text run: desc=%?",
+                                           shared_font.descriptor);
+                                }
+                            }
+                            font_origin.x = font_origin.x + advance;
+                        }
+                    }
 
-                font.draw_text_into_context(render_context,
-                                            new_run,
-                                            &text.range,
-                                            baseline_origin,
-                                            text.color);
-
-                if new_run.underline {
-                    // TODO(eatkinson): Use the font metrics to properly position the underline
-                    // bar.
+                    // Lines are drawn at least a pixel thick so that they do not vanish at small
+                    // sizes.
                     let width = text.base.bounds.size.width;
-                    let underline_size = font.metrics.underline_size;
-                    let underline_bounds = Rect(Point2D(baseline_origin.x, baseline_origin.y),
-                                                Size2D(width, underline_size));
-                    render_context.draw_solid_color(&underline_bounds, text.color);
+                    let draw_line = |center_above_baseline: Au, size: Au, color: Color| {
+                        let size = if size < Au::from_px(1) { Au::from_px(1) } else { size };
+                        let top = baseline_origin.y - center_above_baseline - size.scale_by(0.5);
+                        let line_bounds = Rect(Point2D(baseline_origin.x, top),
+                                               Size2D(width, size));
+                        render_context.draw_solid_color(&line_bounds, color);
+                    };
+                    for &color in text.decorations.underline.iter() {
+                        draw_line(metrics.underline_offset, metrics.underline_size, color);
+                    }
+                    for &color in text.decorations.overline.iter() {
+                        let center = metrics.ascent - metrics.underline_size.scale_by(0.5);
+                        draw_line(center, metrics.underline_size, color);
+                    }
+                    for &color in text.decorations.line_through.iter() {
+                        draw_line(metrics.strikeout_offset, metrics.strikeout_size, color);
+                    }
                 }
             }
 
@@ -148,10 +770,55 @@ impl<E> DisplayItem<E> {
                 render_context.draw_image(image_item.base.bounds, image_item.image.clone())
             }
 
+            BackgroundImageDisplayItemClass(ref image_item) => {
+                do render_context.with_rounded_clip(&image_item.base.bounds, &image_item.radii) {
+                    render_context.draw_tiled_image(&image_item.base.bounds,
+                                                    &image_item.tile_bounds,
+                                                    image_item.repeat_x,
+                                                    image_item.repeat_y,
+                                                    image_item.image.clone())
+                }
+            }
+
             BorderDisplayItemClass(ref border) => {
                 render_context.draw_border(&border.base.bounds,
                                            border.border,
-                                           border.color)
+                                           border.color,
+                                           border.style,
+                                           &border.radii)
+            }
+
+            LinearGradientDisplayItemClass(ref gradient) => {
+                do render_context.with_rounded_clip(&gradient.base.bounds, &gradient.radii) {
+                    render_context.draw_linear_gradient(&gradient.base.bounds,
+                                                        &gradient.start_point,
+                                                        &gradient.end_point,
+                                                        gradient.stops)
+                }
+            }
+
+            RadialGradientDisplayItemClass(ref gradient) => {
+                do render_context.with_rounded_clip(&gradient.base.bounds, &gradient.radii) {
+                    render_context.draw_radial_gradient(&gradient.base.bounds,
+                                                        &gradient.center,
+                                                        &gradient.radius,
+                                                        gradient.stops)
+                }
+            }
+
+            StackingContextDisplayItemClass(ref context) => {
+                do render_context.draw_with_opacity(context.opacity as AzFloat) |render_context| {
+                    context.list.draw_into_context(render_context)
+                }
+            }
+
+            BoxShadowDisplayItemClass(ref shadow) => {
+                render_context.draw_box_shadow(&shadow.box_bounds,
+                                               &shadow.offset,
+                                               shadow.blur_radius,
+                                               shadow.spread_radius,
+                                               shadow.color,
+                                               shadow.inset)
             }
         }
     }
@@ -163,13 +830,549 @@ impl<E> DisplayItem<E> {
                 SolidColorDisplayItemClass(ref solid_color) => transmute_region(&solid_color.base),
                 TextDisplayItemClass(ref text) => transmute_region(&text.base),
                 ImageDisplayItemClass(ref image_item) => transmute_region(&image_item.base),
-                BorderDisplayItemClass(ref border) => transmute_region(&border.base)
+                BackgroundImageDisplayItemClass(ref image_item) => {
+                    transmute_region(&image_item.base)
+                }
+                BorderDisplayItemClass(ref border) => transmute_region(&border.base),
+                LinearGradientDisplayItemClass(ref gradient) => transmute_region(&gradient.base),
+                RadialGradientDisplayItemClass(ref gradient) => transmute_region(&gradient.base),
+                BoxShadowDisplayItemClass(ref shadow) => transmute_region(&shadow.base),
+                StackingContextDisplayItemClass(ref context) => transmute_region(&context.base),
             }
         }
     }
 
+    /// Returns the area this item may draw in, which the display list index culls by. This is
+    /// its bounds, grown to take in what it paints around them: the ink and decorations of text
+    /// hanging out of its line box, the shadows of text, and the blur and spread of outer box
+    /// shadows.
     pub fn bounds(&self) -> Rect<Au> {
-        self.base().bounds
+        match *self {
+            TextDisplayItemClass(ref text) => {
+                // Glyphs may overhang their advances and ascents, and lines may be drawn below
+                // the descent. Half an em covers both for any reasonable font.
+                let run = text.text_run.get();
+                let em_size = run.fonts.iter().fold(Au(0), |em_size, font| {
+                    Au::max(em_size, font.get().metrics.em_size)
+                });
+                let ink_bounds = inflate_rect(&text.base.bounds, em_size.scale_by(0.5));
+                let mut bounds = ink_bounds;
+                for shadow in text.shadows.iter() {
+                    let shadow_bounds = ink_bounds.translate(&shadow.offset);
+                    bounds = bounds.union(&inflate_rect(&shadow_bounds, shadow.blur_radius));
+                }
+                bounds
+            }
+            BoxShadowDisplayItemClass(ref shadow) if !shadow.inset => {
+                let shadow_bounds = shadow.box_bounds.translate(&shadow.offset);
+                let extent = shadow.spread_radius + shadow.blur_radius;
+                shadow.base.bounds.union(&inflate_rect(&shadow_bounds, extent))
+            }
+            _ => self.base().bounds,
+        }
     }
+
+    fn type_name(&self) -> &'static str {
+        match *self {
+            SolidColorDisplayItemClass(*) => "SolidColor",
+            TextDisplayItemClass(*) => "Text",
+            ImageDisplayItemClass(*) => "Image",
+            BackgroundImageDisplayItemClass(*) => "BackgroundImage",
+            BorderDisplayItemClass(*) => "Border",
+            LinearGradientDisplayItemClass(*) => "LinearGradient",
+            RadialGradientDisplayItemClass(*) => "RadialGradient",
+            BoxShadowDisplayItemClass(*) => "BoxShadow",
+            StackingContextDisplayItemClass(*) => "StackingContext",
+        }
+    }
+
+    /// Returns the properties of this item that affect rendering, as name/value pairs.
+    fn properties(&self) -> ~[(~str, Json)] {
+        let base = self.base();
+        let mut properties = ~[(~"type", String(self.type_name().to_owned())),
+                               (~"bounds", rect_to_json(&base.bounds))];
+        if base.is_clipped() {
+            properties.push((~"clip", rect_to_json(&base.clip)));
+        }
+
+        match *self {
+            SolidColorDisplayItemClass(ref solid_color) => {
+                properties.push((~"color", color_to_json(&solid_color.color)));
+                if !solid_color.radii.is_zero() {
+                    properties.push((~"radii", radii_to_json(&solid_color.radii)));
+                }
+            }
+            TextDisplayItemClass(ref text) => {
+                // The range counts characters, not bytes.
+                let run_text = text.text_run.get().text.as_slice();
+                let text_slice = run_text.slice_chars(text.range.begin(), text.range.end());
+                properties.push((~"color", color_to_json(&text.color)));
+                if !text.decorations.is_none() {
+                    properties.push((~"decorations", decorations_to_json(&text.decorations)));
+                }
+                properties.push((~"range", List(~[Number(text.range.begin() as float),
+                                                  Number(text.range.end() as float)])));
+                if text.shadows.len() > 0 {
+                    properties.push((~"shadows", shadows_to_json(text.shadows)));
+                }
+                properties.push((~"text", String(text_slice.to_owned())));
+            }
+            ImageDisplayItemClass(ref image_item) => {
+                properties.push((~"url", String(image_item.url.to_str())));
+            }
+            BackgroundImageDisplayItemClass(ref image_item) => {
+                if !image_item.radii.is_zero() {
+                    properties.push((~"radii", radii_to_json(&image_item.radii)));
+                }
+                properties.push((~"repeat", List(~[Boolean(image_item.repeat_x),
+                                                   Boolean(image_item.repeat_y)])));
+                properties.push((~"tile", rect_to_json(&image_item.tile_bounds)));
+                properties.push((~"url", String(image_item.url.to_str())));
+            }
+            BorderDisplayItemClass(ref border) => {
+                properties.push((~"colors", List(~[color_to_json(&border.color.top),
+                                                   color_to_json(&border.color.right),
+                                                   color_to_json(&border.color.bottom),
+                                                   color_to_json(&border.color.left)])));
+                if !border.radii.is_zero() {
+                    properties.push((~"radii", radii_to_json(&border.radii)));
+                }
+                properties.push((~"styles", List(~[String(border.style.top.name().to_owned()),
+                                                   String(border.style.right.name().to_owned()),
+                                                   String(border.style.bottom.name().to_owned()),
+                                                   String(border.style.left.name().to_owned())])));
+                properties.push((~"widths", List(~[au_to_json(border.border.top),
+                                                   au_to_json(border.border.right),
+                                                   au_to_json(border.border.bottom),
+                                                   au_to_json(border.border.left)])));
+            }
+            LinearGradientDisplayItemClass(ref gradient) => {
+                if !gradient.radii.is_zero() {
+                    properties.push((~"radii", radii_to_json(&gradient.radii)));
+                }
+                properties.push((~"start", point_to_json(&gradient.start_point)));
+                properties.push((~"end", point_to_json(&gradient.end_point)));
+                properties.push((~"stops", stops_to_json(gradient.stops)));
+            }
+            RadialGradientDisplayItemClass(ref gradient) => {
+                properties.push((~"center", point_to_json(&gradient.center)));
+                if !gradient.radii.is_zero() {
+                    properties.push((~"radii", radii_to_json(&gradient.radii)));
+                }
+                properties.push((~"radius", List(~[au_to_json(gradient.radius.width),
+                                                   au_to_json(gradient.radius.height)])));
+                properties.push((~"stops", stops_to_json(gradient.stops)));
+            }
+            BoxShadowDisplayItemClass(ref shadow) => {
+                properties.push((~"box", rect_to_json(&shadow.box_bounds)));
+                properties.push((~"offset", point_to_json(&shadow.offset)));
+                properties.push((~"blur", au_to_json(shadow.blur_radius)));
+                properties.push((~"spread", au_to_json(shadow.spread_radius)));
+                properties.push((~"color", color_to_json(&shadow.color)));
+                properties.push((~"inset", Boolean(shadow.inset)));
+            }
+            StackingContextDisplayItemClass(ref context) => {
+                properties.push((~"opacity", Number(context.opacity as float)));
+                properties.push((~"z-index", Number(context.z_index as float)));
+            }
+        }
+        properties
+    }
+
+    /// Returns a one-line description of this item, e.g.
+    /// `SolidColor bounds=[0,0,800,20] color=[255,255,255,1]`.
+    pub fn debug_str(&self) -> ~str {
+        let properties = self.properties();
+        let mut parts = ~[self.type_name().to_owned()];
+        for &(ref name, ref value) in properties.tail().iter() {
+            parts.push(fmt!("%s=%s", *name, value.to_str()));
+        }
+        parts.connect(" ")
+    }
+
+    // the size of the boxed item, plus whatever else it owns; shared text runs are only counted
+    // if their addresses are not yet in `runs`
+    fn memory_size(&self, runs: &mut HashSet<uint>) -> uint {
+        match *self {
+            SolidColorDisplayItemClass(*) => sys::size_of::<SolidColorDisplayItem<E>>(),
+            TextDisplayItemClass(ref text) => {
+                let run = text.text_run.get();
+                let run_size = if runs.insert(ptr::to_unsafe_ptr(run) as uint) {
+                    run.memory_size()
+                } else {
+                    0
+                };
+                sys::size_of::<TextDisplayItem<E>>() +
+                    text.shadows.len() * sys::size_of::<TextShadow>() + run_size
+            }
+            ImageDisplayItemClass(*) => sys::size_of::<ImageDisplayItem<E>>(),
+            BackgroundImageDisplayItemClass(*) => sys::size_of::<BackgroundImageDisplayItem<E>>(),
+            BorderDisplayItemClass(*) => sys::size_of::<BorderDisplayItem<E>>(),
+            LinearGradientDisplayItemClass(ref gradient) => {
+                sys::size_of::<LinearGradientDisplayItem<E>>() +
+                    gradient.stops.len() * sys::size_of::<GradientStop>()
+            }
+            RadialGradientDisplayItemClass(ref gradient) => {
+                sys::size_of::<RadialGradientDisplayItem<E>>() +
+                    gradient.stops.len() * sys::size_of::<GradientStop>()
+            }
+            BoxShadowDisplayItemClass(*) => sys::size_of::<BoxShadowDisplayItem<E>>(),
+            StackingContextDisplayItemClass(ref context) => {
+                sys::size_of::<StackingContextDisplayItem<E>>() +
+                    context.list.memory_size_with_runs(runs)
+            }
+        }
+    }
+}
+
+
+impl<E> ToJson for DisplayItem<E> {
+    fn to_json(&self) -> Json {
+        let mut object = ~TreeMap::new();
+        for (name, value) in self.properties().move_iter() {
+            object.insert(name, value);
+        }
+        match *self {
+            StackingContextDisplayItemClass(ref context) => {
+                object.insert(~"items", context.list.to_json());
+            }
+            _ => {}
+        }
+        Object(object)
+    }
+}
+
+fn au_to_json(au: Au) -> Json {
+    Number(to_frac_px(au))
+}
+
+fn point_to_json(point: &Point2D<Au>) -> Json {
+    List(~[au_to_json(point.x), au_to_json(point.y)])
+}
+
+// each stop as its offset followed by its color
+fn stops_to_json(stops: &[GradientStop]) -> Json {
+    let mut stops = do stops.iter().map |stop| {
+        List(~[Number(stop.offset as float), color_to_json(&stop.color)])
+    };
+    List(stops.collect())
+}
+
+// the color of each line drawn, by name
+fn decorations_to_json(decorations: &TextDecorations) -> Json {
+    let mut lines = TreeMap::new();
+    let all = [(~"line-through", decorations.line_through),
+               (~"overline", decorations.overline),
+               (~"underline", decorations.underline)];
+    for &(ref name, color) in all.iter() {
+        for color in color.iter() {
+            lines.insert(name.clone(), color_to_json(color));
+        }
+    }
+    Object(~lines)
+}
+
+// each shadow as its offset, blur radius and color
+fn shadows_to_json(shadows: &[TextShadow]) -> Json {
+    let mut shadows = do shadows.iter().map |shadow| {
+        List(~[point_to_json(&shadow.offset),
+               au_to_json(shadow.blur_radius),
+               color_to_json(&shadow.color)])
+    };
+    List(shadows.collect())
+}
+
+// the horizontal and vertical radius of each corner, clockwise from the top left
+fn radii_to_json(radii: &BorderRadii) -> Json {
+    let corners = radii.corners();
+    let mut corners = do corners.iter().map |corner| {
+        List(~[au_to_json(corner.width), au_to_json(corner.height)])
+    };
+    List(corners.collect())
+}
+
+// x, y, width and height in px
+fn rect_to_json(rect: &Rect<Au>) -> Json {
+    List(~[au_to_json(rect.origin.x),
+           au_to_json(rect.origin.y),
+           au_to_json(rect.size.width),
+           au_to_json(rect.size.height)])
+}
+
+// red, green and blue from 0 to 255, alpha from 0 to 1
+fn color_to_json(color: &Color) -> Json {
+    List(~[Number((color.r * 255.0) as float),
+           Number((color.g * 255.0) as float),
+           Number((color.b * 255.0) as float),
+           Number(color.a as float)])
+}
+
+#[cfg(test)]
+fn test_display_list() -> DisplayList<()> {
+    let mut list = DisplayList::new();
+    list.append_item(SolidColorDisplayItemClass(~SolidColorDisplayItem {
+        base: BaseDisplayItem {
+            bounds: Rect(Point2D(Au(0), Au(0)), Size2D(Au::from_px(800), Au::from_px(20))),
+            clip: Au::max_rect(),
+            extra: (),
+        },
+        color: rgb(255, 255, 255),
+        radii: BorderRadii::zero(),
+    }));
+    list.append_item(BorderDisplayItemClass(~BorderDisplayItem {
+        base: BaseDisplayItem {
+            bounds: Rect(Point2D(Au::from_px(10), Au::from_px(5)),
+                         Size2D(Au::from_px(100), Au::from_px(50))),
+            clip: Rect(Point2D(Au(0), Au(0)), Size2D(Au::from_px(50), Au::from_px(50))),
+            extra: (),
+        },
+        border: SideOffsets2D::new(Au::from_px(1), Au::from_px(2), Au::from_px(3), Au::from_px(4)),
+        color: SideOffsets2D::new(rgb(0, 0, 255), rgb(0, 0, 255), rgb(0, 0, 255), rgb(255, 0, 0)),
+        style: SideOffsets2D::new(BorderStyleSolid,
+                                  BorderStyleSolid,
+                                  BorderStyleDashed,
+                                  BorderStyleSolid),
+        radii: BorderRadii {
+            top_left: Size2D(Au::from_px(5), Au::from_px(10)),
+            .. BorderRadii::zero()
+        },
+    }));
+    list
 }
 
+#[test]
+fn test_dump() {
+    let expected = ~"SolidColor bounds=[0,0,800,20] color=[255,255,255,1]\n" +
+        "Border bounds=[10,5,100,50] clip=[0,0,50,50] " +
+        "colors=[[0,0,255,1],[0,0,255,1],[0,0,255,1],[255,0,0,1]] " +
+        "radii=[[5,10],[0,0],[0,0],[0,0]] styles=[\"solid\",\"solid\",\"dashed\",\"solid\"] " +
+        "widths=[1,2,3,4]";
+    assert_eq!(test_display_list().dump(), expected);
+
+    // Text ranges count characters, not bytes.
+    let mut list = DisplayList::new();
+    list.append_item(TextDisplayItemClass(~TextDisplayItem {
+        base: BaseDisplayItem {
+            bounds: Rect(Point2D(Au(0), Au(0)), Size2D(Au::from_px(30), Au::from_px(20))),
+            clip: Au::max_rect(),
+            extra: (),
+        },
+        text_run: Arc::new(sendable_run_for_test("d\u00e9j\u00e0 vu")),
+        range: Range::new(1, 3),
+        color: rgb(0, 0, 0),
+        decorations: TextDecorations::none(),
+        shadows: ~[],
+    }));
+    assert_eq!(list.dump(),
+               ~"Text bounds=[0,0,30,20] color=[0,0,0,1] range=[1,4] text=\"\u00e9j\u00e0\"");
+}
+
+#[test]
+fn test_to_json() {
+    let expected = ~"[{\"bounds\":[0,0,800,20],\"color\":[255,255,255,1]," +
+        "\"type\":\"SolidColor\"}," +
+        "{\"bounds\":[10,5,100,50],\"clip\":[0,0,50,50]," +
+        "\"colors\":[[0,0,255,1],[0,0,255,1],[0,0,255,1],[255,0,0,1]]," +
+        "\"radii\":[[5,10],[0,0],[0,0],[0,0]]," +
+        "\"styles\":[\"solid\",\"solid\",\"dashed\",\"solid\"]," +
+        "\"type\":\"Border\",\"widths\":[1,2,3,4]}]";
+    assert_eq!(test_display_list().to_json().to_str(), expected);
+}
+
+#[test]
+fn test_display_list_index() {
+    let list = test_display_list();
+    let index = DisplayListIndex::new(&list,
+                                      Size2D(Au::from_px(800), Au::from_px(600)),
+                                      Au::from_px(64));
+    let rect = |x: int, y: int, width: int, height: int| -> Rect<Au> {
+        Rect(Point2D(Au::from_px(x), Au::from_px(y)),
+             Size2D(Au::from_px(width), Au::from_px(height)))
+    };
+    assert_eq!(index.items_intersecting(&list, &rect(0, 0, 800, 600)), ~[0, 1]);
+    assert_eq!(index.items_intersecting(&list, &rect(0, 0, 5, 5)), ~[0]);
+    assert_eq!(index.items_intersecting(&list, &rect(20, 20, 10, 10)), ~[1]);
+    assert_eq!(index.items_intersecting(&list, &rect(0, 100, 50, 50)), ~[]);
+    assert_eq!(index.items_intersecting(&list, &rect(700, 500, 500, 500)), ~[]);
+
+    // The items of stacking contexts are indexed separately.
+    let mut list = DisplayList::new();
+    list.append_item(test_stacking_context(0, test_display_list()));
+    let index = DisplayListIndex::new(&list,
+                                      Size2D(Au::from_px(800), Au::from_px(600)),
+                                      Au::from_px(64));
+    assert_eq!(index.items_intersecting(&list, &rect(20, 20, 10, 10)), ~[0]);
+    let context_list = match list.list[0] {
+        StackingContextDisplayItemClass(ref context) => &context.list,
+        _ => fail!(),
+    };
+    let context_index = index.contexts.find(&0).unwrap();
+    assert_eq!(context_index.items_intersecting(context_list, &rect(20, 20, 10, 10)), ~[1]);
+    assert_eq!(context_index.items_intersecting(context_list, &rect(700, 0, 10, 10)), ~[0]);
+}
+
+#[test]
+fn test_display_list_index_covers_shadows() {
+    let rect = |x: int, y: int, width: int, height: int| -> Rect<Au> {
+        Rect(Point2D(Au::from_px(x), Au::from_px(y)),
+             Size2D(Au::from_px(width), Au::from_px(height)))
+    };
+    let mut list = DisplayList::new();
+    list.append_item(TextDisplayItemClass(~TextDisplayItem {
+        base: BaseDisplayItem {
+            bounds: rect(0, 0, 50, 20),
+            clip: Au::max_rect(),
+            extra: (),
+        },
+        text_run: Arc::new(sendable_run_for_test("shadowed")),
+        range: Range::new(0, 8),
+        color: rgb(0, 0, 0),
+        decorations: TextDecorations::none(),
+        shadows: ~[TextShadow {
+            offset: Point2D(Au::from_px(200), Au(0)),
+            blur_radius: Au::from_px(10),
+            color: rgb(0, 0, 0),
+        }],
+    }));
+    list.append_item(BoxShadowDisplayItemClass(~BoxShadowDisplayItem {
+        base: BaseDisplayItem {
+            bounds: rect(0, 300, 50, 50),
+            clip: Au::max_rect(),
+            extra: (),
+        },
+        box_bounds: rect(0, 300, 50, 50),
+        offset: Point2D(Au(0), Au::from_px(100)),
+        blur_radius: Au::from_px(10),
+        spread_radius: Au::from_px(5),
+        color: rgb(0, 0, 0),
+        inset: false,
+    }));
+    let index = DisplayListIndex::new(&list,
+                                      Size2D(Au::from_px(800), Au::from_px(600)),
+                                      Au::from_px(64));
+
+    // Tiles that only the shadows reach still draw the items casting them.
+    assert_eq!(index.items_intersecting(&list, &rect(255, 0, 10, 10)), ~[0]);
+    assert_eq!(index.items_intersecting(&list, &rect(0, 460, 10, 10)), ~[1]);
+    assert_eq!(index.items_intersecting(&list, &rect(300, 0, 10, 10)), ~[]);
+    assert_eq!(index.items_intersecting(&list, &rect(0, 480, 10, 10)), ~[]);
+}
+
+#[test]
+fn test_border_radii_scaled_to_fit() {
+    let size = |width: int, height: int| Size2D(Au::from_px(width), Au::from_px(height));
+    let radii = BorderRadii {
+        top_left: size(40, 10),
+        top_right: size(60, 10),
+        bottom_right: size(20, 30),
+        bottom_left: size(20, 10),
+    };
+
+    // Radii that fit are left alone.
+    assert_eq!(radii.scaled_to_fit(&size(100, 40)), radii.clone());
+
+    // Otherwise the corners that overlap most decide how much all of them shrink.
+    let scaled = radii.scaled_to_fit(&size(50, 40));
+    assert_eq!(scaled.top_left, size(20, 5));
+    assert_eq!(scaled.top_right, size(30, 5));
+    assert_eq!(scaled.bottom_right, size(10, 15));
+    assert_eq!(scaled.bottom_left, size(10, 5));
+
+    assert!(BorderRadii::zero().is_zero());
+    assert!(!radii.is_zero());
+}
+
+#[cfg(test)]
+fn test_stacking_context(z_index: int, list: DisplayList<()>) -> DisplayItem<()> {
+    StackingContextDisplayItemClass(~StackingContextDisplayItem {
+        base: BaseDisplayItem {
+            bounds: list.bounds(),
+            clip: Au::max_rect(),
+            extra: (),
+        },
+        z_index: z_index,
+        opacity: 0.5,
+        list: list,
+    })
+}
+
+#[test]
+fn test_sort_stacking_contexts() {
+    let mut list = test_display_list();
+    list.append_item(test_stacking_context(1, test_display_list()));
+    list.append_item(test_stacking_context(-1, DisplayList::new()));
+    list.append_item(test_stacking_context(0, DisplayList::new()));
+    list.append_item(test_stacking_context(-2, DisplayList::new()));
+    list.append_item(test_stacking_context(-1, test_display_list()));
+    list.sort_stacking_contexts(1);
+
+    let expected = ~"SolidColor bounds=[0,0,800,20] color=[255,255,255,1]\n" +
+        "StackingContext bounds=[0,0,0,0] opacity=0.5 z-index=-2\n" +
+        "StackingContext bounds=[0,0,0,0] opacity=0.5 z-index=-1\n" +
+        "StackingContext bounds=[0,0,800,55] opacity=0.5 z-index=-1\n" +
+        "  SolidColor bounds=[0,0,800,20] color=[255,255,255,1]\n" +
+        "  Border bounds=[10,5,100,50] clip=[0,0,50,50] " +
+        "colors=[[0,0,255,1],[0,0,255,1],[0,0,255,1],[255,0,0,1]] " +
+        "radii=[[5,10],[0,0],[0,0],[0,0]] styles=[\"solid\",\"solid\",\"dashed\",\"solid\"] " +
+        "widths=[1,2,3,4]\n" +
+        "Border bounds=[10,5,100,50] clip=[0,0,50,50] " +
+        "colors=[[0,0,255,1],[0,0,255,1],[0,0,255,1],[255,0,0,1]] " +
+        "radii=[[5,10],[0,0],[0,0],[0,0]] styles=[\"solid\",\"solid\",\"dashed\",\"solid\"] " +
+        "widths=[1,2,3,4]\n" +
+        "StackingContext bounds=[0,0,0,0] opacity=0.5 z-index=0\n" +
+        "StackingContext bounds=[0,0,800,55] opacity=0.5 z-index=1\n" +
+        "  SolidColor bounds=[0,0,800,20] color=[255,255,255,1]\n" +
+        "  Border bounds=[10,5,100,50] clip=[0,0,50,50] " +
+        "colors=[[0,0,255,1],[0,0,255,1],[0,0,255,1],[255,0,0,1]] " +
+        "radii=[[5,10],[0,0],[0,0],[0,0]] styles=[\"solid\",\"solid\",\"dashed\",\"solid\"] " +
+        "widths=[1,2,3,4]";
+    assert_eq!(list.dump(), expected);
+}
+
+#[test]
+fn test_item_at() {
+    let mut list = DisplayList::new();
+    list.append_item(test_stacking_context(0, test_display_list()));
+    let type_at = |x: int, y: int| {
+        list.item_at(&Point2D(Au::from_px(x), Au::from_px(y))).map(|item| item.type_name())
+    };
+
+    // The border is in front of the background, but only inside its clip rect.
+    assert_eq!(type_at(20, 10), Some("Border"));
+    assert_eq!(type_at(70, 10), Some("SolidColor"));
+    assert_eq!(type_at(20, 100), None);
+}
+
+#[test]
+fn test_memory_size_counts_shared_runs_once() {
+    let run = Arc::new(sendable_run_for_test("shared run"));
+    let text_item = |begin: uint| {
+        TextDisplayItemClass(~TextDisplayItem {
+            base: BaseDisplayItem {
+                bounds: Rect(Point2D(Au(0), Au(0)), Size2D(Au::from_px(50), Au::from_px(20))),
+                clip: Au::max_rect(),
+                extra: (),
+            },
+            text_run: run.clone(),
+            range: Range::new(begin, 5),
+            color: rgb(0, 0, 0),
+            decorations: TextDecorations::none(),
+            shadows: ~[],
+        })
+    };
+    let mut list = DisplayList::new();
+    list.append_item(text_item(0));
+    let one_item = list.memory_size();
+    assert!(one_item >= run.get().memory_size());
+
+    // A second item drawing the same run only adds itself, even inside a stacking context.
+    let item_size = sys::size_of::<DisplayItem<()>>() + sys::size_of::<TextDisplayItem<()>>();
+    list.append_item(text_item(5));
+    assert_eq!(list.memory_size(), one_item + item_size);
+    let mut context_list = DisplayList::new();
+    context_list.append_item(text_item(0));
+    list.append_item(test_stacking_context(0, context_list));
+    let context_size = sys::size_of::<DisplayItem<()>>() +
+        sys::size_of::<StackingContextDisplayItem<()>>();
+    assert_eq!(list.memory_size(), one_item + 2 * item_size + context_size);
+}
@@ -10,18 +10,31 @@ use platform::font::{FontHandle, FontTable};
 use render_context::RenderContext;
 use servo_util::range::Range;
 use std::cast;
+use std::cell::Cell;
+use std::managed;
 use std::ptr;
 use std::str;
+use std::sys;
+use std::to_bytes;
+use std::unstable::atomics::{AtomicUint, INIT_ATOMIC_UINT, SeqCst};
 use std::vec;
-use servo_util::cache::{Cache, HashCache};
-use text::glyph::{GlyphStore, GlyphIndex};
+use servo_util::cache::{Cache, CacheStats, WeightedLRUCache};
+use text::glyph::{BreakTypeNone, GlyphStore, GlyphIndex};
+use text::line_break;
 use text::shaping::ShaperMethods;
-use text::{Shaper, TextRun};
-use extra::arc::Arc;
+use text::{SendableTextRun, Shaper, TextRun};
+use text::util::true_type_tag;
+use text::util;
+use extra::arc::{Arc, RWArc};
+use extra::url::Url;
 
 use azure::{AzFloat, AzScaledFontRef};
 use azure::scaled_font::ScaledFont;
-use azure::azure_hl::{BackendType, ColorPattern};
+use azure::azure_hl::{BackendType, ColorPattern, DrawTarget};
+use azure::{struct__AzDrawOptions, struct__AzGlyph, struct__AzGlyphBuffer, struct__AzPoint};
+use azure::azure::AzDrawTargetFillGlyphs;
+use display_list::TextShadow;
+use std::libc::types::common::c99::{uint16_t, uint32_t};
 use geom::{Point2D, Rect, Size2D};
 
 use servo_util::time;
@@ -34,7 +47,8 @@ use servo_util::time::ProfilerChan;
 // resources needed by the graphics layer to draw glyphs.
 
 pub trait FontHandleMethods {
-    fn new_from_buffer(fctx: &FontContextHandle, buf: ~[u8], style: &SpecifiedFontStyle)
+    // the buffer is shared rather than copied, since downloaded fonts are used in many styles.
+    fn new_from_buffer(fctx: &FontContextHandle, buf: Arc<~[u8]>, style: &SpecifiedFontStyle)
                     -> Result<Self,()>;
 
     // an identifier usable by FontContextHandle to recreate this FontHandle.
@@ -50,6 +64,8 @@ pub trait FontHandleMethods {
     fn glyph_h_advance(&self, GlyphIndex) -> Option<FractionalPixel>;
     fn get_metrics(&self) -> FontMetrics;
     fn get_table_for_tag(&self, FontTableTag) -> Option<FontTable>;
+    /// The bytes of font data the platform keeps alive for this face.
+    fn face_data_size(&self) -> uint;
 }
 
 // Used to abstract over the shaper's choice of fixed int representation.
@@ -77,9 +93,14 @@ pub trait FontTableMethods {
     fn with_buffer(&self, &fn(*u8, uint));
 }
 
+#[deriving(Clone)]
 pub struct FontMetrics {
     underline_size:   Au,
+    /// How far the middle of the underline is above the baseline. Negative values are below it.
     underline_offset: Au,
+    strikeout_size:   Au,
+    /// How far the middle of the line-through is above the baseline.
+    strikeout_offset: Au,
     leading:          Au,
     x_height:         Au,
     em_size:          Au,
@@ -89,7 +110,7 @@ pub struct FontMetrics {
 }
 
 // TODO(Issue #200): use enum from CSS bindings for 'font-weight'
-#[deriving(Clone, Eq)]
+#[deriving(Clone, Eq, IterBytes)]
 pub enum CSSFontWeight {
     FontWeight100,
     FontWeight200,
@@ -103,6 +124,11 @@ pub enum CSSFontWeight {
 }
 
 impl CSSFontWeight {
+    /// The numeric value of the weight, from 100 to 900.
+    pub fn to_number(self) -> uint {
+        (self as uint + 1) * 100
+    }
+
     pub fn is_bold(self) -> bool {
         match self {
             FontWeight900 | FontWeight800 | FontWeight700 | FontWeight600 => true,
@@ -117,19 +143,56 @@ impl CSSFontWeight {
 // the instance's properties.
 //
 // For now, the cases are differentiated with a typedef
-#[deriving(Clone, Eq)]
+#[deriving(Clone, Eq, IterBytes)]
 pub struct FontStyle {
     pt_size: float,
     weight: CSSFontWeight,
     italic: bool,
     oblique: bool,
     families: ~str,
-    // TODO(Issue #198): font-stretch, text-decoration, font-variant, size-adjust
+    features: FontFeatures,
+    // TODO(Issue #198): font-stretch, text-decoration, size-adjust
 }
 
 pub type SpecifiedFontStyle = FontStyle;
 pub type UsedFontStyle = FontStyle;
 
+/// The OpenType features that text is shaped with. Features the font lacks have no effect.
+#[deriving(Clone, Eq, IterBytes)]
+pub struct FontFeatures {
+    /// Standard and contextual ligatures, like "fi" (`liga` and `clig`).
+    ligatures: bool,
+    /// Small capitals in place of lowercase letters (`smcp`).
+    small_caps: bool,
+    /// Figures that all have the same width (`tnum`).
+    tabular_numbers: bool,
+    /// Pair kerning (`kern`).
+    kerning: bool,
+}
+
+impl FontFeatures {
+    /// The features the shaper uses when nothing else is specified.
+    pub fn default() -> FontFeatures {
+        FontFeatures {
+            ligatures: true,
+            small_caps: false,
+            tabular_numbers: false,
+            kerning: true,
+        }
+    }
+
+    /// Returns the OpenType tags of these features, each with whether it is on.
+    pub fn to_settings(&self) -> ~[(FontTableTag, bool)] {
+        ~[
+            (true_type_tag('l', 'i', 'g', 'a'), self.ligatures),
+            (true_type_tag('c', 'l', 'i', 'g'), self.ligatures),
+            (true_type_tag('s', 'm', 'c', 'p'), self.small_caps),
+            (true_type_tag('t', 'n', 'u', 'm'), self.tabular_numbers),
+            (true_type_tag('k', 'e', 'r', 'n'), self.kerning),
+        ]
+    }
+}
+
 // FIXME: move me to layout
 struct ResolvedFont {
     group: @FontGroup,
@@ -142,7 +205,7 @@ struct ResolvedFont {
 // It's used to swizzle/unswizzle gfx::Font instances when
 // communicating across tasks, such as the display list between layout
 // and render tasks.
-#[deriving(Clone, Eq)]
+#[deriving(Clone, Eq, IterBytes)]
 pub struct FontDescriptor {
     style: UsedFontStyle,
     selector: FontSelector,
@@ -158,9 +221,73 @@ impl FontDescriptor {
 }
 
 // A FontSelector is a platform-specific strategy for serializing face names.
-#[deriving(Clone, Eq)]
+#[deriving(Clone, Eq, IterBytes)]
 pub enum FontSelector {
     SelectorPlatformIdentifier(~str),
+    /// A font downloaded for an `@font-face` rule. It carries the font file, since the font
+    /// contexts of other tasks have no other way to get at it.
+    SelectorWebFont(WebFontData),
+}
+
+/// The contents of a downloaded font file, identified by the URL it came from.
+pub struct WebFontData {
+    url: ~str,
+    data: Arc<~[u8]>,
+}
+
+impl Clone for WebFontData {
+    fn clone(&self) -> WebFontData {
+        WebFontData {
+            url: self.url.clone(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+impl Eq for WebFontData {
+    fn eq(&self, other: &WebFontData) -> bool {
+        self.url == other.url
+    }
+}
+
+impl IterBytes for WebFontData {
+    fn iter_bytes(&self, lsb0: bool, f: to_bytes::Cb) -> bool {
+        self.url.iter_bytes(lsb0, f)
+    }
+}
+
+/// The descriptors of an `@font-face` rule that the font contexts use.
+#[deriving(Clone)]
+pub struct FontFaceRule {
+    family: ~str,
+    /// The font files, in order of preference.
+    sources: ~[FontFaceSource],
+    weight: CSSFontWeight,
+    italic: bool,
+}
+
+/// A font file given by a `url()` in the `src` of an `@font-face` rule.
+#[deriving(Clone)]
+pub struct FontFaceSource {
+    url: Url,
+    /// The format given by its `format()` hint, in lowercase, if it has one.
+    format: Option<~str>,
+}
+
+impl FontFaceSource {
+    /// Returns false if the source's format hint names a format that the platform font libraries
+    /// are known not to read, so that it need not be downloaded.
+    pub fn is_supported(&self) -> bool {
+        match self.format {
+            Some(ref format) => {
+                match format.as_slice() {
+                    "woff2" | "embedded-opentype" | "svg" => false,
+                    _ => true,
+                }
+            }
+            None => true,
+        }
+    }
 }
 
 // This struct is the result of mapping a specified FontStyle into the
@@ -191,11 +318,53 @@ impl FontGroup {
         self.fonts = ~[];
     }
 
-    pub fn create_textrun(&self, text: ~str, underline: bool) -> TextRun {
+    /// Creates a run of the given text. Each cluster, a base character along with the marks,
+    /// joiners and variation selectors that follow it, is shaped with one font: the first font
+    /// of the group that covers the whole cluster or, failing that, a system font found by the
+    /// font context, or else the first font of the group that covers the base character.
+    /// `char_levels` holds the bidirectional embedding level of each character, resolved over the
+    /// whole paragraph.
+    pub fn create_textrun(&self, font_ctx: @mut FontContext, text: ~str, char_levels: &[u8])
+                          -> TextRun {
         assert!(self.fonts.len() > 0);
 
-        // TODO(Issue #177): Actually fall back through the FontGroup when a font is unsuitable.
-        return TextRun::new(self.fonts[0], text, underline);
+        let mut fonts = self.fonts.clone();
+        let chars: ~[char] = text.iter().collect();
+        let mut char_fonts = vec::with_capacity(chars.len());
+        let mut start = 0;
+        for &length in util::cluster_lengths(text).iter() {
+            let cluster = chars.slice(start, start + length);
+            start += length;
+            let covers = |font: &@mut Font| {
+                cluster.iter().all(|&ch| {
+                    util::is_default_ignorable(ch) || font.glyph_index(ch).is_some()
+                })
+            };
+            let index = match fonts.iter().position(|font| covers(font)) {
+                Some(index) => index,
+                None => {
+                    match font_ctx.get_fallback_font(cluster[0], &self.style) {
+                        Some(font) if covers(&font) => {
+                            match fonts.iter().position(|f| managed::mut_ptr_eq(*f, font)) {
+                                Some(index) => index,
+                                None => {
+                                    fonts.push(font);
+                                    fonts.len() - 1
+                                }
+                            }
+                        }
+                        _ => {
+                            let base = cluster[0];
+                            fonts.iter().position(|font| font.glyph_index(base).is_some())
+                                 .unwrap_or_default(0)
+                        }
+                    }
+                }
+            };
+            char_fonts.grow(length, &index);
+        }
+
+        TextRun::new_with_fallback(fonts, text, char_fonts, char_levels)
     }
 }
 
@@ -227,6 +396,83 @@ impl RunMetrics {
     }
 }
 
+/// The bytes of glyphs the shape cache keeps before it drops the least recently used words.
+static SHAPE_CACHE_BUDGET: uint = 8 * 1024 * 1024;
+
+/// Shaped words, keyed by the descriptor of the font they were shaped with, their text and
+/// whether they were shaped right-to-left. One cache is shared by the fonts of all the layout
+/// tasks, so words outlive both the fonts that shaped them and the documents they appeared in.
+#[deriving(Clone)]
+pub struct ShapeCache {
+    priv cache: RWArc<WeightedLRUCache<(FontDescriptor, ~str, bool), Arc<GlyphStore>>>,
+}
+
+impl ShapeCache {
+    pub fn new() -> ShapeCache {
+        ShapeCache {
+            cache: RWArc::new(WeightedLRUCache::new(SHAPE_CACHE_BUDGET)),
+        }
+    }
+
+    /// Returns the glyphs of the word, shaping it with `shape` if it is not cached. The cache is
+    /// not locked while shaping, so that other tasks are not held up.
+    pub fn find_or_shape(&self,
+                         font: &FontDescriptor,
+                         text: ~str,
+                         rtl: bool,
+                         shape: &fn(&str) -> Arc<GlyphStore>)
+                         -> Arc<GlyphStore> {
+        let key = (font.clone(), text, rtl);
+        match self.cache.write(|cache| cache.find(&key)) {
+            Some(glyphs) => glyphs,
+            None => {
+                let glyphs = match key {
+                    (_, ref text, _) => shape(text.as_slice()),
+                };
+                let entry = Cell::new((key, glyphs.clone()));
+                do self.cache.write |cache| {
+                    let (key, glyphs) = entry.take();
+                    cache.insert(key, glyphs);
+                }
+                glyphs
+            }
+        }
+    }
+
+    /// The hit and miss counters of the cache.
+    pub fn stats(&self) -> CacheStats {
+        self.cache.read(|cache| cache.stats())
+    }
+
+    /// The number of shaped words cached, and the bytes their glyphs use.
+    pub fn size(&self) -> (uint, uint) {
+        self.cache.read(|cache| (cache.len(), cache.weight()))
+    }
+}
+
+/// The next id to give a `SharedFont`.
+static mut NEXT_SHARED_FONT_ID: AtomicUint = INIT_ATOMIC_UINT;
+
+/// What other tasks need to draw with a font that layout chose: how to load the font, its
+/// metrics, and an id that tells it apart from every other shared font. Text runs hold it in an
+/// `Arc`, so it is shared rather than copied. Platform fonts cannot be used from several tasks,
+/// so each render worker loads its own instance once and finds it again by id.
+pub struct SharedFont {
+    id: uint,
+    descriptor: FontDescriptor,
+    metrics: FontMetrics,
+}
+
+impl SharedFont {
+    pub fn new(descriptor: FontDescriptor, metrics: FontMetrics) -> SharedFont {
+        SharedFont {
+            id: unsafe { NEXT_SHARED_FONT_ID.fetch_add(1, SeqCst) },
+            descriptor: descriptor,
+            metrics: metrics,
+        }
+    }
+}
+
 /**
 A font instance. Layout can use this to calculate glyph metrics
 and the renderer can use it to render text.
@@ -239,12 +485,20 @@ pub struct Font {
     metrics: FontMetrics,
     backend: BackendType,
     profiler_chan: ProfilerChan,
-    shape_cache: HashCache<~str, Arc<GlyphStore>>,
+    /// The words shaped by this font and every other font with the same descriptor.
+    shape_cache: ShapeCache,
+    /// How other tasks find this font again.
+    selector: FontSelector,
+    /// The bytes of the font tables handed to the shaper so far.
+    priv table_bytes: uint,
+    /// This font as the text runs it shapes hand it to other tasks.
+    priv shared: Arc<SharedFont>,
 }
 
 impl Font {
     pub fn new_from_buffer(ctx: &FontContext,
-                       buffer: ~[u8],
+                       buffer: Arc<~[u8]>,
+                       selector: FontSelector,
                        style: &SpecifiedFontStyle,
                        backend: BackendType,
                        profiler_chan: ProfilerChan)
@@ -258,6 +512,8 @@ impl Font {
         
         let metrics = handle.get_metrics();
         // TODO(Issue #179): convert between specified and used font style here?
+        let shared = SharedFont::new(FontDescriptor::new((*style).clone(), selector.clone()),
+                                     metrics.clone());
 
         return Ok(@mut Font {
             handle: handle,
@@ -267,14 +523,20 @@ impl Font {
             metrics: metrics,
             backend: backend,
             profiler_chan: profiler_chan,
-            shape_cache: HashCache::new(),
+            shape_cache: ctx.shape_cache.clone(),
+            selector: selector,
+            table_bytes: 0,
+            shared: Arc::new(shared),
         });
     }
 
-    pub fn new_from_adopted_handle(_fctx: &FontContext, handle: FontHandle,
+    pub fn new_from_adopted_handle(fctx: &FontContext, handle: FontHandle,
                                style: &SpecifiedFontStyle, backend: BackendType,
                                profiler_chan: ProfilerChan) -> @mut Font {
         let metrics = handle.get_metrics();
+        let selector = SelectorPlatformIdentifier(handle.face_identifier());
+        let shared = SharedFont::new(FontDescriptor::new((*style).clone(), selector.clone()),
+                                     metrics.clone());
 
         @mut Font {
             handle: handle,
@@ -284,7 +546,10 @@ impl Font {
             metrics: metrics,
             backend: backend,
             profiler_chan: profiler_chan,
-            shape_cache: HashCache::new(),
+            shape_cache: fctx.shape_cache.clone(),
+            selector: selector,
+            table_bytes: 0,
+            shared: Arc::new(shared),
         }
     }
 
@@ -313,10 +578,16 @@ impl Font {
         shaper
     }
 
-    pub fn get_table_for_tag(&self, tag: FontTableTag) -> Option<FontTable> {
+    pub fn get_table_for_tag(&mut self, tag: FontTableTag) -> Option<FontTable> {
         let result = self.handle.get_table_for_tag(tag);
         let status = if result.is_some() { "Found" } else { "Didn't find" };
 
+        for table in result.iter() {
+            do table.with_buffer |_, len| {
+                self.table_bytes += len;
+            }
+        }
+
         debug!("%s font table[%s] with family=%s, face=%s",
                status, tag.tag_to_str(),
                self.handle.family_name(), self.handle.face_name());
@@ -362,31 +633,24 @@ impl Font {
 
 
 impl Font {
+    /// Draws the glyphs for the given range of the run with its left end at `baseline_origin`. If
+    /// `rtl` is set, the glyphs, which are in logical order, are laid out from right to left. The
+    /// shadows are painted beneath the glyphs, with the first one on top.
     pub fn draw_text_into_context(&mut self,
                               rctx: &RenderContext,
-                              run: &TextRun,
+                              run: &SendableTextRun,
                               range: &Range,
                               baseline_origin: Point2D<Au>,
-                              color: Color) {
-        use std::libc::types::common::c99::{uint16_t, uint32_t};
-        use azure::{struct__AzDrawOptions,
-                    struct__AzGlyph,
-                    struct__AzGlyphBuffer,
-                    struct__AzPoint};
-        use azure::azure::{AzDrawTargetFillGlyphs};
-
-        let target = rctx.get_draw_target();
+                              rtl: bool,
+                              color: Color,
+                              shadows: &[TextShadow]) {
         let azfontref = self.get_azure_font();
-        let pattern = ColorPattern(color);
-        let azure_pattern = pattern.azure_color_pattern;
-        assert!(azure_pattern.is_not_null());
-
-        let options = struct__AzDrawOptions {
-            mAlpha: 1f as AzFloat,
-            fields: 0x0200 as uint16_t
-        };
 
+        let advance = self.measure_text(run, range).advance_width;
         let mut origin = baseline_origin.clone();
+        if rtl {
+            origin.x = origin.x + advance;
+        }
         let mut azglyphs = ~[];
         azglyphs.reserve(range.length());
 
@@ -395,6 +659,11 @@ impl Font {
                 let glyph_advance = glyph.advance();
                 let glyph_offset = glyph.offset().unwrap_or_default(Au::zero_point());
 
+                // A right-to-left glyph's pen position is on its left, once its advance has been
+                // taken away.
+                if rtl {
+                    origin = Point2D(origin.x - glyph_advance, origin.y);
+                }
                 let azglyph = struct__AzGlyph {
                     mIndex: glyph.index() as uint32_t,
                     mPosition: struct__AzPoint {
@@ -402,39 +671,53 @@ impl Font {
                         y: (origin.y + glyph_offset.y).to_px() as AzFloat
                     }
                 };
-                origin = Point2D(origin.x + glyph_advance, origin.y);
+                if !rtl {
+                    origin = Point2D(origin.x + glyph_advance, origin.y);
+                }
                 azglyphs.push(azglyph)
             };
         }
 
-        let azglyph_buf_len = azglyphs.len();
-        if azglyph_buf_len == 0 { return; } // Otherwise the Quartz backend will assert.
+        if azglyphs.len() == 0 { return; } // Otherwise the Quartz backend will assert.
 
-        let glyphbuf = struct__AzGlyphBuffer {
-            mGlyphs: vec::raw::to_ptr(azglyphs),
-            mNumGlyphs: azglyph_buf_len as uint32_t            
-        };
-
-        unsafe {
-            // TODO(Issue #64): this call needs to move into azure_hl.rs
-            AzDrawTargetFillGlyphs(target.azure_draw_target,
-                                   azfontref,
-                                   ptr::to_unsafe_ptr(&glyphbuf),
-                                   azure_pattern,
-                                   ptr::to_unsafe_ptr(&options),
-                                   ptr::null());
+        // Blurred shadows only need to cover the text, plus the blur around it.
+        let text_bounds = Rect(Point2D(baseline_origin.x, baseline_origin.y - self.metrics.ascent),
+                               Size2D(advance, self.metrics.ascent + self.metrics.descent));
+        for shadow in shadows.rev_iter() {
+            let (dx, dy) = (shadow.offset.x.to_px() as AzFloat, shadow.offset.y.to_px() as AzFloat);
+            let mut shadow_glyphs = do azglyphs.iter().map |azglyph| {
+                struct__AzGlyph {
+                    mIndex: azglyph.mIndex,
+                    mPosition: struct__AzPoint {
+                        x: azglyph.mPosition.x + dx,
+                        y: azglyph.mPosition.y + dy
+                    }
+                }
+            };
+            let shadow_glyphs: ~[struct__AzGlyph] = shadow_glyphs.collect();
+
+            if shadow.blur_radius <= Au(0) {
+                fill_glyphs(rctx.get_draw_target(), azfontref, shadow_glyphs, shadow.color);
+            } else {
+                let blur = shadow.blur_radius;
+                let area = text_bounds.translate(&shadow.offset);
+                let area = Rect(Point2D(area.origin.x - blur, area.origin.y - blur),
+                                Size2D(area.size.width + blur.scale_by(2.0),
+                                       area.size.height + blur.scale_by(2.0)));
+                do rctx.draw_blurred(&area, blur, shadow.color) |shadow_ctx| {
+                    fill_glyphs(shadow_ctx.get_draw_target(), azfontref, shadow_glyphs,
+                                shadow.color);
+                }
+            }
         }
+
+        fill_glyphs(rctx.get_draw_target(), azfontref, azglyphs, color);
     }
 
-    pub fn measure_text(&self, run: &TextRun, range: &Range) -> RunMetrics {
+    pub fn measure_text(&self, run: &SendableTextRun, range: &Range) -> RunMetrics {
         // TODO(Issue #199): alter advance direction for RTL
         // TODO(Issue #98): using inter-char and inter-word spacing settings  when measuring text
-        let mut advance = Au(0);
-        for (glyphs, _offset, slice_range) in run.iter_slices_for_range(range) {
-            for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
-                advance = advance + glyph.advance();
-            }
-        }
+        let advance = run.advance_for_range(range);
         RunMetrics::new(advance, self.metrics.ascent, self.metrics.descent)
     }
 
@@ -449,19 +732,44 @@ impl Font {
         RunMetrics::new(advance, self.metrics.ascent, self.metrics.descent)
     }
 
-    pub fn shape_text(@mut self, text: ~str, is_whitespace: bool) -> Arc<GlyphStore> {
+    pub fn shape_text(@mut self, text: ~str, is_whitespace: bool, rtl: bool)
+                      -> Arc<GlyphStore> {
         do profile(time::LayoutShapingCategory, self.profiler_chan.clone()) {
             let shaper = self.get_shaper();
-            do self.shape_cache.find_or_create(&text) |txt| {
-                let mut glyphs = GlyphStore::new(text.char_len(), is_whitespace);
-                shaper.shape_text(*txt, &mut glyphs);
+            do self.shape_cache.find_or_shape(&self.shared.get().descriptor, text, rtl) |txt| {
+                let mut glyphs = GlyphStore::new(txt.char_len(), is_whitespace);
+                shaper.shape_text(txt, rtl, &mut glyphs);
+
+                // The line break opportunities within the text, which depend on it alone.
+                let breaks = line_break::break_opportunities(txt);
+                for (i, &break_type) in breaks.iter().enumerate() {
+                    if break_type != BreakTypeNone {
+                        glyphs.set_can_break_before(i, break_type);
+                    }
+                }
                 Arc::new(glyphs)
             }
         }
     }
 
+    /// The bytes this font keeps alive: the instance itself, the platform's face data and the
+    /// tables loaded for shaping. The data of downloaded fonts is shared with the font context,
+    /// which reports it once.
+    pub fn memory_size(&self) -> uint {
+        let face_data = match self.selector {
+            SelectorWebFont(_) => 0,
+            SelectorPlatformIdentifier(_) => self.handle.face_data_size(),
+        };
+        sys::size_of::<Font>() + face_data + self.table_bytes
+    }
+
     pub fn get_descriptor(&self) -> FontDescriptor {
-        FontDescriptor::new(self.style.clone(), SelectorPlatformIdentifier(self.handle.face_identifier()))
+        FontDescriptor::new(self.style.clone(), self.selector.clone())
+    }
+
+    /// Returns this font as other tasks see it, shared rather than copied.
+    pub fn shared(&self) -> Arc<SharedFont> {
+        self.shared.clone()
     }
 
     pub fn glyph_index(&self, codepoint: char) -> Option<GlyphIndex> {
@@ -476,6 +784,86 @@ impl Font {
     }
 }
 
+// Fills the glyphs of the font in the given color.
+fn fill_glyphs(target: &DrawTarget,
+               azfontref: AzScaledFontRef,
+               azglyphs: &[struct__AzGlyph],
+               color: Color) {
+    let pattern = ColorPattern(color);
+    let azure_pattern = pattern.azure_color_pattern;
+    assert!(azure_pattern.is_not_null());
+
+    let options = struct__AzDrawOptions {
+        mAlpha: 1f as AzFloat,
+        fields: 0x0200 as uint16_t
+    };
+
+    let glyphbuf = struct__AzGlyphBuffer {
+        mGlyphs: vec::raw::to_ptr(azglyphs),
+        mNumGlyphs: azglyphs.len() as uint32_t
+    };
+
+    unsafe {
+        // TODO(Issue #64): this call needs to move into azure_hl.rs
+        AzDrawTargetFillGlyphs(target.azure_draw_target,
+                               azfontref,
+                               ptr::to_unsafe_ptr(&glyphbuf),
+                               azure_pattern,
+                               ptr::to_unsafe_ptr(&options),
+                               ptr::null());
+    }
+}
+
+#[test]
+fn test_font_feature_settings() {
+    let settings = FontFeatures::default().to_settings();
+    let on: ~[~str] = settings.iter().filter(|&&(_, on)| on).map(|&(tag, _)| tag.tag_to_str())
+                              .collect();
+    assert_eq!(on, ~[~"liga", ~"clig", ~"kern"]);
+
+    let features = FontFeatures {
+        ligatures: false,
+        small_caps: true,
+        tabular_numbers: true,
+        kerning: true,
+    };
+    let settings = features.to_settings();
+    let off: ~[~str] = settings.iter().filter(|&&(_, on)| !on).map(|&(tag, _)| tag.tag_to_str())
+                               .collect();
+    assert_eq!(off, ~[~"liga", ~"clig"]);
+}
+
+#[test]
+fn test_shape_cache() {
+    use font_context::dummy_style;
+
+    let cache = ShapeCache::new();
+    let other_task_cache = cache.clone();
+    let font = FontDescriptor::new(dummy_style(), SelectorPlatformIdentifier(~"Serif"));
+    let mut shaped = 0;
+    let shape = |text: &str| {
+        shaped += 1;
+        Arc::new(GlyphStore::new(text.char_len(), false))
+    };
+
+    cache.find_or_shape(&font, ~"word", false, |text| shape(text));
+    // Fonts with the same descriptor share their words, even across tasks.
+    other_task_cache.find_or_shape(&font, ~"word", false, |text| shape(text));
+    assert!(shaped == 1);
+
+    // Words shaped in the other direction or with another font are shaped again.
+    cache.find_or_shape(&font, ~"word", true, |text| shape(text));
+    let mut bold = font.clone();
+    bold.style.weight = FontWeight700;
+    cache.find_or_shape(&bold, ~"word", false, |text| shape(text));
+    assert!(shaped == 3);
+
+    let stats = cache.stats();
+    assert!(stats.hits == 1 && stats.misses == 3);
+    let (count, _) = cache.size();
+    assert!(count == 3);
+}
+
 /*fn should_destruct_on_fail_without_leaking() {
     #[test];
     #[should_fail];
@@ -545,3 +933,4 @@ fn should_be_able_to_create_instances_in_multiple_threads() {
 }
 
 */
+
@@ -3,28 +3,66 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use font::{Font, FontDescriptor, FontGroup, FontHandleMethods, FontStyle,
-           SelectorPlatformIdentifier};
-use font::{SpecifiedFontStyle, UsedFontStyle};
+           SelectorPlatformIdentifier, SelectorWebFont};
+use font::{FontFaceRule, ShapeCache, SharedFont, SpecifiedFontStyle, UsedFontStyle};
+use font::WebFontData;
 use font_list::FontList;
 use servo_util::cache::{Cache, LRUCache};
-use servo_util::time::ProfilerChan;
+use servo_util::memory::MemoryReport;
+use servo_util::time::{CacheStatsMsg, ProfilerChan};
 
 use platform::font::FontHandle;
 use platform::font_context::FontContextHandle;
 
 use azure::azure_hl::BackendType;
+use extra::arc::Arc;
+use std::ascii::StrAsciiExt;
 use std::hashmap::HashMap;
+use std::sys;
 
 // TODO(Rust #3934): creating lots of new dummy styles is a workaround
 // for not being able to store symbolic enums in top-level constants.
 pub fn dummy_style() -> FontStyle {
-    use font::FontWeight300;
+    use font::{FontFeatures, FontWeight300};
     return FontStyle {
         pt_size: 20f,
         weight: FontWeight300,
         italic: false,
         oblique: false,
         families: ~"serif, sans-serif",
+        features: FontFeatures::default(),
+    }
+}
+
+/// Returns true if the platform font library can load a font from the given file, which need not
+/// be in a format it reads. Used to pick the first usable source of an `@font-face` rule.
+pub fn can_load_web_font(data: &[u8]) -> bool {
+    let handle = FontContextHandle::new();
+    let data = Arc::new(data.to_owned());
+    let result: Result<FontHandle, ()> = FontHandleMethods::new_from_buffer(&handle,
+                                                                           data,
+                                                                           &dummy_style());
+    result.is_ok()
+}
+
+/// Ranks a face of the given weight for a style that asks for the desired weight, lower being
+/// better, following the CSS Fonts matching rules: for 400, 500 is tried next and for 500, 400;
+/// then lighter weights are tried for desired weights up to 500 and heavier ones above it,
+/// nearest first, before the weights on the other side.
+fn weight_preference(desired: uint, weight: uint) -> (uint, uint) {
+    if weight == desired {
+        return (0, 0);
+    }
+    if (desired == 400 && weight == 500) || (desired == 500 && weight == 400) {
+        return (1, 0);
+    }
+    let lighter = weight < desired;
+    let prefer_lighter = desired <= 500;
+    let distance = if lighter { desired - weight } else { weight - desired };
+    if lighter == prefer_lighter {
+        (2, distance)
+    } else {
+        (3, distance)
     }
 }
 
@@ -33,19 +71,35 @@ pub trait FontContextHandleMethods {
     fn create_font_from_identifier(&self, ~str, UsedFontStyle) -> Result<FontHandle, ()>;
 }
 
+/// A font downloaded for an `@font-face` rule.
+struct WebFont {
+    rule: FontFaceRule,
+    data: WebFontData,
+}
+
 pub struct FontContext {
     instance_cache: LRUCache<FontDescriptor, @mut Font>,
+    /// This context's instances of the fonts other tasks shared with it, by id.
+    shared_font_cache: LRUCache<uint, @mut Font>,
     font_list: Option<FontList>, // only needed by layout
     group_cache: LRUCache<SpecifiedFontStyle, @FontGroup>,
+    /// The system font family used for each character that no font of its group covers, keyed
+    /// by the character and whether the style is bold and italic.
+    fallback_cache: LRUCache<(char, bool, bool), Option<~str>>,
     handle: FontContextHandle,
     backend: BackendType,
     generic_fonts: HashMap<~str,~str>,
+    /// The fonts downloaded for the document, by lowercased family name.
+    web_fonts: HashMap<~str, ~[WebFont]>,
+    /// The words shaped by the fonts of this context, shared with the other font contexts.
+    shape_cache: ShapeCache,
     profiler_chan: ProfilerChan,
 }
 
 impl<'self> FontContext {
     pub fn new(backend: BackendType,
            needs_font_list: bool,
+           shape_cache: ShapeCache,
            profiler_chan: ProfilerChan)
            -> FontContext {
         let handle = FontContextHandle::new();
@@ -63,11 +117,15 @@ impl<'self> FontContext {
 
         FontContext { 
             instance_cache: LRUCache::new(10),
+            shared_font_cache: LRUCache::new(10),
             font_list: font_list,
             group_cache: LRUCache::new(10),
+            fallback_cache: LRUCache::new(256),
             handle: handle,
             backend: backend,
             generic_fonts: generic_fonts,
+            web_fonts: HashMap::new(),
+            shape_cache: shape_cache,
             profiler_chan: profiler_chan,
         }
     }
@@ -91,6 +149,29 @@ impl<'self> FontContext {
         }
     }
 
+    /// Makes room for at least `count` fonts in the cache of font instances, so that drawing a
+    /// display list that uses that many does not keep evicting and recreating them.
+    pub fn reserve_fonts(&mut self, count: uint) {
+        self.instance_cache.reserve(count);
+        self.shared_font_cache.reserve(count);
+    }
+
+    /// Returns this context's instance of a font that another task shared, loading it the first
+    /// time it is asked for. Later lookups go by the font's id rather than its descriptor.
+    pub fn get_shared_font(&mut self, font: &SharedFont) -> Result<@mut Font, ()> {
+        match self.shared_font_cache.find(&font.id) {
+            Some(instance) => Ok(instance),
+            None => {
+                let result = self.get_font_by_descriptor(&font.descriptor);
+                match result {
+                    Ok(instance) => self.shared_font_cache.insert(font.id, instance),
+                    Err(()) => {}
+                }
+                result
+            }
+        }
+    }
+
     pub fn get_font_by_descriptor(&mut self, desc: &FontDescriptor) -> Result<@mut Font, ()> {
         match self.instance_cache.find(desc) {
             Some(f) => {
@@ -110,6 +191,109 @@ impl<'self> FontContext {
         }
     }
 
+    /// Makes a font downloaded for an `@font-face` rule available to font groups, where it takes
+    /// precedence over installed fonts of the same family.
+    pub fn add_web_font(&mut self, rule: FontFaceRule, data: WebFontData) {
+        debug!("font context: adding web font `%s` from %s", rule.family, data.url);
+        let family = rule.family.to_ascii_lower();
+        self.web_fonts.find_or_insert_with(family, |_| ~[]).push(WebFont {
+            rule: rule,
+            data: data,
+        });
+
+        // Groups created before the font arrived have to be created again.
+        self.group_cache.evict_all();
+    }
+
+    // Picks the downloaded face of the family that best matches the style: among the faces of the
+    // requested italicness, or all of them if there are none, the one whose weight comes first
+    // in the order set by the CSS Fonts font matching algorithm.
+    fn find_web_font(&self, family: &str, style: &SpecifiedFontStyle) -> Option<WebFontData> {
+        let family = family.trim_chars(&'"').trim_chars(&'\'').to_ascii_lower();
+        let italic = style.italic || style.oblique;
+        let desired = style.weight.to_number();
+        do self.web_fonts.find(&family).chain |faces| {
+            let styled: ~[&WebFont] = faces.iter().filter(|face| face.rule.italic == italic)
+                                                 .collect();
+            let candidates = if styled.is_empty() {
+                faces.iter().collect()
+            } else {
+                styled
+            };
+            let best = do candidates.iter().min_by |face| {
+                weight_preference(desired, face.rule.weight.to_number())
+            };
+            best.map(|face| face.data.clone())
+        }
+    }
+
+    /// Returns a system font in the given style that has a glyph for the character, if there is
+    /// one.
+    pub fn get_fallback_font(&mut self, codepoint: char, style: &SpecifiedFontStyle)
+                             -> Option<@mut Font> {
+        let key = (codepoint, style.weight.is_bold(), style.italic || style.oblique);
+        let family = match self.fallback_cache.find(&key) {
+            Some(family) => family,
+            None => {
+                let family = do self.font_list.chain_ref |fl| {
+                    fl.find_fallback_family(codepoint, style)
+                };
+                self.fallback_cache.insert(key, family.clone());
+                family
+            }
+        };
+
+        let entry = do family.chain |family| {
+            do self.font_list.chain_ref |fl| {
+                fl.find_font_in_family(family, style)
+            }
+        };
+        do entry.chain |entry| {
+            let font_id = SelectorPlatformIdentifier(entry.handle.face_identifier());
+            let font_desc = FontDescriptor::new((*style).clone(), font_id);
+            match self.get_font_by_descriptor(&font_desc) {
+                Ok(font) => Some(font),
+                Err(()) => None,
+            }
+        }
+    }
+
+    /// Sends the hit and miss counters of the font caches to the profiler.
+    pub fn report_cache_stats(&self) {
+        self.profiler_chan.send(CacheStatsMsg(~"FontContext::instance_cache",
+                                              self.instance_cache.stats()));
+        self.profiler_chan.send(CacheStatsMsg(~"FontContext::shared_font_cache",
+                                              self.shared_font_cache.stats()));
+        self.profiler_chan.send(CacheStatsMsg(~"FontContext::group_cache",
+                                              self.group_cache.stats()));
+        self.profiler_chan.send(CacheStatsMsg(~"FontContext::fallback_cache",
+                                              self.fallback_cache.stats()));
+        self.profiler_chan.send(CacheStatsMsg(~"ShapeCache", self.shape_cache.stats()));
+    }
+
+    /// Measures the fonts and font groups this context keeps alive.
+    pub fn memory_reports(&self) -> ~[MemoryReport] {
+        let fonts = self.instance_cache.len();
+        let groups = self.group_cache.len();
+        let mut font_bytes = 0;
+        for font in self.instance_cache.values().iter() {
+            font_bytes += font.memory_size();
+        }
+        let mut web_fonts = 0;
+        let mut web_font_bytes = 0;
+        for faces in self.web_fonts.iter().map(|(_, faces)| faces) {
+            web_fonts += faces.len();
+            for face in faces.iter() {
+                web_font_bytes += face.data.data.get().len();
+            }
+        }
+        ~[
+            MemoryReport::new("fonts", fonts, font_bytes),
+            MemoryReport::new("font groups", groups, groups * sys::size_of::<FontGroup>()),
+            MemoryReport::new("web fonts", web_fonts, web_font_bytes),
+        ]
+    }
+
     fn transform_family(&self, family: &str) -> ~str {
         // FIXME: Need a find_like() in HashMap.
         let family = family.to_str();
@@ -128,6 +312,23 @@ impl<'self> FontContext {
         // TODO(Issue #193): make iteration over 'font-family' more robust.
         for family in style.families.split_iter(',') {
             let family_name = family.trim();
+
+            // Fonts downloaded for the document take precedence over installed ones.
+            let web_font = self.find_web_font(family_name, style);
+            let mut found_web_font = false;
+            for data in web_font.iter() {
+                let font_desc = FontDescriptor::new((*style).clone(),
+                                                    SelectorWebFont(data.clone()));
+                for font in self.get_font_by_descriptor(&font_desc).iter() {
+                    fonts.push(*font);
+                    found_web_font = true;
+                }
+            }
+            if found_web_font {
+                debug!("(create font group) using web font for `%s`", family_name);
+                loop
+            }
+
             let transformed_family_name = self.transform_family(family_name);
             debug!("(create font group) transformed family is `%s`", transformed_family_name);
 
@@ -196,6 +397,29 @@ impl<'self> FontContext {
                                                      self.profiler_chan.clone()))
                 }
             }
+            &SelectorWebFont(ref data) => {
+                Font::new_from_buffer(self,
+                                      data.data.clone(),
+                                      desc.selector.clone(),
+                                      &desc.style,
+                                      self.backend,
+                                      self.profiler_chan.clone())
+            }
         };
     }
 }
+
+#[test]
+fn test_weight_preference() {
+    use extra::sort::merge_sort;
+
+    let order = |desired: uint| {
+        do merge_sort([100u, 200, 300, 400, 500, 600, 700, 800, 900]) |&a, &b| {
+            weight_preference(desired, a) <= weight_preference(desired, b)
+        }
+    };
+    assert!(order(400) == ~[400, 500, 300, 200, 100, 600, 700, 800, 900]);
+    assert!(order(500) == ~[500, 400, 300, 200, 100, 600, 700, 800, 900]);
+    assert!(order(300) == ~[300, 200, 100, 400, 500, 600, 700, 800, 900]);
+    assert!(order(700) == ~[700, 800, 900, 600, 500, 400, 300, 200, 100]);
+}
@@ -19,6 +19,7 @@ trait FontListHandleMethods {
     fn get_available_families(&self, fctx: &FontContextHandle) -> FontFamilyMap;
     fn load_variations_for_family(&self, family: @mut FontFamily);
     fn get_last_resort_font_families() -> ~[~str];
+    fn find_family_for_char(&self, codepoint: char, style: &SpecifiedFontStyle) -> Option<~str>;
 }
 
 /// The platform-independent font list abstraction.
@@ -76,6 +77,21 @@ impl FontList {
         result
     }
 
+    /// Asks the system for a font family with a glyph for the given character, for use when
+    /// none of the requested families has one.
+    pub fn find_fallback_family(&self, codepoint: char, style: &SpecifiedFontStyle)
+                                -> Option<~str> {
+        let family = self.handle.find_family_for_char(codepoint, style);
+
+        // The system may name a family that is missing from the list of available ones.
+        let family = family.filtered(|family| self.find_family(*family).is_some());
+
+        let decision = if family.is_some() { "Found" } else { "Couldn't find" };
+        debug!("FontList: %s fallback font family for character %?", decision, codepoint);
+
+        family
+    }
+
     fn find_family(&self, family_name: &str) -> Option<@mut FontFamily> {
         // look up canonical name
         let family = self.family_map.find_equiv(&family_name);
@@ -6,6 +6,7 @@ use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 
+use std::i32;
 use std::num::{NumCast, One, Zero};
 
 #[deriving(Clone,Eq)]
@@ -108,6 +109,13 @@ impl Au {
         Rect(Point2D(z, z), Size2D(z, z))
     }
 
+    /// Returns a rect that covers every representable coordinate, for use as "no clip".
+    pub fn max_rect() -> Rect<Au> {
+        let min = Au(i32::min_value / 2);
+        let max = Au(i32::max_value);
+        Rect(Point2D(min, min), Size2D(max, max))
+    }
+
     pub fn from_pt(f: float) -> Au {
         from_px(pt_to_px(f) as int)
     }
@@ -8,6 +8,7 @@
 use azure::azure_hl::{BackendType, CairoBackend, CoreGraphicsBackend};
 use azure::azure_hl::{CoreGraphicsAcceleratedBackend, Direct2DBackend, SkiaBackend};
 
+use servo_util::time::{ProfilerOutputFormat, TextOutput};
 use std::float;
 use std::result;
 use std::uint;
@@ -19,8 +20,17 @@ pub struct Opts {
     n_render_threads: uint,
     tile_size: uint,
     profiler_period: Option<float>,
+    profiler_output_format: ProfilerOutputFormat,
+    profiler_percentiles: ~[float],
+    profiler_reset_on_navigation: bool,
+    trace_file: Option<~str>,
+    memory_profiler_period: Option<float>,
     exit_after_load: bool,
+    dump_display_list: bool,
     output_file: Option<~str>,
+    headless: bool,
+    output_full_page: bool,
+    prefetch_margin: uint,
 }
 
 pub fn from_cmdline_args(args: &[~str]) -> Opts {
@@ -34,7 +44,16 @@ pub fn from_cmdline_args(args: &[~str]) -> Opts {
         getopts::optopt("s"),  // size of tiles
         getopts::optopt("t"),  // threads to render with
         getopts::optflagopt("p"),  // profiler flag and output interval
+        getopts::optopt("profiler-format"),  // text, csv or json
+        getopts::optopt("profiler-percentiles"),  // comma-separated percentiles to report
+        getopts::optflag("profiler-reset-on-navigation"),
+        getopts::optopt("trace-file"),  // Chrome trace output file
+        getopts::optflagopt("m"),  // memory profiler flag and output interval
         getopts::optflag("x"), // exit after load flag
+        getopts::optflag("dump-display-list"),  // print each display list layout builds
+        getopts::optflag("z"),  // headless: composite in software, without a window
+        getopts::optflag("full-page"),  // write the whole page to the output file
+        getopts::optopt("prefetch-margin"),  // px around the viewport to render when idle
     ];
 
     let opt_match = match getopts::getopts(args, opts) {
@@ -82,17 +101,69 @@ pub fn from_cmdline_args(args: &[~str]) -> Opts {
         float::from_str(*period).unwrap()
     };
 
+    let profiler_output_format = match getopts::opt_maybe_str(&opt_match, "profiler-format") {
+        Some(format_str) => match ProfilerOutputFormat::from_str(format_str) {
+            Some(format) => format,
+            None => fail!(~"unknown profiler output format"),
+        },
+        None => TextOutput,
+    };
+
+    let profiler_percentiles = match getopts::opt_maybe_str(&opt_match, "profiler-percentiles") {
+        Some(percentiles_str) => {
+            let mut percentiles = do percentiles_str.split_iter(',').map |p| {
+                let percentile = float::from_str(p.trim()).expect("percentiles must be numbers");
+                if !(percentile >= 0f && percentile <= 100f) {
+                    fail!(fmt!("percentile %s is not between 0 and 100", p.trim()))
+                }
+                percentile
+            };
+            percentiles.collect()
+        }
+        None => ~[90f, 99f],
+    };
+
+    let profiler_reset_on_navigation = getopts::opt_present(&opt_match,
+                                                            "profiler-reset-on-navigation");
+
+    let trace_file = getopts::opt_maybe_str(&opt_match, "trace-file");
+
+    // if only flag is present, default to 5 second period
+    let memory_profiler_period = do getopts::opt_default(&opt_match, "m", "5").map |period| {
+        float::from_str(*period).unwrap()
+    };
+
     let exit_after_load = getopts::opt_present(&opt_match, "x");
 
+    let dump_display_list = getopts::opt_present(&opt_match, "dump-display-list");
+
     let output_file = getopts::opt_maybe_str(&opt_match, "o");
 
+    let headless = getopts::opt_present(&opt_match, "z");
+
+    let output_full_page = getopts::opt_present(&opt_match, "full-page");
+
+    let prefetch_margin: uint = match getopts::opt_maybe_str(&opt_match, "prefetch-margin") {
+        Some(prefetch_margin_str) => uint::from_str(prefetch_margin_str).unwrap(),
+        None => 256,
+    };
+
     Opts {
         urls: urls,
         render_backend: render_backend,
         n_render_threads: n_render_threads,
         tile_size: tile_size,
         profiler_period: profiler_period,
+        profiler_output_format: profiler_output_format,
+        profiler_percentiles: profiler_percentiles,
+        profiler_reset_on_navigation: profiler_reset_on_navigation,
+        trace_file: trace_file,
+        memory_profiler_period: memory_profiler_period,
         exit_after_load: exit_after_load,
+        dump_display_list: dump_display_list,
         output_file: output_file,
+        headless: headless,
+        output_full_page: output_full_page,
+        prefetch_margin: prefetch_margin,
     }
 }
@@ -29,6 +29,8 @@ use std::cast;
 use std::ptr;
 use std::str;
 
+use extra::arc::Arc;
+
 fn float_to_fixed_ft(f: float) -> i32 {
     float_to_fixed(6, f)
 }
@@ -48,7 +50,7 @@ impl FontTableMethods for FontTable {
 }
 
 enum FontSource {
-    FontSourceMem(~[u8]),
+    FontSourceMem(Arc<~[u8]>),
     FontSourceFile(~str)
 }
 
@@ -74,13 +76,13 @@ impl Drop for FontHandle {
 
 impl FontHandleMethods for FontHandle {
     fn new_from_buffer(fctx: &FontContextHandle,
-                           buf: ~[u8],
+                           buf: Arc<~[u8]>,
                            style: &SpecifiedFontStyle)
                         -> Result<FontHandle, ()> {
         let ft_ctx: FT_Library = fctx.ctx.ctx;
         if ft_ctx.is_null() { return Err(()); }
 
-        let face_result = do buf.as_imm_buf |bytes: *u8, len: uint| {
+        let face_result = do buf.get().as_imm_buf |bytes: *u8, len: uint| {
             create_face_from_buffer(ft_ctx, bytes, len, style.pt_size)
         };
 
@@ -224,9 +226,24 @@ impl FontHandleMethods for FontHandle {
         let descent = self.font_units_to_au(face.descender as float);
         let max_advance = self.font_units_to_au(face.max_advance_width as float);
 
+        // The OS/2 table gives the position of the top of the strikeout stroke. Without one, use
+        // an underline-sized stroke a third of the way up the ascent.
+        let (strikeout_size, strikeout_offset) = unsafe {
+            let os2 = FT_Get_Sfnt_Table(self.face, ft_sfnt_os2) as *TT_OS2;
+            if os2.is_not_null() && (*os2).version != 0xffff {
+                let size = self.font_units_to_au((*os2).yStrikeoutSize as float);
+                let position = self.font_units_to_au((*os2).yStrikeoutPosition as float);
+                (size, position - size.scale_by(0.5))
+            } else {
+                (underline_size, ascent.scale_by(1.0 / 3.0))
+            }
+        };
+
         return FontMetrics {
             underline_size:   underline_size,
             underline_offset: underline_offset,
+            strikeout_size:   strikeout_size,
+            strikeout_offset: strikeout_offset,
             leading:          geometry::from_pt(0.0), //FIXME
             x_height:         geometry::from_pt(0.0), //FIXME
             em_size:          em_size,
@@ -239,6 +256,16 @@ impl FontHandleMethods for FontHandle {
     fn get_table_for_tag(&self, _: FontTableTag) -> Option<FontTable> {
         None
     }
+
+    fn face_data_size(&self) -> uint {
+        match self.source {
+            FontSourceMem(ref buf) => buf.get().len(),
+            // FreeType maps the whole file for faces it opens itself.
+            FontSourceFile(ref file) => {
+                Path(file.as_slice()).stat().map_default(0, |stat| stat.st_size as uint)
+            }
+        }
+    }
 }
 
 impl<'self> FontHandle {
@@ -6,7 +6,7 @@ extern mod freetype;
 extern mod fontconfig;
 
 use fontconfig::fontconfig::{
-    FcChar8, FcResultMatch, FcSetSystem, FcPattern,
+    FcChar8, FcChar32, FcCharSet, FcResultMatch, FcSetSystem, FcPattern,
     FcResultNoMatch, FcMatchPattern, FC_SLANT_ITALIC, FC_WEIGHT_BOLD
 };
 use fontconfig::fontconfig::{
@@ -14,11 +14,12 @@ use fontconfig::fontconfig::{
     FcPatternDestroy, FcFontSetDestroy, FcConfigSubstitute,
     FcDefaultSubstitute, FcPatternCreate, FcPatternAddString, FcPatternAddInteger,
     FcFontMatch, FcFontSetList, FcObjectSetCreate, FcObjectSetDestroy,
-    FcObjectSetAdd, FcPatternGetInteger
+    FcObjectSetAdd, FcPatternGetInteger, FcCharSetCreate, FcCharSetAddChar,
+    FcCharSetDestroy, FcCharSetHasChar, FcPatternAddCharSet, FcPatternGetCharSet
 };
 
 
-use font::{FontHandleMethods, UsedFontStyle};
+use font::{FontHandleMethods, SpecifiedFontStyle, UsedFontStyle};
 use font_context::FontContextHandleMethods;
 use font_list::{FontEntry, FontFamily, FontFamilyMap};
 use platform::font::FontHandle;
@@ -131,6 +132,66 @@ impl FontListHandle {
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial"]
     }
+
+    /// Asks fontconfig for the family that best matches the style among those with a glyph for
+    /// the character.
+    pub fn find_family_for_char(&self, codepoint: char, style: &SpecifiedFontStyle)
+                                -> Option<~str> {
+        unsafe {
+            let config = FcConfigGetCurrent();
+            let wrapper = AutoPattern { pattern: FcPatternCreate() };
+            let pattern = wrapper.pattern;
+
+            // The pattern keeps its own copy of the charset.
+            let charset = FcCharSetCreate();
+            FcCharSetAddChar(charset, codepoint as FcChar32);
+            let res = do "charset".to_c_str().with_ref |FC_CHARSET| {
+                FcPatternAddCharSet(pattern, FC_CHARSET, charset)
+            };
+            FcCharSetDestroy(charset);
+            if res != 1 {
+                debug!("adding charset to pattern failed");
+                return None;
+            }
+            if add_style_to_pattern(pattern, style).is_err() {
+                return None;
+            }
+
+            if FcConfigSubstitute(config, pattern, FcMatchPattern) != 1 {
+                debug!("substitution failed");
+                return None;
+            }
+            FcDefaultSubstitute(pattern);
+            let result = FcResultNoMatch;
+            let result_wrapper = AutoPattern { pattern: FcFontMatch(config, pattern, &result) };
+            let result_pattern = result_wrapper.pattern;
+            if result != FcResultMatch || result_pattern.is_null() {
+                debug!("obtaining match to pattern failed");
+                return None;
+            }
+
+            // The closest match is not guaranteed to have the character.
+            let matched_charset: *FcCharSet = ptr::null();
+            let res = do "charset".to_c_str().with_ref |FC_CHARSET| {
+                FcPatternGetCharSet(result_pattern, FC_CHARSET, 0, &matched_charset)
+            };
+            if res != FcResultMatch ||
+                    FcCharSetHasChar(matched_charset, codepoint as FcChar32) == 0 {
+                debug!("no font has a glyph for %?", codepoint);
+                return None;
+            }
+
+            let family: *FcChar8 = ptr::null();
+            let res = do "family".to_c_str().with_ref |FC_FAMILY| {
+                FcPatternGetString(result_pattern, FC_FAMILY, 0, &family)
+            };
+            if res != FcResultMatch {
+                debug!("getting family for font failed");
+                return None;
+            }
+            Some(str::raw::from_c_str(family as *c_char))
+        }
+    }
 }
 
 struct AutoPattern {
@@ -160,23 +221,8 @@ pub fn path_from_identifier(name: ~str, style: &UsedFontStyle) -> Result<~str, (
             return Err(());
         }
 
-        if style.italic {
-            let res = do "slant".to_c_str().with_ref |FC_SLANT| {
-                FcPatternAddInteger(pattern, FC_SLANT, FC_SLANT_ITALIC)
-            };
-            if res != 1 {
-                debug!("adding slant to pattern failed");
-                return Err(());
-            }
-        }
-        if style.weight.is_bold() {
-            let res = do "weight".to_c_str().with_ref |FC_WEIGHT| {
-                FcPatternAddInteger(pattern, FC_WEIGHT, FC_WEIGHT_BOLD)
-            };
-            if res != 1 {
-                debug!("adding weight to pattern failed");
-                return Err(());
-            }
+        if add_style_to_pattern(pattern, style).is_err() {
+            return Err(());
         }
 
         if FcConfigSubstitute(config, pattern, FcMatchPattern) != 1 {
@@ -203,3 +249,26 @@ pub fn path_from_identifier(name: ~str, style: &UsedFontStyle) -> Result<~str, (
         Ok(str::raw::from_c_str(file as *c_char))
     }
 }
+
+// Asks for an italic and/or bold face, as the style requires.
+unsafe fn add_style_to_pattern(pattern: *FcPattern, style: &UsedFontStyle) -> Result<(), ()> {
+    if style.italic {
+        let res = do "slant".to_c_str().with_ref |FC_SLANT| {
+            FcPatternAddInteger(pattern, FC_SLANT, FC_SLANT_ITALIC)
+        };
+        if res != 1 {
+            debug!("adding slant to pattern failed");
+            return Err(());
+        }
+    }
+    if style.weight.is_bold() {
+        let res = do "weight".to_c_str().with_ref |FC_WEIGHT| {
+            FcPatternAddInteger(pattern, FC_WEIGHT, FC_WEIGHT_BOLD)
+        };
+        if res != 1 {
+            debug!("adding weight to pattern failed");
+            return Err(());
+        }
+    }
+    Ok(())
+}
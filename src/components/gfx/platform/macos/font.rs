@@ -30,6 +30,8 @@ use core_text;
 
 use std::ptr;
 
+use extra::arc::Arc;
+
 pub struct FontTable {
     data: CFData,
 }
@@ -54,6 +56,11 @@ impl FontTableMethods for FontTable {
 pub struct FontHandle {
     priv cgfont: Option<CGFont>,
     ctfont: CTFont,
+    /// The length of the buffer the font was created from. System fonts are mapped by CoreText
+    /// itself and shared between processes, so they count as zero.
+    priv data_size: uint,
+    /// The buffer the font was created from, which the data provider reads without copying.
+    priv data: Option<Arc<~[u8]>>,
 }
 
 impl FontHandle {
@@ -61,6 +68,8 @@ impl FontHandle {
         Ok(FontHandle {
             cgfont: None,
             ctfont: ctfont,
+            data_size: 0,
+            data: None,
         })
     }
 
@@ -77,9 +86,9 @@ impl FontHandle {
 }
 
 impl FontHandleMethods for FontHandle {
-    fn new_from_buffer(_: &FontContextHandle, buf: ~[u8], style: &SpecifiedFontStyle)
+    fn new_from_buffer(_: &FontContextHandle, buf: Arc<~[u8]>, style: &SpecifiedFontStyle)
                     -> Result<FontHandle, ()> {
-        let fontprov : CGDataProvider = do buf.as_imm_buf |cbuf, len| {
+        let fontprov : CGDataProvider = do buf.get().as_imm_buf |cbuf, len| {
             core_graphics::data_provider::new_from_buffer(cbuf, len)
         };
 
@@ -89,6 +98,8 @@ impl FontHandleMethods for FontHandle {
         let result = Ok(FontHandle {
             cgfont: Some(cgfont),
             ctfont: ctfont,
+            data_size: buf.get().len(),
+            data: Some(buf.clone()),
         });
 
         return result;
@@ -163,16 +174,23 @@ impl FontHandleMethods for FontHandle {
 
         let scale = px_to_pt(self.ctfont.pt_size() as float) / (self.ctfont.ascent() as float + self.ctfont.descent() as float);
 
+        let underline_size = Au::from_pt(self.ctfont.underline_thickness() as float);
+        let x_height = Au::from_pt(self.ctfont.x_height() as float);
+
         let metrics =  FontMetrics {
-            underline_size:   Au::from_pt(self.ctfont.underline_thickness() as float),
+            underline_size:   underline_size,
             // TODO(Issue #201): underline metrics are not reliable. Have to pull out of font table
             // directly.
             //
             // see also: https://bugs.webkit.org/show_bug.cgi?id=16768
             // see also: https://bugreports.qt-project.org/browse/QTBUG-13364
             underline_offset: Au::from_pt(self.ctfont.underline_position() as float),
+            // Core Text has no strikeout metrics, so strike through the middle of the lowercase
+            // letters with a stroke as thick as the underline.
+            strikeout_size:   underline_size,
+            strikeout_offset: x_height.scale_by(0.5),
             leading:          Au::from_pt(self.ctfont.leading() as float),
-            x_height:         Au::from_pt(self.ctfont.x_height() as float),
+            x_height:         x_height,
             em_size:          em_size,
             ascent:           ascent.scale_by(scale),
             descent:          descent.scale_by(scale),
@@ -193,5 +211,9 @@ impl FontHandleMethods for FontHandle {
     fn face_identifier(&self) -> ~str {
         self.ctfont.postscript_name()
     }
+
+    fn face_data_size(&self) -> uint {
+        self.data_size
+    }
 }
 
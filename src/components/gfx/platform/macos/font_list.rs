@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use font::FontHandleMethods;
+use font::{FontHandleMethods, SpecifiedFontStyle};
 use font_context::FontContextHandleMethods;
 use font_list::{FontEntry, FontFamily, FontFamilyMap};
 use platform::macos::font::FontHandle;
@@ -59,4 +59,11 @@ impl FontListHandle {
     pub fn get_last_resort_font_families() -> ~[~str] {
         ~[~"Arial Unicode MS",~"Arial"]
     }
+
+    /// FIXME: Ask Core Text for a cascade list instead. Until then, characters that no font of
+    /// the group covers fall back to the last resort families, which cover most scripts.
+    pub fn find_family_for_char(&self, _codepoint: char, _style: &SpecifiedFontStyle)
+                                -> Option<~str> {
+        None
+    }
 }
@@ -4,13 +4,21 @@
 
 use servo_msg::compositor_msg::LayerBuffer;
 use font_context::FontContext;
-use geometry::Au;
+use geometry::{Au, to_frac_px};
+use display_list::{BorderRadii, BorderStyle, BorderStyleDashed, BorderStyleDotted};
+use display_list::{BorderStyleDouble, BorderStyleGroove, BorderStyleHidden, BorderStyleInset};
+use display_list::{BorderStyleNone, BorderStyleOutset, BorderStyleRidge, BorderStyleSolid};
+use display_list::GradientStop;
 use opts::Opts;
 
 use azure::azure_hl::{B8G8R8A8, Color, ColorPattern, DrawOptions};
-use azure::azure_hl::{DrawSurfaceOptions, DrawTarget, Linear, StrokeOptions};
+use azure::azure_hl::{DrawSurfaceOptions, DrawTarget, ExtendClamp, GradientStops, Linear};
+use azure::azure_hl::{LinearGradientPattern, PathBuilder, RadialGradientPattern};
+use AzGradientStop = azure::azure_hl::GradientStop;
 use azure::AzFloat;
-use std::libc::types::common::c99::uint16_t;
+use std::num;
+use std::vec;
+use geom::matrix2d::Matrix2D;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
@@ -29,52 +37,424 @@ impl<'self> RenderContext<'self>  {
         &self.canvas.draw_target
     }
 
+    /// Restricts all drawing until the matching `pop_clip` to the given rect.
+    pub fn push_clip(&self, clip: &Rect<Au>) {
+        self.canvas.draw_target.make_current();
+        self.canvas.draw_target.push_clip_rect(&clip.to_azure_rect());
+    }
+
+    pub fn pop_clip(&self) {
+        self.canvas.draw_target.pop_clip();
+    }
+
+    /// Runs `f` with a render context that draws into a transparent surface the size of this one,
+    /// then draws that surface onto this one with the given opacity. This makes overlapping items
+    /// drawn by `f` fade as one group instead of each showing through the others.
+    pub fn draw_with_opacity(&self, opacity: AzFloat, f: &fn(&RenderContext)) {
+        if opacity >= 1.0 {
+            return f(self)
+        }
+
+        let draw_target = &self.canvas.draw_target;
+        draw_target.make_current();
+        let size = Size2D(self.canvas.screen_pos.size.width as i32,
+                          self.canvas.screen_pos.size.height as i32);
+        let group = LayerBuffer {
+            draw_target: draw_target.create_similar_draw_target(&size, B8G8R8A8),
+            rect: self.canvas.rect,
+            screen_pos: self.canvas.screen_pos,
+            resolution: self.canvas.resolution,
+            stride: self.canvas.stride,
+        };
+        let transform = draw_target.get_transform();
+        group.draw_target.set_transform(&transform);
+        f(&RenderContext {
+            canvas: &group,
+            font_ctx: self.font_ctx,
+            opts: self.opts,
+        });
+        group.draw_target.flush();
+
+        // The surfaces line up pixel for pixel, so composite without the page transform.
+        let rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                        Size2D(size.width as AzFloat, size.height as AzFloat));
+        draw_target.make_current();
+        draw_target.set_transform(&Matrix2D::identity());
+        draw_target.draw_surface(group.draw_target.snapshot(),
+                                 rect,
+                                 rect,
+                                 DrawSurfaceOptions(Linear, true),
+                                 DrawOptions(opacity, 0));
+        draw_target.set_transform(&transform);
+    }
+
     pub fn draw_solid_color(&self, bounds: &Rect<Au>, color: Color) {
         self.canvas.draw_target.make_current();
         self.canvas.draw_target.fill_rect(&bounds.to_azure_rect(), &ColorPattern(color));
     }
 
+    /// Runs `f` with drawing restricted to the given rect with rounded corners. Square corners
+    /// need no clip beyond the bounds of what is drawn, so they add none.
+    pub fn with_rounded_clip(&self, bounds: &Rect<Au>, radii: &BorderRadii, f: &fn()) {
+        if radii.is_zero() {
+            return f()
+        }
+
+        self.canvas.draw_target.make_current();
+        let builder = self.canvas.draw_target.create_path_builder();
+        RoundedRect::new(bounds, radii).add_to_path(&builder, true);
+        self.canvas.draw_target.push_clip(&builder.finish());
+        f();
+        self.canvas.draw_target.pop_clip();
+    }
+
+    /// Draws a border with the given widths inside the bounds. Each side is clipped to the
+    /// trapezoid between the outer and inner corners of the border, which gives the mitred joins
+    /// CSS asks for between sides of different colors or styles.
     pub fn draw_border(&self,
                        bounds: &Rect<Au>,
                        border: SideOffsets2D<Au>,
-                       color: Color) {
-        let pattern = ColorPattern(color);
-        let draw_opts = DrawOptions(1 as AzFloat, 0 as uint16_t);
-        let stroke_fields = 2; // CAP_SQUARE
-        let mut stroke_opts = StrokeOptions(0 as AzFloat, 10 as AzFloat, stroke_fields);
+                       color: SideOffsets2D<Color>,
+                       style: SideOffsets2D<BorderStyle>,
+                       radii: &BorderRadii) {
+        self.canvas.draw_target.make_current();
+
+        let widths = border.to_float_px();
+        let outer = RoundedRect::new(bounds, radii);
+        let sides = [
+            (TopSide, widths.top, color.top, style.top),
+            (RightSide, widths.right, color.right, style.right),
+            (BottomSide, widths.bottom, color.bottom, style.bottom),
+            (LeftSide, widths.left, color.left, style.left),
+        ];
+        for &(side, width, color, style) in sides.iter() {
+            if width <= 0.0 || style == BorderStyleNone || style == BorderStyleHidden {
+                loop
+            }
+
+            let builder = self.canvas.draw_target.create_path_builder();
+            let corners = outer.side_corners(side, &widths);
+            builder.move_to(corners[0]);
+            for corner in corners.slice_from(1).iter() {
+                builder.line_to(*corner);
+            }
+            builder.close();
+            self.canvas.draw_target.push_clip(&builder.finish());
+            self.draw_border_side(side, width, color, style, &outer, &widths);
+            self.canvas.draw_target.pop_clip();
+        }
+    }
 
-        let rect = bounds.to_azure_rect();
-        let border = border.to_float_px();
+    // Draws one side of a border. The caller clips the drawing to that side.
+    fn draw_border_side(&self,
+                        side: BorderSide,
+                        width: AzFloat,
+                        color: Color,
+                        style: BorderStyle,
+                        outer: &RoundedRect,
+                        widths: &SideOffsets2D<AzFloat>) {
+        // Three-dimensional styles darken the sides facing away from the light, which comes from
+        // the top left.
+        let dark = shade_color(color, 2.0 / 3.0);
+        let lit = match side {
+            TopSide | LeftSide => true,
+            BottomSide | RightSide => false,
+        };
+
+        match style {
+            BorderStyleNone | BorderStyleHidden => {}
+            BorderStyleSolid => self.fill_border_ring(outer, widths, 0.0, 1.0, color),
+            BorderStyleDouble => {
+                self.fill_border_ring(outer, widths, 0.0, 1.0 / 3.0, color);
+                self.fill_border_ring(outer, widths, 2.0 / 3.0, 1.0, color);
+            }
+            BorderStyleGroove | BorderStyleRidge => {
+                let (outer_color, inner_color) = if lit == (style == BorderStyleGroove) {
+                    (dark, color)
+                } else {
+                    (color, dark)
+                };
+                self.fill_border_ring(outer, widths, 0.0, 0.5, outer_color);
+                self.fill_border_ring(outer, widths, 0.5, 1.0, inner_color);
+            }
+            BorderStyleInset | BorderStyleOutset => {
+                let color = if lit == (style == BorderStyleInset) { dark } else { color };
+                self.fill_border_ring(outer, widths, 0.0, 1.0, color);
+            }
+            BorderStyleDotted | BorderStyleDashed => {
+                // Keep the dots and dashes inside the border where its corners are rounded.
+                let builder = self.canvas.draw_target.create_path_builder();
+                outer.add_to_path(&builder, true);
+                outer.deflate(widths, 1.0).add_to_path(&builder, false);
+                self.canvas.draw_target.push_clip(&builder.finish());
+
+                // Dashes are three times as long as the border is wide and dots are round. The
+                // gaps are stretched so that the side starts and ends with a dash or dot.
+                let dash_length = if style == BorderStyleDashed { width * 3.0 } else { width };
+                let side_length = match side {
+                    TopSide | BottomSide => outer.rect.size.width,
+                    LeftSide | RightSide => outer.rect.size.height,
+                };
+                let dash_count = ((side_length + width) / (dash_length + width)).round();
+                let dash_count = num::max(dash_count, 1.0 as AzFloat) as uint;
+                let gap = if dash_count > 1 {
+                    (side_length - dash_length * (dash_count as AzFloat)) /
+                        ((dash_count - 1) as AzFloat)
+                } else {
+                    0.0
+                };
+
+                let builder = self.canvas.draw_target.create_path_builder();
+                for i in range(0, dash_count) {
+                    let position = (i as AzFloat) * (dash_length + gap);
+                    let dash = outer.side_band(side, width, position, dash_length);
+                    let radius = if style == BorderStyleDotted { width / 2.0 } else { 0.0 };
+                    let dash = RoundedRect {
+                        rect: dash,
+                        radii: [Size2D(radius, radius), ..4],
+                    };
+                    dash.add_to_path(&builder, true);
+                }
+                self.fill_path(builder, color);
+                self.canvas.draw_target.pop_clip();
+            }
+        }
+    }
+
+    // Fills the part of the border between the given fractions of its widths, measured from its
+    // outer edge.
+    fn fill_border_ring(&self,
+                        outer: &RoundedRect,
+                        widths: &SideOffsets2D<AzFloat>,
+                        from: AzFloat,
+                        to: AzFloat,
+                        color: Color) {
+        let builder = self.canvas.draw_target.create_path_builder();
+        outer.deflate(widths, from).add_to_path(&builder, true);
+        outer.deflate(widths, to).add_to_path(&builder, false);
+        self.fill_path(builder, color);
+    }
+
+    fn fill_path(&self, builder: PathBuilder, color: Color) {
+        let draw_options = DrawOptions(1.0 as AzFloat, 0);
+        self.canvas.draw_target.fill(&builder.finish(), &ColorPattern(color), &draw_options);
+    }
+
+    pub fn draw_linear_gradient(&self,
+                                bounds: &Rect<Au>,
+                                start_point: &Point2D<Au>,
+                                end_point: &Point2D<Au>,
+                                stops: &[GradientStop]) {
+        self.canvas.draw_target.make_current();
+        let pattern = LinearGradientPattern::new(&start_point.to_azure_point(),
+                                                 &end_point.to_azure_point(),
+                                                 self.create_gradient_stops(stops),
+                                                 &Matrix2D::identity());
+        self.canvas.draw_target.fill_rect(&bounds.to_azure_rect(), &pattern);
+    }
+
+    /// Fills the bounds with a gradient whose ending shape is an ellipse with the given center
+    /// and horizontal and vertical radii. A gradient with a zero radius is not drawn.
+    pub fn draw_radial_gradient(&self,
+                                bounds: &Rect<Au>,
+                                center: &Point2D<Au>,
+                                radius: &Size2D<Au>,
+                                stops: &[GradientStop]) {
+        if radius.width <= Au(0) || radius.height <= Au(0) {
+            return
+        }
 
         self.canvas.draw_target.make_current();
+        let center = center.to_azure_point();
+        // The gradient is circular in pattern space, and the pattern is scaled vertically about
+        // its center to make it elliptical.
+        let scale = (to_frac_px(radius.height) / to_frac_px(radius.width)) as AzFloat;
+        let matrix = Matrix2D::new(1.0, 0.0, 0.0, scale, 0.0, center.y * (1.0 - scale));
+        let pattern = RadialGradientPattern::new(&center,
+                                                 &center,
+                                                 0 as AzFloat,
+                                                 to_frac_px(radius.width) as AzFloat,
+                                                 self.create_gradient_stops(stops),
+                                                 &matrix);
+        self.canvas.draw_target.fill_rect(&bounds.to_azure_rect(), &pattern);
+    }
+
+    fn create_gradient_stops(&self, stops: &[GradientStop]) -> GradientStops {
+        let mut stops = do stops.iter().map |stop| {
+            AzGradientStop {
+                offset: stop.offset as AzFloat,
+                color: stop.color,
+            }
+        };
+        let stops: ~[AzGradientStop] = stops.collect();
+        self.canvas.draw_target.create_gradient_stops(stops, ExtendClamp)
+    }
+
+    /// Draws the shadow cast by the box with the given bounds: its border box for an outer shadow,
+    /// and its padding box for an inset one. The shadow is rendered on the CPU into an alpha mask
+    /// in device pixels, covering only the part of it that can reach this surface, blurred, then
+    /// drawn in its color. Outer shadows are not drawn beneath the box, and inset shadows are
+    /// confined to it.
+    pub fn draw_box_shadow(&self,
+                           box_bounds: &Rect<Au>,
+                           offset: &Point2D<Au>,
+                           blur_radius: Au,
+                           spread_radius: Au,
+                           color: Color,
+                           inset: bool) {
+        // Work in device pixels, relative to this surface.
+        let scale = self.canvas.resolution;
+        let to_device = |page: Au, origin: f32| -> int {
+            ((to_frac_px(page) as f32 - origin) * scale).round() as int
+        };
+        let left = to_device(box_bounds.origin.x, self.canvas.rect.origin.x);
+        let top = to_device(box_bounds.origin.y, self.canvas.rect.origin.y);
+        let box_rect = Rect(Point2D(left, top),
+                            Size2D(to_device(box_bounds.origin.x + box_bounds.size.width,
+                                             self.canvas.rect.origin.x) - left,
+                                   to_device(box_bounds.origin.y + box_bounds.size.height,
+                                             self.canvas.rect.origin.y) - top));
+        let device_offset = Point2D(to_device(offset.x, 0.0), to_device(offset.y, 0.0));
+        let spread = to_device(if inset { -spread_radius } else { spread_radius }, 0.0);
+        let shape = inflate_rect(&box_rect.translate(&device_offset), spread);
+        let blur = num::max(to_device(blur_radius, 0.0), 0) as uint;
+        let area = if inset { box_rect } else { inflate_rect(&shape, blur as int) };
+
+        // Pixels further from this surface than the blur reaches cannot affect it.
+        let surface = Rect(Point2D(0, 0), Size2D(self.canvas.screen_pos.size.width as int,
+                                                 self.canvas.screen_pos.size.height as int));
+        let mask_rect = match area.intersection(&surface) {
+            Some(visible) => inflate_rect(&visible, blur_extent(blur) as int).intersection(&area),
+            None => None,
+        };
+        let mask_rect = match mask_rect {
+            Some(mask_rect) if mask_rect.size.width > 0 && mask_rect.size.height > 0 => mask_rect,
+            _ => return,
+        };
+
+        let mask = box_shadow_mask(&mask_rect, &shape, &box_rect, blur, inset);
+        let (width, height) = (mask_rect.size.width as uint, mask_rect.size.height as uint);
 
-        // draw top border
-        stroke_opts.line_width = border.top;
-        let y = rect.origin.y + border.top * 0.5;
-        let start = Point2D(rect.origin.x, y);
-        let end = Point2D(rect.origin.x + rect.size.width, y);
-        self.canvas.draw_target.stroke_line(start, end, &pattern, &stroke_opts, &draw_opts);
-
-        // draw bottom border
-        stroke_opts.line_width = border.bottom;
-        let y = rect.origin.y + rect.size.height - border.bottom * 0.5;
-        let start = Point2D(rect.origin.x, y);
-        let end = Point2D(rect.origin.x + rect.size.width, y);
-        self.canvas.draw_target.stroke_line(start, end, &pattern, &stroke_opts, &draw_opts);
-
-        // draw left border
-        stroke_opts.line_width = border.left;
-        let x = rect.origin.x + border.left * 0.5;
-        let start = Point2D(x, rect.origin.y);
-        let end = Point2D(x, rect.origin.y + rect.size.height);
-        self.canvas.draw_target.stroke_line(start, end, &pattern, &stroke_opts, &draw_opts);
-
-        // draw right border
-        stroke_opts.line_width = border.right;
-        let x = rect.origin.x + rect.size.width - border.right * 0.5;
-        let start = Point2D(x, rect.origin.y);
-        let end = Point2D(x, rect.origin.y + rect.size.height);
-        self.canvas.draw_target.stroke_line(start, end, &pattern, &stroke_opts, &draw_opts);
+        // The mask lines up with this surface pixel for pixel, so draw it without the transform.
+        let draw_target = &self.canvas.draw_target;
+        draw_target.make_current();
+        let transform = draw_target.get_transform();
+        let dest_rect = Rect(Point2D(mask_rect.origin.x as AzFloat, mask_rect.origin.y as AzFloat),
+                             Size2D(width as AzFloat, height as AzFloat));
+        draw_target.set_transform(&Matrix2D::identity());
+        self.draw_alpha_mask(mask, width, height, &dest_rect, color);
+        draw_target.set_transform(&transform);
+    }
+
+    /// Draws the shape that `draw_shape` draws, blurred by the given radius, in the given color.
+    /// The shape is drawn into a surface of its own covering the part of `area` within this one,
+    /// whose alpha is read back and blurred on the CPU. Nothing is drawn outside `area`, so it
+    /// should leave room for the blur.
+    pub fn draw_blurred(&self,
+                        area: &Rect<Au>,
+                        blur_radius: Au,
+                        color: Color,
+                        draw_shape: &fn(&RenderContext)) {
+        // Work in device pixels, within the bounds of this surface.
+        let scale = self.canvas.resolution;
+        let to_device = |page: Au, origin: f32| -> int {
+            ((page.to_px() as f32 - origin) * scale) as int
+        };
+        let left = num::max(to_device(area.origin.x, self.canvas.rect.origin.x), 0);
+        let top = num::max(to_device(area.origin.y, self.canvas.rect.origin.y), 0);
+        let right = num::min(to_device(area.origin.x + area.size.width, self.canvas.rect.origin.x),
+                             self.canvas.screen_pos.size.width as int);
+        let bottom = num::min(to_device(area.origin.y + area.size.height,
+                                        self.canvas.rect.origin.y),
+                              self.canvas.screen_pos.size.height as int);
+        if right <= left || bottom <= top {
+            return
+        }
+
+        // The shape's surface covers only the area, so its transform is this one's moved by the
+        // area's offset in device pixels.
+        let (width, height) = ((right - left) as uint, (bottom - top) as uint);
+        let draw_target = &self.canvas.draw_target;
+        draw_target.make_current();
+        let shape = LayerBuffer {
+            draw_target: draw_target.create_similar_draw_target(&Size2D(width as i32,
+                                                                        height as i32),
+                                                                B8G8R8A8),
+            rect: Rect(Point2D(self.canvas.rect.origin.x + (left as f32) / scale,
+                               self.canvas.rect.origin.y + (top as f32) / scale),
+                       Size2D((width as f32) / scale, (height as f32) / scale)),
+            screen_pos: Rect(Point2D(0, 0), Size2D(width, height)),
+            resolution: scale,
+            stride: width * 4,
+        };
+        let transform = draw_target.get_transform();
+        shape.draw_target.set_transform(&Matrix2D::new(transform.m11,
+                                                       transform.m12,
+                                                       transform.m21,
+                                                       transform.m22,
+                                                       transform.m31 - left as AzFloat,
+                                                       transform.m32 - top as AzFloat));
+        draw_shape(&RenderContext {
+            canvas: &shape,
+            font_ctx: self.font_ctx,
+            opts: self.opts,
+        });
+        shape.draw_target.flush();
+
+        let mut mask = vec::from_elem(width * height, 0u8);
+        let data_surface = shape.draw_target.snapshot().get_data_surface();
+        let stride = data_surface.stride() as uint;
+        do data_surface.with_data |data| {
+            for y in range(0, height) {
+                for x in range(0, width) {
+                    mask[y * width + x] = data[y * stride + x * 4 + 3];
+                }
+            }
+        }
+        blur_alpha_mask(mask, width, height, (to_frac_px(blur_radius) as f32 * scale) as uint, 0);
+
+        // The mask lines up with this surface pixel for pixel, so draw it without the transform.
+        let dest_rect = Rect(Point2D(left as AzFloat, top as AzFloat),
+                             Size2D(width as AzFloat, height as AzFloat));
+        draw_target.set_transform(&Matrix2D::identity());
+        self.draw_alpha_mask(mask, width, height, &dest_rect, color);
+        draw_target.set_transform(&transform);
+    }
+
+    // Draws a `width` by `height` alpha mask into the given rect, in the given color.
+    fn draw_alpha_mask(&self,
+                       mask: &[u8],
+                       width: uint,
+                       height: uint,
+                       dest_rect: &Rect<AzFloat>,
+                       color: Color) {
+        // Premultiplied BGRA, as Azure expects.
+        let data = do vec::from_fn(width * height * 4) |i| {
+            let alpha = (mask[i / 4] as AzFloat) / (255.0 as AzFloat) * color.a;
+            let component = match i % 4 {
+                0 => color.b * alpha,
+                1 => color.g * alpha,
+                2 => color.r * alpha,
+                _ => alpha,
+            };
+            (component * (255.0 as AzFloat)) as u8
+        };
+
+        self.canvas.draw_target.make_current();
+        let draw_target_ref = &self.canvas.draw_target;
+        let size = Size2D(width as i32, height as i32);
+        let azure_surface = draw_target_ref.create_source_surface_from_data(data, size,
+                                                                            (width * 4) as i32,
+                                                                            B8G8R8A8);
+        let source_rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                               Size2D(width as AzFloat, height as AzFloat));
+        let draw_surface_options = DrawSurfaceOptions(Linear, true);
+        let draw_options = DrawOptions(1.0f as AzFloat, 0);
+        draw_target_ref.draw_surface(azure_surface,
+                                     *dest_rect,
+                                     source_rect,
+                                     draw_surface_options,
+                                     draw_options);
     }
 
     pub fn draw_image(&self, bounds: Rect<Au>, image: Arc<~Image>) {
@@ -98,6 +478,63 @@ impl<'self> RenderContext<'self>  {
                                      draw_options);
     }
 
+    /// Draws copies of the image within `bounds`. One copy fills `tile_bounds`, which may lie
+    /// partly or wholly outside `bounds`; the others are laid edge to edge from it along each axis
+    /// the image repeats in. Copies outside the part of the page this context covers are skipped.
+    pub fn draw_tiled_image(&self,
+                            bounds: &Rect<Au>,
+                            tile_bounds: &Rect<Au>,
+                            repeat_x: bool,
+                            repeat_y: bool,
+                            image: Arc<~Image>) {
+        let page_rect = Rect(Point2D(Au::from_frac_px(self.canvas.rect.origin.x as float),
+                                     Au::from_frac_px(self.canvas.rect.origin.y as float)),
+                             Size2D(Au::from_frac_px(self.canvas.rect.size.width as float),
+                                    Au::from_frac_px(self.canvas.rect.size.height as float)));
+        let visible = match bounds.intersection(&page_rect) {
+            Some(visible) => visible,
+            None => return,
+        };
+        let columns = image_tile_positions(tile_bounds.origin.x,
+                                           tile_bounds.size.width,
+                                           repeat_x,
+                                           visible.origin.x,
+                                           visible.origin.x + visible.size.width);
+        let rows = image_tile_positions(tile_bounds.origin.y,
+                                        tile_bounds.size.height,
+                                        repeat_y,
+                                        visible.origin.y,
+                                        visible.origin.y + visible.size.height);
+        if columns.is_empty() || rows.is_empty() {
+            return
+        }
+
+        let image = image.get();
+        let size = Size2D(image.width as i32, image.height as i32);
+        let stride = image.width * 4;
+
+        self.canvas.draw_target.make_current();
+        let draw_target_ref = &self.canvas.draw_target;
+        let azure_surface = draw_target_ref.create_source_surface_from_data(image.data,
+                                                                            size,
+                                                                            stride as i32,
+                                                                            B8G8R8A8);
+        let source_rect = Rect(Point2D(0 as AzFloat, 0 as AzFloat),
+                               Size2D(image.width as AzFloat, image.height as AzFloat));
+        self.push_clip(&visible);
+        for &y in rows.iter() {
+            for &x in columns.iter() {
+                let dest_rect = Rect(Point2D(x, y), tile_bounds.size).to_azure_rect();
+                draw_target_ref.draw_surface(azure_surface,
+                                             dest_rect,
+                                             source_rect,
+                                             DrawSurfaceOptions(Linear, true),
+                                             DrawOptions(1.0f as AzFloat, 0));
+            }
+        }
+        self.pop_clip();
+    }
+
     pub fn clear(&self) {
         let pattern = ColorPattern(Color(1.0, 1.0, 1.0, 1.0));
         let rect = Rect(Point2D(self.canvas.rect.origin.x as AzFloat,
@@ -109,6 +546,376 @@ impl<'self> RenderContext<'self>  {
     }
 }
 
+// How far along the tangents at its ends the control points of a cubic Bézier curve approximating
+// a quarter of an ellipse lie, as a fraction of the radius.
+static ELLIPSE_CONTROL_POINT_RATIO: AzFloat = 0.5522847;
+
+#[deriving(Clone, Eq)]
+enum BorderSide {
+    TopSide,
+    RightSide,
+    BottomSide,
+    LeftSide,
+}
+
+/// A rect with elliptical corners, in pixels.
+struct RoundedRect {
+    rect: Rect<AzFloat>,
+
+    /// The horizontal and vertical radii of the corners, clockwise from the top left.
+    radii: [Size2D<AzFloat>, ..4],
+}
+
+impl RoundedRect {
+    fn new(rect: &Rect<Au>, radii: &BorderRadii) -> RoundedRect {
+        let corners = radii.corners();
+        let mut radii = [Size2D(0.0 as AzFloat, 0.0 as AzFloat), ..4];
+        for (i, corner) in corners.iter().enumerate() {
+            radii[i] = Size2D(corner.width.to_px() as AzFloat, corner.height.to_px() as AzFloat);
+        }
+        RoundedRect {
+            rect: rect.to_azure_rect(),
+            radii: radii,
+        }
+    }
+
+    /// Moves each edge inwards by the given fraction of its width. The corners keep their
+    /// centers, so their radii shrink by as much as the edges next to them move.
+    fn deflate(&self, widths: &SideOffsets2D<AzFloat>, fraction: AzFloat) -> RoundedRect {
+        let (top, right) = (widths.top * fraction, widths.right * fraction);
+        let (bottom, left) = (widths.bottom * fraction, widths.left * fraction);
+        let shrink = |radius: Size2D<AzFloat>, horizontal: AzFloat, vertical: AzFloat| {
+            Size2D(num::max(radius.width - horizontal, 0.0),
+                   num::max(radius.height - vertical, 0.0))
+        };
+        RoundedRect {
+            rect: Rect(Point2D(self.rect.origin.x + left, self.rect.origin.y + top),
+                       Size2D(num::max(self.rect.size.width - left - right, 0.0),
+                              num::max(self.rect.size.height - top - bottom, 0.0))),
+            radii: [
+                shrink(self.radii[0], left, top),
+                shrink(self.radii[1], right, top),
+                shrink(self.radii[2], right, bottom),
+                shrink(self.radii[3], left, bottom),
+            ],
+        }
+    }
+
+    /// Returns the outline as a start point and the cubic Bézier segments following it, clockwise
+    /// from the end of the top left corner. Straight edges are segments whose control points are
+    /// their ends. The last segment ends at the start point.
+    fn segments(&self) -> (Point2D<AzFloat>, ~[[Point2D<AzFloat>, ..3]]) {
+        let (x, y) = (self.rect.origin.x, self.rect.origin.y);
+        let (right, bottom) = (x + self.rect.size.width, y + self.rect.size.height);
+        let k = ELLIPSE_CONTROL_POINT_RATIO;
+        let (top_left, top_right) = (self.radii[0], self.radii[1]);
+        let (bottom_right, bottom_left) = (self.radii[2], self.radii[3]);
+
+        let start = Point2D(x + top_left.width, y);
+        let mut segments = ~[line_segment(start, Point2D(right - top_right.width, y))];
+        segments.push([Point2D(right - top_right.width * (1.0 - k), y),
+                       Point2D(right, y + top_right.height * (1.0 - k)),
+                       Point2D(right, y + top_right.height)]);
+        segments.push(line_segment(Point2D(right, y + top_right.height),
+                                   Point2D(right, bottom - bottom_right.height)));
+        segments.push([Point2D(right, bottom - bottom_right.height * (1.0 - k)),
+                       Point2D(right - bottom_right.width * (1.0 - k), bottom),
+                       Point2D(right - bottom_right.width, bottom)]);
+        segments.push(line_segment(Point2D(right - bottom_right.width, bottom),
+                                   Point2D(x + bottom_left.width, bottom)));
+        segments.push([Point2D(x + bottom_left.width * (1.0 - k), bottom),
+                       Point2D(x, bottom - bottom_left.height * (1.0 - k)),
+                       Point2D(x, bottom - bottom_left.height)]);
+        segments.push(line_segment(Point2D(x, bottom - bottom_left.height),
+                                   Point2D(x, y + top_left.height)));
+        segments.push([Point2D(x, y + top_left.height * (1.0 - k)),
+                       Point2D(x + top_left.width * (1.0 - k), y),
+                       start]);
+        (start, segments)
+    }
+
+    /// Adds the outline to the path as a closed figure. Filling an outline together with a
+    /// smaller one inside it going the other way fills only the ring between them.
+    fn add_to_path(&self, builder: &PathBuilder, clockwise: bool) {
+        let (start, segments) = self.segments();
+        builder.move_to(start);
+        if clockwise {
+            for segment in segments.iter() {
+                builder.bezier_to(segment[0], segment[1], segment[2]);
+            }
+        } else {
+            // Each segment backwards starts at its old end and ends where the one before it did.
+            for i in range(0, segments.len()).invert() {
+                let end = if i == 0 { start } else { segments[i - 1][2] };
+                builder.bezier_to(segments[i][1], segments[i][0], end);
+            }
+        }
+        builder.close();
+    }
+
+    /// Returns the outer corner, the other outer corner and the inner corners of one side of a
+    /// border with the given widths drawn along the inside of this rect.
+    fn side_corners(&self, side: BorderSide, widths: &SideOffsets2D<AzFloat>)
+                    -> [Point2D<AzFloat>, ..4] {
+        let outer = self.rect;
+        let inner = self.deflate(widths, 1.0).rect;
+        let top_left = (outer.origin, inner.origin);
+        let top_right = (Point2D(outer.origin.x + outer.size.width, outer.origin.y),
+                         Point2D(inner.origin.x + inner.size.width, inner.origin.y));
+        let bottom_right = (outer.origin + Point2D(outer.size.width, outer.size.height),
+                            inner.origin + Point2D(inner.size.width, inner.size.height));
+        let bottom_left = (Point2D(outer.origin.x, outer.origin.y + outer.size.height),
+                           Point2D(inner.origin.x, inner.origin.y + inner.size.height));
+        let ((first_outer, first_inner), (second_outer, second_inner)) = match side {
+            TopSide => (top_left, top_right),
+            RightSide => (top_right, bottom_right),
+            BottomSide => (bottom_right, bottom_left),
+            LeftSide => (bottom_left, top_left),
+        };
+        [first_outer, second_outer, second_inner, first_inner]
+    }
+
+    /// Returns the part of the band of the given width along one side of this rect that starts
+    /// `position` from the top or left end of the side and is `length` long.
+    fn side_band(&self, side: BorderSide, width: AzFloat, position: AzFloat, length: AzFloat)
+                 -> Rect<AzFloat> {
+        let (x, y) = (self.rect.origin.x, self.rect.origin.y);
+        let (right, bottom) = (x + self.rect.size.width, y + self.rect.size.height);
+        match side {
+            TopSide => Rect(Point2D(x + position, y), Size2D(length, width)),
+            BottomSide => Rect(Point2D(x + position, bottom - width), Size2D(length, width)),
+            LeftSide => Rect(Point2D(x, y + position), Size2D(width, length)),
+            RightSide => Rect(Point2D(right - width, y + position), Size2D(width, length)),
+        }
+    }
+}
+
+// A straight line as a cubic Bézier segment from `from` to `to`.
+fn line_segment(from: Point2D<AzFloat>, to: Point2D<AzFloat>) -> [Point2D<AzFloat>, ..3] {
+    [from, to, to]
+}
+
+// Scales the red, green and blue of the color by the factor, leaving its alpha alone.
+fn shade_color(color: Color, factor: AzFloat) -> Color {
+    Color {
+        r: color.r * factor,
+        g: color.g * factor,
+        b: color.b * factor,
+        a: color.a,
+    }
+}
+
+fn inflate_rect(rect: &Rect<int>, amount: int) -> Rect<int> {
+    Rect(Point2D(rect.origin.x - amount, rect.origin.y - amount),
+         Size2D(rect.size.width + amount * 2, rect.size.height + amount * 2))
+}
+
+/// Returns the alpha mask of a box shadow over `mask_rect`, stored in rows. The shadow's shape is
+/// `shape` before blurring; inset shadows cover everything outside it and outer shadows everything
+/// inside it. Outer shadows are cut out of the box, `box_rect`. All rects are in device pixels.
+pub fn box_shadow_mask(mask_rect: &Rect<int>,
+                       shape: &Rect<int>,
+                       box_rect: &Rect<int>,
+                       blur_radius: uint,
+                       inset: bool)
+                       -> ~[u8] {
+    let (width, height) = (mask_rect.size.width as uint, mask_rect.size.height as uint);
+    let (outside, inside) = if inset { (255u8, 0u8) } else { (0u8, 255u8) };
+    let mut mask = vec::from_elem(width * height, outside);
+    let fill = |mask: &mut [u8], rect: &Rect<int>, value: u8| {
+        match rect.intersection(mask_rect) {
+            Some(rect) => {
+                for y in range(rect.origin.y, rect.origin.y + rect.size.height) {
+                    let row = ((y - mask_rect.origin.y) as uint) * width;
+                    for x in range(rect.origin.x, rect.origin.x + rect.size.width) {
+                        mask[row + (x - mask_rect.origin.x) as uint] = value;
+                    }
+                }
+            }
+            None => {}
+        }
+    };
+    fill(mask, shape, inside);
+    blur_alpha_mask(mask, width, height, blur_radius, outside);
+    if !inset {
+        fill(mask, box_rect, 0);
+    }
+    mask
+}
+
+// The radius of each of the three box blurs that approximate a blur with the given radius.
+fn box_blur_radius(radius: uint) -> uint {
+    let sigma = (radius as float) / 2.0;
+    (((4.0 * sigma * sigma + 1.0).sqrt() - 1.0) / 2.0).round() as uint
+}
+
+/// Returns how far, in pixels, `blur_alpha_mask` can spread a pixel with the given blur radius.
+pub fn blur_extent(radius: uint) -> uint {
+    box_blur_radius(radius) * 3
+}
+
+/// Blurs a `width` by `height` alpha mask, stored in rows, with the given blur radius. Following
+/// CSS, this approximates a Gaussian blur with a standard deviation of half the radius, using
+/// three box blurs in each direction. Pixels beyond the edges of the mask are taken to be `edge`.
+pub fn blur_alpha_mask(mask: &mut [u8], width: uint, height: uint, radius: uint, edge: u8) {
+    let box_radius = box_blur_radius(radius);
+    if box_radius == 0 {
+        return
+    }
+
+    let mut line = vec::from_elem(num::max(width, height), 0u8);
+    let mut blurred = line.clone();
+    for y in range(0, height) {
+        for x in range(0, width) {
+            line[x] = mask[y * width + x];
+        }
+        for _ in range(0, 3) {
+            box_blur_line(line.slice(0, width), blurred.mut_slice(0, width), box_radius, edge);
+            line = blurred.clone();
+        }
+        for x in range(0, width) {
+            mask[y * width + x] = line[x];
+        }
+    }
+    for x in range(0, width) {
+        for y in range(0, height) {
+            line[y] = mask[y * width + x];
+        }
+        for _ in range(0, 3) {
+            box_blur_line(line.slice(0, height), blurred.mut_slice(0, height), box_radius, edge);
+            line = blurred.clone();
+        }
+        for y in range(0, height) {
+            mask[y * width + x] = line[y];
+        }
+    }
+}
+
+// Sets each value of `dst` to the mean of the values of `src` within `radius` of it.
+fn box_blur_line(src: &[u8], dst: &mut [u8], radius: uint, edge: u8) {
+    let sample = |i: int| -> uint {
+        if i < 0 || i >= src.len() as int { edge as uint } else { src[i] as uint }
+    };
+    let (radius, window) = (radius as int, radius * 2 + 1);
+    let mut sum = 0;
+    for i in range(-radius, radius + 1) {
+        sum += sample(i);
+    }
+    for i in range(0, src.len() as int) {
+        dst[i] = (sum / window) as u8;
+        sum += sample(i + radius + 1);
+        sum -= sample(i - radius);
+    }
+}
+
+/// Returns where the copies of an image tile of the given length that overlap `min` to `max` start
+/// along one axis, given where one copy starts. An image that does not repeat has only that copy.
+pub fn image_tile_positions(start: Au, length: Au, repeat: bool, min: Au, max: Au) -> ~[Au] {
+    if length <= Au(0) {
+        return ~[]
+    }
+    if !repeat {
+        return if start < max && start + length > min { ~[start] } else { ~[] }
+    }
+
+    // Step back from `start` to the last copy that starts at or before `min`.
+    let remainder = *(start - min) % *length;
+    let mut position = if remainder > 0 {
+        min + Au(remainder) - length
+    } else {
+        min + Au(remainder)
+    };
+    let mut positions = ~[];
+    while position < max {
+        positions.push(position);
+        position = position + length;
+    }
+    positions
+}
+
+#[test]
+fn test_image_tile_positions() {
+    fn px(px: int) -> Au { Au::from_px(px) }
+
+    // A copy that does not repeat is kept only if it overlaps the range.
+    assert_eq!(image_tile_positions(px(5), px(10), false, px(0), px(30)), ~[px(5)]);
+    assert_eq!(image_tile_positions(px(30), px(10), false, px(0), px(30)), ~[]);
+
+    // Repeating copies cover the range whichever side of it the first copy starts on.
+    assert_eq!(image_tile_positions(px(5), px(10), true, px(0), px(30)),
+               ~[px(-5), px(5), px(15), px(25)]);
+    assert_eq!(image_tile_positions(px(-25), px(10), true, px(0), px(20)),
+               ~[px(-5), px(5), px(15)]);
+    assert_eq!(image_tile_positions(px(40), px(10), true, px(0), px(20)), ~[px(0), px(10)]);
+
+    // Empty tiles draw nothing.
+    assert_eq!(image_tile_positions(px(0), px(0), true, px(0), px(20)), ~[]);
+}
+
+#[test]
+fn test_blur_alpha_mask() {
+    // A zero radius leaves the mask alone.
+    let mut mask = ~[0, 255, 0, 255];
+    blur_alpha_mask(mask, 2, 2, 0, 0);
+    assert_eq!(mask, ~[0, 255, 0, 255]);
+
+    // A mask that is uniform, edges included, stays uniform.
+    let mut mask = vec::from_elem(16, 255u8);
+    blur_alpha_mask(mask, 4, 4, 6, 255);
+    assert_eq!(mask, vec::from_elem(16, 255u8));
+
+    // A square in the middle spreads out evenly, fading away from its center.
+    let mut mask = vec::from_elem(81, 0u8);
+    for y in range(3u, 6) {
+        for x in range(3u, 6) {
+            mask[y * 9 + x] = 255;
+        }
+    }
+    blur_alpha_mask(mask, 9, 9, 4, 0);
+    for y in range(0u, 9) {
+        for x in range(0u, 9) {
+            assert_eq!(mask[y * 9 + x], mask[x * 9 + y]);
+            assert_eq!(mask[y * 9 + x], mask[y * 9 + (8 - x)]);
+        }
+    }
+    for x in range(0u, 4) {
+        assert!(mask[4 * 9 + x] < mask[4 * 9 + x + 1]);
+    }
+    assert!(mask[4 * 9 + 4] < 255);
+}
+
+#[test]
+fn test_box_shadow_mask() {
+    let box_rect = Rect(Point2D(2, 2), Size2D(4, 4));
+
+    // An unblurred outer shadow covers its shape except where the box is.
+    let shape = Rect(Point2D(4, 4), Size2D(4, 4));
+    let mask = box_shadow_mask(&Rect(Point2D(0, 0), Size2D(10, 10)), &shape, &box_rect, 0, false);
+    assert_eq!(mask[5 * 10 + 5], 0);
+    assert_eq!(mask[7 * 10 + 7], 255);
+    assert_eq!(mask[7 * 10 + 3], 0);
+
+    // Only the part of the shadow within the mask rect is computed.
+    let mask = box_shadow_mask(&Rect(Point2D(6, 6), Size2D(2, 3)), &shape, &box_rect, 0, false);
+    assert_eq!(mask, ~[255, 255, 255, 255, 0, 0]);
+
+    // Inset shadows cover what lies outside their shape.
+    let shape = Rect(Point2D(3, 3), Size2D(3, 3));
+    let mask = box_shadow_mask(&box_rect, &shape, &box_rect, 0, true);
+    assert_eq!(mask, ~[255, 255, 255, 255,
+                       255, 0, 0, 0,
+                       255, 0, 0, 0,
+                       255, 0, 0, 0]);
+
+    // Blurred outer shadows fade out towards the edge of the mask.
+    let shape = Rect(Point2D(10, 10), Size2D(10, 10));
+    let mask = box_shadow_mask(&Rect(Point2D(0, 0), Size2D(30, 30)), &shape, &box_rect, 8,
+                               false);
+    assert!(mask[15 * 30 + 15] > mask[15 * 30 + 9]);
+    assert!(mask[15 * 30 + 9] > mask[15 * 30 + 7]);
+    assert!(mask[15 * 30 + 7] > 0);
+}
+
 trait to_float {
     fn to_float(&self) -> float;
 }
@@ -130,6 +937,16 @@ impl ToAzureRect for Rect<Au> {
     }
 }
 
+trait ToAzurePoint {
+    fn to_azure_point(&self) -> Point2D<AzFloat>;
+}
+
+impl ToAzurePoint for Point2D<Au> {
+    fn to_azure_point(&self) -> Point2D<AzFloat> {
+        Point2D(self.x.to_px() as AzFloat, self.y.to_px() as AzFloat)
+    }
+}
+
 trait ToSideOffsetsPx {
     fn to_float_px(&self) -> SideOffsets2D<AzFloat>;
 }
@@ -142,3 +959,35 @@ impl ToSideOffsetsPx for SideOffsets2D<Au> {
                            self.left.to_px() as AzFloat)
     }
 }
+
+#[test]
+fn test_rounded_rect() {
+    let rect = Rect(Point2D(Au::from_px(10), Au::from_px(20)),
+                    Size2D(Au::from_px(100), Au::from_px(50)));
+    let radii = BorderRadii {
+        top_left: Size2D(Au::from_px(8), Au::from_px(4)),
+        .. BorderRadii::zero()
+    };
+    let rounded = RoundedRect::new(&rect, &radii);
+    let widths = SideOffsets2D::new(2.0 as AzFloat, 4.0, 6.0, 10.0);
+
+    // Deflating moves the edges in and shrinks the radii, but never below zero.
+    let inner = rounded.deflate(&widths, 1.0);
+    assert_eq!(inner.rect, Rect(Point2D(20.0 as AzFloat, 22.0), Size2D(86.0 as AzFloat, 42.0)));
+    assert_eq!(inner.radii[0], Size2D(0.0 as AzFloat, 2.0));
+    assert_eq!(inner.radii[1], Size2D(0.0 as AzFloat, 0.0));
+    let halfway = rounded.deflate(&widths, 0.5);
+    assert_eq!(halfway.radii[0], Size2D(3.0 as AzFloat, 3.0));
+
+    // The outline is closed and starts after the top left corner.
+    let (start, segments) = rounded.segments();
+    assert_eq!(start, Point2D(18.0 as AzFloat, 20.0));
+    assert_eq!(segments.len(), 8);
+    assert_eq!(segments[7][2], start);
+    assert_eq!(segments[0][2], Point2D(110.0 as AzFloat, 20.0));
+
+    // Each side is clipped to the trapezoid between its outer and inner corners.
+    let corners = rounded.side_corners(LeftSide, &widths);
+    assert_eq!(corners, [Point2D(10.0 as AzFloat, 70.0), Point2D(10.0 as AzFloat, 20.0),
+                         Point2D(20.0 as AzFloat, 22.0), Point2D(20.0 as AzFloat, 64.0)]);
+}
@@ -4,37 +4,43 @@
 
 // The task that handles all rendering/painting.
 
-use azure::{AzFloat, AzGLContext};
-use azure::azure_hl::{B8G8R8A8, DrawTarget};
-use display_list::DisplayList;
-use servo_msg::compositor_msg::{RenderListener, IdleRenderState, RenderingRenderState, LayerBuffer};
+use display_list::{DisplayList, DisplayListIndex};
+use servo_msg::compositor_msg::{RenderListener, IdleRenderState, RenderingRenderState};
 use servo_msg::compositor_msg::{LayerBufferSet};
 use servo_msg::constellation_msg::PipelineId;
-use font_context::FontContext;
-use geom::matrix2d::Matrix2D;
+use geometry::Au;
 use geom::size::Size2D;
 use geom::rect::Rect;
 use opts::Opts;
-use render_context::RenderContext;
+use render_worker::RenderWorkerPool;
 
 use std::cell::Cell;
 use std::comm::{Chan, Port, SharedChan};
 
-use servo_util::time::{ProfilerChan, profile};
+use servo_util::memory::MemoryReport;
+use servo_util::time::{CounterMsg, ProfilerChan};
 use servo_util::time;
 
 use extra::arc;
 
+/// The width and height, in px, of the cells of the grid used to find the display items in a tile.
+static INDEX_CELL_SIZE: uint = 256;
+
 pub struct RenderLayer {
-    display_list: DisplayList<()>,
+    /// Shared with the render workers, which each draw some of the tiles.
+    display_list: arc::Arc<DisplayList<()>>,
     size: Size2D<uint>
 }
 
 pub enum Msg {
     RenderMsg(RenderLayer),
     ReRenderMsg(~[BufferRequest], f32, PipelineId),
+    /// Like `ReRenderMsg`, but for offscreen tiles requested in case they scroll into view. It is
+    /// dropped if another request arrives before it is started.
+    PrefetchMsg(~[BufferRequest], f32, PipelineId),
     PaintPermissionGranted,
     PaintPermissionRevoked,
+    ReportMemoryMsg(Chan<~[MemoryReport]>),
     ExitMsg(Chan<()>),
 }
 
@@ -75,16 +81,17 @@ struct RenderTask<C> {
     id: PipelineId,
     port: Port<Msg>,
     compositor: C,
-    font_ctx: @mut FontContext,
-    opts: Opts,
 
     /// A channel to the profiler.
     profiler_chan: ProfilerChan,
 
-    share_gl_context: AzGLContext,
+    /// The tasks that draw the tiles, `opts.n_render_threads` of them
+    worker_pool: RenderWorkerPool,
 
     /// The layer to be rendered
     render_layer: Option<RenderLayer>,
+    /// Finds the display items that intersect each tile of `render_layer`
+    display_list_index: Option<arc::Arc<DisplayListIndex>>,
     /// Permission to send paint messages to the compositor
     paint_permission: bool,
     /// Cached copy of last layers rendered
@@ -107,19 +114,21 @@ impl<C: RenderListener + Send> RenderTask<C> {
             let share_gl_context = compositor.get_gl_context();
             let opts = opts.take();
             let profiler_chan = profiler_chan.take();
+            let worker_pool = RenderWorkerPool::new(opts.n_render_threads,
+                                                    &opts,
+                                                    profiler_chan.for_task("RenderWorker",
+                                                                           Some(*id)),
+                                                    share_gl_context);
 
             // FIXME: rust/#5967
             let mut render_task = RenderTask {
                 id: id,
                 port: port.take(),
                 compositor: compositor,
-                font_ctx: @mut FontContext::new(opts.render_backend.clone(),
-                                                false,
-                                                profiler_chan.clone()),
-                opts: opts,
-                profiler_chan: profiler_chan,
-                share_gl_context: share_gl_context,
+                profiler_chan: profiler_chan.for_task("RenderTask", Some(*id)),
+                worker_pool: worker_pool,
                 render_layer: None,
+                display_list_index: None,
 
                 paint_permission: false,
                 last_paint_msg: None,
@@ -133,34 +142,102 @@ impl<C: RenderListener + Send> RenderTask<C> {
         debug!("render_task: beginning rendering loop");
 
         loop {
-            match self.port.recv() {
-                RenderMsg(render_layer) => {
-                    if self.paint_permission {
-                        self.compositor.new_layer(self.id, render_layer.size);
-                    }
-                    self.render_layer = Some(render_layer);
+            // Take every message that has arrived, so that stale requests can be dropped before
+            // any time is spent on them.
+            let mut msgs = ~[self.port.recv()];
+            while self.port.peek() {
+                msgs.push(self.port.recv());
+            }
+
+            let stale = find_stale_prefetches(msgs);
+            for (msg, stale) in msgs.move_iter().zip(stale.move_iter()) {
+                if stale {
+                    self.drop_request(msg);
+                } else if !self.handle_msg(msg) {
+                    return
                 }
-                ReRenderMsg(tiles, scale, id) => {
-                    self.render(tiles, scale, id);
+            }
+        }
+    }
+
+    /// Tells the compositor that the tiles of a request will not be rendered after all.
+    fn drop_request(&self, msg: Msg) {
+        match msg {
+            ReRenderMsg(tiles, _, id) | PrefetchMsg(tiles, _, id) => {
+                debug!("render_task: dropping a request for %u tiles", tiles.len());
+                let mut page_rects = tiles.iter().map(|tile| tile.page_rect);
+                self.compositor.tiles_dropped(id, page_rects.collect());
+            }
+            _ => fail!(~"render_task: only requests for tiles can be dropped"),
+        }
+    }
+
+    /// Handles one message, and returns false if the render task should exit.
+    fn handle_msg(&mut self, msg: Msg) -> bool {
+        match msg {
+            RenderMsg(render_layer) => {
+                if self.paint_permission {
+                    self.compositor.new_layer(self.id, render_layer.size);
                 }
-                PaintPermissionGranted => {
-                    self.paint_permission = true;
-                    match self.render_layer {
-                        Some(ref render_layer) => {
-                            self.compositor.new_layer(self.id, render_layer.size);
-                        }
-                        None => {}
+                let layer_size = Size2D(Au::from_px(render_layer.size.width as int),
+                                        Au::from_px(render_layer.size.height as int));
+                self.display_list_index = Some(arc::Arc::new(DisplayListIndex::new(
+                    render_layer.display_list.get(),
+                    layer_size,
+                    Au::from_px(INDEX_CELL_SIZE as int))));
+                self.render_layer = Some(render_layer);
+            }
+            ReRenderMsg(tiles, scale, id) | PrefetchMsg(tiles, scale, id) => {
+                self.render(tiles, scale, id);
+            }
+            PaintPermissionGranted => {
+                self.paint_permission = true;
+                match self.render_layer {
+                    Some(ref render_layer) => {
+                        self.compositor.new_layer(self.id, render_layer.size);
                     }
+                    None => {}
                 }
-                PaintPermissionRevoked => {
-                    self.paint_permission = false;
-                }
-                ExitMsg(response_ch) => {
-                    response_ch.send(());
-                    break;
+            }
+            PaintPermissionRevoked => {
+                self.paint_permission = false;
+            }
+            ReportMemoryMsg(response_ch) => {
+                response_ch.send(self.memory_reports());
+            }
+            ExitMsg(response_ch) => {
+                self.worker_pool.exit();
+                response_ch.send(());
+                return false
+            }
+        }
+        true
+    }
+
+    fn memory_reports(&self) -> ~[MemoryReport] {
+        let mut reports = ~[];
+        match self.render_layer {
+            Some(ref render_layer) => {
+                let display_list = render_layer.display_list.get();
+                reports.push(MemoryReport::new("display list items",
+                                               display_list.len(),
+                                               display_list.memory_size()));
+            }
+            None => {}
+        }
+        match self.last_paint_msg {
+            Some((ref layer_buffer_set, _)) => {
+                let buffers = &layer_buffer_set.get().buffers;
+                let mut bytes = 0;
+                for buffer in buffers.iter() {
+                    bytes += buffer.stride * buffer.screen_pos.size.height;
                 }
+                reports.push(MemoryReport::new("cached layer buffers", buffers.len(), bytes));
             }
+            None => {}
         }
+        reports.push_all_move(self.worker_pool.memory_reports());
+        reports
     }
 
     fn render(&mut self, tiles: ~[BufferRequest], scale: f32, id: PipelineId) {
@@ -172,64 +249,21 @@ impl<C: RenderListener + Send> RenderTask<C> {
             _ => return, // nothing to do
         }
 
+        let display_list_index = self.display_list_index.get_ref();
+
+        let tiles = Cell::new(tiles);
         self.compositor.set_render_state(RenderingRenderState);
         do time::profile(time::RenderingCategory, self.profiler_chan.clone()) {
+            let rendered = self.worker_pool.render(tiles.take(),
+                                                   scale,
+                                                   &render_layer.display_list,
+                                                   display_list_index);
 
-            // FIXME: Try not to create a new array here.
-            let mut new_buffers = ~[];
-
-            // Divide up the layer into tiles.
-            do time::profile(time::RenderingPrepBuffCategory, self.profiler_chan.clone()) {
-                for tile in tiles.iter() {
-                    let width = tile.screen_rect.size.width;
-                    let height = tile.screen_rect.size.height;
-                    
-                    let buffer = LayerBuffer {
-                        draw_target: DrawTarget::new_with_fbo(self.opts.render_backend,
-                                                              self.share_gl_context,
-                                                              Size2D(width as i32, height as i32),
-                                                              B8G8R8A8),
-                        rect: tile.page_rect,
-                        screen_pos: tile.screen_rect,
-                        resolution: scale,
-                        stride: (width * 4) as uint
-                    };
-                    
-                    
-                    {
-                        // Build the render context.
-                        let ctx = RenderContext {
-                            canvas: &buffer,
-                            font_ctx: self.font_ctx,
-                            opts: &self.opts
-                        };
-
-                        // Apply the translation to render the tile we want.
-                        let matrix: Matrix2D<AzFloat> = Matrix2D::identity();
-                        let matrix = matrix.scale(scale as AzFloat, scale as AzFloat);
-                        let matrix = matrix.translate(-(buffer.rect.origin.x) as AzFloat,
-                                                      -(buffer.rect.origin.y) as AzFloat);
-                        
-                        ctx.canvas.draw_target.set_transform(&matrix);
-                        
-                        // Clear the buffer.
-                        ctx.clear();
-                        
-                        // Draw the display list.
-                        do profile(time::RenderingDrawingCategory, self.profiler_chan.clone()) {
-                            render_layer.display_list.draw_into_context(&ctx);
-                            ctx.canvas.draw_target.flush();
-                        }
-                    }
-                    
-                    new_buffers.push(buffer);
-                    
-                }
-
-            }
+            self.profiler_chan.send(CounterMsg(~"display items drawn", rendered.items_drawn));
+            self.profiler_chan.send(CounterMsg(~"display items culled", rendered.items_culled));
 
             let layer_buffer_set = LayerBufferSet {
-                buffers: new_buffers,
+                buffers: rendered.buffers,
             };
             let layer_buffer_set = arc::Arc::new(layer_buffer_set);
 
@@ -244,3 +278,37 @@ impl<C: RenderListener + Send> RenderTask<C> {
     }
 }
 
+/// Returns, for each message, whether it is a prefetch request that a later request for tiles
+/// supersedes. The compositor asks for offscreen tiles based on where the viewport is, so once it
+/// has asked for anything else, the viewport has probably moved on.
+fn find_stale_prefetches(msgs: &[Msg]) -> ~[bool] {
+    let mut stale = ~[];
+    let mut superseded = false;
+    for msg in msgs.rev_iter() {
+        match *msg {
+            PrefetchMsg(*) => {
+                stale.push(superseded);
+                superseded = true;
+            }
+            ReRenderMsg(*) => {
+                stale.push(false);
+                superseded = true;
+            }
+            _ => stale.push(false),
+        }
+    }
+    stale.reverse();
+    stale
+}
+
+#[test]
+fn test_find_stale_prefetches() {
+    let id = PipelineId(0);
+    let msgs = ~[PrefetchMsg(~[], 1.0, id),
+                 ReRenderMsg(~[], 1.0, id),
+                 PrefetchMsg(~[], 1.0, id),
+                 PaintPermissionGranted,
+                 PrefetchMsg(~[], 1.0, id)];
+    assert_eq!(find_stale_prefetches(msgs), ~[true, false, true, false, false]);
+    assert_eq!(find_stale_prefetches([ReRenderMsg(~[], 1.0, id)]), ~[false]);
+}
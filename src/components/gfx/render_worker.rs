@@ -0,0 +1,325 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The tasks that rasterize tiles on behalf of the render task, so that the tiles of one request
+//! are drawn in parallel.
+
+use azure::{AzFloat, AzGLContext};
+use azure::azure_hl::{B8G8R8A8, DrawTarget};
+use display_list::{DisplayList, DisplayListIndex};
+use servo_msg::compositor_msg::LayerBuffer;
+use font::ShapeCache;
+use font_context::FontContext;
+use geometry::Au;
+use geom::matrix2d::Matrix2D;
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use opts::Opts;
+use render_context::RenderContext;
+use render_task::BufferRequest;
+
+use std::cell::Cell;
+use std::comm::{Chan, Port, SharedChan, stream};
+use std::num;
+use std::vec;
+
+use servo_util::memory::MemoryReport;
+use servo_util::time::{ProfilerChan, profile};
+use servo_util::time;
+
+use extra::arc;
+use extra::sync::Mutex;
+
+/// The tiles of one request that a single worker draws.
+struct TileBatch {
+    /// Each tile along with its position in the request, so that the results can be put back
+    /// in order.
+    tiles: ~[(uint, BufferRequest)],
+    scale: f32,
+    display_list: arc::Arc<DisplayList<()>>,
+    display_list_index: arc::Arc<DisplayListIndex>,
+}
+
+/// The buffers a worker drew for a `TileBatch`.
+struct RenderedTiles {
+    buffers: ~[(uint, LayerBuffer)],
+    items_drawn: uint,
+    items_culled: uint,
+}
+
+enum WorkerMsg {
+    RenderTilesMsg(TileBatch, SharedChan<RenderedTiles>),
+    WorkerMemoryMsg(Chan<~[MemoryReport]>),
+    WorkerExitMsg(Chan<()>),
+}
+
+/// The tiles drawn for a request, in the order they were requested.
+pub struct RenderedLayer {
+    buffers: ~[LayerBuffer],
+    items_drawn: uint,
+    items_culled: uint,
+}
+
+/// A fixed set of worker tasks, each with its own font context, among which the tiles of each
+/// request are shared out.
+pub struct RenderWorkerPool {
+    priv chans: ~[Chan<WorkerMsg>],
+}
+
+impl RenderWorkerPool {
+    /// Starts `n_workers` workers, or one if that is zero.
+    pub fn new(n_workers: uint,
+               opts: &Opts,
+               profiler_chan: ProfilerChan,
+               share_gl_context: AzGLContext)
+               -> RenderWorkerPool {
+        let share_gl_lock = Mutex::new();
+        let chans = do vec::from_fn(num::max(n_workers, 1)) |index| {
+            let (port, chan) = stream();
+            RenderWorker::create(index,
+                                 port,
+                                 opts.clone(),
+                                 profiler_chan.for_worker(index),
+                                 share_gl_context,
+                                 share_gl_lock.clone());
+            chan
+        };
+        RenderWorkerPool {
+            chans: chans,
+        }
+    }
+
+    /// Draws the given tiles of the display list at the given scale, and waits for all of them.
+    /// Neighboring tiles go to different workers, since they tend to be equally costly to draw.
+    pub fn render(&self,
+                  tiles: ~[BufferRequest],
+                  scale: f32,
+                  display_list: &arc::Arc<DisplayList<()>>,
+                  display_list_index: &arc::Arc<DisplayListIndex>)
+                  -> RenderedLayer {
+        let n_tiles = tiles.len();
+        let n_workers = self.chans.len();
+        let mut batches = vec::from_fn(n_workers, |_| ~[]);
+        for (position, tile) in tiles.move_iter().enumerate() {
+            batches[position % n_workers].push((position, tile));
+        }
+
+        let (result_port, result_chan) = stream();
+        let result_chan = SharedChan::new(result_chan);
+        let mut n_batches = 0;
+        for (chan, batch) in self.chans.iter().zip(batches.move_iter()) {
+            if batch.is_empty() {
+                loop
+            }
+            chan.send(RenderTilesMsg(TileBatch {
+                tiles: batch,
+                scale: scale,
+                display_list: display_list.clone(),
+                display_list_index: display_list_index.clone(),
+            }, result_chan.clone()));
+            n_batches += 1;
+        }
+
+        let mut slots = vec::from_fn(n_tiles, |_| None);
+        let mut items_drawn = 0;
+        let mut items_culled = 0;
+        for _ in range(0, n_batches) {
+            let rendered = result_port.recv();
+            items_drawn += rendered.items_drawn;
+            items_culled += rendered.items_culled;
+            for (position, buffer) in rendered.buffers.move_iter() {
+                slots[position] = Some(buffer);
+            }
+        }
+
+        let mut buffers = slots.move_iter().map(|buffer| buffer.unwrap());
+        RenderedLayer {
+            buffers: buffers.collect(),
+            items_drawn: items_drawn,
+            items_culled: items_culled,
+        }
+    }
+
+    /// Returns the memory reports of all the workers' font contexts.
+    pub fn memory_reports(&self) -> ~[MemoryReport] {
+        let mut reports = ~[];
+        for chan in self.chans.iter() {
+            let (port, response_chan) = stream();
+            chan.send(WorkerMemoryMsg(response_chan));
+            reports.push_all_move(port.recv());
+        }
+        reports
+    }
+
+    /// Stops all the workers, waiting for each to finish.
+    pub fn exit(&self) {
+        for chan in self.chans.iter() {
+            let (port, response_chan) = stream();
+            chan.send(WorkerExitMsg(response_chan));
+            port.recv();
+        }
+    }
+}
+
+struct RenderWorker {
+    port: Port<WorkerMsg>,
+    font_ctx: @mut FontContext,
+    opts: Opts,
+
+    /// A channel to the profiler, which attributes spans to this worker.
+    profiler_chan: ProfilerChan,
+
+    /// The compositor's GL context. Each tile's draw target gets a GL context of its own that
+    /// shares textures with this one, so workers draw in parallel without sharing a context.
+    share_gl_context: AzGLContext,
+
+    /// Held by a worker while it creates a GL context from `share_gl_context`, since creating
+    /// several contexts from the same share context at once is not thread safe on every platform.
+    share_gl_lock: Mutex,
+}
+
+impl RenderWorker {
+    fn create(index: uint,
+              port: Port<WorkerMsg>,
+              opts: Opts,
+              profiler_chan: ProfilerChan,
+              share_gl_context: AzGLContext,
+              share_gl_lock: Mutex) {
+        let port = Cell::new(port);
+        let opts = Cell::new(opts);
+        let profiler_chan = Cell::new(profiler_chan);
+        let share_gl_lock = Cell::new(share_gl_lock);
+
+        do spawn {
+            debug!("render worker %u: starting", index);
+            let opts = opts.take();
+            let profiler_chan = profiler_chan.take();
+            let mut worker = RenderWorker {
+                port: port.take(),
+                // Workers draw runs that layout has already shaped, so they need no words of
+                // their own.
+                font_ctx: @mut FontContext::new(opts.render_backend.clone(),
+                                                false,
+                                                ShapeCache::new(),
+                                                profiler_chan.clone()),
+                opts: opts,
+                profiler_chan: profiler_chan,
+                share_gl_context: share_gl_context,
+                share_gl_lock: share_gl_lock.take(),
+            };
+            worker.start();
+        }
+    }
+
+    fn start(&mut self) {
+        loop {
+            match self.port.recv() {
+                RenderTilesMsg(batch, result_chan) => {
+                    let rendered = do profile(time::RenderingWorkerCategory,
+                                              self.profiler_chan.clone()) {
+                        self.render_batch(&batch)
+                    };
+                    result_chan.send(rendered);
+                }
+                WorkerMemoryMsg(response_chan) => {
+                    response_chan.send(self.font_ctx.memory_reports());
+                }
+                WorkerExitMsg(response_chan) => {
+                    response_chan.send(());
+                    break;
+                }
+            }
+        }
+    }
+
+    fn render_batch(&self, batch: &TileBatch) -> RenderedTiles {
+        let display_list = batch.display_list.get();
+        let display_list_index = batch.display_list_index.get();
+        self.font_ctx.reserve_fonts(display_list.font_count());
+        let mut buffers = ~[];
+        let mut items_drawn = 0;
+        let mut items_culled = 0;
+
+        for &(position, ref tile) in batch.tiles.iter() {
+            let buffer = do profile(time::RenderingPrepBuffCategory, self.profiler_chan.clone()) {
+                let width = tile.screen_rect.size.width;
+                let height = tile.screen_rect.size.height;
+                let size = Size2D(width as i32, height as i32);
+
+                // Without a window there is no GL context, so headless runs draw in memory.
+                let draw_target = if self.opts.headless {
+                    DrawTarget::new(self.opts.render_backend, size, B8G8R8A8)
+                } else {
+                    do self.share_gl_lock.lock {
+                        DrawTarget::new_with_fbo(self.opts.render_backend,
+                                                 self.share_gl_context,
+                                                 size,
+                                                 B8G8R8A8)
+                    }
+                };
+                LayerBuffer {
+                    draw_target: draw_target,
+                    rect: tile.page_rect,
+                    screen_pos: tile.screen_rect,
+                    resolution: batch.scale,
+                    stride: (width * 4) as uint
+                }
+            };
+
+            {
+                // Build the render context.
+                let ctx = RenderContext {
+                    canvas: &buffer,
+                    font_ctx: self.font_ctx,
+                    opts: &self.opts
+                };
+
+                // Apply the translation to render the tile we want.
+                let matrix: Matrix2D<AzFloat> = Matrix2D::identity();
+                let matrix = matrix.scale(batch.scale as AzFloat, batch.scale as AzFloat);
+                let matrix = matrix.translate(-(buffer.rect.origin.x) as AzFloat,
+                                              -(buffer.rect.origin.y) as AzFloat);
+
+                ctx.canvas.draw_target.set_transform(&matrix);
+
+                // Clear the buffer.
+                ctx.clear();
+
+                // Draw the part of the display list that falls within this tile. Items are
+                // snapped and antialiased to whole device pixels, so one that ends within a pixel
+                // of the tile can still touch it.
+                let pixel = 1.0 / batch.scale;
+                let (origin, size) = (tile.page_rect.origin, tile.page_rect.size);
+                let tile_rect = page_rect_to_au(&Rect(Point2D(origin.x - pixel, origin.y - pixel),
+                                                      Size2D(size.width + pixel * 2.0,
+                                                             size.height + pixel * 2.0)));
+                do profile(time::RenderingDrawingCategory, self.profiler_chan.clone()) {
+                    let (drawn, culled) =
+                        display_list.draw_intersecting_into_context(&ctx,
+                                                                    display_list_index,
+                                                                    &tile_rect);
+                    items_drawn += drawn;
+                    items_culled += culled;
+                    ctx.canvas.draw_target.flush();
+                }
+            }
+
+            buffers.push((position, buffer));
+        }
+
+        RenderedTiles {
+            buffers: buffers,
+            items_drawn: items_drawn,
+            items_culled: items_culled,
+        }
+    }
+}
+
+fn page_rect_to_au(rect: &Rect<f32>) -> Rect<Au> {
+    Rect(Point2D(Au::from_frac_px(rect.origin.x as float),
+                 Au::from_frac_px(rect.origin.y as float)),
+         Size2D(Au::from_frac_px(rect.size.width as float),
+                Au::from_frac_px(rect.size.height as float)))
+}
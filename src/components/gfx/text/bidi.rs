@@ -0,0 +1,296 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The Unicode Bidirectional Algorithm (UAX #9), which finds the embedding level of each character
+//! of a paragraph of mixed left-to-right and right-to-left text, and from the levels the order in
+//! which the characters of a line are displayed.
+//!
+//! Explicit embeddings, overrides and isolates are not supported, so every character starts out
+//! at the paragraph's level and the whole paragraph is a single isolating run sequence.
+
+use std::vec;
+
+/// The bidirectional character types that the algorithm uses, from UAX #9 § 3.2.
+#[deriving(Eq, Clone)]
+pub enum BidiClass {
+    /// Left-to-right.
+    L,
+    /// Right-to-left, as in Hebrew.
+    R,
+    /// Right-to-left Arabic.
+    AL,
+    /// European number.
+    EN,
+    /// European number separator.
+    ES,
+    /// European number terminator.
+    ET,
+    /// Arabic number.
+    AN,
+    /// Common number separator.
+    CS,
+    /// Nonspacing mark.
+    NSM,
+    /// Paragraph separator.
+    B,
+    /// Segment separator.
+    S,
+    /// Whitespace.
+    WS,
+    /// Other neutrals.
+    ON,
+}
+
+/// Returns the bidirectional type of the given character.
+///
+/// FIXME: This covers the scripts we ship translations for, plus the common punctuation and
+/// digits; it should use the full `Bidi_Class` table from the Unicode Character Database.
+pub fn bidi_class(ch: char) -> BidiClass {
+    match ch as u32 {
+        0x30 .. 0x39 | 0x6F0 .. 0x6F9 | 0xB2 | 0xB3 | 0xB9 => EN,
+        0x2B | 0x2D => ES,
+        0x23 .. 0x25 | 0xA2 .. 0xA5 | 0xB0 | 0xB1 | 0x66A | 0x2030 .. 0x2034 |
+        0x20A0 .. 0x20CF => ET,
+        0x2C | 0x2E | 0x2F | 0x3A | 0xA0 | 0x60C | 0x202F => CS,
+        0x660 .. 0x669 | 0x66B | 0x66C => AN,
+        0x0A | 0x0D | 0x1C .. 0x1E | 0x85 | 0x2029 => B,
+        0x09 | 0x0B | 0x1F => S,
+        0x0C | 0x20 | 0x2000 .. 0x200A | 0x2028 | 0x205F | 0x3000 => WS,
+        0x300 .. 0x36F | 0x591 .. 0x5BD | 0x5BF | 0x5C1 | 0x5C2 | 0x5C4 | 0x5C5 | 0x5C7 |
+        0x610 .. 0x61A | 0x64B .. 0x65F | 0x670 | 0x6D6 .. 0x6DC | 0x6DF .. 0x6E4 | 0x6E7 |
+        0x6E8 | 0x6EA .. 0x6ED => NSM,
+        0x200E => L,
+        0x200F | 0x590 .. 0x5FF | 0x7C0 .. 0x85F | 0xFB1D .. 0xFB4F => R,
+        0x600 .. 0x7BF | 0x860 .. 0x8FF | 0xFB50 .. 0xFDFF | 0xFE70 .. 0xFEFF => AL,
+        0x21 | 0x22 | 0x26 .. 0x2A | 0x3B .. 0x40 | 0x5B .. 0x60 | 0x7B .. 0x7E | 0xA1 |
+        0xA6 .. 0xA9 | 0xAB .. 0xAF | 0xB4 | 0xB6 .. 0xB8 | 0xBB .. 0xBF | 0xD7 | 0xF7 |
+        0x2010 .. 0x2027 | 0x2035 .. 0x205E | 0x2190 .. 0x2BFF | 0xFFFC => ON,
+        _ => L,
+    }
+}
+
+/// Returns the level of a paragraph whose direction is set to "auto": 1 if its first strong
+/// character is right-to-left, 0 if it is left-to-right, and `None` if it has no strong characters
+/// (rules P2 and P3).
+pub fn paragraph_level(text: &str) -> Option<u8> {
+    for ch in text.iter() {
+        match bidi_class(ch) {
+            L => return Some(0),
+            R | AL => return Some(1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the embedding level of each character of `text`, which is a paragraph at the given
+/// level. Even levels are left-to-right and odd levels right-to-left.
+pub fn resolve_levels(text: &str, base_level: u8) -> ~[u8] {
+    let mut classes: ~[BidiClass] = text.iter().map(bidi_class).collect();
+    let len = classes.len();
+    let embedding = if base_level % 2 == 0 { L } else { R };
+
+    // W1: Nonspacing marks take the type of the preceding character.
+    let mut prev = embedding;
+    for i in range(0, len) {
+        if classes[i] == NSM {
+            classes[i] = prev;
+        }
+        prev = classes[i];
+    }
+
+    // W2 and W3: European numbers after Arabic letters are Arabic numbers, and Arabic letters are
+    // then right-to-left.
+    let mut last_strong = embedding;
+    for i in range(0, len) {
+        match classes[i] {
+            L | R => last_strong = classes[i],
+            AL => {
+                last_strong = AL;
+                classes[i] = R;
+            }
+            EN if last_strong == AL => classes[i] = AN,
+            _ => {}
+        }
+    }
+
+    // W4: A single separator between two numbers of the same type joins them.
+    for i in range(1, if len > 0 { len - 1 } else { 0 }) {
+        match (classes[i - 1], classes[i], classes[i + 1]) {
+            (EN, ES, EN) | (EN, CS, EN) => classes[i] = EN,
+            (AN, CS, AN) => classes[i] = AN,
+            _ => {}
+        }
+    }
+
+    // W5: Terminators next to European numbers are part of them.
+    let mut i = 0;
+    while i < len {
+        if classes[i] != ET {
+            i += 1;
+            loop
+        }
+        let start = i;
+        while i < len && classes[i] == ET {
+            i += 1;
+        }
+        if (start > 0 && classes[start - 1] == EN) || (i < len && classes[i] == EN) {
+            for j in range(start, i) {
+                classes[j] = EN;
+            }
+        }
+    }
+
+    // W6: Any remaining separators and terminators are neutral.
+    // W7: European numbers in left-to-right text are left-to-right.
+    let mut last_strong = embedding;
+    for i in range(0, len) {
+        match classes[i] {
+            ES | ET | CS => classes[i] = ON,
+            L | R => last_strong = classes[i],
+            EN if last_strong == L => classes[i] = L,
+            _ => {}
+        }
+    }
+
+    // N1 and N2: A sequence of neutrals takes the direction of the text around it if that is the
+    // same on both sides, and the paragraph's direction otherwise. Numbers count as
+    // right-to-left here.
+    let strong_direction = |class: BidiClass| -> Option<BidiClass> {
+        match class {
+            L => Some(L),
+            R | EN | AN => Some(R),
+            _ => None,
+        }
+    };
+    let mut i = 0;
+    while i < len {
+        if strong_direction(classes[i]).is_some() {
+            i += 1;
+            loop
+        }
+        let start = i;
+        while i < len && strong_direction(classes[i]).is_none() {
+            i += 1;
+        }
+        let before = if start == 0 {
+            embedding
+        } else {
+            strong_direction(classes[start - 1]).unwrap()
+        };
+        let after = if i == len { embedding } else { strong_direction(classes[i]).unwrap() };
+        let direction = if before == after { before } else { embedding };
+        for j in range(start, i) {
+            // Separators keep their type for rule L1.
+            match classes[j] {
+                B | S => {}
+                _ => classes[j] = direction,
+            }
+        }
+    }
+
+    // I1 and I2: Characters against the direction of their level are raised.
+    let mut levels = vec::from_elem(len, base_level);
+    for i in range(0, len) {
+        levels[i] = match (base_level % 2 == 0, classes[i]) {
+            (true, R) => base_level + 1,
+            (true, AN) | (true, EN) => base_level + 2,
+            (false, L) | (false, EN) | (false, AN) => base_level + 1,
+            _ => base_level,
+        };
+    }
+
+    // L1: Separators, and the whitespace before them and at the end of the paragraph, go back to
+    // the paragraph's level.
+    let original: ~[BidiClass] = text.iter().map(bidi_class).collect();
+    let mut at_separator = true;
+    for i in range(0, len).invert() {
+        match original[i] {
+            B | S => {
+                levels[i] = base_level;
+                at_separator = true;
+            }
+            WS if at_separator => levels[i] = base_level,
+            _ => at_separator = false,
+        }
+    }
+
+    levels
+}
+
+/// Returns the indices of the given levels in the order they are displayed from left to right.
+/// From the highest level down to the lowest odd one, each sequence of items at that level or
+/// above is reversed (rule L2).
+pub fn visual_order(levels: &[u8]) -> ~[uint] {
+    let mut order = vec::from_fn(levels.len(), |i| i);
+    if levels.is_empty() {
+        return order;
+    }
+
+    let highest = *levels.iter().max().unwrap();
+    let lowest_odd = match levels.iter().filter(|&&level| level % 2 == 1).min() {
+        Some(&level) => level,
+        None => return order,
+    };
+
+    let mut level = highest;
+    while level >= lowest_odd {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                loop
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order.mut_slice(start, i).reverse();
+        }
+        level -= 1;
+    }
+    order
+}
+
+#[test]
+fn test_resolve_levels() {
+    // Left-to-right text stays at the paragraph's level.
+    assert_eq!(resolve_levels("abc def", 0), ~[0u8, 0, 0, 0, 0, 0, 0]);
+
+    // Hebrew in an English paragraph is raised, but the spaces around it are not.
+    assert_eq!(resolve_levels("a אב b", 0), ~[0u8, 0, 1, 1, 0, 0]);
+
+    // Numbers in a right-to-left paragraph are raised twice, with their separators.
+    assert_eq!(resolve_levels("א 1.5", 1), ~[1u8, 1, 2, 2, 2]);
+
+    // Digits after Arabic letters are Arabic numbers, and marks follow their base letter.
+    assert_eq!(resolve_levels("اً 12", 0), ~[1u8, 1, 1, 2, 2]);
+
+    // Neutrals between text of different directions take the paragraph's direction.
+    assert_eq!(resolve_levels("a, א", 1), ~[2u8, 1, 1, 1]);
+
+    // Trailing whitespace goes back to the paragraph's level.
+    assert_eq!(resolve_levels("א ", 0), ~[1u8, 0]);
+
+    // Objects such as images are neutrals, so one between right-to-left letters goes with them.
+    assert_eq!(resolve_levels("א\ufffcב", 0), ~[1u8, 1, 1]);
+}
+
+#[test]
+fn test_paragraph_level() {
+    assert_eq!(paragraph_level("12 א b"), Some(1));
+    assert_eq!(paragraph_level("(b) א"), Some(0));
+    assert_eq!(paragraph_level("12 ."), None);
+}
+
+#[test]
+fn test_visual_order() {
+    assert_eq!(visual_order([0, 0, 0]), ~[0u, 1, 2]);
+    assert_eq!(visual_order([1, 1, 1]), ~[2u, 1, 0]);
+    assert_eq!(visual_order([0, 1, 1, 0]), ~[0u, 2, 1, 3]);
+
+    // Numbers within right-to-left text keep their order.
+    assert_eq!(visual_order([1, 1, 2, 2, 1]), ~[4u, 2, 3, 1, 0]);
+    assert_eq!(visual_order([]), ~[]);
+}
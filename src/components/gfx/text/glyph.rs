@@ -3,6 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use servo_util::vec::*;
+use servo_util::cache::CacheWeight;
 use servo_util::range::Range;
 
 use geometry::Au;
@@ -10,6 +11,7 @@ use geometry;
 
 use std::cmp::{Ord, Eq};
 use std::num::NumCast;
+use std::sys;
 use std::u16;
 use std::vec;
 use std::util;
@@ -89,7 +91,7 @@ impl GlyphEntry {
 pub type GlyphIndex = u32;
 
 // TODO: unify with bit flags?
-#[deriving(Eq)]
+#[deriving(Clone, Eq)]
 pub enum BreakType {
     BreakTypeNone,
     BreakTypeNormal,
@@ -186,7 +188,8 @@ impl GlyphEntry {
     }
 
     fn is_cluster_start(&self) -> bool {
-        self.has_flag(!FLAG_NOT_CLUSTER_START)
+        // Simple glyphs always start a cluster, and keep their glyph index in the flag bits.
+        self.is_simple() || !self.has_flag(FLAG_NOT_CLUSTER_START)
     }
     
     // True if original char was normal (U+0020) space. Other chars may
@@ -676,6 +679,15 @@ impl<'self> GlyphStore {
     }
 }
 
+impl CacheWeight for GlyphStore {
+    /// The bytes used by the glyphs, which is what shaped text caches are bounded by.
+    fn cache_weight(&self) -> uint {
+        self.entry_buffer.len() * sys::size_of::<GlyphEntry>() +
+            self.detail_store.detail_buffer.len() * sys::size_of::<DetailedGlyph>() +
+            self.detail_store.detail_lookup.len() * sys::size_of::<DetailedGlyphRecord>()
+    }
+}
+
 pub struct GlyphIterator<'self> {
     priv store:       &'self GlyphStore,
     priv char_index:  uint,
@@ -0,0 +1,337 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Line breaking opportunities, following the Unicode Line Breaking Algorithm (UAX #14).
+//!
+//! The pair rules LB4 to LB31 are implemented, apart from those for Hangul syllables (LB26 and
+//! LB27), regional indicators (LB30a) and emoji modifiers (LB30b). LB25 is the simple pair
+//! version that the algorithm suggests as a tailoring, rather than the regular expression for
+//! whole numbers.
+//! Mandatory breaks are reported as ordinary opportunities, since collapsed whitespace has no
+//! line feeds left in it.
+
+use text::glyph::{BreakType, BreakTypeHyphen, BreakTypeNone, BreakTypeNormal};
+
+use std::vec;
+
+/// The values of the CSS `word-break` property, which says whether lines may break within words.
+#[deriving(Clone, Eq)]
+pub enum WordBreak {
+    /// Lines break at the opportunities UAX #14 finds.
+    WordBreakNormal,
+    /// Lines may also break between any two letters.
+    WordBreakBreakAll,
+    /// Lines may not break within words, even between CJK characters.
+    WordBreakKeepAll,
+}
+
+/// The line breaking classes that the algorithm uses, from UAX #14 § 5.1.
+#[deriving(Clone, Eq)]
+pub enum LineBreakClass {
+    /// Mandatory break.
+    BK,
+    /// Carriage return.
+    CR,
+    /// Line feed.
+    LF,
+    /// Space.
+    SP,
+    /// Zero width space.
+    ZW,
+    /// Word joiner.
+    WJ,
+    /// Non-breaking glue.
+    GL,
+    /// Combining mark.
+    CM,
+    /// Opening punctuation.
+    OP,
+    /// Closing punctuation.
+    CL,
+    /// Closing parenthesis.
+    CP,
+    /// Ambiguous quotation.
+    QU,
+    /// Exclamation or interrogation.
+    EX,
+    /// Infix numeric separator.
+    IS,
+    /// Symbols allowing a break after them, such as the slash.
+    SY,
+    /// Numeric.
+    NU,
+    /// Prefix numeric, such as currency signs.
+    PR,
+    /// Postfix numeric, such as the percent sign.
+    PO,
+    /// Hyphen-minus.
+    HY,
+    /// Break after.
+    BA,
+    /// Break before.
+    BB,
+    /// Nonstarter, such as small kana.
+    NS,
+    /// Break opportunity before and after, such as the em dash.
+    B2,
+    /// Inseparable characters, such as the ellipsis.
+    IN,
+    /// Contingent break opportunity, such as the object replacement character.
+    CB,
+    /// Ideographic.
+    ID,
+    /// Hebrew letter.
+    HL,
+    /// Alphabetic and everything else.
+    AL,
+}
+
+/// Returns the line breaking class of the given character.
+///
+/// FIXME: This covers Latin, Hebrew, Arabic and CJK text along with the common punctuation; it
+/// should use the full `Line_Break` table from the Unicode Character Database.
+pub fn line_break_class(ch: char) -> LineBreakClass {
+    match ch as u32 {
+        0x0A => LF,
+        0x0D => CR,
+        0x0B | 0x0C | 0x85 | 0x2028 | 0x2029 => BK,
+        0x20 => SP,
+        0x200B => ZW,
+        0x2060 | 0xFEFF => WJ,
+        0xA0 | 0x2007 | 0x2011 | 0x202F => GL,
+        0x300 .. 0x36F | 0x483 .. 0x489 | 0x591 .. 0x5BD | 0x5BF | 0x5C1 | 0x5C2 | 0x5C4 |
+        0x5C5 | 0x5C7 | 0x610 .. 0x61A | 0x64B .. 0x65F | 0x670 | 0x6D6 .. 0x6DC |
+        0x6DF .. 0x6E4 | 0x6E7 | 0x6E8 | 0x6EA .. 0x6ED | 0x200C | 0x200D | 0x20D0 .. 0x20FF |
+        0xFE00 .. 0xFE0F => CM,
+        0x28 | 0x5B | 0x7B | 0xA1 | 0xBF | 0x201A | 0x201E | 0x3008 | 0x300A | 0x300C |
+        0x300E | 0x3010 | 0x3014 | 0x3016 | 0x3018 | 0x301A | 0xFF08 | 0xFF3B | 0xFF5B => OP,
+        0x29 | 0x5D | 0xFF09 | 0xFF3D => CP,
+        0x7D | 0x3001 | 0x3002 | 0x3009 | 0x300B | 0x300D | 0x300F | 0x3011 | 0x3015 |
+        0x3017 | 0x3019 | 0x301B | 0xFF0C | 0xFF0E | 0xFF5D => CL,
+        0x22 | 0x27 | 0xAB | 0xBB | 0x2018 | 0x2019 | 0x201B .. 0x201D | 0x201F | 0x2039 |
+        0x203A => QU,
+        0x21 | 0x3F | 0xFF01 | 0xFF1F => EX,
+        0x2C | 0x2E | 0x3A | 0x3B | 0x37E | 0x589 | 0x60C | 0x60D | 0x2044 => IS,
+        0x2F => SY,
+        0x30 .. 0x39 | 0x660 .. 0x669 | 0x66B | 0x66C | 0x6F0 .. 0x6F9 => NU,
+        0x24 | 0x2B | 0x5C | 0xA3 | 0xA5 | 0xB1 | 0x20A0 .. 0x20CF | 0x2116 | 0xFFE5 |
+        0xFFE6 => PR,
+        0x25 | 0xA2 | 0xB0 | 0x66A | 0x2030 .. 0x2037 | 0x2103 | 0x2109 | 0xFFE0 => PO,
+        0x2D => HY,
+        0x2014 | 0x2E3A | 0x2E3B => B2,
+        0x2024 .. 0x2026 | 0x22EF | 0xFE19 => IN,
+        0xFFFC => CB,
+        0x5D0 .. 0x5EA | 0x5EF .. 0x5F2 | 0xFB1D | 0xFB1F .. 0xFB28 | 0xFB2A .. 0xFB4F => HL,
+        0x09 | 0x7C | 0xAD | 0x58A | 0x1680 | 0x2000 .. 0x2006 | 0x2008 .. 0x200A | 0x2010 |
+        0x2012 | 0x2013 | 0x205F | 0x3000 => BA,
+        0xB4 | 0x2C8 | 0x2CC | 0x2DF => BB,
+        0x203C | 0x203D | 0x2047 .. 0x2049 | 0x3005 | 0x301C | 0x303B | 0x303C |
+        0x309B .. 0x309E | 0x30A0 | 0x30FB .. 0x30FE | 0xFE54 | 0xFE55 | 0xFF1A | 0xFF1B |
+        0xFF65 | 0xFF9E | 0xFF9F => NS,
+        // Small kana, which the algorithm classes as conditional Japanese starters and resolves
+        // as nonstarters.
+        0x3041 | 0x3043 | 0x3045 | 0x3047 | 0x3049 | 0x3063 | 0x3083 | 0x3085 | 0x3087 |
+        0x308E | 0x3095 | 0x3096 | 0x30A1 | 0x30A3 | 0x30A5 | 0x30A7 | 0x30A9 | 0x30C3 |
+        0x30E3 | 0x30E5 | 0x30E7 | 0x30EE | 0x30F5 | 0x30F6 | 0x31F0 .. 0x31FF |
+        0xFF67 .. 0xFF70 => NS,
+        0x2E80 .. 0x2FFF | 0x3003 .. 0x3004 | 0x3006 .. 0x3007 | 0x3012 .. 0x3013 |
+        0x3020 .. 0x303A | 0x303D .. 0x303F | 0x3040 .. 0x30FF | 0x3100 .. 0x31EF |
+        0x3200 .. 0x4DBF | 0x4E00 .. 0x9FFF | 0xA000 .. 0xA4CF | 0xAC00 .. 0xD7AF |
+        0xF900 .. 0xFAFF | 0xFE30 .. 0xFE4F | 0xFF00 .. 0xFF65 | 0xFFE0 .. 0xFFEF |
+        0x1F000 .. 0x1FAFF | 0x20000 .. 0x3FFFD => ID,
+        _ => AL,
+    }
+}
+
+/// Returns the kind of line break allowed before each character of `text`. There is never one
+/// before the first character; whether a line may break there depends on the text before it.
+/// Breaks after hyphens are reported as `BreakTypeHyphen`, and all others as `BreakTypeNormal`.
+pub fn break_opportunities(text: &str) -> ~[BreakType] {
+    let chars: ~[char] = text.iter().collect();
+
+    // LB9 and LB10: Combining marks take the class of the character they attach to, or are
+    // alphabetic if there is none.
+    let mut classes: ~[LineBreakClass] = vec::with_capacity(chars.len());
+    for &ch in chars.iter() {
+        let class = match line_break_class(ch) {
+            CM => {
+                match classes.last_opt() {
+                    Some(&prev) if !is_break_or_space(prev) => prev,
+                    _ => AL,
+                }
+            }
+            class => class,
+        };
+        classes.push(class);
+    }
+
+    let mut breaks = vec::from_elem(chars.len(), BreakTypeNone);
+    for i in range(1, chars.len()) {
+        if can_break_between(classes, i) {
+            breaks[i] = match (classes[i - 1], chars[i - 1]) {
+                (HY, _) | (_, '\u2010') | (_, '\u00ad') => BreakTypeHyphen,
+                _ => BreakTypeNormal,
+            };
+        }
+    }
+    breaks
+}
+
+fn is_break_or_space(class: LineBreakClass) -> bool {
+    match class {
+        BK | CR | LF | SP | ZW => true,
+        _ => false,
+    }
+}
+
+/// Applies the pair rules to decide whether a line may break between `classes[i - 1]` and
+/// `classes[i]`.
+fn can_break_between(classes: &[LineBreakClass], i: uint) -> bool {
+    let (before, after) = (classes[i - 1], classes[i]);
+
+    // The class of the last character before any spaces that precede the break.
+    let mut j = i;
+    while j > 0 && classes[j - 1] == SP {
+        j -= 1;
+    }
+    let before_spaces = if j > 0 { Some(classes[j - 1]) } else { None };
+
+    match (before, after) {
+        // LB4 and LB5: Always break after hard line breaks.
+        (BK, _) | (LF, _) => return true,
+        (CR, LF) => return false,
+        (CR, _) => return true,
+        // LB6 and LB7: Never break before hard line breaks, spaces or zero width spaces.
+        (_, BK) | (_, CR) | (_, LF) | (_, SP) | (_, ZW) => return false,
+        _ => {}
+    }
+
+    // LB8: Break after zero width spaces, even with spaces in between.
+    if before_spaces == Some(ZW) {
+        return true;
+    }
+
+    match (before, after) {
+        // LB11, LB12 and LB12a: Word joiners and glue hold their neighbors together.
+        (WJ, _) | (_, WJ) | (GL, _) => return false,
+        (SP, GL) | (BA, GL) | (HY, GL) => {}
+        (_, GL) => return false,
+        // LB13: Never break before closing punctuation and separators.
+        (_, CL) | (_, CP) | (_, EX) | (_, IS) | (_, SY) => return false,
+        _ => {}
+    }
+
+    // LB14 to LB17: Never break after opening punctuation, between a quote and opening
+    // punctuation, between closing punctuation and a nonstarter, or between two em dashes, even
+    // with spaces between.
+    match (before_spaces, after) {
+        (Some(OP), _) | (Some(QU), OP) | (Some(CL), NS) | (Some(CP), NS) | (Some(B2), B2) => {
+            return false
+        }
+        _ => {}
+    }
+
+    match (before, after) {
+        // LB18: Break after spaces.
+        (SP, _) => return true,
+        // LB19: Never break around quotes.
+        (QU, _) | (_, QU) => return false,
+        // LB20: Break around contingent break opportunities.
+        (CB, _) | (_, CB) => return true,
+        // LB21: Never break before hyphens, break-after characters and nonstarters, or after
+        // break-before characters.
+        (_, BA) | (_, HY) | (_, NS) | (BB, _) => return false,
+        // LB21a: Never break after a hyphen that follows a Hebrew letter.
+        (HY, _) | (BA, _) if i >= 2 && classes[i - 2] == HL => return false,
+        // LB21b: Never break between a solidus and a Hebrew letter.
+        (SY, HL) => return false,
+        // LB22: Never break before inseparable characters.
+        (_, IN) => return false,
+        _ => {}
+    }
+
+    // The remaining rules treat Hebrew letters as alphabetic.
+    match (alphabetic(before), alphabetic(after)) {
+        // LB23 and LB24: Keep letters together with numbers and their prefixes and postfixes.
+        (AL, NU) | (NU, AL) | (PR, ID) | (PR, AL) | (PO, AL) | (AL, PR) | (AL, PO) => false,
+        // LB25: Keep numbers together.
+        (CL, PO) | (CP, PO) | (CL, PR) | (CP, PR) | (NU, PO) | (NU, PR) | (PO, OP) |
+        (PO, NU) | (PR, OP) | (PR, NU) | (HY, NU) | (IS, NU) | (NU, NU) | (SY, NU) |
+        (OP, NU) => false,
+        // LB28 and LB29: Never break within words.
+        (AL, AL) | (IS, AL) => false,
+        // LB30: Never break between letters or numbers and parentheses.
+        (AL, OP) | (NU, OP) | (CP, AL) | (CP, NU) => false,
+        // LB31: Break everywhere else.
+        _ => true,
+    }
+}
+
+fn alphabetic(class: LineBreakClass) -> LineBreakClass {
+    match class {
+        HL => AL,
+        class => class,
+    }
+}
+
+#[cfg(test)]
+fn breaks_of(text: &str) -> ~[uint] {
+    let mut breaks = ~[];
+    for (i, &break_type) in break_opportunities(text).iter().enumerate() {
+        if break_type != BreakTypeNone {
+            breaks.push(i);
+        }
+    }
+    breaks
+}
+
+#[test]
+fn test_break_opportunities() {
+    // Lines break after spaces, not before them.
+    assert_eq!(breaks_of("hello  world"), ~[7u]);
+
+    // A URL breaks after its slashes, but not at its dots.
+    assert_eq!(breaks_of("http://a.b/c"), ~[7u, 11]);
+
+    // Ideographs break between each other, but not before closing punctuation.
+    assert_eq!(breaks_of("中文。字"), ~[1u, 3]);
+
+    // Numbers stay with their prefixes and postfixes, and parentheses with their contents.
+    assert_eq!(breaks_of("$10 50% (a)"), ~[4u, 8]);
+
+    // Glue and combining marks hold their neighbors together.
+    assert_eq!(breaks_of("a\u00a0b c\u0301d"), ~[4u]);
+    assert_eq!(breaks_of(""), ~[]);
+}
+
+#[test]
+fn test_break_after_hyphen() {
+    let breaks = break_opportunities("well-known -5");
+    assert!(breaks[5] == BreakTypeHyphen);
+    assert!(breaks[11] == BreakTypeNormal);
+
+    // A hyphen before a number is a minus sign.
+    assert!(breaks[12] == BreakTypeNone);
+}
+
+#[test]
+fn test_break_hebrew_and_dashes() {
+    // Hebrew letters stay together, and a hyphen after one does not break (LB21a).
+    assert_eq!(breaks_of("שלום-עולם"), ~[]);
+    assert_eq!(breaks_of("a-b"), ~[2u]);
+
+    // A solidus does not break before a Hebrew letter (LB21b).
+    assert_eq!(breaks_of("/ש"), ~[]);
+
+    // Em dashes break around themselves, but not between each other (LB17).
+    assert_eq!(breaks_of("a—b"), ~[1u, 2]);
+    assert_eq!(breaks_of("——"), ~[]);
+    assert_eq!(breaks_of("— —"), ~[]);
+
+    // Ellipses stay with what comes before them (LB22).
+    assert_eq!(breaks_of("中…"), ~[]);
+
+    // Object replacement characters break on both sides (LB20).
+    assert_eq!(breaks_of("a￼b"), ~[1u, 2]);
+}
@@ -12,7 +12,9 @@ pub use text::shaping::Shaper;
 pub use text::text_run::SendableTextRun;
 pub use text::text_run::TextRun;
 
+pub mod bidi;
 pub mod glyph;
+pub mod line_break;
 #[path="shaping/mod.rs"] pub mod shaping;
 pub mod text_run;
 pub mod util;
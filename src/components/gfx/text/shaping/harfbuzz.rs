@@ -24,7 +24,7 @@ use harfbuzz::{hb_blob_create, hb_face_create_for_tables};
 use harfbuzz::{hb_buffer_add_utf8};
 use harfbuzz::{hb_buffer_get_glyph_positions};
 use harfbuzz::{hb_buffer_set_direction};
-use harfbuzz::{hb_buffer_destroy};
+use harfbuzz::{hb_buffer_destroy, hb_buffer_reverse};
 use harfbuzz::{hb_face_destroy};
 use harfbuzz::{hb_font_create};
 use harfbuzz::{hb_font_destroy, hb_buffer_create};
@@ -36,12 +36,12 @@ use harfbuzz::{hb_font_set_funcs};
 use harfbuzz::{hb_font_set_ppem};
 use harfbuzz::{hb_font_set_scale};
 use harfbuzz::{hb_shape, hb_buffer_get_glyph_infos};
-use harfbuzz::{HB_MEMORY_MODE_READONLY, HB_DIRECTION_LTR};
+use harfbuzz::{HB_MEMORY_MODE_READONLY, HB_DIRECTION_LTR, HB_DIRECTION_RTL};
 use harfbuzz::{hb_blob_t};
 use harfbuzz::{hb_bool_t};
 use harfbuzz::{hb_face_t, hb_font_t};
 use harfbuzz::{hb_font_funcs_t, hb_buffer_t, hb_codepoint_t};
-use harfbuzz::{hb_glyph_info_t};
+use harfbuzz::{hb_feature_t, hb_glyph_info_t};
 use harfbuzz::{hb_glyph_position_t};
 use harfbuzz::{hb_position_t, hb_tag_t};
 
@@ -210,10 +210,11 @@ impl Shaper {
 impl ShaperMethods for Shaper {
     /// Calculate the layout metrics associated with the given text when rendered in a specific
     /// font.
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore) {
+    fn shape_text(&self, text: &str, rtl: bool, glyphs: &mut GlyphStore) {
         unsafe {
             let hb_buffer: *hb_buffer_t = hb_buffer_create();
-            hb_buffer_set_direction(hb_buffer, HB_DIRECTION_LTR);
+            let direction = if rtl { HB_DIRECTION_RTL } else { HB_DIRECTION_LTR };
+            hb_buffer_set_direction(hb_buffer, direction);
 
             // Using as_imm_buf because it never does a copy - we don't need the trailing null
             do text.as_imm_buf |ctext: *u8, _: uint| {
@@ -224,7 +225,24 @@ impl ShaperMethods for Shaper {
                                    text.len() as c_int);
             }
 
-            hb_shape(self.hb_font, hb_buffer, null(), 0);
+            let features = do self.font.style.features.to_settings().map |&(tag, on)| {
+                hb_feature_t {
+                    tag: tag as hb_tag_t,
+                    value: if on { 1 } else { 0 },
+                    start: 0,
+                    end: uint::max_value as c_uint,
+                }
+            };
+            hb_shape(self.hb_font,
+                     hb_buffer,
+                     vec::raw::to_ptr(features),
+                     features.len() as c_uint);
+
+            // Right-to-left glyphs come out in visual order, but the glyph store is indexed by
+            // character, so put them back in the order of the text.
+            if rtl {
+                hb_buffer_reverse(hb_buffer);
+            }
             self.save_glyph_results(text, glyphs, hb_buffer);
             hb_buffer_destroy(hb_buffer);
         }
@@ -516,7 +534,7 @@ extern fn glyph_h_advance_func(_: *hb_font_t,
 // Callback to get a font table out of a font.
 extern fn get_font_table_func(_: *hb_face_t, tag: hb_tag_t, user_data: *c_void) -> *hb_blob_t {
     unsafe {
-        let font: *Font = user_data as *Font;
+        let font: *mut Font = user_data as *mut Font;
         assert!(font.is_not_null());
 
         // TODO(Issue #197): reuse font table data, which will change the unsound trickery here.
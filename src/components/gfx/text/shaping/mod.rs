@@ -14,6 +14,8 @@ pub use Shaper = text::shaping::harfbuzz::Shaper;
 pub mod harfbuzz;
 
 pub trait ShaperMethods {
-    fn shape_text(&self, text: &str, glyphs: &mut GlyphStore);
+    /// Shapes the given text, which is right-to-left if `rtl` is set, into `glyphs`. The glyphs
+    /// are stored in logical order either way.
+    fn shape_text(&self, text: &str, rtl: bool, glyphs: &mut GlyphStore);
 }
 
@@ -2,43 +2,199 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::sys;
 use std::vec::VecIterator;
+use std::vec;
 
-use font_context::FontContext;
 use geometry::Au;
-use text::glyph::GlyphStore;
-use font::{Font, FontDescriptor, RunMetrics};
+use text::bidi;
+use text::glyph::{BreakType, BreakTypeHyphen, BreakTypeNone, GlyphStore};
+#[cfg(test)]
+use text::glyph::GlyphData;
+use text::line_break;
+use text::line_break::{WordBreak, WordBreakBreakAll, WordBreakKeepAll, WordBreakNormal};
+use font::{Font, RunMetrics, SharedFont};
+use servo_util::cache::CacheWeight;
 use servo_util::range::Range;
 use extra::arc::Arc;
 
 /// A text run.
 pub struct TextRun {
-    text: ~str,
+    /// The first font of the run, whose metrics are used for the run as a whole.
     font: @mut Font,
-    underline: bool,
-    glyphs: ~[Arc<GlyphStore>],
+    /// The fonts the slices of the run are shaped with. The first is always `font`; the others
+    /// are fallbacks for characters it has no glyphs for.
+    fonts: ~[@mut Font],
+    /// The text and its glyphs, which are shared with the display items that draw the run.
+    priv sendable: Arc<SendableTextRun>,
 }
 
-/// This is a hack until TextRuns are normally sendable, or we instead use Arc<TextRun> everywhere.
+/// The parts of a text run that do not belong to the task that shaped it: the text, its glyphs,
+/// and the shared forms of the fonts they were shaped with. Layout shares one with every display
+/// item drawing part of the run, and render tasks draw from it without copying it.
 pub struct SendableTextRun {
     text: ~str,
-    font: FontDescriptor,
-    underline: bool,
+    fonts: ~[Arc<SharedFont>],
     priv glyphs: ~[Arc<GlyphStore>],
+    /// The index into `fonts` of the font each slice in `glyphs` was shaped with.
+    priv slice_fonts: ~[uint],
+    /// The bidirectional embedding level of each slice in `glyphs`. Slices at odd levels are
+    /// right-to-left.
+    priv slice_levels: ~[u8],
+    /// The kind of line break allowed before each slice in `glyphs`. Breaks within a slice are
+    /// recorded in its glyph store.
+    priv slice_breaks: ~[BreakType],
 }
 
-impl SendableTextRun {
-    pub fn deserialize(&self, fctx: @mut FontContext) -> TextRun {
-        let font = match fctx.get_font_by_descriptor(&self.font) {
-            Ok(f) => f,
-            Err(_) => fail!(fmt!("Font descriptor deserialization failed! desc=%?", self.font))
-        };
+impl<'self> SendableTextRun {
+    pub fn char_len(&self) -> uint {
+        do self.glyphs.iter().fold(0u) |len, slice_glyphs| {
+            len + slice_glyphs.get().char_len()
+        }
+    }
 
-        TextRun {
-            text: self.text.clone(),
-            font: font,
-            underline: self.underline,
-            glyphs: self.glyphs.clone(),
+    pub fn glyphs(&'self self) -> &'self ~[Arc<GlyphStore>] { &self.glyphs }
+
+    /// Returns an estimate of the memory the run owns, in bytes, glyphs included. The glyph stores
+    /// may also be held by the shape cache.
+    pub fn memory_size(&self) -> uint {
+        let mut size = sys::size_of::<SendableTextRun>() + self.text.len() +
+            self.fonts.len() * sys::size_of::<Arc<SharedFont>>() +
+            self.glyphs.len() * (sys::size_of::<Arc<GlyphStore>>() + sys::size_of::<uint>() +
+                                 sys::size_of::<u8>() + sys::size_of::<BreakType>());
+        for glyphs in self.glyphs.iter() {
+            size += sys::size_of::<GlyphStore>() + glyphs.get().cache_weight();
+        }
+        size
+    }
+
+    /// Returns the parts of the given range that are drawn with a single font, in order, along
+    /// with the index of that font in `fonts`.
+    pub fn font_ranges_for_range(&self, range: &Range) -> ~[(uint, Range)] {
+        group_slices(self.glyphs, self.slice_fonts, range)
+    }
+
+    /// Returns the parts of the given range that are at a single bidirectional embedding level,
+    /// in logical order, along with that level.
+    pub fn level_ranges_for_range(&self, range: &Range) -> ~[(u8, Range)] {
+        group_slices(self.glyphs, self.slice_levels, range)
+    }
+
+    /// Returns the parts of the given range that are at a single bidirectional embedding level,
+    /// in the order they are displayed from left to right, and whether each is right-to-left.
+    pub fn visual_ranges_for_range(&self, range: &Range) -> ~[(Range, bool)] {
+        let level_ranges = self.level_ranges_for_range(range);
+        let levels = level_ranges.map(|&(level, _)| level);
+        do bidi::visual_order(levels).iter().map |&i| {
+            let (level, range) = level_ranges[i];
+            (range, level % 2 == 1)
+        }.collect()
+    }
+
+    /// Divides the given range at the places a line may break under the given `word-break` rule.
+    /// Each piece is returned as the text that has to stay on one line, which is empty if the
+    /// range starts with whitespace, and the whitespace after it, which may hang off the end of
+    /// a line.
+    ///
+    /// With `keep-all`, lines only break at whitespace and after hyphens; this also keeps CJK
+    /// punctuation with the text after it.
+    pub fn break_pieces_for_range(&self, range: &Range, word_break: WordBreak)
+                                  -> ~[(Range, Range)] {
+        let mut pieces = ~[];
+        let mut content = Range::new(range.begin(), 0);
+        let mut whitespace = Range::new(range.begin(), 0);
+        let mut offset = 0;
+        let slices = self.glyphs.iter().zip(self.slice_breaks.iter());
+        for (slice_glyphs, &slice_break) in slices {
+            let glyphs = slice_glyphs.get();
+            let slice_range = Range::new(offset, glyphs.char_len());
+            offset += glyphs.char_len();
+
+            let char_range = range.intersect(&slice_range);
+            if char_range.is_empty() {
+                loop
+            }
+            if glyphs.is_whitespace() {
+                whitespace.extend_to(char_range.end());
+                loop
+            }
+
+            for i in char_range.eachi() {
+                let slice_i = i - slice_range.begin();
+                let can_break = if i == range.begin() {
+                    false
+                } else if !whitespace.is_empty() {
+                    true
+                } else {
+                    let break_type = if slice_i == 0 {
+                        slice_break
+                    } else {
+                        glyphs.can_break_before(slice_i)
+                    };
+                    match word_break {
+                        WordBreakNormal => break_type != BreakTypeNone,
+                        WordBreakBreakAll => {
+                            break_type != BreakTypeNone || glyphs.is_cluster_start(slice_i)
+                        }
+                        WordBreakKeepAll => break_type == BreakTypeHyphen,
+                    }
+                };
+
+                if can_break {
+                    pieces.push((content, whitespace));
+                    content = Range::new(i, 0);
+                }
+                content.extend_by(1);
+                whitespace = Range::new(content.end(), 0);
+            }
+        }
+        pieces.push((content, whitespace));
+        pieces
+    }
+
+    /// Returns where to end the longest part of the given range that starts at its beginning,
+    /// ends between two clusters and is no wider than `max_width`. At least one cluster is
+    /// included however narrow `max_width` is, so that the result is past the range's start.
+    pub fn cluster_break_for_width(&self, range: &Range, max_width: Au) -> uint {
+        let mut width = Au(0);
+        let mut last_fit = None;
+        for (glyphs, offset, slice_range) in self.iter_slices_for_range(range) {
+            for i in slice_range.eachi() {
+                if offset + i > range.begin() && glyphs.is_cluster_start(i) {
+                    if width > max_width {
+                        return last_fit.unwrap_or(offset + i);
+                    }
+                    last_fit = Some(offset + i);
+                }
+                for (_, glyph) in glyphs.iter_glyphs_for_char_index(i) {
+                    width = width + glyph.advance();
+                }
+            }
+        }
+
+        if width > max_width && last_fit.is_some() {
+            last_fit.unwrap()
+        } else {
+            range.end()
+        }
+    }
+
+    /// The sum of the advances of the glyphs of the given range.
+    pub fn advance_for_range(&self, range: &Range) -> Au {
+        let mut advance = Au(0);
+        for (glyphs, _offset, slice_range) in self.iter_slices_for_range(range) {
+            for (_i, glyph) in glyphs.iter_glyphs_for_char_range(&slice_range) {
+                advance = advance + glyph.advance();
+            }
+        }
+        advance
+    }
+
+    pub fn iter_slices_for_range(&'self self, range: &Range) -> SliceIterator<'self> {
+        SliceIterator {
+            glyph_iter: self.glyphs.iter(),
+            range:      *range,
+            offset:     0,
         }
     }
 }
@@ -116,93 +272,80 @@ impl<'self> Iterator<Range> for LineIterator<'self> {
 }
 
 impl<'self> TextRun {
-    pub fn new(font: @mut Font, text: ~str, underline: bool) -> TextRun {
-        let glyphs = TextRun::break_and_shape(font, text);
-
-        let run = TextRun {
-            text: text,
-            font: font,
-            underline: underline,
-            glyphs: glyphs,
-        };
-        return run;
+    /// Creates a run of left-to-right text that is a paragraph of its own.
+    pub fn new(font: @mut Font, text: ~str) -> TextRun {
+        let char_fonts = vec::from_elem(text.char_len(), 0u);
+        let char_levels = bidi::resolve_levels(text, 0);
+        TextRun::new_with_fallback(~[font], text, char_fonts, char_levels)
     }
 
-    pub fn teardown(&self) {
-        self.font.teardown();
-    }
-
-    pub fn break_and_shape(font: @mut Font, text: &str) -> ~[Arc<GlyphStore>] {
-        // TODO(Issue #230): do a better job. See Gecko's LineBreaker.
+    /// Creates a run whose characters are shaped with the given fonts. `char_fonts` holds the
+    /// index into `fonts` of the font for each character of `text`, and `char_levels` its
+    /// bidirectional embedding level, resolved over the whole paragraph the run is part of.
+    pub fn new_with_fallback(fonts: ~[@mut Font],
+                             text: ~str,
+                             char_fonts: &[uint],
+                             char_levels: &[u8])
+                             -> TextRun {
+        assert!(fonts.len() > 0);
+        assert!(char_fonts.len() == text.char_len());
+        assert!(char_levels.len() == text.char_len());
 
+        let char_breaks = line_break::break_opportunities(text);
         let mut glyphs = ~[];
-        let mut byte_i = 0u;
-        let mut cur_slice_is_whitespace = false;
-        let mut byte_last_boundary = 0;
-        while byte_i < text.len() {
-            let range = text.char_range_at(byte_i);
-            let ch = range.ch;
-            let next = range.next;
-
-            // Slices alternate between whitespace and non-whitespace,
-            // representing line break opportunities.
-            let can_break_before = if cur_slice_is_whitespace {
-                match ch {
-                    ' ' | '\t' | '\n' => false,
-                    _ => {
-                        cur_slice_is_whitespace = false;
-                        true
-                    }
-                }
-            } else {
-                match ch {
-                    ' ' | '\t' | '\n' => {
-                        cur_slice_is_whitespace = true;
-                        true
-                    },
-                    _ => false
-                }
-            };
-
-            // Create a glyph store for this slice if it's nonempty.
-            if can_break_before && byte_i > byte_last_boundary {
-                let slice = text.slice(byte_last_boundary, byte_i).to_owned();
-                debug!("creating glyph store for slice %? (ws? %?), %? - %? in run %?",
-                        slice, !cur_slice_is_whitespace, byte_last_boundary, byte_i, text);
-                glyphs.push(font.shape_text(slice, !cur_slice_is_whitespace));
-                byte_last_boundary = byte_i;
-            }
+        let mut slice_fonts = ~[];
+        let mut slice_levels = ~[];
+        let mut slice_breaks = ~[];
+        let mut char_offset = 0;
+        for slice in split_into_slices(text, char_fonts, char_levels).iter() {
+            let slice_text = text.slice(slice.byte_range.begin(), slice.byte_range.end());
+            debug!("creating glyph store for slice %? (ws? %?, font %u, level %u) in run %?",
+                   slice_text, slice.is_whitespace, slice.font, slice.level as uint, text);
+            glyphs.push(fonts[slice.font].shape_text(slice_text.to_owned(),
+                                                     slice.is_whitespace,
+                                                     slice.level % 2 == 1));
+            slice_fonts.push(slice.font);
+            slice_levels.push(slice.level);
+            slice_breaks.push(char_breaks[char_offset]);
+            char_offset += glyphs.last().get().char_len();
+        }
 
-            byte_i = next;
+        let sendable = SendableTextRun {
+            text: text,
+            fonts: fonts.map(|font| font.shared()),
+            glyphs: glyphs,
+            slice_fonts: slice_fonts,
+            slice_levels: slice_levels,
+            slice_breaks: slice_breaks,
+        };
+        TextRun {
+            font: fonts[0],
+            fonts: fonts,
+            sendable: Arc::new(sendable),
         }
+    }
 
-        // Create a glyph store for the final slice if it's nonempty.
-        if byte_i > byte_last_boundary {
-            let slice = text.slice(byte_last_boundary, text.len()).to_owned();
-            debug!("creating glyph store for final slice %? (ws? %?), %? - %? in run %?",
-                slice, cur_slice_is_whitespace, byte_last_boundary, text.len(), text);
-            glyphs.push(font.shape_text(slice, cur_slice_is_whitespace));
+    pub fn teardown(&self) {
+        for font in self.fonts.iter() {
+            font.teardown();
         }
+    }
 
-        glyphs
+    /// Returns the parts of the run that display items need to draw it, shared rather than
+    /// copied.
+    pub fn sendable(&self) -> Arc<SendableTextRun> {
+        self.sendable.clone()
     }
 
-    pub fn serialize(&self) -> SendableTextRun {
-        SendableTextRun {
-            text: self.text.clone(),
-            font: self.font.get_descriptor(),
-            underline: self.underline,
-            glyphs: self.glyphs.clone(),
-        }
+    pub fn text(&'self self) -> &'self str {
+        self.sendable.get().text.as_slice()
     }
 
     pub fn char_len(&self) -> uint {
-        do self.glyphs.iter().fold(0u) |len, slice_glyphs| {
-            len + slice_glyphs.get().char_len()
-        }
+        self.sendable.get().char_len()
     }
 
-    pub fn glyphs(&'self self) -> &'self ~[Arc<GlyphStore>] { &self.glyphs }
+    pub fn glyphs(&'self self) -> &'self ~[Arc<GlyphStore>] { self.sendable.get().glyphs() }
 
     pub fn range_is_trimmable_whitespace(&self, range: &Range) -> bool {
         for (slice_glyphs, _, _) in self.iter_slices_for_range(range) {
@@ -212,30 +355,54 @@ impl<'self> TextRun {
     }
 
     pub fn metrics_for_range(&self, range: &Range) -> RunMetrics {
-        self.font.measure_text(self, range)
+        self.font.measure_text(self.sendable.get(), range)
     }
 
     pub fn metrics_for_slice(&self, glyphs: &GlyphStore, slice_range: &Range) -> RunMetrics {
         self.font.measure_text_for_slice(glyphs, slice_range)
     }
 
-    pub fn min_width_for_range(&self, range: &Range) -> Au {
+    /// Returns the width of the widest part of the given range that cannot be broken across
+    /// lines under the given `word-break` rule.
+    pub fn min_width_for_range(&self, range: &Range, word_break: WordBreak) -> Au {
         let mut max_piece_width = Au(0);
         debug!("iterating outer range %?", range);
-        for (glyphs, offset, slice_range) in self.iter_slices_for_range(range) {
-            debug!("iterated on %?[%?]", offset, slice_range);
-            let metrics = self.font.measure_text_for_slice(glyphs, &slice_range);
+        for &(content, _) in self.break_pieces_for_range(range, word_break).iter() {
+            debug!("iterated on unbreakable piece %?", content);
+            let metrics = self.metrics_for_range(&content);
             max_piece_width = Au::max(max_piece_width, metrics.advance_width);
         }
         max_piece_width
     }
 
+    pub fn break_pieces_for_range(&self, range: &Range, word_break: WordBreak)
+                                  -> ~[(Range, Range)] {
+        self.sendable.get().break_pieces_for_range(range, word_break)
+    }
+
+    pub fn cluster_break_for_width(&self, range: &Range, max_width: Au) -> uint {
+        self.sendable.get().cluster_break_for_width(range, max_width)
+    }
+
+    /// Returns the parts of the given range that are drawn with a single font, in order, along
+    /// with that font.
+    pub fn font_ranges_for_range(&self, range: &Range) -> ~[(@mut Font, Range)] {
+        let font_ranges = self.sendable.get().font_ranges_for_range(range);
+        do font_ranges.move_iter().map |(font, range)| {
+            (self.fonts[font], range)
+        }.collect()
+    }
+
+    pub fn level_ranges_for_range(&self, range: &Range) -> ~[(u8, Range)] {
+        self.sendable.get().level_ranges_for_range(range)
+    }
+
+    pub fn visual_ranges_for_range(&self, range: &Range) -> ~[(Range, bool)] {
+        self.sendable.get().visual_ranges_for_range(range)
+    }
+
     pub fn iter_slices_for_range(&'self self, range: &Range) -> SliceIterator<'self> {
-        SliceIterator {
-            glyph_iter: self.glyphs.iter(),
-            range:      *range,
-            offset:     0,
-        }
+        self.sendable.get().iter_slices_for_range(range)
     }
 
     pub fn iter_natural_lines_for_range(&'self self, range: &Range) -> LineIterator<'self> {
@@ -246,3 +413,207 @@ impl<'self> TextRun {
         }
     }
 }
+
+/// Merges the adjacent slices that fall within `range` and share a value in `slice_values`,
+/// returning each group's value and character range in order.
+fn group_slices<T: Eq + Clone>(glyphs: &[Arc<GlyphStore>], slice_values: &[T], range: &Range)
+                               -> ~[(T, Range)] {
+    let mut values: ~[T] = ~[];
+    let mut ranges: ~[Range] = ~[];
+    let mut offset = 0;
+    for (slice_glyphs, value) in glyphs.iter().zip(slice_values.iter()) {
+        let slice_range = Range::new(offset, slice_glyphs.get().char_len());
+        offset += slice_range.length();
+
+        let char_range = range.intersect(&slice_range);
+        if char_range.is_empty() {
+            loop
+        }
+        if !values.is_empty() && values.last() == value {
+            let last = ranges.len() - 1;
+            ranges[last].extend_to(char_range.end());
+        } else {
+            values.push(value.clone());
+            ranges.push(char_range);
+        }
+    }
+    values.move_iter().zip(ranges.move_iter()).collect()
+}
+
+/// A part of a run that is shaped as a unit.
+#[deriving(Eq)]
+struct TextSlice {
+    byte_range: Range,
+    is_whitespace: bool,
+    /// The index of the font the slice is shaped with.
+    font: uint,
+    /// The bidirectional embedding level of the slice.
+    level: u8,
+}
+
+/// Splits text into slices that alternate between whitespace and non-whitespace, representing
+/// line break opportunities. A slice also ends wherever the font or the embedding level changes,
+/// so that each slice is shaped in a single direction.
+fn split_into_slices(text: &str, char_fonts: &[uint], char_levels: &[u8]) -> ~[TextSlice] {
+    // TODO(Issue #230): do a better job. See Gecko's LineBreaker.
+    let mut slices = ~[];
+    let mut cur_slice_is_whitespace = false;
+    let mut cur_font = 0;
+    let mut cur_level = 0;
+    let mut byte_last_boundary = 0;
+    for (char_i, (byte_i, ch)) in text.char_offset_iter().enumerate() {
+        let is_whitespace = match ch {
+            ' ' | '\t' | '\n' => true,
+            _ => false
+        };
+        let font = char_fonts[char_i];
+        let level = char_levels[char_i];
+
+        // Create a slice for the preceding characters if it's nonempty.
+        if (is_whitespace != cur_slice_is_whitespace || font != cur_font || level != cur_level) &&
+                byte_i > byte_last_boundary {
+            slices.push(TextSlice {
+                byte_range: Range::new(byte_last_boundary, byte_i - byte_last_boundary),
+                is_whitespace: cur_slice_is_whitespace,
+                font: cur_font,
+                level: cur_level,
+            });
+            byte_last_boundary = byte_i;
+        }
+        cur_slice_is_whitespace = is_whitespace;
+        cur_font = font;
+        cur_level = level;
+    }
+
+    // Create a slice for the final characters if it's nonempty.
+    if text.len() > byte_last_boundary {
+        slices.push(TextSlice {
+            byte_range: Range::new(byte_last_boundary, text.len() - byte_last_boundary),
+            is_whitespace: cur_slice_is_whitespace,
+            font: cur_font,
+            level: cur_level,
+        });
+    }
+
+    slices
+}
+
+#[test]
+fn test_split_into_slices() {
+    let slice = |begin: uint, length: uint, is_whitespace: bool, font: uint, level: u8| {
+        TextSlice {
+            byte_range: Range::new(begin, length),
+            is_whitespace: is_whitespace,
+            font: font,
+            level: level,
+        }
+    };
+
+    // Words and the spaces between them are separate slices.
+    assert_eq!(split_into_slices("ab  c", [0, 0, 0, 0, 0], [0, 0, 0, 0, 0]),
+               ~[slice(0, 2, false, 0, 0), slice(2, 2, true, 0, 0), slice(4, 1, false, 0, 0)]);
+
+    // A change of font within a word splits it. The second character takes three bytes.
+    assert_eq!(split_into_slices("a\u4e2db c", [0, 1, 0, 0, 0], [0, 0, 0, 0, 0]),
+               ~[slice(0, 1, false, 0, 0), slice(1, 3, false, 1, 0), slice(4, 1, false, 0, 0),
+                 slice(5, 1, true, 0, 0), slice(6, 1, false, 0, 0)]);
+
+    // So does a change of direction. Hebrew letters take two bytes each.
+    assert_eq!(split_into_slices("a\u05d0\u05d1", [0, 0, 0], [0, 1, 1]),
+               ~[slice(0, 1, false, 0, 0), slice(1, 4, false, 0, 1)]);
+
+    assert_eq!(split_into_slices("", [], []), ~[]);
+}
+
+// Builds the sendable part of a run of `text` at level 0 in which each character is a glyph ten
+// pixels wide, except for combining marks, which have no glyphs and join the cluster before them.
+#[cfg(test)]
+pub fn sendable_run_for_test(text: &str) -> SendableTextRun {
+    let char_count = text.char_len();
+    let char_breaks = line_break::break_opportunities(text);
+    let slices = split_into_slices(text,
+                                   vec::from_elem(char_count, 0u),
+                                   vec::from_elem(char_count, 0u8));
+    let mut glyphs = ~[];
+    let mut slice_breaks = ~[];
+    let mut char_offset = 0;
+    for slice in slices.iter() {
+        let slice_text = text.slice(slice.byte_range.begin(), slice.byte_range.end());
+        let mut store = GlyphStore::new(slice_text.char_len(), slice.is_whitespace);
+        for (i, ch) in slice_text.iter().enumerate() {
+            if i > 0 && line_break::line_break_class(ch) == line_break::CM {
+                store.add_nonglyph_for_char_index(i, false, false);
+            } else {
+                let glyph = GlyphData::new(1, Au::from_px(10), None, false, true, true);
+                store.add_glyph_for_char_index(i, &glyph);
+            }
+            if i > 0 && char_breaks[char_offset + i] != BreakTypeNone {
+                store.set_can_break_before(i, char_breaks[char_offset + i]);
+            }
+        }
+        store.finalize_changes();
+        slice_breaks.push(char_breaks[char_offset]);
+        char_offset += store.char_len();
+        glyphs.push(Arc::new(store));
+    }
+
+    SendableTextRun {
+        text: text.to_owned(),
+        fonts: ~[],
+        slice_fonts: vec::from_elem(glyphs.len(), 0u),
+        slice_levels: vec::from_elem(glyphs.len(), 0u8),
+        glyphs: glyphs,
+        slice_breaks: slice_breaks,
+    }
+}
+
+#[test]
+fn test_break_pieces_for_range() {
+    let pieces = |text: &str, begin: uint, length: uint, word_break: WordBreak| {
+        let run = sendable_run_for_test(text);
+        do run.break_pieces_for_range(&Range::new(begin, length), word_break).map
+                |&(content, whitespace)| {
+            (content.begin(), content.length(), whitespace.begin(), whitespace.length())
+        }
+    };
+
+    // Lines break after spaces and hyphens, with the spaces hanging off the piece before them.
+    let expected = ~[(0u, 2u, 2u, 1u), (3, 3, 6, 0), (6, 2, 8, 0)];
+    assert_eq!(pieces("ab cd-ef", 0, 8, WordBreakNormal), expected.clone());
+    assert_eq!(pieces("ab cd-ef", 0, 8, WordBreakKeepAll), expected.clone());
+    assert_eq!(pieces("ab cd-ef", 0, 8, WordBreakBreakAll),
+               ~[(0u, 1u, 1u, 0u), (1, 1, 2, 1), (3, 1, 4, 0), (4, 1, 5, 0), (5, 1, 6, 0),
+                 (6, 1, 7, 0), (7, 1, 8, 0)]);
+
+    // A range starting with whitespace has an empty first piece.
+    assert_eq!(pieces("ab cd-ef", 2, 6, WordBreakNormal),
+               ~[(2u, 0u, 2u, 1u), (3, 3, 6, 0), (6, 2, 8, 0)]);
+
+    // Ideographs break between each other, except with `keep-all`.
+    assert_eq!(pieces("中文 字", 0, 4, WordBreakNormal),
+               ~[(0u, 1u, 1u, 0u), (1, 1, 2, 1), (3, 1, 4, 0)]);
+    assert_eq!(pieces("中文 字", 0, 4, WordBreakKeepAll), ~[(0u, 2u, 2u, 1u), (3, 1, 4, 0)]);
+
+    // `break-all` never separates a combining mark from its base.
+    assert_eq!(pieces("ae\u0301", 0, 3, WordBreakNormal), ~[(0u, 3u, 3u, 0u)]);
+    assert_eq!(pieces("ae\u0301", 0, 3, WordBreakBreakAll), ~[(0u, 1u, 1u, 0u), (1, 2, 3, 0)]);
+}
+
+#[test]
+fn test_cluster_break_for_width() {
+    let run = sendable_run_for_test("abcd");
+    let range = Range::new(0, 4);
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(25)), 2);
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(30)), 3);
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(40)), 4);
+
+    // At least one cluster is included, however narrow the width.
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(5)), 1);
+    assert_eq!(run.cluster_break_for_width(&Range::new(1, 3), Au::from_px(15)), 2);
+
+    // A combining mark is never separated from its base.
+    let run = sendable_run_for_test("ae\u0301b");
+    let range = Range::new(0, 4);
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(15)), 1);
+    assert_eq!(run.cluster_break_for_width(&range, Au::from_px(25)), 3);
+}
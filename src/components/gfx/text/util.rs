@@ -2,6 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::unicode::general_category;
+
 #[deriving(Eq)]
 enum CompressionMode {
     CompressNone,
@@ -117,11 +119,57 @@ pub fn true_type_tag(a: char, b: char, c: char, d: char) -> u32 {
     (a << 24 | b << 16 | c << 8 | d) as u32
 }
 
+/// Whether the character is drawn with the character before it: combining marks, joiners,
+/// variation selectors, emoji modifiers and tag characters.
+pub fn extends_cluster(ch: char) -> bool {
+    general_category::Mn(ch) || general_category::Mc(ch) || general_category::Me(ch) ||
+        is_default_ignorable(ch) || (ch as u32 >= 0x1F3FB && ch as u32 <= 0x1F3FF)
+}
+
+/// Whether the character selects or joins glyphs rather than having one of its own, so that fonts
+/// need not cover it.
+pub fn is_default_ignorable(ch: char) -> bool {
+    match ch as u32 {
+        0x200C | 0x200D | 0xFE00 .. 0xFE0F | 0xE0020 .. 0xE007F | 0xE0100 .. 0xE01EF => true,
+        _ => false,
+    }
+}
+
+/// Returns the number of characters in each cluster of the text, in order. A cluster is a base
+/// character followed by the characters that extend it; a zero-width joiner also joins the
+/// character after it, as in emoji sequences.
+pub fn cluster_lengths(text: &str) -> ~[uint] {
+    let mut lengths: ~[uint] = ~[];
+    let mut prev = None;
+    for ch in text.iter() {
+        if lengths.is_empty() || !(extends_cluster(ch) || prev == Some('\u200d')) {
+            lengths.push(1);
+        } else {
+            lengths[lengths.len() - 1] += 1;
+        }
+        prev = Some(ch);
+    }
+    lengths
+}
+
 #[test]
 fn test_true_type_tag() {
     assert!(true_type_tag('c', 'm', 'a', 'p') == 0x_63_6D_61_70_u32);
 }
 
+#[test]
+fn test_cluster_lengths() {
+    // a combining acute accent stays with its base
+    assert!(cluster_lengths("e\u0301a") == ~[2, 1]);
+    // a variation selector and an emoji modifier stay with their base
+    assert!(cluster_lengths("\u2764\ufe0fx\U0001f44d\U0001f3fd") == ~[2, 1, 2]);
+    // a zero-width joiner joins the characters on both sides
+    assert!(cluster_lengths("\U0001f469\u200d\U0001f4bb!") == ~[3, 1]);
+    // a leading mark has no base and starts a cluster of its own
+    assert!(cluster_lengths("\u0301a") == ~[1, 1]);
+    assert!(cluster_lengths("") == ~[]);
+}
+
 #[test]
 fn test_transform_compress_none() {
 
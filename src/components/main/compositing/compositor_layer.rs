@@ -6,15 +6,18 @@ use geom::point::Point2D;
 use geom::size::Size2D;
 use geom::rect::Rect;
 use geom::matrix::identity;
-use gfx::render_task::ReRenderMsg;
+use gfx::render_task::{BufferRequest, PrefetchMsg, ReRenderMsg};
 use servo_msg::compositor_msg::{LayerBuffer, LayerBufferSet};
 use servo_msg::constellation_msg::PipelineId;
 use script::dom::event::{ClickEvent, MouseDownEvent, MouseUpEvent};
 use script::script_task::SendEventMsg;
 use windowing::{MouseWindowEvent, MouseWindowClickEvent, MouseWindowMouseDownEvent, MouseWindowMouseUpEvent};
 use compositing::quadtree::{Quadtree, Invalid};
+use compositing::headless::SoftwareCanvas;
+use azure::azure_hl::SourceSurfaceMethods;
 use layers::layers::{ContainerLayerKind, ContainerLayer, TextureLayerKind, TextureLayer, TextureManager};
 use pipeline::Pipeline;
+use extra::sort::merge_sort;
 
 /// The CompositorLayer represents an element on a page that has a unique scroll
 /// or animation behavior. This can include absolute positioned elements, iframes, etc.
@@ -29,6 +32,9 @@ pub struct CompositorLayer {
     /// The offset of the page due to scrolling. (0,0) is when the window sees the
     /// top left corner of the page.
     scroll_offset: Point2D<f32>,
+    /// The direction in which the part of the page in view last moved, in page coordinates.
+    /// Tiles ahead of the viewport in this direction are requested before tiles behind it.
+    scroll_direction: Point2D<f32>,
     /// This layer's children. These could be iframes or any element which
     /// differs in scroll behavior from its parent. Each is associated with a
     /// ContainerLayer which determines its position relative to its parent and
@@ -68,6 +74,7 @@ impl CompositorLayer {
             pipeline: pipeline,
             page_size: page_size,
             scroll_offset: Point2D(0f32, 0f32),
+            scroll_direction: Point2D(0f32, 0f32),
             children: ~[],
             quadtree: match page_size {
                 None => NoTree(tile_size, max_mem),
@@ -119,6 +126,7 @@ impl CompositorLayer {
         if old_origin - self.scroll_offset == Point2D(0f32, 0f32) {
             return false;
         }
+        self.scroll_direction = old_origin - self.scroll_offset;
 
         self.root_layer.common.set_transform(identity().translate(self.scroll_offset.x,
                                                                   self.scroll_offset.y,
@@ -172,6 +180,7 @@ impl CompositorLayer {
             let (request, r) = quadtree.get_tile_rects_page(rect, scale);
             redisplay = r; // workaround to make redisplay visible outside block
             if !request.is_empty() {
+                let request = prioritize_tiles(request, &rect, &self.scroll_direction);
                 self.pipeline.render_chan.send(ReRenderMsg(request, scale, self.pipeline.id.clone()));
             }
         }
@@ -202,6 +211,74 @@ impl CompositorLayer {
     }
 
 
+    // Like get_buffer_request, but asks for the tiles within `margin` of the window in page
+    // coordinates, in case they are scrolled into view. Meant for when the renderer is idle; the
+    // renderer drops these requests if others arrive first. The margin shrinks until the tiles
+    // fit in the quadtree's memory limit, and nothing is asked for if the new tiles would push the
+    // quadtree over it, so that prefetched tiles never evict the visible ones.
+    pub fn get_prefetch_request(&mut self, window_rect: Rect<f32>, scale: f32, margin: f32)
+                                -> bool {
+        let viewport = Rect(Point2D(-self.scroll_offset.x + window_rect.origin.x,
+                                    -self.scroll_offset.y + window_rect.origin.y),
+                            window_rect.size);
+        let mut redisplay = false;
+        { // block here to prevent double mutable borrow of self
+            let quadtree = match self.quadtree {
+                NoTree(_, _) => return false,
+                Tree(ref mut quadtree) => quadtree,
+            };
+            let margin = match quadtree.get_max_mem() {
+                Some(max_mem) => fit_prefetch_margin(&viewport.size, scale,
+                                                     quadtree.get_tile_size(), margin, max_mem),
+                None => margin,
+            };
+            if margin > 0.0 {
+                let origin = Point2D((viewport.origin.x - margin).max(&0.0),
+                                     (viewport.origin.y - margin).max(&0.0));
+                let rect = Rect(origin,
+                                Size2D(viewport.origin.x + viewport.size.width + margin - origin.x,
+                                       viewport.origin.y + viewport.size.height + margin - origin.y));
+                let (request, r) = quadtree.get_tile_rects_page(rect, scale);
+                redisplay = r;
+                let new_mem = request.iter().fold(0u, |mem, tile| {
+                    mem + tile.screen_rect.size.width * tile.screen_rect.size.height
+                });
+                let fits = match quadtree.get_max_mem() {
+                    Some(max_mem) => quadtree.get_mem() + new_mem <= max_mem,
+                    None => true,
+                };
+                if !fits {
+                    for tile in request.iter() {
+                        quadtree.cancel_tile_page(tile.page_rect.origin.x, tile.page_rect.origin.y);
+                    }
+                } else if !request.is_empty() {
+                    let request = prioritize_tiles(request, &viewport, &self.scroll_direction);
+                    self.pipeline.render_chan.send(PrefetchMsg(request, scale,
+                                                               self.pipeline.id.clone()));
+                }
+            }
+        }
+        if redisplay {
+            self.build_layer_tree();
+        }
+        let transform = |x: &mut CompositorLayerChild| -> bool {
+            match x.container.scissor {
+                Some(scissor) => {
+                    match window_rect.intersection(&scissor) {
+                        Some(new_rect) => x.child.get_prefetch_request(new_rect, scale, margin),
+                        None => false, // Layer is offscreen
+                    }
+                }
+                None => {
+                    fail!("CompositorLayer: Child layer not clipped");
+                }
+            }
+        };
+        self.children.mut_iter().filter(|x| !x.child.hidden)
+            .map(transform)
+            .fold(false, |a, b| a || b) || redisplay
+    }
+
     // Move the sublayer to an absolute position in page coordinates relative to its parent,
     // and clip the layer to the specified size in page coordinates.
     // This method returns false if the specified layer is not found.
@@ -367,6 +444,25 @@ impl CompositorLayer {
         }
     }
 
+    // Makes the tiles at the given page rects requestable again, after the renderer dropped the
+    // requests for them. Returns false if the layer is not found.
+    pub fn cancel_tiles(&mut self, pipeline_id: PipelineId, page_rects: &[Rect<f32>]) -> bool {
+        if self.pipeline.id == pipeline_id {
+            match self.quadtree {
+                NoTree(_, _) => {}
+                Tree(ref mut quadtree) => {
+                    for rect in page_rects.iter() {
+                        quadtree.cancel_tile_page(rect.origin.x, rect.origin.y);
+                    }
+                }
+            }
+            return true;
+        }
+        // ID does not match ours, so recurse on descendents (including hidden children).
+        self.children.mut_iter().map(|x| &mut x.child)
+            .any(|x| x.cancel_tiles(pipeline_id, page_rects))
+    }
+
     pub fn invalidate_rect(&mut self, pipeline_id: PipelineId, rect: Rect<f32>) -> bool {
         if self.pipeline.id == pipeline_id {
             let quadtree = match self.quadtree {
@@ -380,7 +476,95 @@ impl CompositorLayer {
         self.children.mut_iter().map(|x| &mut x.child).any(|x| x.invalidate_rect(pipeline_id, rect))
     }
     
-    // Adds a child.
+    // Copies the tiles of this layer and of its visible children into the canvas, with this
+    // layer's origin at `origin` in canvas pixels, drawing nothing outside `clip`. Used by the
+    // headless compositor in place of the layer tree, which needs GL to draw.
+    pub fn composite_in_software(&self,
+                                 canvas: &mut SoftwareCanvas,
+                                 origin: Point2D<f32>,
+                                 clip: Rect<f32>) {
+        let origin = origin + self.scroll_offset;
+        let pixel_clip = Rect(Point2D(clip.origin.x as int, clip.origin.y as int),
+                              Size2D(clip.size.width as int, clip.size.height as int));
+        match self.quadtree {
+            NoTree(_, _) => {}
+            Tree(ref quadtree) => {
+                for buffer in quadtree.get_all_tiles().iter() {
+                    let tile_origin = Point2D((origin.x + buffer.rect.origin.x) as int,
+                                              (origin.y + buffer.rect.origin.y) as int);
+                    let data_surface = buffer.draw_target.snapshot().get_data_surface();
+                    let stride = data_surface.stride() as uint;
+                    do data_surface.with_data |data| {
+                        canvas.draw_tile(data,
+                                         stride,
+                                         buffer.screen_pos.size,
+                                         tile_origin,
+                                         &pixel_clip);
+                    }
+                }
+            }
+        }
+
+        for child in self.children.iter().filter(|x| !x.child.hidden) {
+            match child.container.scissor {
+                Some(scissor) => {
+                    let child_origin = origin + scissor.origin;
+                    match Rect(child_origin, scissor.size).intersection(&clip) {
+                        Some(child_clip) => {
+                            child.child.composite_in_software(canvas, child_origin, child_clip)
+                        }
+                        None => {} // Layer is outside the clip
+                    }
+                }
+                None => {
+                    fail!("CompositorLayer: Child layer not clipped");
+                }
+            }
+        }
+    }
+
+    // Returns true if this layer and its visible children hold valid tiles for all of the window,
+    // as asked for by get_buffer_request.
+    pub fn has_tiles(&self, window_rect: Rect<f32>, scale: f32) -> bool {
+        let rect = Rect(Point2D(-self.scroll_offset.x + window_rect.origin.x,
+                                -self.scroll_offset.y + window_rect.origin.y),
+                        window_rect.size);
+        let has_own_tiles = match self.quadtree {
+            NoTree(_, _) => false,
+            Tree(ref quadtree) => quadtree.has_tiles_page(rect, scale),
+        };
+        has_own_tiles && self.children.iter().filter(|x| !x.child.hidden).all(|x| {
+            match x.container.scissor {
+                Some(scissor) => {
+                    match window_rect.intersection(&scissor) {
+                        Some(new_rect) => x.child.has_tiles(new_rect, scale),
+                        None => true, // Layer is offscreen
+                    }
+                }
+                None => {
+                    fail!("CompositorLayer: Child layer not clipped");
+                }
+            }
+        })
+    }
+
+    // Returns the number of tiles held by this layer and its descendents (including hidden
+    // children), along with the memory they use.
+    pub fn get_tile_mem(&self) -> (uint, uint) {
+        let (mut count, mut mem) = match self.quadtree {
+            NoTree(_, _) => (0, 0),
+            Tree(ref quadtree) => (quadtree.get_all_tiles().len(), quadtree.get_mem()),
+        };
+        for child in self.children.iter() {
+            let (child_count, child_mem) = child.child.get_tile_mem();
+            count += child_count;
+            mem += child_mem;
+        }
+        (count, mem)
+    }
+
+    // Adds a child. The child's layer is scissored to `clipping_rect`, so the content of a frame
+    // never paints outside the frame's box.
     pub fn add_child(&mut self, pipeline: Pipeline, page_size: Option<Size2D<f32>>, tile_size: uint,
                      max_mem: Option<uint>, clipping_rect: Rect<f32>) {
         let container = @mut ContainerLayer();
@@ -397,3 +581,91 @@ impl CompositorLayer {
         
     }
 }
+
+/// Returns the largest margin, no bigger than `margin` and in steps of one tile, such that the tiles
+/// covering the viewport grown by it on every side fit within `max_mem`, counted like
+/// `Tile::get_mem` counts a tile's memory. Returns 0 if no margin fits.
+pub fn fit_prefetch_margin(viewport_size: &Size2D<f32>,
+                           scale: f32,
+                           tile_size: uint,
+                           margin: f32,
+                           max_mem: uint)
+                           -> f32 {
+    // A span of `length` device pixels can straddle one more tile than it fills.
+    let tiles_across = |length: f32| -> uint {
+        (length * scale / tile_size as f32).ceil() as uint + 1
+    };
+    let step = tile_size as f32 / scale;
+    let mut margin = margin;
+    while margin > 0.0 {
+        let tiles = tiles_across(viewport_size.width + 2.0 * margin) *
+            tiles_across(viewport_size.height + 2.0 * margin);
+        if tiles * tile_size * tile_size <= max_mem {
+            return margin;
+        }
+        margin -= step;
+    }
+    0.0
+}
+
+/// Orders tile requests so that the tiles in the viewport come first, then the tiles lying in the
+/// direction the viewport is moving, then the rest. Within each group, tiles nearer the center of
+/// the viewport come first.
+pub fn prioritize_tiles(tiles: ~[BufferRequest],
+                        viewport: &Rect<f32>,
+                        scroll_direction: &Point2D<f32>)
+                        -> ~[BufferRequest] {
+    let center = Point2D(viewport.origin.x + viewport.size.width / 2.0,
+                         viewport.origin.y + viewport.size.height / 2.0);
+    let priority = |tile: &BufferRequest| -> (uint, uint, f32) {
+        let rect = &tile.page_rect;
+        let offset = Point2D(rect.origin.x + rect.size.width / 2.0 - center.x,
+                             rect.origin.y + rect.size.height / 2.0 - center.y);
+        let visible = if rect.intersects(viewport) { 0 } else { 1 };
+        let ahead = offset.x * scroll_direction.x + offset.y * scroll_direction.y > 0.0;
+        let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+        (visible, if ahead { 0 } else { 1 }, distance)
+    };
+    merge_sort(tiles, |a, b| priority(a) <= priority(b))
+}
+
+#[test]
+fn test_prioritize_tiles() {
+    fn tile(x: f32, y: f32) -> BufferRequest {
+        BufferRequest(Rect(Point2D(x as uint, y as uint), Size2D(10u, 10u)),
+                      Rect(Point2D(x, y), Size2D(10f32, 10f32)))
+    }
+
+    let viewport = Rect(Point2D(20f32, 20f32), Size2D(20f32, 20f32));
+    let tiles = ~[tile(0.0, 25.0), tile(50.0, 25.0), tile(25.0, 25.0), tile(20.0, 20.0),
+                  tile(25.0, 50.0)];
+
+    // Scrolling down, visible tiles come first, then the tile below the viewport.
+    let order = prioritize_tiles(tiles.clone(), &viewport, &Point2D(0f32, 1f32));
+    let origins: ~[Point2D<f32>] = order.iter().map(|tile| tile.page_rect.origin).collect();
+    assert!(origins == ~[Point2D(25f32, 25f32), Point2D(20f32, 20f32), Point2D(25f32, 50f32),
+                         Point2D(0f32, 25f32), Point2D(50f32, 25f32)]);
+
+    // Scrolling left, the tile to the left comes before the others that are out of view.
+    let order = prioritize_tiles(tiles, &viewport, &Point2D(-1f32, 0f32));
+    assert!(order[2].page_rect.origin == Point2D(0f32, 25f32));
+}
+
+#[test]
+fn test_fit_prefetch_margin() {
+    let viewport = Size2D(800f32, 600f32);
+
+    // Plenty of memory leaves the margin alone.
+    assert!(fit_prefetch_margin(&viewport, 1.0, 512, 256.0, 100000000) == 256.0);
+
+    // 800x600 plus 256px on each side straddles 4x4 tiles of 512px. With less room than that,
+    // the next step down is no margin at all.
+    assert!(fit_prefetch_margin(&viewport, 1.0, 512, 256.0, 16 * 512 * 512) == 256.0);
+    assert!(fit_prefetch_margin(&viewport, 1.0, 512, 256.0, 16 * 512 * 512 - 1) == 0.0);
+
+    // Zooming in makes the same margin cover more tiles.
+    assert!(fit_prefetch_margin(&viewport, 2.0, 512, 256.0, 16 * 512 * 512) == 0.0);
+
+    // Large margins shrink a tile at a time until they fit.
+    assert!(fit_prefetch_margin(&viewport, 1.0, 512, 1280.0, 6 * 5 * 512 * 512) == 256.0);
+}
@@ -0,0 +1,277 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A compositor that needs neither a window nor a GL context, for machines that have neither. It
+//! copies the tiles of each layer into an image in software, and writes the image to the output
+//! file once the page has loaded.
+
+use compositing::{CompositorTask, Exit, GetSize, GetGLContext, NewLayer, ResizeLayer};
+use compositing::{DeleteLayer, InvalidateRect, Paint, ChangeReadyState, ChangeRenderState};
+use compositing::{SetIds, ReportMemory, TilesDropped};
+use compositing::compositor_layer::CompositorLayer;
+use pipeline::Pipeline;
+
+use servo_msg::compositor_msg::{FinishedLoading, IdleRenderState, ReadyState, RenderState};
+use servo_msg::compositor_msg::Blank;
+use servo_util::memory::MemoryReport;
+use servo_util::time::profile;
+use servo_util::time;
+
+use geom::point::Point2D;
+use geom::rect::Rect;
+use geom::size::Size2D;
+use png;
+use std::num;
+use std::ptr;
+use std::vec;
+
+/// The size of the viewport, in px. This is the size the windowed compositor starts with.
+static VIEWPORT_WIDTH: uint = 800;
+static VIEWPORT_HEIGHT: uint = 600;
+
+/// An RGB image that the tiles of layers are copied into, in place of a window's framebuffer.
+pub struct SoftwareCanvas {
+    width: uint,
+    height: uint,
+    pixels: ~[u8],
+}
+
+impl SoftwareCanvas {
+    /// Creates a white canvas of the given size.
+    pub fn new(width: uint, height: uint) -> SoftwareCanvas {
+        SoftwareCanvas {
+            width: width,
+            height: height,
+            pixels: vec::from_elem(width * height * 3, 255u8),
+        }
+    }
+
+    /// Copies a tile of BGRA pixels, with rows `stride` bytes apart, so that its top left corner
+    /// lands at `origin`. Only the part within both `clip` and the canvas is copied. The renderer
+    /// clears every tile to opaque white before drawing into it, so alpha is ignored.
+    pub fn draw_tile(&mut self,
+                     data: &[u8],
+                     stride: uint,
+                     size: Size2D<uint>,
+                     origin: Point2D<int>,
+                     clip: &Rect<int>) {
+        let left = num::max(num::max(origin.x, clip.origin.x), 0);
+        let top = num::max(num::max(origin.y, clip.origin.y), 0);
+        let right = num::min(num::min(origin.x + size.width as int,
+                                      clip.origin.x + clip.size.width),
+                             self.width as int);
+        let bottom = num::min(num::min(origin.y + size.height as int,
+                                       clip.origin.y + clip.size.height),
+                              self.height as int);
+
+        for y in range(top, bottom) {
+            for x in range(left, right) {
+                let source = ((y - origin.y) as uint) * stride + ((x - origin.x) as uint) * 4;
+                let dest = ((y as uint) * self.width + (x as uint)) * 3;
+                self.pixels[dest] = data[source + 2];
+                self.pixels[dest + 1] = data[source + 1];
+                self.pixels[dest + 2] = data[source];
+            }
+        }
+    }
+
+    pub fn to_png_image(self) -> png::Image {
+        png::Image {
+            width: self.width as u32,
+            height: self.height as u32,
+            color_type: png::RGB8,
+            pixels: self.pixels,
+        }
+    }
+}
+
+impl CompositorTask {
+    /// Runs the compositor without a window, until it is asked to exit or, with `-x`, until the
+    /// page has loaded. Tiles are requested for the viewport, or for the whole page if
+    /// `--full-page` is given, at a scale of 1.
+    pub fn run_headless(&self) {
+        let mut pipeline: Option<Pipeline> = None;
+        let mut compositor_layer: Option<CompositorLayer> = None;
+        let mut ready_state: ReadyState = Blank;
+        let mut render_state: RenderState = IdleRenderState;
+        let mut done = false;
+
+        while !done {
+            match self.port.recv() {
+                Exit => done = true,
+
+                // The page may finish loading before or after the last tiles are rendered, so
+                // the output is written on whichever comes second.
+                ChangeReadyState(new_ready_state) => {
+                    ready_state = new_ready_state;
+                    done = self.write_output_if_loaded(&compositor_layer, ready_state,
+                                                       render_state);
+                }
+
+                ChangeRenderState(new_render_state) => {
+                    render_state = new_render_state;
+                    done = self.write_output_if_loaded(&compositor_layer, ready_state,
+                                                       render_state);
+                }
+
+                SetIds(frame_tree, response_chan) => {
+                    pipeline = Some(frame_tree.pipeline);
+                    response_chan.send(());
+                }
+
+                GetSize(chan) => {
+                    chan.send(Size2D(VIEWPORT_WIDTH as int, VIEWPORT_HEIGHT as int));
+                }
+
+                // There is no GL context to share, so the renderer draws in memory instead.
+                GetGLContext(chan) => chan.send(ptr::null()),
+
+                ReportMemory(chan) => {
+                    let (count, mem) = match compositor_layer {
+                        Some(ref layer) => layer.get_tile_mem(),
+                        None => (0, 0),
+                    };
+                    chan.send(~[MemoryReport::new("layer buffer tiles", count, mem)]);
+                }
+
+                NewLayer(_id, new_size) => {
+                    let p = match pipeline {
+                        Some(ref pipeline) => pipeline,
+                        None => {
+                            fail!("Compositor: Received new layer without initialized pipeline")
+                        }
+                    };
+                    let page_size = Size2D(new_size.width as f32, new_size.height as f32);
+
+                    // The whole page has to stay in memory until it is written out.
+                    let max_mem = if self.opts.output_full_page { None } else { Some(10000000u) };
+                    let mut new_layer = CompositorLayer::new(p.clone(), Some(page_size),
+                                                             self.opts.tile_size, max_mem);
+                    let rect = self.output_rect(&new_layer);
+                    new_layer.get_buffer_request(rect, 1f32);
+                    compositor_layer = Some(new_layer);
+                }
+
+                ResizeLayer(id, new_size) => {
+                    for layer in compositor_layer.mut_iter() {
+                        let viewport = Size2D(VIEWPORT_WIDTH as f32, VIEWPORT_HEIGHT as f32);
+                        assert!(layer.resize(id, Size2D(new_size.width as f32,
+                                                        new_size.height as f32),
+                                             viewport));
+                        let rect = self.output_rect(layer);
+                        layer.get_buffer_request(rect, 1f32);
+                    }
+                }
+
+                DeleteLayer(id) => {
+                    for layer in compositor_layer.mut_iter() {
+                        assert!(layer.delete(id));
+                        let rect = self.output_rect(layer);
+                        layer.get_buffer_request(rect, 1f32);
+                    }
+                }
+
+                Paint(id, new_layer_buffer_set) => {
+                    match compositor_layer {
+                        Some(ref mut layer) => {
+                            assert!(layer.add_buffers(id, new_layer_buffer_set.get()));
+                        }
+                        None => {
+                            fail!("Compositor: given paint command with no CompositorLayer \
+                                   initialized");
+                        }
+                    }
+                }
+
+                TilesDropped(id, page_rects) => {
+                    for layer in compositor_layer.mut_iter() {
+                        layer.cancel_tiles(id, page_rects);
+                    }
+                }
+
+                InvalidateRect(id, rect) => {
+                    for layer in compositor_layer.mut_iter() {
+                        layer.invalidate_rect(id, Rect(Point2D(rect.origin.x as f32,
+                                                               rect.origin.y as f32),
+                                                       Size2D(rect.size.width as f32,
+                                                              rect.size.height as f32)));
+                        let rect = self.output_rect(layer);
+                        layer.get_buffer_request(rect, 1f32);
+                    }
+                }
+            }
+        }
+
+        self.shutdown_chan.send(())
+    }
+
+    // The part of the page that is requested and written out, in px.
+    fn output_rect(&self, layer: &CompositorLayer) -> Rect<f32> {
+        let size = match layer.page_size {
+            Some(page_size) if self.opts.output_full_page => page_size,
+            _ => Size2D(VIEWPORT_WIDTH as f32, VIEWPORT_HEIGHT as f32),
+        };
+        Rect(Point2D(0f32, 0f32), size)
+    }
+
+    // Writes the output file once the page has loaded, the renderer is idle and every requested
+    // tile has arrived. Returns true if the compositor should then exit.
+    fn write_output_if_loaded(&self,
+                              compositor_layer: &Option<CompositorLayer>,
+                              ready_state: ReadyState,
+                              render_state: RenderState)
+                              -> bool {
+        if ready_state != FinishedLoading || render_state != IdleRenderState {
+            return false;
+        }
+        match *compositor_layer {
+            Some(ref layer) => {
+                let rect = self.output_rect(layer);
+                if !layer.has_tiles(rect, 1f32) {
+                    return false;
+                }
+                self.write_output_file(layer, &rect);
+                self.opts.exit_after_load
+            }
+            None => false,
+        }
+    }
+
+    // Composites the given part of the page and writes it to the output file, if there is one.
+    fn write_output_file(&self, layer: &CompositorLayer, rect: &Rect<f32>) {
+        let path = match self.opts.output_file {
+            Some(ref output_file) => Path(*output_file),
+            None => return,
+        };
+
+        let mut canvas = SoftwareCanvas::new(rect.size.width as uint, rect.size.height as uint);
+        do profile(time::CompositingCategory, self.profiler_chan.clone()) {
+            layer.composite_in_software(&mut canvas, Point2D(-rect.origin.x, -rect.origin.y),
+                                        Rect(Point2D(0f32, 0f32), rect.size));
+        }
+
+        let res = png::store_png(&canvas.to_png_image(), &path);
+        assert!(res.is_ok());
+    }
+}
+
+#[test]
+fn test_draw_tile() {
+    // A 2x2 tile of blue, green, red and black pixels, with one byte of padding per row.
+    let data = [255u8, 0, 0, 255, 0, 255, 0, 255, 0,
+                0, 0, 255, 255, 0, 0, 0, 255, 0];
+    let size = Size2D(2u, 2u);
+
+    // Tiles are converted to RGB.
+    let mut canvas = SoftwareCanvas::new(2, 2);
+    canvas.draw_tile(data, 9, size, Point2D(0, 0), &Rect(Point2D(0, 0), Size2D(2, 2)));
+    assert_eq!(canvas.pixels, ~[0u8, 0, 255, 0, 255, 0, 255, 0, 0, 0, 0, 0]);
+
+    // Parts outside the canvas or the clip are dropped.
+    let mut canvas = SoftwareCanvas::new(3, 1);
+    canvas.draw_tile(data, 9, size, Point2D(-1, 0), &Rect(Point2D(0, 0), Size2D(3, 1)));
+    assert_eq!(canvas.pixels, ~[0u8, 255, 0, 255, 255, 255, 255, 255, 255]);
+    canvas.draw_tile(data, 9, size, Point2D(1, 0), &Rect(Point2D(2, 0), Size2D(1, 1)));
+    assert_eq!(canvas.pixels, ~[0u8, 255, 0, 255, 255, 255, 0, 255, 0]);
+}
@@ -10,9 +10,10 @@ pub use windowing;
 use windowing::{ApplicationMethods, WindowEvent, WindowMethods};
 use windowing::{IdleWindowEvent, ResizeWindowEvent, LoadUrlWindowEvent, MouseWindowEventClass};
 use windowing::{ScrollWindowEvent, ZoomWindowEvent, NavigationWindowEvent, FinishedWindowEvent};
+use windowing::{MemoryReportWindowEvent};
 use windowing::{QuitWindowEvent, MouseWindowClickEvent, MouseWindowMouseDownEvent, MouseWindowMouseUpEvent};
 
-use servo_msg::compositor_msg::{RenderListener, LayerBufferSet, RenderState};
+use servo_msg::compositor_msg::{RenderListener, LayerBufferSet, RenderState, IdleRenderState};
 use servo_msg::compositor_msg::{ReadyState, ScriptListener};
 use servo_msg::constellation_msg::PipelineId;
 use servo_msg::constellation_msg;
@@ -36,6 +37,8 @@ use layers::rendergl;
 use layers::scene::Scene;
 use opengles::gl2;
 use png;
+use servo_util::memory::{MemoryProfilerChan, MemoryReport};
+use servo_util::memory;
 use servo_util::{time, url};
 use servo_util::time::profile;
 use servo_util::time::ProfilerChan;
@@ -49,6 +52,7 @@ use compositing::compositor_layer::CompositorLayer;
 
 mod quadtree;
 mod compositor_layer;
+mod headless;
 
 
 /// The implementation of the layers-based compositor.
@@ -85,6 +89,10 @@ impl RenderListener for CompositorChan {
         self.chan.send(Paint(id, layer_buffer_set))
     }
 
+    fn tiles_dropped(&self, id: PipelineId, page_rects: ~[Rect<f32>]) {
+        self.chan.send(TilesDropped(id, page_rects))
+    }
+
     fn new_layer(&self, id: PipelineId, page_size: Size2D<uint>) {
         self.chan.send(NewLayer(id, page_size))
     }
@@ -140,12 +148,17 @@ pub enum Msg {
 
     /// Requests that the compositor paint the given layer buffer set for the given page size.
     Paint(PipelineId, arc::Arc<LayerBufferSet>),
+    /// Alerts the compositor that the renderer dropped its requests for the tiles at the given
+    /// page rects.
+    TilesDropped(PipelineId, ~[Rect<f32>]),
     /// Alerts the compositor to the current status of page loading.
     ChangeReadyState(ReadyState),
     /// Alerts the compositor to the current status of rendering.
     ChangeRenderState(RenderState),
     /// Sets the channel to the current layout and render tasks, along with their id
     SetIds(SendableFrameTree, Chan<()>),
+    /// Requests measurements of the tiles held by the compositor.
+    ReportMemory(Chan<~[MemoryReport]>),
 }
 
 /// Azure surface wrapping to work with the layers infrastructure.
@@ -177,6 +190,7 @@ pub struct CompositorTask {
     opts: Opts,
     port: Port<Msg>,
     profiler_chan: ProfilerChan,
+    mem_profiler_chan: MemoryProfilerChan,
     shutdown_chan: SharedChan<()>,
 }
 
@@ -184,18 +198,24 @@ impl CompositorTask {
     pub fn new(opts: Opts,
                port: Port<Msg>,
                profiler_chan: ProfilerChan,
+               mem_profiler_chan: MemoryProfilerChan,
                shutdown_chan: Chan<()>)
                -> CompositorTask {
         CompositorTask {
             opts: opts,
             port: port,
             profiler_chan: profiler_chan,
+            mem_profiler_chan: mem_profiler_chan,
             shutdown_chan: SharedChan::new(shutdown_chan),
         }
     }
 
     /// Starts the compositor, which listens for messages on the specified port. 
     pub fn run(&self) {
+        if self.opts.headless {
+            return self.run_headless()
+        }
+
         let app: Application = ApplicationMethods::new();
         let window: @mut Window = WindowMethods::new(&app);
 
@@ -234,6 +254,21 @@ impl CompositorTask {
             }
         };
         
+        // Get speculative BufferRequests for the tiles around the window from each layer.
+        let prefetch_margin = self.opts.prefetch_margin as f32;
+        let ask_for_prefetch_tiles = || {
+            if prefetch_margin > 0.0 {
+                let window_size_page = Size2D(window_size.width as f32 / world_zoom,
+                                              window_size.height as f32 / world_zoom);
+                for layer in compositor_layer.mut_iter() {
+                    recomposite = layer.get_prefetch_request(Rect(Point2D(0f32, 0f32),
+                                                                  window_size_page),
+                                                             world_zoom,
+                                                             prefetch_margin) || recomposite;
+                }
+            }
+        };
+
         let check_for_messages: &fn(&Port<Msg>) = |port: &Port<Msg>| {
             // Handle messages
             while port.peek() {
@@ -241,7 +276,14 @@ impl CompositorTask {
                     Exit => done = true,
 
                     ChangeReadyState(ready_state) => window.set_ready_state(ready_state),
-                    ChangeRenderState(render_state) => window.set_render_state(render_state),
+                    ChangeRenderState(render_state) => {
+                        window.set_render_state(render_state);
+
+                        // Use the idle renderer to draw the tiles around the viewport.
+                        if render_state == IdleRenderState && !zoom_action {
+                            ask_for_prefetch_tiles();
+                        }
+                    }
 
                     SetIds(frame_tree, response_chan) => {
                         pipeline = Some(frame_tree.pipeline);
@@ -255,6 +297,14 @@ impl CompositorTask {
 
                     GetGLContext(chan) => chan.send(current_gl_context()),
 
+                    ReportMemory(chan) => {
+                        let (count, mem) = match compositor_layer {
+                            Some(ref layer) => layer.get_tile_mem(),
+                            None => (0, 0),
+                        };
+                        chan.send(~[MemoryReport::new("layer buffer tiles", count, mem)]);
+                    }
+
                     NewLayer(_id, new_size) => {
                         // FIXME: This should create an additional layer instead of replacing the current one.
                         // Once ResizeLayer messages are set up, we can switch to the new functionality.
@@ -319,6 +369,12 @@ impl CompositorTask {
                         // it wishes.
                     }
 
+                    TilesDropped(id, page_rects) => {
+                        for layer in compositor_layer.mut_iter() {
+                            layer.cancel_tiles(id, page_rects);
+                        }
+                    }
+
                     InvalidateRect(id, rect) => {
                         match compositor_layer {
                             Some(ref mut layer) => {
@@ -425,6 +481,10 @@ impl CompositorTask {
                     }
                 }
                 
+                MemoryReportWindowEvent => {
+                    self.mem_profiler_chan.send(memory::PrintMsg);
+                }
+
                 QuitWindowEvent => {
                     done = true;
                 }
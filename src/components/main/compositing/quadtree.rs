@@ -168,6 +168,16 @@ impl<T: Tile> Quadtree<T> {
         self.root.get_all_tiles()
     }
 
+    /// Get the total memory used by the tiles in the tree
+    pub fn get_mem(&self) -> uint {
+        self.root.tile_mem
+    }
+
+    /// Get the maximum allowed total memory of tiles in the tree, if there is one
+    pub fn get_max_mem(&self) -> Option<uint> {
+        self.max_mem
+    }
+
     /// Ask a tile to be deleted from the quadtree. This tries to delete a tile that is far from the
     /// given point in pixel coordinates.
     pub fn remove_tile_pixel(&mut self, x: uint, y: uint, scale: f32) -> T {
@@ -266,6 +276,23 @@ impl<T: Tile> Quadtree<T> {
         self.root.set_status(rect, status, include_border);
     }
 
+    /// Returns true if every part of the window, in page coordinates, is covered by a tile that is
+    /// valid at the given scale and is not waiting to be rendered again.
+    pub fn has_tiles_page(&self, window: Rect<f32>, scale: f32) -> bool {
+        let clip = Rect(Point2D(0f32, 0f32),
+                        Size2D(self.clip_size.width as f32, self.clip_size.height as f32));
+        match window.intersection(&clip) {
+            Some(window) => self.root.has_tiles(&window, scale),
+            None => true,
+        }
+    }
+
+    /// Mark the tile containing the given point in page coordinates as no longer being rendered,
+    /// so that it is requested again. Used when the renderer drops a request.
+    pub fn cancel_tile_page(&mut self, x: f32, y: f32) {
+        self.root.cancel_tile(x, y);
+    }
+
     /// Generate html to visualize the tree. For debugging purposes only.
     pub fn get_html(&self) -> ~str {
         static HEADER: &'static str = "<!DOCTYPE html><html>";
@@ -672,6 +699,56 @@ impl<T: Tile> QuadtreeNode<T> {
         }
     }
 
+    /// Returns true if the part of the window within this node is covered by valid tiles.
+    fn has_tiles(&self, window: &Rect<f32>, scale: f32) -> bool {
+        if !window.intersects(&Rect(self.origin, Size2D(self.size, self.size))) {
+            return true;
+        }
+        if self.status == Rendering || self.status == Invalid {
+            return false;
+        }
+        match self.tile {
+            Some(ref tile) if tile.is_valid(scale) => return true,
+            _ => {}
+        }
+
+        let half = self.size / 2.0;
+        let quads = [TL, TR, BL, BR];
+        for quad in quads.iter() {
+            let covered = match self.quadrants[*quad as int] {
+                Some(ref child) => child.has_tiles(window, scale),
+                None => {
+                    let x = match *quad {
+                        TL | BL => self.origin.x,
+                        TR | BR => self.origin.x + half,
+                    };
+                    let y = match *quad {
+                        TL | TR => self.origin.y,
+                        BL | BR => self.origin.y + half,
+                    };
+                    !window.intersects(&Rect(Point2D(x, y), Size2D(half, half)))
+                }
+            };
+            if !covered {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Find the node containing the point that is waiting for a tile, and reset its status.
+    fn cancel_tile(&mut self, x: f32, y: f32) {
+        if self.status == Rendering {
+            self.status = Normal;
+            return;
+        }
+        let quad = self.get_quadrant(x, y);
+        match self.quadrants[quad as int] {
+            Some(ref mut child) => child.cancel_tile(x, y),
+            None => {}
+        }
+    }
+
     /// Generate html to visualize the tree.
     /// This is really inefficient, but it's for testing only.
     fn get_html(&self) -> ~str {
@@ -782,3 +859,69 @@ pub fn test() {
     assert!(redisplay);
     assert!(q.root.tile_mem == 1);
 }
+
+#[test]
+pub fn test_cancel_tile() {
+    struct T {
+        a: int,
+    }
+
+    impl Tile for T {
+        fn get_mem(&self) -> uint {
+            1
+        }
+
+        fn is_valid(&self, _: f32) -> bool {
+            true
+        }
+    }
+
+    let mut q: Quadtree<T> = Quadtree::new(8, 8, 4, None);
+    let (request, _) = q.get_tile_rects_page(Rect(Point2D(0f32, 0f32), Size2D(8f32, 8f32)), 1f32);
+    assert!(request.len() == 4);
+
+    // Tiles that are being rendered are not requested again until they are cancelled.
+    let (request, _) = q.get_tile_rects_page(Rect(Point2D(0f32, 0f32), Size2D(8f32, 8f32)), 1f32);
+    assert!(request.is_empty());
+    q.cancel_tile_page(4f32, 0f32);
+    let (request, _) = q.get_tile_rects_page(Rect(Point2D(0f32, 0f32), Size2D(8f32, 8f32)), 1f32);
+    assert!(request.len() == 1);
+    assert!(request[0].page_rect.origin == Point2D(4f32, 0f32));
+}
+
+#[test]
+pub fn test_has_tiles() {
+    struct T {
+        a: int,
+    }
+
+    impl Tile for T {
+        fn get_mem(&self) -> uint {
+            1
+        }
+
+        fn is_valid(&self, scale: f32) -> bool {
+            scale == 1f32
+        }
+    }
+
+    let mut q: Quadtree<T> = Quadtree::new(6, 6, 4, None);
+    let window = Rect(Point2D(0f32, 0f32), Size2D(6f32, 6f32));
+    assert!(!q.has_tiles_page(window, 1f32));
+
+    // Tiles that have been requested but not added are missing.
+    let (request, _) = q.get_tile_rects_page(window, 1f32);
+    assert!(request.len() == 4);
+    q.add_tile_page(0f32, 0f32, 1f32, T{a: 0});
+    q.add_tile_page(4f32, 0f32, 1f32, T{a: 1});
+    q.add_tile_page(0f32, 4f32, 1f32, T{a: 2});
+    assert!(!q.has_tiles_page(window, 1f32));
+    assert!(q.has_tiles_page(Rect(Point2D(0f32, 0f32), Size2D(6f32, 4f32)), 1f32));
+
+    // Parts of the window beyond the layer do not need tiles.
+    q.add_tile_page(4f32, 4f32, 1f32, T{a: 3});
+    assert!(q.has_tiles_page(Rect(Point2D(0f32, 0f32), Size2D(100f32, 100f32)), 1f32));
+
+    // Tiles rendered at another scale do not count.
+    assert!(!q.has_tiles_page(window, 2f32));
+}
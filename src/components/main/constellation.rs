@@ -9,6 +9,7 @@ use std::comm;
 use std::comm::Port;
 use std::task;
 use geom::size::Size2D;
+use gfx::font::ShapeCache;
 use gfx::opts::Opts;
 use pipeline::Pipeline;
 use servo_msg::constellation_msg::{ConstellationChan, ExitMsg};
@@ -20,7 +21,8 @@ use script::script_task::{ResizeInactiveMsg, ExecuteMsg};
 use servo_net::image_cache_task::{ImageCacheTask, ImageCacheTaskClient};
 use servo_net::resource_task::ResourceTask;
 use servo_net::resource_task;
-use servo_util::time::ProfilerChan;
+use servo_util::memory::MemoryProfilerChan;
+use servo_util::time::{ProfilerChan, ResetMsg};
 use std::hashmap::HashMap;
 use std::util::replace;
 use extra::future::from_value;
@@ -32,11 +34,14 @@ pub struct Constellation {
     compositor_chan: CompositorChan,
     resource_task: ResourceTask,
     image_cache_task: ImageCacheTask,
+    /// The words shaped by the layout tasks of every pipeline.
+    shape_cache: ShapeCache,
     pipelines: HashMap<PipelineId, @mut Pipeline>,
     navigation_context: NavigationContext,
     priv next_pipeline_id: PipelineId,
     pending_frames: ~[FrameChange],
     profiler_chan: ProfilerChan,
+    mem_profiler_chan: MemoryProfilerChan,
     opts: Opts,
 }
 
@@ -222,7 +227,9 @@ impl Constellation {
                  opts: &Opts,
                  resource_task: ResourceTask,
                  image_cache_task: ImageCacheTask,
-                 profiler_chan: ProfilerChan)
+                 shape_cache: ShapeCache,
+                 profiler_chan: ProfilerChan,
+                 mem_profiler_chan: MemoryProfilerChan)
                  -> ConstellationChan {
             
         let opts = Cell::new((*opts).clone());
@@ -235,7 +242,9 @@ impl Constellation {
 
         let resource_task = Cell::new(resource_task);
         let image_cache_task = Cell::new(image_cache_task);
+        let shape_cache = Cell::new(shape_cache);
         let profiler_chan = Cell::new(profiler_chan);
+        let mem_profiler_chan = Cell::new(mem_profiler_chan);
 
         do task::spawn {
             let mut constellation = Constellation {
@@ -244,11 +253,13 @@ impl Constellation {
                 compositor_chan: compositor_chan.take(),
                 resource_task: resource_task.take(),
                 image_cache_task: image_cache_task.take(),
+                shape_cache: shape_cache.take(),
                 pipelines: HashMap::new(),
                 navigation_context: NavigationContext::new(),
                 next_pipeline_id: PipelineId(0),
                 pending_frames: ~[],
                 profiler_chan: profiler_chan.take(),
+                mem_profiler_chan: mem_profiler_chan.take(),
                 opts: opts.take(),
             };
             constellation.run();
@@ -300,8 +311,10 @@ impl Constellation {
                                                      self.chan.clone(),
                                                      self.compositor_chan.clone(),
                                                      self.image_cache_task.clone(),
+                                                     self.shape_cache.clone(),
                                                      self.resource_task.clone(),
                                                      self.profiler_chan.clone(),
+                                                     self.mem_profiler_chan.clone(),
                                                      self.opts.clone(),
                                                      {
                                                          let size = self.compositor_chan.get_size();
@@ -367,8 +380,11 @@ impl Constellation {
                                           self.chan.clone(),
                                           self.compositor_chan.clone(),
                                           self.image_cache_task.clone(),
+                                          self.shape_cache.clone(),
                                           self.profiler_chan.clone(),
+                                          self.mem_profiler_chan.clone(),
                                           self.opts.clone(),
+                                          self.resource_task.clone(),
                                           source_pipeline,
                                           size_future)
                 } else {
@@ -378,8 +394,10 @@ impl Constellation {
                                      self.chan.clone(),
                                      self.compositor_chan.clone(),
                                      self.image_cache_task.clone(),
+                                     self.shape_cache.clone(),
                                      self.resource_task.clone(),
                                      self.profiler_chan.clone(),
+                                     self.mem_profiler_chan.clone(),
                                      self.opts.clone(),
                                      size_future)
                 };
@@ -428,14 +446,19 @@ impl Constellation {
                 let parent = source_frame.parent.clone();
                 let subpage_id = source_frame.pipeline.subpage_id.clone();
                 let next_pipeline_id = self.get_next_pipeline_id();
+                if parent.is_none() {
+                    self.reset_profiler_on_navigation();
+                }
 
                 let pipeline = @mut Pipeline::create(next_pipeline_id,
                                                      subpage_id,
                                                      self.chan.clone(),
                                                      self.compositor_chan.clone(),
                                                      self.image_cache_task.clone(),
+                                                     self.shape_cache.clone(),
                                                      self.resource_task.clone(),
                                                      self.profiler_chan.clone(),
+                                                     self.mem_profiler_chan.clone(),
                                                      self.opts.clone(),
                                                      size_future);
 
@@ -490,6 +513,7 @@ impl Constellation {
                         self.navigation_context.back()
                     }
                 };
+                self.reset_profiler_on_navigation();
 
                 for frame in destination_frame.iter() {
                     let pipeline = &frame.pipeline;
@@ -620,6 +644,14 @@ impl Constellation {
         }
     }
 
+    // Discards the profiler's metrics when the root frame navigates, if asked to. Navigations
+    // within iframes keep them.
+    fn reset_profiler_on_navigation(&self) {
+        if self.opts.profiler_reset_on_navigation {
+            self.profiler_chan.send(ResetMsg);
+        }
+    }
+
     fn set_ids(&self, frame_tree: @mut FrameTree) {
         let (port, chan) = comm::stream();
         self.compositor_chan.send(SetIds(frame_tree.to_sendable(), chan));
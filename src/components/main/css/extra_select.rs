@@ -0,0 +1,533 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Selector matching and cascading for the properties that the CSS library does not support.
+//!
+//! Only the selectors that most style sheets use to reach elements are supported: type, universal,
+//! ID, class and attribute selectors, combined with descendant and child combinators. Selectors
+//! that use anything else, such as pseudo-classes, never match.
+
+use css::extra_style::ExtraStyle;
+#[cfg(test)]
+use css::extra_style::{GradientImage, UrlImage, parse_gradient};
+
+use std::ascii::StrAsciiExt;
+use script::html::cssparse::{Declaration, RawStylesheet};
+#[cfg(test)]
+use script::html::cssparse::StyleRule;
+#[cfg(test)]
+use servo_util::url::make_url;
+use extra::sort::merge_sort;
+use extra::url::Url;
+
+/// The callbacks that selector matching uses to query the document.
+pub trait ElementSelectHandler<N> {
+    /// Calls `f` with the tag name of the element, in lowercase.
+    fn with_element_name<R>(&self, element: &N, f: &fn(&str) -> R) -> R;
+    /// Calls `f` with the value of the named attribute of the element, if it has one.
+    fn with_element_attr<R>(&self, element: &N, name: &str, f: &fn(Option<&str>) -> R) -> R;
+    /// Returns the parent of the element, if it is an element.
+    fn parent_element(&self, element: &N) -> Option<N>;
+}
+
+/// The style rules of the document's style sheets, in document order.
+pub struct ExtraSelectCtx {
+    priv blocks: ~[DeclarationBlock],
+    /// One rule for each selector of each style rule, in order.
+    priv rules: ~[ExtraRule],
+}
+
+// The declarations of a style rule, with the URL of its style sheet, which relative URLs in them
+// are resolved against.
+struct DeclarationBlock {
+    base_url: Url,
+    declarations: ~[Declaration],
+}
+
+// A selector with the block of declarations it applies.
+struct ExtraRule {
+    selector: Selector,
+    specificity: uint,
+    block: uint,
+}
+
+// A complex selector: compound selectors from left to right, with the combinators between them.
+struct Selector {
+    compounds: ~[CompoundSelector],
+    combinators: ~[Combinator],
+}
+
+struct CompoundSelector {
+    name: Option<~str>,
+    ids: ~[~str],
+    classes: ~[~str],
+    attributes: ~[AttributeSelector],
+}
+
+struct AttributeSelector {
+    name: ~str,
+    value: Option<~str>,
+    /// Whether the value is one of the whitespace-separated words of the attribute, as in `~=`,
+    /// rather than all of it.
+    word: bool,
+}
+
+enum Combinator {
+    DescendantCombinator,
+    ChildCombinator,
+}
+
+impl ExtraSelectCtx {
+    pub fn new() -> ExtraSelectCtx {
+        ExtraSelectCtx {
+            blocks: ~[],
+            rules: ~[],
+        }
+    }
+
+    /// Adds the rules of a style sheet after those of the sheets added before it.
+    pub fn append_sheet(&mut self, sheet: RawStylesheet) {
+        let RawStylesheet { url, rules } = sheet;
+        for rule in rules.move_iter() {
+            let block = self.blocks.len();
+            for selector in rule.selectors.split_iter(',') {
+                match parse_selector(selector.trim().to_ascii_lower()) {
+                    Some(selector) => {
+                        self.rules.push(ExtraRule {
+                            specificity: selector.specificity(),
+                            selector: selector,
+                            block: block,
+                        })
+                    }
+                    None => debug!("(extra select) dropping unsupported selector %s", selector),
+                }
+            }
+            self.blocks.push(DeclarationBlock {
+                base_url: url.clone(),
+                declarations: rule.declarations,
+            });
+        }
+    }
+
+    /// Cascades the declarations that apply to the element: those of the rules whose selectors
+    /// match it, ordered by specificity and then by position in the document, followed by its
+    /// `style` attribute, with `!important` declarations after all the others. Relative URLs in
+    /// the `style` attribute are resolved against `document_url`.
+    pub fn select_style<N, H: ElementSelectHandler<N>>(&self,
+                                                       element: &N,
+                                                       handler: &H,
+                                                       inline_style: &[Declaration],
+                                                       document_url: &Url,
+                                                       parent_style: &ExtraStyle)
+                                                       -> ExtraStyle {
+        let mut matched = ~[];
+        for (index, rule) in self.rules.iter().enumerate() {
+            if rule.selector.matches(element, handler) {
+                matched.push((rule.specificity, index, rule.block));
+            }
+        }
+        let matched = do merge_sort(matched) |&(a_specificity, a_index, _),
+                                              &(b_specificity, b_index, _)| {
+            (a_specificity, a_index) <= (b_specificity, b_index)
+        };
+
+        let mut style = ExtraStyle::new_from_parent(parent_style);
+        for &important in [false, true].iter() {
+            for &(_, _, block) in matched.iter() {
+                let block = &self.blocks[block];
+                for declaration in block.declarations.iter() {
+                    if declaration.important == important {
+                        style.apply(declaration, &block.base_url, parent_style);
+                    }
+                }
+            }
+            for declaration in inline_style.iter() {
+                if declaration.important == important {
+                    style.apply(declaration, document_url, parent_style);
+                }
+            }
+        }
+        style
+    }
+}
+
+impl Selector {
+    // Returns the specificity of the selector, with IDs counting above classes and attributes,
+    // and those above type selectors.
+    fn specificity(&self) -> uint {
+        let (mut ids, mut classes, mut names) = (0, 0, 0);
+        for compound in self.compounds.iter() {
+            ids += compound.ids.len();
+            classes += compound.classes.len() + compound.attributes.len();
+            if compound.name.is_some() {
+                names += 1;
+            }
+        }
+        (ids << 16) + (classes << 8) + names
+    }
+
+    fn matches<N, H: ElementSelectHandler<N>>(&self, element: &N, handler: &H) -> bool {
+        self.matches_from(self.compounds.len() - 1, element, handler)
+    }
+
+    // Returns whether the element matches the compound selector at `index` and some of its
+    // ancestors match the ones to the left of it.
+    fn matches_from<N, H: ElementSelectHandler<N>>(&self, index: uint, element: &N, handler: &H)
+                                                   -> bool {
+        if !self.compounds[index].matches(element, handler) {
+            return false
+        }
+        if index == 0 {
+            return true
+        }
+
+        let mut ancestor = handler.parent_element(element);
+        loop {
+            match ancestor {
+                None => return false,
+                Some(parent) => {
+                    if self.matches_from(index - 1, &parent, handler) {
+                        return true
+                    }
+                    match self.combinators[index - 1] {
+                        ChildCombinator => return false,
+                        DescendantCombinator => ancestor = handler.parent_element(&parent),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl CompoundSelector {
+    fn matches<N, H: ElementSelectHandler<N>>(&self, element: &N, handler: &H) -> bool {
+        let name_matches = match self.name {
+            None => true,
+            Some(ref name) => handler.with_element_name(element, |element_name| {
+                element_name == name.as_slice()
+            }),
+        };
+        name_matches &&
+            self.ids.iter().all(|id| {
+                handler.with_element_attr(element, "id", |value| value == Some(id.as_slice()))
+            }) &&
+            self.classes.iter().all(|class| {
+                handler.with_element_attr(element, "class", |value| {
+                    match value {
+                        None => false,
+                        Some(value) => value.word_iter().any(|word| word == class.as_slice()),
+                    }
+                })
+            }) &&
+            self.attributes.iter().all(|attribute| attribute.matches(element, handler))
+    }
+}
+
+impl AttributeSelector {
+    fn matches<N, H: ElementSelectHandler<N>>(&self, element: &N, handler: &H) -> bool {
+        do handler.with_element_attr(element, self.name) |value| {
+            match (value, &self.value) {
+                (None, _) => false,
+                (Some(_), &None) => true,
+                (Some(value), &Some(ref expected)) if self.word => {
+                    value.word_iter().any(|word| word == expected.as_slice())
+                }
+                (Some(value), &Some(ref expected)) => value == expected.as_slice(),
+            }
+        }
+    }
+}
+
+// Parses a selector in lowercase. Returns `None` if it uses anything unsupported.
+fn parse_selector(selector: &str) -> Option<Selector> {
+    let mut compounds = ~[];
+    let mut combinators = ~[];
+    let mut rest = selector;
+    loop {
+        let (compound, remainder) = match parse_compound_selector(rest) {
+            Some(result) => result,
+            None => return None,
+        };
+        compounds.push(compound);
+
+        let trimmed = remainder.trim_left();
+        if trimmed.is_empty() {
+            break
+        }
+        if trimmed.starts_with(">") {
+            combinators.push(ChildCombinator);
+            rest = trimmed.slice_from(1).trim_left();
+        } else if trimmed.len() < remainder.len() {
+            combinators.push(DescendantCombinator);
+            rest = trimmed;
+        } else {
+            return None
+        }
+    }
+    Some(Selector {
+        compounds: compounds,
+        combinators: combinators,
+    })
+}
+
+// Parses the compound selector at the start of `selector`. Returns it with the text after it.
+fn parse_compound_selector<'a>(selector: &'a str) -> Option<(CompoundSelector, &'a str)> {
+    let mut compound = CompoundSelector {
+        name: None,
+        ids: ~[],
+        classes: ~[],
+        attributes: ~[],
+    };
+
+    let mut rest = selector;
+    if rest.starts_with("*") {
+        rest = rest.slice_from(1);
+    } else {
+        let (name, remainder) = split_identifier(rest);
+        if !name.is_empty() {
+            compound.name = Some(name.to_owned());
+        }
+        rest = remainder;
+    }
+    let mut empty = rest.len() == selector.len();
+
+    loop {
+        if rest.starts_with("#") || rest.starts_with(".") {
+            let (identifier, remainder) = split_identifier(rest.slice_from(1));
+            if identifier.is_empty() {
+                return None
+            }
+            if rest.starts_with("#") {
+                compound.ids.push(identifier.to_owned());
+            } else {
+                compound.classes.push(identifier.to_owned());
+            }
+            rest = remainder;
+        } else if rest.starts_with("[") {
+            let close = match rest.find(']') {
+                Some(close) => close,
+                None => return None,
+            };
+            match parse_attribute_selector(rest.slice(1, close)) {
+                Some(attribute) => compound.attributes.push(attribute),
+                None => return None,
+            }
+            rest = rest.slice_from(close + 1);
+        } else {
+            break
+        }
+        empty = false;
+    }
+
+    if empty {
+        None
+    } else {
+        Some((compound, rest))
+    }
+}
+
+// Parses the inside of an attribute selector: a name, optionally followed by `=` or `~=` and a
+// value that may be quoted.
+fn parse_attribute_selector(selector: &str) -> Option<AttributeSelector> {
+    let (name, rest) = split_identifier(selector.trim());
+    if name.is_empty() {
+        return None
+    }
+    let rest = rest.trim();
+    let (word, value) = if rest.is_empty() {
+        return Some(AttributeSelector {
+            name: name.to_owned(),
+            value: None,
+            word: false,
+        })
+    } else if rest.starts_with("~=") {
+        (true, rest.slice_from(2))
+    } else if rest.starts_with("=") {
+        (false, rest.slice_from(1))
+    } else {
+        return None
+    };
+    Some(AttributeSelector {
+        name: name.to_owned(),
+        value: Some(value.trim().trim_chars(&'"').trim_chars(&'\'').to_owned()),
+        word: word,
+    })
+}
+
+// Splits the identifier at the start of `value` from the text after it.
+fn split_identifier<'a>(value: &'a str) -> (&'a str, &'a str) {
+    for (offset, c) in value.char_offset_iter() {
+        if !(c.is_alphanumeric() || c == '-' || c == '_' || !c.is_ascii()) {
+            return (value.slice_to(offset), value.slice_from(offset))
+        }
+    }
+    (value, "")
+}
+
+#[cfg(test)]
+struct FakeElement {
+    name: ~str,
+    attributes: ~[(~str, ~str)],
+    parent: Option<uint>,
+}
+
+// A document whose elements are referred to by their indices.
+#[cfg(test)]
+struct FakeDocument {
+    elements: ~[FakeElement],
+}
+
+#[cfg(test)]
+impl ElementSelectHandler<uint> for FakeDocument {
+    fn with_element_name<R>(&self, element: &uint, f: &fn(&str) -> R) -> R {
+        f(self.elements[*element].name)
+    }
+
+    fn with_element_attr<R>(&self, element: &uint, name: &str, f: &fn(Option<&str>) -> R) -> R {
+        for &(ref attribute, ref value) in self.elements[*element].attributes.iter() {
+            if attribute.as_slice() == name {
+                return f(Some(value.as_slice()))
+            }
+        }
+        f(None)
+    }
+
+    fn parent_element(&self, element: &uint) -> Option<uint> {
+        self.elements[*element].parent
+    }
+}
+
+// <html><body class="dark page"><div id="main"><p lang="en">, with the `p` at index 3.
+#[cfg(test)]
+fn fake_document() -> FakeDocument {
+    let element = |name: &str, attributes: ~[(~str, ~str)], parent: Option<uint>| {
+        FakeElement {
+            name: name.to_owned(),
+            attributes: attributes,
+            parent: parent,
+        }
+    };
+    FakeDocument {
+        elements: ~[
+            element("html", ~[], None),
+            element("body", ~[(~"class", ~"dark page")], Some(0)),
+            element("div", ~[(~"id", ~"main")], Some(1)),
+            element("p", ~[(~"lang", ~"en")], Some(2)),
+        ]
+    }
+}
+
+#[cfg(test)]
+fn selector_matches(selector: &str, element: uint) -> bool {
+    match parse_selector(selector) {
+        Some(selector) => selector.matches(&element, &fake_document()),
+        None => false,
+    }
+}
+
+#[test]
+fn test_selector_matching() {
+    assert!(selector_matches("p", 3));
+    assert!(selector_matches("*", 3));
+    assert!(!selector_matches("div", 3));
+    assert!(selector_matches("#main", 2));
+    assert!(selector_matches("body.page.dark", 1));
+    assert!(!selector_matches("body.light", 1));
+    assert!(selector_matches("p[lang]", 3));
+    assert!(selector_matches("[lang=\"en\"]", 3));
+    assert!(!selector_matches("[lang=fr]", 3));
+    assert!(selector_matches("[class~=dark]", 1));
+    assert!(selector_matches("html p", 3));
+    assert!(selector_matches(".dark > #main > p", 3));
+    assert!(!selector_matches("body > p", 3));
+    assert!(selector_matches("body div > p", 3));
+    assert!(!selector_matches("p:first-child", 3));
+    assert!(!selector_matches("div + p", 3));
+}
+
+#[test]
+fn test_specificity() {
+    let specificity = |selector: &str| parse_selector(selector).unwrap().specificity();
+    assert!(specificity("*") == 0);
+    assert!(specificity("body p") == 2);
+    assert!(specificity("p.a[lang]") == (2 << 8) + 1);
+    assert!(specificity("#main p") == (1 << 16) + 1);
+}
+
+#[cfg(test)]
+fn style_rule(selectors: &str, name: &str, value: &str, important: bool) -> StyleRule {
+    StyleRule {
+        selectors: selectors.to_owned(),
+        declarations: ~[Declaration {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            important: important,
+        }],
+    }
+}
+
+#[test]
+fn test_cascade_order() {
+    let linear = "linear-gradient(red, blue)";
+    let radial = "radial-gradient(red, blue)";
+    let mut ctx = ExtraSelectCtx::new();
+    ctx.append_sheet(RawStylesheet {
+        url: make_url(~"http://example.com/style.css", None),
+        rules: ~[
+            style_rule("#main", "background-image", linear, false),
+            style_rule("body div, p", "background-image", radial, false),
+            style_rule("html > body", "background-image", radial, true),
+        ],
+    });
+
+    let document = fake_document();
+    let document_url = make_url(~"http://example.com/index.html", None);
+    let parent = ExtraStyle::initial();
+    let image = |element: uint, inline_style: &[Declaration]| {
+        ctx.select_style(&element, &document, inline_style, &document_url, &parent)
+           .background_image
+    };
+    let gradient = |value: &str| Some(GradientImage(parse_gradient(value).unwrap()));
+
+    // The ID selector wins over the later but less specific rule.
+    assert!(image(2, &[]) == gradient(linear));
+    assert!(image(3, &[]) == gradient(radial));
+
+    // The `style` attribute wins over rules, but not over important ones.
+    let inline_style = ~[Declaration {
+        name: ~"background-image",
+        value: ~"none",
+        important: false,
+    }];
+    assert!(image(2, inline_style.as_slice()) == None);
+    assert!(image(1, inline_style.as_slice()) == gradient(radial));
+}
+
+#[test]
+fn test_url_resolution() {
+    let mut ctx = ExtraSelectCtx::new();
+    ctx.append_sheet(RawStylesheet {
+        url: make_url(~"http://example.com/css/style.css", None),
+        rules: ~[style_rule("p", "background-image", "url(Images/A.png)", false)],
+    });
+
+    // URLs in style sheets are relative to the sheet, and those in `style` attributes to the
+    // document.
+    let document = fake_document();
+    let document_url = make_url(~"http://example.com/pages/index.html", None);
+    let parent = ExtraStyle::initial();
+    let image = |element: uint, inline_style: &[Declaration]| {
+        ctx.select_style(&element, &document, inline_style, &document_url, &parent)
+           .background_image
+    };
+    let url_image = |url: &str| Some(UrlImage(make_url(url.to_owned(), None)));
+    assert!(image(3, &[]) == url_image("http://example.com/css/Images/A.png"));
+
+    let inline_style = ~[Declaration {
+        name: ~"background",
+        value: ~"url('B.png') no-repeat",
+        important: false,
+    }];
+    assert!(image(2, inline_style.as_slice()) == url_image("http://example.com/pages/B.png"));
+}
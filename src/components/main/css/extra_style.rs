@@ -0,0 +1,1383 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Computed values of the properties that the CSS library does not support. Layout cascades the
+//! declarations of these properties itself, from the style rules as written (see
+//! `css::extra_select`), and keeps the results next to the CSS library's.
+
+use std::ascii::StrAsciiExt;
+use std::float;
+use std::int;
+use script::html::cssparse::Declaration;
+use servo_util::url::make_url;
+use extra::url::Url;
+
+/// The computed values of the properties that the CSS library does not support.
+#[deriving(Clone, Eq)]
+pub struct ExtraStyle {
+    /// The image given by `background-image`. Only the first layer is kept, as for the other
+    /// background properties.
+    background_image: Option<ImageValue>,
+    background_repeat: RepeatValue,
+    background_position: PositionValue,
+    background_size: SizeValue,
+    /// The shadows given by `box-shadow`, from the top one to the bottom one.
+    box_shadow: ~[ShadowValue],
+    border_top_left_radius: RadiusValue,
+    border_top_right_radius: RadiusValue,
+    border_bottom_right_radius: RadiusValue,
+    border_bottom_left_radius: RadiusValue,
+    /// From 0 for invisible to 1 for opaque.
+    opacity: float,
+    /// The `z-index`, or `None` for `auto`.
+    z_index: Option<int>,
+    /// The shadows given by `text-shadow`, from the top one to the bottom one.
+    text_shadow: ~[ShadowValue],
+    word_break: WordBreakValue,
+    /// The value of `overflow-wrap`, or of `word-wrap`, its older name.
+    overflow_wrap: OverflowWrapValue,
+}
+
+/// The properties whose values an element takes from its parent unless it declares its own.
+static INHERITED_PROPERTIES: &'static [&'static str] = &[
+    "text-shadow",
+    "word-break",
+    "overflow-wrap",
+];
+
+impl ExtraStyle {
+    /// Returns the style of an element that declares nothing and has no parent.
+    pub fn initial() -> ExtraStyle {
+        ExtraStyle {
+            background_image: None,
+            background_repeat: RepeatBoth,
+            background_position: PositionValue {
+                x: LengthPercentage(0.0),
+                y: LengthPercentage(0.0),
+            },
+            background_size: LengthsSize(None, None),
+            box_shadow: ~[],
+            border_top_left_radius: RadiusValue::zero(),
+            border_top_right_radius: RadiusValue::zero(),
+            border_bottom_right_radius: RadiusValue::zero(),
+            border_bottom_left_radius: RadiusValue::zero(),
+            opacity: 1.0,
+            z_index: None,
+            text_shadow: ~[],
+            word_break: NormalWordBreak,
+            overflow_wrap: NormalOverflowWrap,
+        }
+    }
+
+    /// Returns the style of an element that declares nothing: inherited properties take the
+    /// values of its parent's style, and the others their initial values.
+    pub fn new_from_parent(parent: &ExtraStyle) -> ExtraStyle {
+        let mut style = ExtraStyle::initial();
+        for name in INHERITED_PROPERTIES.iter() {
+            style.copy_property(*name, parent);
+        }
+        style
+    }
+
+    /// Applies a declaration on top of the ones applied before it. Declarations of properties
+    /// that the CSS library supports, and declarations with invalid values, are ignored. Relative
+    /// URLs are resolved against `base_url`, the URL of the style sheet the declaration is from.
+    pub fn apply(&mut self, declaration: &Declaration, base_url: &Url, parent: &ExtraStyle) {
+        let name = declaration.name.as_slice();
+        match declaration.value.to_ascii_lower().as_slice() {
+            "inherit" => self.copy_property(name, parent),
+            "initial" => self.copy_property(name, &ExtraStyle::initial()),
+            _ => {
+                if !self.set_property(name, declaration.value, base_url) {
+                    debug!("(extra style) ignoring invalid value for %s: %s",
+                           name,
+                           declaration.value);
+                }
+            }
+        }
+    }
+
+    // Gives the named property the value it has in `from`.
+    fn copy_property(&mut self, name: &str, from: &ExtraStyle) {
+        match name {
+            "background" => {
+                self.background_image = from.background_image.clone();
+                self.background_repeat = from.background_repeat;
+                self.background_position = from.background_position.clone();
+                self.background_size = from.background_size.clone();
+            }
+            "background-image" => self.background_image = from.background_image.clone(),
+            "background-repeat" => self.background_repeat = from.background_repeat,
+            "background-position" => self.background_position = from.background_position.clone(),
+            "background-size" => self.background_size = from.background_size.clone(),
+            "box-shadow" => self.box_shadow = from.box_shadow.clone(),
+            "border-radius" => {
+                self.border_top_left_radius = from.border_top_left_radius.clone();
+                self.border_top_right_radius = from.border_top_right_radius.clone();
+                self.border_bottom_right_radius = from.border_bottom_right_radius.clone();
+                self.border_bottom_left_radius = from.border_bottom_left_radius.clone();
+            }
+            "border-top-left-radius" => {
+                self.border_top_left_radius = from.border_top_left_radius.clone()
+            }
+            "border-top-right-radius" => {
+                self.border_top_right_radius = from.border_top_right_radius.clone()
+            }
+            "border-bottom-right-radius" => {
+                self.border_bottom_right_radius = from.border_bottom_right_radius.clone()
+            }
+            "border-bottom-left-radius" => {
+                self.border_bottom_left_radius = from.border_bottom_left_radius.clone()
+            }
+            "opacity" => self.opacity = from.opacity,
+            "z-index" => self.z_index = from.z_index,
+            "text-shadow" => self.text_shadow = from.text_shadow.clone(),
+            "word-break" => self.word_break = from.word_break,
+            "overflow-wrap" | "word-wrap" => self.overflow_wrap = from.overflow_wrap,
+            _ => {}
+        }
+    }
+
+    // Gives the named property the given value. Returns false if the value is invalid.
+    fn set_property(&mut self, name: &str, value: &str, base_url: &Url) -> bool {
+        // URLs are case-sensitive, so the properties that take images parse the value as written.
+        let written_value = value;
+        let value = value.to_ascii_lower();
+        match name {
+            "background" => {
+                match parse_background(written_value, base_url) {
+                    Some((image, repeat, position, size)) => {
+                        self.background_image = image;
+                        self.background_repeat = repeat;
+                        self.background_position = position;
+                        self.background_size = size;
+                    }
+                    None => return false,
+                }
+            }
+            "background-image" => {
+                match parse_background_image(written_value, base_url) {
+                    Some(image) => self.background_image = image,
+                    None => return false,
+                }
+            }
+            "background-repeat" => {
+                match parse_repeat(first_layer_words(value)) {
+                    Some(repeat) => self.background_repeat = repeat,
+                    None => return false,
+                }
+            }
+            "background-position" => {
+                match parse_position(first_layer_words(value)) {
+                    Some(position) => self.background_position = position,
+                    None => return false,
+                }
+            }
+            "background-size" => {
+                match parse_size(first_layer_words(value)) {
+                    Some(size) => self.background_size = size,
+                    None => return false,
+                }
+            }
+            "box-shadow" => {
+                match parse_shadows(value, true) {
+                    Some(shadows) => self.box_shadow = shadows,
+                    None => return false,
+                }
+            }
+            "border-radius" => {
+                match parse_border_radius(value) {
+                    Some(radii) => {
+                        self.border_top_left_radius = radii[0].clone();
+                        self.border_top_right_radius = radii[1].clone();
+                        self.border_bottom_right_radius = radii[2].clone();
+                        self.border_bottom_left_radius = radii[3].clone();
+                    }
+                    None => return false,
+                }
+            }
+            "border-top-left-radius" | "border-top-right-radius" |
+            "border-bottom-right-radius" | "border-bottom-left-radius" => {
+                let radius = match parse_corner_radius(value) {
+                    Some(radius) => radius,
+                    None => return false,
+                };
+                match name {
+                    "border-top-left-radius" => self.border_top_left_radius = radius,
+                    "border-top-right-radius" => self.border_top_right_radius = radius,
+                    "border-bottom-right-radius" => self.border_bottom_right_radius = radius,
+                    _ => self.border_bottom_left_radius = radius,
+                }
+            }
+            "opacity" => {
+                // Values outside the range are valid, and clamped to it.
+                match float::from_str(value) {
+                    Some(opacity) => self.opacity = opacity.max(&0.0).min(&1.0),
+                    None => return false,
+                }
+            }
+            "z-index" => {
+                match value.as_slice() {
+                    "auto" => self.z_index = None,
+                    _ => match int::from_str(value) {
+                        Some(z_index) => self.z_index = Some(z_index),
+                        None => return false,
+                    },
+                }
+            }
+            "text-shadow" => {
+                match parse_shadows(value, false) {
+                    Some(shadows) => self.text_shadow = shadows,
+                    None => return false,
+                }
+            }
+            "word-break" => {
+                match value.as_slice() {
+                    "normal" => self.word_break = NormalWordBreak,
+                    "break-all" => self.word_break = BreakAllWordBreak,
+                    "keep-all" => self.word_break = KeepAllWordBreak,
+                    _ => return false,
+                }
+            }
+            "overflow-wrap" | "word-wrap" => {
+                match value.as_slice() {
+                    "normal" => self.overflow_wrap = NormalOverflowWrap,
+                    "break-word" => self.overflow_wrap = BreakWordOverflowWrap,
+                    _ => return false,
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+}
+
+/// A length, which may be relative to the font size or to some other length.
+#[deriving(Clone, Eq)]
+pub enum LengthValue {
+    /// An absolute length in pixels. Other absolute units are converted to pixels.
+    LengthPx(float),
+    /// A multiple of the element's font size.
+    LengthEm(float),
+    /// A percentage of a length that depends on the property.
+    LengthPercentage(float),
+}
+
+/// A color. `currentColor` is kept as is, since it refers to the `color` of the element that
+/// uses the value.
+#[deriving(Clone, Eq)]
+pub enum ColorValue {
+    CurrentColor,
+    /// Red, green and blue components, and an opacity from 0 to 1.
+    RgbaColor(u8, u8, u8, float),
+}
+
+/// A position within a box, as given by `background-position` or the `at` clause of a radial
+/// gradient. Keywords are converted to percentages.
+#[deriving(Clone, Eq)]
+pub struct PositionValue {
+    x: LengthValue,
+    y: LengthValue,
+}
+
+/// An image given by `background-image`.
+#[deriving(Clone, Eq)]
+pub enum ImageValue {
+    /// An image to load from the given URL.
+    UrlImage(Url),
+    GradientImage(GradientValue),
+}
+
+/// The axes along which a background image is repeated, as given by `background-repeat`.
+#[deriving(Clone, Eq)]
+pub enum RepeatValue {
+    RepeatBoth,
+    RepeatX,
+    RepeatY,
+    NoRepeat,
+}
+
+/// Where lines may break within words, as given by `word-break`.
+#[deriving(Clone, Eq)]
+pub enum WordBreakValue {
+    NormalWordBreak,
+    BreakAllWordBreak,
+    KeepAllWordBreak,
+}
+
+/// Whether a word too long for its line may be broken anywhere, as given by `overflow-wrap`.
+#[deriving(Clone, Eq)]
+pub enum OverflowWrapValue {
+    NormalOverflowWrap,
+    BreakWordOverflowWrap,
+}
+
+/// The size of a background image, as given by `background-size`.
+#[deriving(Clone, Eq)]
+pub enum SizeValue {
+    ContainSize,
+    CoverSize,
+    /// A width and a height, where `None` is `auto` and percentages are of the padding box.
+    LengthsSize(Option<LengthValue>, Option<LengthValue>),
+}
+
+/// A gradient given by `linear-gradient()` or `radial-gradient()`.
+#[deriving(Clone, Eq)]
+pub enum GradientValue {
+    LinearGradientValue(GradientDirection, ~[ColorStopValue]),
+    /// A radial gradient whose ending shape has the given shape and size and is centered at the
+    /// given position.
+    RadialGradientValue(GradientShape, GradientExtent, PositionValue, ~[ColorStopValue]),
+}
+
+/// The direction of the gradient line of a linear gradient.
+#[deriving(Clone, Eq)]
+pub enum GradientDirection {
+    /// An angle in degrees, clockwise from "to top". Sides are converted to angles.
+    AngleDirection(float),
+    /// Towards a corner, whose angle depends on the proportions of the box: whether it is a right
+    /// corner, and whether it is a bottom one.
+    CornerDirection(bool, bool),
+}
+
+/// The shape of the ending shape of a radial gradient.
+#[deriving(Clone, Eq)]
+pub enum GradientShape {
+    CircleShape,
+    EllipseShape,
+}
+
+/// Which side or corner of the box the ending shape of a radial gradient meets.
+#[deriving(Clone, Eq)]
+pub enum GradientExtent {
+    ClosestSideExtent,
+    ClosestCornerExtent,
+    FarthestSideExtent,
+    FarthestCornerExtent,
+}
+
+/// A color stop of a gradient, with its position along the gradient line if it has one.
+#[deriving(Clone, Eq)]
+pub struct ColorStopValue {
+    color: ColorValue,
+    position: Option<LengthValue>,
+}
+
+/// The horizontal and vertical radii of a rounded corner of a border, where percentages are of the
+/// width and the height of the border box.
+#[deriving(Clone, Eq)]
+pub struct RadiusValue {
+    horizontal: LengthValue,
+    vertical: LengthValue,
+}
+
+impl RadiusValue {
+    pub fn zero() -> RadiusValue {
+        RadiusValue {
+            horizontal: LengthPx(0.0),
+            vertical: LengthPx(0.0),
+        }
+    }
+}
+
+/// A shadow given by `box-shadow` or `text-shadow`.
+#[deriving(Clone, Eq)]
+pub struct ShadowValue {
+    offset_x: LengthValue,
+    offset_y: LengthValue,
+    blur_radius: LengthValue,
+    spread_radius: LengthValue,
+    color: ColorValue,
+    inset: bool,
+}
+
+/// Splits a value at each `separator` that is outside parentheses and quoted strings, and trims
+/// the pieces. A space separator stands for any whitespace, and empty pieces are then dropped.
+pub fn split_components<'a>(value: &'a str, separator: char) -> ~[&'a str] {
+    let mut components = ~[];
+    let mut start = 0;
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (offset, c) in value.char_offset_iter() {
+        match quote {
+            Some(quote_char) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == quote_char {
+                    quote = None;
+                }
+            }
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '(' => depth += 1,
+            None if c == ')' && depth > 0 => depth -= 1,
+            None if depth == 0 && (c == separator || (separator == ' ' && c.is_whitespace())) => {
+                components.push(value.slice(start, offset).trim());
+                start = offset + c.len_utf8_bytes();
+            }
+            None => {}
+        }
+    }
+    components.push(value.slice_from(start).trim());
+
+    if separator == ' ' {
+        components = components.move_iter().filter(|component| !component.is_empty()).collect();
+    }
+    components
+}
+
+// Splits a dimension such as `1.5em` into its number and its unit.
+fn split_dimension<'a>(value: &'a str) -> Option<(float, &'a str)> {
+    let mut unit_start = value.len();
+    for (offset, c) in value.char_offset_iter() {
+        if !(c.is_digit() || c == '.' || c == '-' || c == '+') {
+            unit_start = offset;
+            break
+        }
+    }
+    match float::from_str(value.slice_to(unit_start)) {
+        Some(number) => Some((number, value.slice_from(unit_start))),
+        None => None,
+    }
+}
+
+/// Parses a length that may not be a percentage. The value must be in lowercase.
+pub fn parse_length(value: &str) -> Option<LengthValue> {
+    match parse_length_or_percentage(value) {
+        Some(LengthPercentage(*)) => None,
+        length => length,
+    }
+}
+
+/// Parses a length or a percentage. The value must be in lowercase.
+pub fn parse_length_or_percentage(value: &str) -> Option<LengthValue> {
+    let (number, unit) = match split_dimension(value) {
+        Some(dimension) => dimension,
+        None => return None,
+    };
+    match unit {
+        "px" => Some(LengthPx(number)),
+        "pt" => Some(LengthPx(number * 96.0 / 72.0)),
+        "pc" => Some(LengthPx(number * 16.0)),
+        "in" => Some(LengthPx(number * 96.0)),
+        "cm" => Some(LengthPx(number * 96.0 / 2.54)),
+        "mm" => Some(LengthPx(number * 96.0 / 25.4)),
+        "em" => Some(LengthEm(number)),
+        "%" => Some(LengthPercentage(number)),
+        "" if number == 0.0 => Some(LengthPx(0.0)),
+        _ => None,
+    }
+}
+
+/// Parses an angle into degrees. The value must be in lowercase.
+pub fn parse_angle(value: &str) -> Option<float> {
+    let (number, unit) = match split_dimension(value) {
+        Some(dimension) => dimension,
+        None => return None,
+    };
+    match unit {
+        "deg" => Some(number),
+        "grad" => Some(number * 0.9),
+        "rad" => Some(number * 180.0 / float::consts::pi),
+        "turn" => Some(number * 360.0),
+        "" if number == 0.0 => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Parses a color: a hexadecimal color, `rgb()`, `rgba()`, `transparent`, `currentColor` or a
+/// CSS 2.1 color keyword. The value must be in lowercase.
+pub fn parse_color(value: &str) -> Option<ColorValue> {
+    if value.starts_with("#") {
+        return parse_hex_color(value.slice_from(1))
+    }
+    if value.ends_with(")") {
+        if value.starts_with("rgb(") {
+            return parse_rgb_color(value.slice(4, value.len() - 1), false)
+        }
+        if value.starts_with("rgba(") {
+            return parse_rgb_color(value.slice(5, value.len() - 1), true)
+        }
+        return None
+    }
+
+    let (red, green, blue) = match value {
+        "currentcolor" => return Some(CurrentColor),
+        "transparent" => return Some(RgbaColor(0, 0, 0, 0.0)),
+        "black" => (0x00, 0x00, 0x00),
+        "silver" => (0xc0, 0xc0, 0xc0),
+        "gray" => (0x80, 0x80, 0x80),
+        "white" => (0xff, 0xff, 0xff),
+        "maroon" => (0x80, 0x00, 0x00),
+        "red" => (0xff, 0x00, 0x00),
+        "purple" => (0x80, 0x00, 0x80),
+        "fuchsia" => (0xff, 0x00, 0xff),
+        "green" => (0x00, 0x80, 0x00),
+        "lime" => (0x00, 0xff, 0x00),
+        "olive" => (0x80, 0x80, 0x00),
+        "yellow" => (0xff, 0xff, 0x00),
+        "navy" => (0x00, 0x00, 0x80),
+        "blue" => (0x00, 0x00, 0xff),
+        "teal" => (0x00, 0x80, 0x80),
+        "aqua" => (0x00, 0xff, 0xff),
+        "orange" => (0xff, 0xa5, 0x00),
+        _ => return None,
+    };
+    Some(RgbaColor(red, green, blue, 1.0))
+}
+
+// Parses the digits of a `#rgb` or `#rrggbb` color.
+fn parse_hex_color(digits: &str) -> Option<ColorValue> {
+    let mut values = ~[];
+    for c in digits.iter() {
+        match c.to_digit(16) {
+            Some(value) => values.push(value as u8),
+            None => return None,
+        }
+    }
+    match values.len() {
+        3 => Some(RgbaColor(values[0] * 17, values[1] * 17, values[2] * 17, 1.0)),
+        6 => {
+            Some(RgbaColor(values[0] * 16 + values[1],
+                           values[2] * 16 + values[3],
+                           values[4] * 16 + values[5],
+                           1.0))
+        }
+        _ => None,
+    }
+}
+
+// Parses the arguments of `rgb()` or, if `has_alpha` is set, `rgba()`. Components are numbers or
+// percentages, and are clamped to their ranges.
+fn parse_rgb_color(arguments: &str, has_alpha: bool) -> Option<ColorValue> {
+    let arguments = split_components(arguments, ',');
+    let expected_len = if has_alpha { 4 } else { 3 };
+    if arguments.len() != expected_len {
+        return None
+    }
+
+    let mut components = ~[];
+    for argument in arguments.slice_to(3).iter() {
+        let value = if argument.ends_with("%") {
+            float::from_str(argument.slice_to(argument.len() - 1)).map(|value| *value * 2.55)
+        } else {
+            float::from_str(*argument)
+        };
+        match value {
+            Some(value) => components.push(value.max(&0.0).min(&255.0).round() as u8),
+            None => return None,
+        }
+    }
+    let alpha = if has_alpha {
+        match float::from_str(arguments[3]) {
+            Some(alpha) => alpha.max(&0.0).min(&1.0),
+            None => return None,
+        }
+    } else {
+        1.0
+    };
+    Some(RgbaColor(components[0], components[1], components[2], alpha))
+}
+
+/// Parses a position from one or two words in lowercase: keywords, lengths or percentages. With
+/// one word, the other axis is centered.
+pub fn parse_position(words: &[&str]) -> Option<PositionValue> {
+    let (first, second) = match words.len() {
+        1 => (words[0], "center"),
+        2 => (words[0], words[1]),
+        _ => return None,
+    };
+    let (x, y) = if first == "top" || first == "bottom" || second == "left" || second == "right" {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    let x = parse_position_component(x, "left", "right");
+    let y = parse_position_component(y, "top", "bottom");
+    match (x, y) {
+        (Some(x), Some(y)) => Some(PositionValue { x: x, y: y }),
+        _ => None,
+    }
+}
+
+// Parses the position along one axis, whose start and end keywords are given.
+fn parse_position_component(word: &str, start: &str, end: &str) -> Option<LengthValue> {
+    if word == start {
+        Some(LengthPercentage(0.0))
+    } else if word == "center" {
+        Some(LengthPercentage(50.0))
+    } else if word == end {
+        Some(LengthPercentage(100.0))
+    } else {
+        parse_length_or_percentage(word)
+    }
+}
+
+/// Parses a list of shadows, or `none`. Each shadow is made of two offsets, optionally followed
+/// by a blur radius and, if `box_shadow` is set, a spread radius, with a color before or after
+/// them. A box shadow may also start or end with `inset`. The value must be in lowercase.
+pub fn parse_shadows(value: &str, box_shadow: bool) -> Option<~[ShadowValue]> {
+    if value == "none" {
+        return Some(~[])
+    }
+
+    let mut shadows = ~[];
+    for shadow in split_components(value, ',').iter() {
+        let mut words = split_components(*shadow, ' ');
+        let mut inset = false;
+        if box_shadow && !words.is_empty() && words[0] == "inset" {
+            inset = true;
+            words.shift();
+        } else if box_shadow && !words.is_empty() && words[words.len() - 1] == "inset" {
+            inset = true;
+            words.pop();
+        }
+
+        let mut color = CurrentColor;
+        if !words.is_empty() && parse_length(words[0]).is_none() {
+            match parse_color(words.shift()) {
+                Some(value) => color = value,
+                None => return None,
+            }
+        } else if !words.is_empty() && parse_length(words[words.len() - 1]).is_none() {
+            match parse_color(words.pop()) {
+                Some(value) => color = value,
+                None => return None,
+            }
+        }
+
+        let max_lengths = if box_shadow { 4 } else { 3 };
+        if words.len() < 2 || words.len() > max_lengths {
+            return None
+        }
+        let mut lengths = ~[];
+        for word in words.iter() {
+            match parse_length(*word) {
+                Some(length) => lengths.push(length),
+                None => return None,
+            }
+        }
+        match lengths.len() {
+            2 => lengths.push(LengthPx(0.0)),
+            _ => {}
+        }
+        match lengths.len() {
+            3 => lengths.push(LengthPx(0.0)),
+            _ => {}
+        }
+        match lengths[2] {
+            LengthPx(blur) if blur < 0.0 => return None,
+            LengthEm(blur) if blur < 0.0 => return None,
+            _ => {}
+        }
+
+        shadows.push(ShadowValue {
+            offset_x: lengths[0].clone(),
+            offset_y: lengths[1].clone(),
+            blur_radius: lengths[2].clone(),
+            spread_radius: lengths[3].clone(),
+            color: color,
+            inset: inset,
+        });
+    }
+    Some(shadows)
+}
+
+// Parses a length or percentage that may not be negative.
+fn parse_non_negative_length(value: &str) -> Option<LengthValue> {
+    match parse_length_or_percentage(value) {
+        Some(LengthPx(length)) if length < 0.0 => None,
+        Some(LengthEm(length)) if length < 0.0 => None,
+        Some(LengthPercentage(length)) if length < 0.0 => None,
+        length => length,
+    }
+}
+
+/// Parses one of the `border-*-radius` properties: a radius for both directions, or a horizontal
+/// one followed by a vertical one. The value must be in lowercase.
+pub fn parse_corner_radius(value: &str) -> Option<RadiusValue> {
+    let words = split_components(value, ' ');
+    let (horizontal, vertical) = match words.len() {
+        1 => (words[0], words[0]),
+        2 => (words[0], words[1]),
+        _ => return None,
+    };
+    match (parse_non_negative_length(horizontal), parse_non_negative_length(vertical)) {
+        (Some(horizontal), Some(vertical)) => {
+            Some(RadiusValue {
+                horizontal: horizontal,
+                vertical: vertical,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parses `border-radius` into the radii of the corners, clockwise from the top left. One to four
+/// horizontal radii, given as for `margin`, may be followed by a slash and one to four vertical
+/// radii, which are otherwise the same as the horizontal ones. The value must be in lowercase.
+pub fn parse_border_radius(value: &str) -> Option<~[RadiusValue]> {
+    let (horizontal, vertical) = match value.find('/') {
+        Some(slash) => (value.slice_to(slash), value.slice_from(slash + 1)),
+        None => (value, value),
+    };
+    match (parse_corner_lengths(horizontal), parse_corner_lengths(vertical)) {
+        (Some(horizontal), Some(vertical)) => {
+            let radius = |corner: uint| {
+                RadiusValue {
+                    horizontal: horizontal[corner].clone(),
+                    vertical: vertical[corner].clone(),
+                }
+            };
+            Some(~[radius(0), radius(1), radius(2), radius(3)])
+        }
+        _ => None,
+    }
+}
+
+// Parses one to four lengths and expands them to the four corners, clockwise from the top left.
+fn parse_corner_lengths(value: &str) -> Option<~[LengthValue]> {
+    let mut lengths = ~[];
+    for word in split_components(value, ' ').iter() {
+        match parse_non_negative_length(*word) {
+            Some(length) => lengths.push(length),
+            None => return None,
+        }
+    }
+    let (top_left, top_right, bottom_right, bottom_left) = match lengths.len() {
+        1 => (0, 0, 0, 0),
+        2 => (0, 1, 0, 1),
+        3 => (0, 1, 2, 1),
+        4 => (0, 1, 2, 3),
+        _ => return None,
+    };
+    Some(~[lengths[top_left].clone(),
+           lengths[top_right].clone(),
+           lengths[bottom_right].clone(),
+           lengths[bottom_left].clone()])
+}
+
+// Returns the words of the first of several comma-separated layers.
+fn first_layer_words<'a>(value: &'a str) -> ~[&'a str] {
+    // FIXME: Only the first of several layers is kept.
+    split_components(split_components(value, ',')[0], ' ')
+}
+
+/// Parses `url()`, resolving the URL against `base_url`. Unlike most parsers here, this one takes
+/// the value as written, since URLs are case-sensitive.
+pub fn parse_url(value: &str, base_url: &Url) -> Option<Url> {
+    if !value.to_ascii_lower().starts_with("url(") || !value.ends_with(")") {
+        return None
+    }
+    let url = value.slice(4, value.len() - 1).trim();
+    let quoted = url.len() >= 2 &&
+        ((url.starts_with("\"") && url.ends_with("\"")) ||
+         (url.starts_with("'") && url.ends_with("'")));
+    let url = if quoted { url.slice(1, url.len() - 1) } else { url };
+    Some(make_url(url.to_owned(), Some(base_url.clone())))
+}
+
+// Parses `none`, `url()` or a gradient, where `None` is `none`. The value is taken as written.
+fn parse_image(value: &str, base_url: &Url) -> Option<Option<ImageValue>> {
+    let lowercase_value = value.to_ascii_lower();
+    if lowercase_value.as_slice() == "none" {
+        return Some(None)
+    }
+    match parse_url(value, base_url) {
+        Some(url) => return Some(Some(UrlImage(url))),
+        None => {}
+    }
+    match parse_gradient(lowercase_value.as_slice()) {
+        Some(gradient) => Some(Some(GradientImage(gradient))),
+        None => None,
+    }
+}
+
+// Parses `background-image`, where `None` is `none`. The value is taken as written.
+fn parse_background_image(value: &str, base_url: &Url) -> Option<Option<ImageValue>> {
+    // FIXME: Only the first of several layers is kept.
+    parse_image(split_components(value, ',')[0], base_url)
+}
+
+/// Parses `background-repeat` from one or two words in lowercase: a keyword for both axes, or
+/// one for each.
+pub fn parse_repeat(words: &[&str]) -> Option<RepeatValue> {
+    let second = match words.len() {
+        1 => None,
+        2 => Some(words[1]),
+        _ => return None,
+    };
+    match (words[0], second) {
+        ("repeat", None) | ("repeat", Some("repeat")) => Some(RepeatBoth),
+        ("repeat-x", None) | ("repeat", Some("no-repeat")) => Some(RepeatX),
+        ("repeat-y", None) | ("no-repeat", Some("repeat")) => Some(RepeatY),
+        ("no-repeat", None) | ("no-repeat", Some("no-repeat")) => Some(NoRepeat),
+        _ => None,
+    }
+}
+
+/// Parses `background-size` from one or two words in lowercase: `contain`, `cover`, or a width
+/// and a height, each a length, a percentage or `auto`. A missing height is `auto`.
+pub fn parse_size(words: &[&str]) -> Option<SizeValue> {
+    if words.len() == 1 && words[0] == "contain" {
+        return Some(ContainSize)
+    }
+    if words.len() == 1 && words[0] == "cover" {
+        return Some(CoverSize)
+    }
+    if words.len() < 1 || words.len() > 2 {
+        return None
+    }
+    let mut lengths = ~[];
+    for word in words.iter() {
+        if *word == "auto" {
+            lengths.push(None);
+            loop
+        }
+        match parse_non_negative_length(*word) {
+            Some(length) => lengths.push(Some(length)),
+            None => return None,
+        }
+    }
+    let height = if lengths.len() == 2 { lengths[1].clone() } else { None };
+    Some(LengthsSize(lengths[0].clone(), height))
+}
+
+/// Parses the `background` shorthand into the image, repeat, position and size of its first
+/// layer. Colors and the keywords of the other background properties are allowed but dropped,
+/// since the CSS library takes care of them. The value is taken as written.
+pub fn parse_background(value: &str, base_url: &Url)
+                        -> Option<(Option<ImageValue>, RepeatValue, PositionValue, SizeValue)> {
+    // FIXME: Only the first of several layers is kept.
+    let layer = split_components(value, ',')[0];
+
+    // A slash separates the position from the size, with or without spaces around it. Only URLs
+    // keep their case.
+    let mut words = ~[];
+    for word in split_components(layer, ' ').iter() {
+        let lowercase_word = word.to_ascii_lower();
+        if lowercase_word.starts_with("url(") {
+            words.push(word.to_owned());
+        } else if lowercase_word.contains_char('(') {
+            words.push(lowercase_word);
+        } else {
+            for (i, piece) in lowercase_word.split_iter('/').enumerate() {
+                if i > 0 {
+                    words.push(~"/");
+                }
+                if !piece.is_empty() {
+                    words.push(piece.to_owned());
+                }
+            }
+        }
+    }
+
+    let mut image = None;
+    let mut repeat = None;
+    let mut position = None;
+    let mut size = None;
+    let mut i = 0;
+    while i < words.len() {
+        if image.is_none() {
+            match parse_image(words[i].as_slice(), base_url) {
+                Some(parsed_image) => {
+                    image = Some(parsed_image);
+                    i += 1;
+                    loop
+                }
+                None => {}
+            }
+        }
+
+        let count = count_words(words, i, is_repeat_word);
+        if repeat.is_none() && count > 0 {
+            repeat = parse_repeat(word_slices(words, i, count));
+            if repeat.is_none() {
+                return None
+            }
+            i += count;
+            loop
+        }
+
+        let count = count_words(words, i, is_position_word);
+        if position.is_none() && count > 0 {
+            position = parse_position(word_slices(words, i, count));
+            if position.is_none() {
+                return None
+            }
+            i += count;
+            if i < words.len() && words[i].as_slice() == "/" {
+                let count = count_words(words, i + 1, is_size_word);
+                size = parse_size(word_slices(words, i + 1, count));
+                if size.is_none() {
+                    return None
+                }
+                i += 1 + count;
+            }
+            loop
+        }
+
+        match words[i].as_slice() {
+            "scroll" | "fixed" | "local" | "border-box" | "padding-box" | "content-box" => {}
+            word if parse_color(word).is_some() => {}
+            _ => return None,
+        }
+        i += 1;
+    }
+
+    // The properties the value leaves out are reset to their initial values.
+    let initial = ExtraStyle::initial();
+    let image = match image { Some(image) => image, None => initial.background_image.clone() };
+    let repeat = match repeat { Some(repeat) => repeat, None => initial.background_repeat };
+    let position = match position {
+        Some(position) => position,
+        None => initial.background_position.clone(),
+    };
+    let size = match size { Some(size) => size, None => initial.background_size.clone() };
+    Some((image, repeat, position, size))
+}
+
+// Counts the words, up to two, that `accept` accepts from `start` on.
+fn count_words(words: &[~str], start: uint, accept: &fn(&str) -> bool) -> uint {
+    let mut count = 0;
+    while count < 2 && start + count < words.len() && accept(words[start + count].as_slice()) {
+        count += 1;
+    }
+    count
+}
+
+fn word_slices<'a>(words: &'a [~str], start: uint, count: uint) -> ~[&'a str] {
+    words.slice(start, start + count).iter().map(|word| word.as_slice()).collect()
+}
+
+fn is_repeat_word(word: &str) -> bool {
+    word == "repeat" || word == "repeat-x" || word == "repeat-y" || word == "no-repeat"
+}
+
+fn is_position_word(word: &str) -> bool {
+    word == "left" || word == "right" || word == "top" || word == "bottom" || word == "center" ||
+        parse_length_or_percentage(word).is_some()
+}
+
+fn is_size_word(word: &str) -> bool {
+    word == "auto" || word == "contain" || word == "cover" ||
+        parse_length_or_percentage(word).is_some()
+}
+
+/// Parses `linear-gradient()` or `radial-gradient()`. The value must be in lowercase.
+pub fn parse_gradient(value: &str) -> Option<GradientValue> {
+    if !value.ends_with(")") {
+        return None
+    }
+    let linear = "linear-gradient(";
+    let radial = "radial-gradient(";
+    if value.starts_with(linear) {
+        parse_linear_gradient(value.slice(linear.len(), value.len() - 1))
+    } else if value.starts_with(radial) {
+        parse_radial_gradient(value.slice(radial.len(), value.len() - 1))
+    } else {
+        None
+    }
+}
+
+fn parse_linear_gradient(arguments: &str) -> Option<GradientValue> {
+    let arguments = split_components(arguments, ',');
+    let (direction, stops) = match parse_gradient_direction(arguments[0]) {
+        Some(direction) => (direction, arguments.slice_from(1)),
+        None => (AngleDirection(180.0), arguments.as_slice()),
+    };
+    match parse_color_stops(stops) {
+        Some(stops) => Some(LinearGradientValue(direction, stops)),
+        None => None,
+    }
+}
+
+// Parses an angle, or `to` followed by a side or a corner.
+fn parse_gradient_direction(value: &str) -> Option<GradientDirection> {
+    let words = split_components(value, ' ');
+    if words.len() == 1 {
+        return parse_angle(words[0]).map(|angle| AngleDirection(*angle))
+    }
+    if words.len() < 2 || words[0] != "to" {
+        return None
+    }
+
+    // Whether the line points to the right or to the bottom, if it says.
+    let (mut right, mut bottom) = (None, None);
+    for word in words.slice_from(1).iter() {
+        match *word {
+            "left" if right.is_none() => right = Some(false),
+            "right" if right.is_none() => right = Some(true),
+            "top" if bottom.is_none() => bottom = Some(false),
+            "bottom" if bottom.is_none() => bottom = Some(true),
+            _ => return None,
+        }
+    }
+    match (right, bottom) {
+        (Some(right), Some(bottom)) => Some(CornerDirection(right, bottom)),
+        (Some(right), None) => Some(AngleDirection(if right { 90.0 } else { 270.0 })),
+        (None, Some(bottom)) => Some(AngleDirection(if bottom { 180.0 } else { 0.0 })),
+        (None, None) => None,
+    }
+}
+
+fn parse_radial_gradient(arguments: &str) -> Option<GradientValue> {
+    let arguments = split_components(arguments, ',');
+    let (shape, extent, position, stops) = match parse_ending_shape(arguments[0]) {
+        Some((shape, extent, position)) => (shape, extent, position, arguments.slice_from(1)),
+        None => {
+            let center = PositionValue {
+                x: LengthPercentage(50.0),
+                y: LengthPercentage(50.0),
+            };
+            (EllipseShape, FarthestCornerExtent, center, arguments.as_slice())
+        }
+    };
+    match parse_color_stops(stops) {
+        Some(stops) => Some(RadialGradientValue(shape, extent, position, stops)),
+        None => None,
+    }
+}
+
+// Parses the shape, size and position that may start the arguments of `radial-gradient()`.
+//
+// FIXME: Sizes given as lengths are not supported.
+fn parse_ending_shape(value: &str) -> Option<(GradientShape, GradientExtent, PositionValue)> {
+    let words = split_components(value, ' ');
+    if words.is_empty() {
+        return None
+    }
+
+    let at = words.iter().position(|word| *word == "at").unwrap_or(words.len());
+    let (mut shape, mut extent) = (None, None);
+    for word in words.slice_to(at).iter() {
+        match *word {
+            "circle" if shape.is_none() => shape = Some(CircleShape),
+            "ellipse" if shape.is_none() => shape = Some(EllipseShape),
+            "closest-side" if extent.is_none() => extent = Some(ClosestSideExtent),
+            "closest-corner" if extent.is_none() => extent = Some(ClosestCornerExtent),
+            "farthest-side" if extent.is_none() => extent = Some(FarthestSideExtent),
+            "farthest-corner" if extent.is_none() => extent = Some(FarthestCornerExtent),
+            _ => return None,
+        }
+    }
+    let position = if at < words.len() {
+        match parse_position(words.slice_from(at + 1)) {
+            Some(position) => position,
+            None => return None,
+        }
+    } else {
+        PositionValue {
+            x: LengthPercentage(50.0),
+            y: LengthPercentage(50.0),
+        }
+    };
+    Some((shape.unwrap_or(EllipseShape), extent.unwrap_or(FarthestCornerExtent), position))
+}
+
+// Parses a list of at least two color stops, each a color optionally followed by a position.
+fn parse_color_stops(arguments: &[&str]) -> Option<~[ColorStopValue]> {
+    if arguments.len() < 2 {
+        return None
+    }
+
+    let mut stops = ~[];
+    for argument in arguments.iter() {
+        let words = split_components(*argument, ' ');
+        let position = match words.len() {
+            1 => None,
+            2 => {
+                match parse_length_or_percentage(words[1]) {
+                    Some(position) => Some(position),
+                    None => return None,
+                }
+            }
+            _ => return None,
+        };
+        match parse_color(words[0]) {
+            Some(color) => {
+                stops.push(ColorStopValue {
+                    color: color,
+                    position: position,
+                })
+            }
+            None => return None,
+        }
+    }
+    Some(stops)
+}
+
+#[cfg(test)]
+fn base_url() -> Url {
+    make_url(~"http://example.com/css/style.css", None)
+}
+
+#[cfg(test)]
+fn declaration(name: &str, value: &str) -> Declaration {
+    Declaration {
+        name: name.to_owned(),
+        value: value.to_owned(),
+        important: false,
+    }
+}
+
+#[test]
+fn test_split_components() {
+    assert!(split_components("a, rgb(1, 2, 3) , 'b,c'", ',') == ~["a", "rgb(1, 2, 3)", "'b,c'"]);
+    assert!(split_components("  red\t1px  rgba(0, 0, 0, 0.5) ", ' ') ==
+            ~["red", "1px", "rgba(0, 0, 0, 0.5)"]);
+}
+
+#[test]
+fn test_lengths_and_angles() {
+    assert!(parse_length("12px") == Some(LengthPx(12.0)));
+    assert!(parse_length("1in") == Some(LengthPx(96.0)));
+    assert!(parse_length("1.5em") == Some(LengthEm(1.5)));
+    assert!(parse_length("0") == Some(LengthPx(0.0)));
+    assert!(parse_length("5") == None);
+    assert!(parse_length("50%") == None);
+    assert!(parse_length_or_percentage("50%") == Some(LengthPercentage(50.0)));
+    assert!(parse_angle("45deg") == Some(45.0));
+    assert!(parse_angle("0.5turn") == Some(180.0));
+    assert!(parse_angle("100grad") == Some(90.0));
+    assert!(parse_angle("45") == None);
+}
+
+#[test]
+fn test_colors() {
+    assert!(parse_color("#f00") == Some(RgbaColor(255, 0, 0, 1.0)));
+    assert!(parse_color("#0080ff") == Some(RgbaColor(0, 128, 255, 1.0)));
+    assert!(parse_color("#12345") == None);
+    assert!(parse_color("rgb(255, 0, 300)") == Some(RgbaColor(255, 0, 255, 1.0)));
+    assert!(parse_color("rgb(100%, 50%, 0%)") == Some(RgbaColor(255, 128, 0, 1.0)));
+    assert!(parse_color("rgba(0, 0, 255, 0.5)") == Some(RgbaColor(0, 0, 255, 0.5)));
+    assert!(parse_color("rgba(0, 0, 255)") == None);
+    assert!(parse_color("navy") == Some(RgbaColor(0, 0, 128, 1.0)));
+    assert!(parse_color("transparent") == Some(RgbaColor(0, 0, 0, 0.0)));
+    assert!(parse_color("currentcolor") == Some(CurrentColor));
+    assert!(parse_color("chartreuse") == None);
+}
+
+#[test]
+fn test_positions() {
+    let center = Some(PositionValue { x: LengthPercentage(50.0), y: LengthPercentage(50.0) });
+    assert!(parse_position(&["center"]) == center);
+    assert!(parse_position(&["top"]) ==
+            Some(PositionValue { x: LengthPercentage(50.0), y: LengthPercentage(0.0) }));
+    assert!(parse_position(&["top", "right"]) ==
+            Some(PositionValue { x: LengthPercentage(100.0), y: LengthPercentage(0.0) }));
+    assert!(parse_position(&["10px", "25%"]) ==
+            Some(PositionValue { x: LengthPx(10.0), y: LengthPercentage(25.0) }));
+    assert!(parse_position(&["left", "left"]) == None);
+}
+
+#[test]
+fn test_linear_gradients() {
+    let red = ColorStopValue { color: RgbaColor(255, 0, 0, 1.0), position: None };
+    let blue = ColorStopValue { color: RgbaColor(0, 0, 255, 1.0), position: None };
+    assert!(parse_gradient("linear-gradient(red, blue)") ==
+            Some(LinearGradientValue(AngleDirection(180.0), ~[red.clone(), blue.clone()])));
+    assert!(parse_gradient("linear-gradient(to left, red, blue)") ==
+            Some(LinearGradientValue(AngleDirection(270.0), ~[red.clone(), blue.clone()])));
+    assert!(parse_gradient("linear-gradient(to top right, red, blue)") ==
+            Some(LinearGradientValue(CornerDirection(true, false), ~[red.clone(), blue.clone()])));
+    let half_red = ColorStopValue { color: RgbaColor(255, 0, 0, 1.0),
+                                    position: Some(LengthPercentage(50.0)) };
+    assert!(parse_gradient("linear-gradient(45deg, red 50%, blue)") ==
+            Some(LinearGradientValue(AngleDirection(45.0), ~[half_red, blue.clone()])));
+    assert!(parse_gradient("linear-gradient(red)") == None);
+    assert!(parse_gradient("linear-gradient(to top bottom, red, blue)") == None);
+}
+
+#[test]
+fn test_radial_gradients() {
+    let stops = ~[ColorStopValue { color: RgbaColor(255, 0, 0, 1.0), position: None },
+                  ColorStopValue { color: RgbaColor(0, 0, 255, 1.0), position: None }];
+    let center = PositionValue { x: LengthPercentage(50.0), y: LengthPercentage(50.0) };
+    assert!(parse_gradient("radial-gradient(red, blue)") ==
+            Some(RadialGradientValue(EllipseShape, FarthestCornerExtent, center.clone(),
+                                     stops.clone())));
+    assert!(parse_gradient("radial-gradient(circle closest-side at left 20px, red, blue)") ==
+            Some(RadialGradientValue(CircleShape,
+                                     ClosestSideExtent,
+                                     PositionValue { x: LengthPercentage(0.0), y: LengthPx(20.0) },
+                                     stops.clone())));
+    assert!(parse_gradient("radial-gradient(circle circle, red, blue)") == None);
+}
+
+#[test]
+fn test_box_shadows() {
+    let shadow = |x: float, y: float, blur: float, spread: float, color: ColorValue, inset: bool| {
+        ShadowValue {
+            offset_x: LengthPx(x),
+            offset_y: LengthPx(y),
+            blur_radius: LengthPx(blur),
+            spread_radius: LengthPx(spread),
+            color: color,
+            inset: inset,
+        }
+    };
+    let red = RgbaColor(255, 0, 0, 1.0);
+    assert!(parse_shadows("none", true) == Some(~[]));
+    assert!(parse_shadows("1px 2px", true) ==
+            Some(~[shadow(1.0, 2.0, 0.0, 0.0, CurrentColor, false)]));
+    assert!(parse_shadows("red 1px 2px 3px 4px", true) ==
+            Some(~[shadow(1.0, 2.0, 3.0, 4.0, red.clone(), false)]));
+    assert!(parse_shadows("inset 0 0 5px #f00, 1px 1px 0 0 red inset", true) ==
+            Some(~[shadow(0.0, 0.0, 5.0, 0.0, red.clone(), true),
+                   shadow(1.0, 1.0, 0.0, 0.0, red.clone(), true)]));
+    assert!(parse_shadows("1px", true) == None);
+    assert!(parse_shadows("1px 1px -1px", true) == None);
+    assert!(parse_shadows("1px 1px 1px 1px 1px", true) == None);
+    assert!(parse_shadows("1px 1px 1px 1px", false) == None);
+    assert!(parse_shadows("inset 1px 1px", false) == None);
+}
+
+#[test]
+fn test_border_radius() {
+    let radius = |horizontal: LengthValue, vertical: LengthValue| {
+        RadiusValue {
+            horizontal: horizontal,
+            vertical: vertical,
+        }
+    };
+    let px = |length: float| radius(LengthPx(length), LengthPx(length));
+
+    assert!(parse_corner_radius("5px") == Some(px(5.0)));
+    assert!(parse_corner_radius("5px 10%") ==
+            Some(radius(LengthPx(5.0), LengthPercentage(10.0))));
+    assert!(parse_corner_radius("-5px") == None);
+
+    assert!(parse_border_radius("1px") == Some(~[px(1.0), px(1.0), px(1.0), px(1.0)]));
+    assert!(parse_border_radius("1px 2px") == Some(~[px(1.0), px(2.0), px(1.0), px(2.0)]));
+    assert!(parse_border_radius("1px 2px 3px") == Some(~[px(1.0), px(2.0), px(3.0), px(2.0)]));
+    assert!(parse_border_radius("1px 2px 3px 4px / 5px") ==
+            Some(~[radius(LengthPx(1.0), LengthPx(5.0)),
+                   radius(LengthPx(2.0), LengthPx(5.0)),
+                   radius(LengthPx(3.0), LengthPx(5.0)),
+                   radius(LengthPx(4.0), LengthPx(5.0))]));
+    assert!(parse_border_radius("1px 2px 3px 4px 5px") == None);
+    assert!(parse_border_radius("1px /") == None);
+}
+
+#[test]
+fn test_cascade() {
+    let parent = ExtraStyle::initial();
+    let mut style = ExtraStyle::new_from_parent(&parent);
+    let gradient = declaration("background-image", "Linear-Gradient(RED, Blue)");
+    style.apply(&gradient, &base_url(), &parent);
+    assert!(style.background_image.is_some());
+
+    // Invalid values leave the previous value alone.
+    style.apply(&declaration("background-image", "linear-gradient(red)"), &base_url(), &parent);
+    assert!(style.background_image.is_some());
+
+    style.apply(&declaration("background-image", "initial"), &base_url(), &parent);
+    assert!(style.background_image.is_none());
+}
+
+#[test]
+fn test_background_images() {
+    let url = |url: &str| make_url(url.to_owned(), None);
+    assert!(parse_url("url(a.png)", &base_url()) == Some(url("http://example.com/css/a.png")));
+    assert!(parse_url("URL( \"/B.png\" )", &base_url()) == Some(url("http://example.com/B.png")));
+    assert!(parse_url("url(a.png", &base_url()) == None);
+
+    assert!(parse_repeat(&["repeat-x"]) == Some(RepeatX));
+    assert!(parse_repeat(&["no-repeat", "repeat"]) == Some(RepeatY));
+    assert!(parse_repeat(&["no-repeat", "no-repeat"]) == Some(NoRepeat));
+    assert!(parse_repeat(&["repeat-x", "repeat"]) == None);
+
+    assert!(parse_size(&["cover"]) == Some(CoverSize));
+    assert!(parse_size(&["10px"]) == Some(LengthsSize(Some(LengthPx(10.0)), None)));
+    assert!(parse_size(&["auto", "50%"]) == Some(LengthsSize(None, Some(LengthPercentage(50.0)))));
+    assert!(parse_size(&["-10px"]) == None);
+    assert!(parse_size(&["cover", "10px"]) == None);
+
+    let initial = ExtraStyle::initial();
+    assert!(parse_background("url(a.png) no-repeat red", &base_url()) ==
+            Some((Some(UrlImage(url("http://example.com/css/a.png"))),
+                  NoRepeat,
+                  initial.background_position.clone(),
+                  initial.background_size.clone())));
+    assert!(parse_background("#fff right 10px/contain repeat-y", &base_url()) ==
+            Some((None,
+                  RepeatY,
+                  PositionValue { x: LengthPercentage(100.0), y: LengthPx(10.0) },
+                  ContainSize)));
+    assert!(parse_background("none center / 50% auto", &base_url()) ==
+            Some((None,
+                  RepeatBoth,
+                  PositionValue { x: LengthPercentage(50.0), y: LengthPercentage(50.0) },
+                  LengthsSize(Some(LengthPercentage(50.0)), None))));
+    assert!(parse_background("url(a.png) url(b.png)", &base_url()) == None);
+    assert!(parse_background("repeat-x / cover", &base_url()) == None);
+
+    // The shorthand resets the properties it leaves out.
+    let parent = ExtraStyle::initial();
+    let mut style = ExtraStyle::new_from_parent(&parent);
+    style.apply(&declaration("background-repeat", "repeat-y"), &base_url(), &parent);
+    style.apply(&declaration("background", "url(a.png)"), &base_url(), &parent);
+    assert!(style.background_repeat == RepeatBoth);
+    assert!(style.background_image.is_some());
+}
+
+#[test]
+fn test_opacity_and_z_index() {
+    let parent = ExtraStyle::initial();
+    let mut style = ExtraStyle::new_from_parent(&parent);
+    style.apply(&declaration("opacity", "0.25"), &base_url(), &parent);
+    assert_eq!(style.opacity, 0.25);
+    style.apply(&declaration("opacity", "1.5"), &base_url(), &parent);
+    assert_eq!(style.opacity, 1.0);
+    style.apply(&declaration("opacity", "-1"), &base_url(), &parent);
+    assert_eq!(style.opacity, 0.0);
+    style.apply(&declaration("opacity", "50%"), &base_url(), &parent);
+    assert_eq!(style.opacity, 0.0);
+
+    // Neither property is inherited.
+    assert_eq!(ExtraStyle::new_from_parent(&style).opacity, 1.0);
+
+    style.apply(&declaration("z-index", "-2"), &base_url(), &parent);
+    assert_eq!(style.z_index, Some(-2));
+    style.apply(&declaration("z-index", "1.5"), &base_url(), &parent);
+    assert_eq!(style.z_index, Some(-2));
+    style.apply(&declaration("z-index", "AUTO"), &base_url(), &parent);
+    assert_eq!(style.z_index, None);
+}
+
+#[test]
+fn test_word_breaking() {
+    let parent = ExtraStyle::initial();
+    let mut style = ExtraStyle::new_from_parent(&parent);
+    style.apply(&declaration("word-break", "Keep-All"), &base_url(), &parent);
+    style.apply(&declaration("word-wrap", "break-word"), &base_url(), &parent);
+    assert!(style.word_break == KeepAllWordBreak);
+    assert!(style.overflow_wrap == BreakWordOverflowWrap);
+
+    // Both are inherited, and `overflow-wrap` is the same property as `word-wrap`.
+    let mut child = ExtraStyle::new_from_parent(&style);
+    assert!(child.word_break == KeepAllWordBreak);
+    assert!(child.overflow_wrap == BreakWordOverflowWrap);
+    child.apply(&declaration("overflow-wrap", "normal"), &base_url(), &style);
+    child.apply(&declaration("word-break", "break-word"), &base_url(), &style);
+    assert!(child.word_break == KeepAllWordBreak);
+    assert!(child.overflow_wrap == NormalOverflowWrap);
+}
+
+#[test]
+fn test_text_shadow_is_inherited() {
+    let parent = ExtraStyle::initial();
+    let mut style = ExtraStyle::new_from_parent(&parent);
+    style.apply(&declaration("text-shadow", "1px 1px red"), &base_url(), &parent);
+    assert_eq!(style.text_shadow.len(), 1);
+    assert_eq!(ExtraStyle::new_from_parent(&style).text_shadow, style.text_shadow.clone());
+
+    style.apply(&declaration("text-shadow", "inset 1px 1px"), &base_url(), &parent);
+    assert_eq!(style.text_shadow.len(), 1);
+    style.apply(&declaration("text-shadow", "none"), &base_url(), &parent);
+    assert!(style.text_shadow.is_empty());
+}
@@ -4,17 +4,25 @@
 
 // High-level interface to CSS selector matching.
 
+use css::extra_select::ExtraSelectCtx;
+use css::extra_style::ExtraStyle;
 use css::node_util::NodeUtil;
 use css::select_handler::NodeSelectHandler;
 use layout::incremental;
+use layout::incremental::RestyleDamage;
 
 use script::dom::node::{AbstractNode, LayoutView};
 use newcss::complete::CompleteSelectResults;
 use newcss::select::{SelectCtx, SelectResults};
+use script::html::cssparse::parse_declarations;
 use servo_util::tree::TreeNodeRef;
+use extra::url::Url;
 
 pub trait MatchMethods {
-    fn restyle_subtree(&self, select_ctx: &SelectCtx);
+    fn restyle_subtree(&self,
+                       select_ctx: &SelectCtx,
+                       extra_select_ctx: &ExtraSelectCtx,
+                       document_url: &Url);
 }
 
 impl MatchMethods for AbstractNode<LayoutView> {
@@ -23,9 +31,13 @@ impl MatchMethods for AbstractNode<LayoutView> {
      *
      * This is, importantly, the function that updates the layout data for
      * the node (the reader-auxiliary box in the COW model) with the
-     * computed style.
+     * computed style. Relative URLs in `style` attributes are resolved
+     * against `document_url`.
      */
-    fn restyle_subtree(&self, select_ctx: &SelectCtx) {
+    fn restyle_subtree(&self,
+                       select_ctx: &SelectCtx,
+                       extra_select_ctx: &ExtraSelectCtx,
+                       document_url: &Url) {
         // Only elements have styles
         if self.is_element() {
             do self.with_imm_element |elem| {
@@ -38,18 +50,40 @@ impl MatchMethods for AbstractNode<LayoutView> {
                 // Combine this node's results with its parent's to resolve all inherited values
                 let complete_results = compose_results(*self, incomplete_results);
 
+                // Cascade the properties that the CSS library does not support.
+                let parent_extra_style = match find_parent_element_node(*self) {
+                    None => @ExtraStyle::initial(),
+                    Some(parent_node) => parent_node.get_extra_style(),
+                };
+                let inline_extra_style = match elem.get_attr("style") {
+                    None => ~[],
+                    Some(style) => parse_declarations(style),
+                };
+                let extra_style = @extra_select_ctx.select_style(self,
+                                                                 &select_handler,
+                                                                 inline_extra_style.as_slice(),
+                                                                 document_url,
+                                                                 &*parent_extra_style);
+
                 // If there was an existing style, compute the damage that
                 // incremental layout will need to fix.
                 if self.have_css_select_results() {
-                    let damage = incremental::compute_damage(self, self.get_css_select_results(), &complete_results);
+                    let mut damage = incremental::compute_damage(self,
+                                                                 self.get_css_select_results(),
+                                                                 &complete_results);
+                    // FIXME: Most of these properties only need a repaint.
+                    if *self.get_extra_style() != *extra_style {
+                        damage.union_in_place(RestyleDamage::all());
+                    }
                     self.set_restyle_damage(damage);
                 }
                 self.set_css_select_results(complete_results);
+                self.set_extra_style(extra_style);
             };
         }
 
         for kid in self.children() {
-            kid.restyle_subtree(select_ctx); 
+            kid.restyle_subtree(select_ctx, extra_select_ctx, document_url);
         }
     }
 }
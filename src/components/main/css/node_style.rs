@@ -4,6 +4,7 @@
 
 // Style retrieval from DOM elements.
 
+use css::extra_style::ExtraStyle;
 use css::node_util::NodeUtil;
 use layout::incremental::RestyleDamage;
 
@@ -13,6 +14,7 @@ use script::dom::node::{AbstractNode, LayoutView};
 /// Node mixin providing `style` method that returns a `NodeStyle`
 pub trait StyledNode {
     fn style(&self) -> CompleteStyle;
+    fn extra_style(&self) -> @ExtraStyle;
     fn restyle_damage(&self) -> RestyleDamage;
 }
 
@@ -23,6 +25,11 @@ impl StyledNode for AbstractNode<LayoutView> {
         results.computed_style()
     }
 
+    fn extra_style(&self) -> @ExtraStyle {
+        assert!(self.is_element()); // Only elements can have styles
+        self.get_extra_style()
+    }
+
     fn restyle_damage(&self) -> RestyleDamage {
         self.get_restyle_damage()
     }
@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use css::extra_style::ExtraStyle;
 use layout::aux::LayoutAuxMethods;
 use layout::incremental::RestyleDamage;
 
@@ -14,6 +15,9 @@ pub trait NodeUtil<'self> {
     fn set_css_select_results(self, decl: CompleteSelectResults);
     fn have_css_select_results(self) -> bool;
 
+    fn get_extra_style(self) -> @ExtraStyle;
+    fn set_extra_style(self, style: @ExtraStyle);
+
     fn get_restyle_damage(self) -> RestyleDamage;
     fn set_restyle_damage(self, damage: RestyleDamage);
 }
@@ -51,6 +55,28 @@ impl<'self> NodeUtil<'self> for AbstractNode<LayoutView> {
         self.layout_data().style = Some(decl);
     }
 
+    /// Returns the computed values of the properties that the CSS library does not support. If
+    /// CSS selector matching has not yet been performed, fails.
+    fn get_extra_style(self) -> @ExtraStyle {
+        if !self.has_layout_data() {
+            fail!(~"get_extra_style() called on a node without aux data!");
+        }
+
+        match self.layout_data().extra_style {
+            None => fail!(~"get_extra_style() called on node without a style!"),
+            Some(style) => style,
+        }
+    }
+
+    /// Updates the computed values of the properties that the CSS library does not support.
+    fn set_extra_style(self, style: @ExtraStyle) {
+        if !self.has_layout_data() {
+            fail!(~"set_extra_style() called on a node without aux data!");
+        }
+
+        self.layout_data().extra_style = Some(style);
+    }
+
     /// Get the description of how to account for recent style changes.
     /// This is a simple bitfield and fine to copy by value.
     fn get_restyle_damage(self) -> RestyleDamage {
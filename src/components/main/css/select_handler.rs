@@ -6,6 +6,8 @@
 /// Implementation of the callbacks that the CSS selector engine uses to query the DOM.
 ///
 
+use css::extra_select::ElementSelectHandler;
+
 use std::str::eq_slice;
 use newcss::select::SelectHandler;
 use script::dom::node::{AbstractNode, LayoutView};
@@ -134,3 +136,30 @@ impl SelectHandler<AbstractNode<LayoutView>> for NodeSelectHandler {
         }
     }
 }
+
+impl ElementSelectHandler<AbstractNode<LayoutView>> for NodeSelectHandler {
+    fn with_element_name<R>(&self, element: &AbstractNode<LayoutView>, f: &fn(&str) -> R) -> R {
+        with_node_name(*element, f)
+    }
+
+    fn with_element_attr<R>(&self,
+                            element: &AbstractNode<LayoutView>,
+                            name: &str,
+                            f: &fn(Option<&str>) -> R)
+                            -> R {
+        if !element.is_element() {
+            fail!(~"attempting to style non-element node");
+        }
+        do element.with_imm_element |element_n| {
+            f(element_n.get_attr(name))
+        }
+    }
+
+    fn parent_element(&self, element: &AbstractNode<LayoutView>)
+                      -> Option<AbstractNode<LayoutView>> {
+        match element.parent_node() {
+            Some(parent) if parent.is_element() => Some(parent),
+            _ => None,
+        }
+    }
+}
@@ -4,6 +4,7 @@
 
 //! Code for managing the layout data in the DOM.
 
+use css::extra_style::ExtraStyle;
 use layout::flow::FlowContext;
 use layout::incremental::RestyleDamage;
 
@@ -16,6 +17,9 @@ pub struct LayoutData {
     /// The results of CSS styling for this node.
     style: Option<CompleteSelectResults>,
 
+    /// The computed values of the properties that the CSS library does not support.
+    extra_style: Option<@ExtraStyle>,
+
     /// Description of how to account for recent style changes.
     restyle_damage: Option<RestyleDamage>,
 
@@ -28,6 +32,7 @@ impl LayoutData {
     pub fn new() -> LayoutData {
         LayoutData {
             style: None,
+            extra_style: None,
             restyle_damage: None,
             flow: None,
         }
@@ -369,11 +369,48 @@ impl BlockFlowData {
             return false;
         }
 
+        // The root builds the whole display list, which is the root stacking context. Other
+        // blocks forming stacking contexts add one to the list holding their descendants' items.
+        let flow = BlockFlow(self);
+        if self.is_root {
+            do builder.build_stacking_context_items(&flow, dirty, list) {
+                self.build_own_display_items(builder, dirty, list)
+            }
+            return false
+        }
+        match self.box {
+            Some(box) => match box.stacking_context_z_index() {
+                Some(z_index) => {
+                    do builder.build_stacking_context(&flow, box, z_index, dirty, list) |list| {
+                        self.build_own_display_items(builder, dirty, list)
+                    }
+                    return false
+                }
+                None => {}
+            },
+            None => {}
+        }
+
+        self.build_own_display_items(builder, dirty, list);
+        true
+    }
+
+    // Adds the items of this block's box to the list and positions its children for building
+    // theirs.
+    fn build_own_display_items<E:ExtraDisplayListData>(@mut self,
+                                                       builder: &DisplayListBuilder,
+                                                       dirty: &Rect<Au>,
+                                                       list: &Cell<DisplayList<E>>) {
         // add box that starts block context
+        let clip = self.common.clip;
         self.box.map(|&box| {
-            box.build_display_list(builder, dirty, &self.common.abs_position, list)
+            box.build_display_list(builder, dirty, &self.common.abs_position, &clip, list)
         });
 
+        let child_clip = match self.box {
+            Some(box) => box.clip_for_descendants(&self.common.abs_position, &clip),
+            None => clip,
+        };
 
         // TODO: handle any out-of-flow elements
 
@@ -382,10 +419,9 @@ impl BlockFlowData {
         for child in flow.children() {
             do child.with_mut_base |base| {
                 base.abs_position = self.common.abs_position + base.position.origin;
+                base.clip = child_clip;
             }
         }
-
-        true
     }
 }
 
@@ -4,6 +4,14 @@
 
 //! The `RenderBox` type, which represents the leaves of the layout tree.
 
+use css::extra_style::{AngleDirection, CircleShape, ClosestCornerExtent, ClosestSideExtent};
+use css::extra_style::{ColorStopValue, ColorValue, CornerDirection, CurrentColor, EllipseShape};
+use css::extra_style::{ExtraStyle, FarthestCornerExtent, FarthestSideExtent, GradientImage};
+use css::extra_style::{LengthEm, LengthPercentage, LengthPx, LengthValue, LinearGradientValue};
+use css::extra_style::{BreakAllWordBreak, BreakWordOverflowWrap, ContainSize, CoverSize};
+use css::extra_style::{KeepAllWordBreak, LengthsSize, NoRepeat, NormalOverflowWrap};
+use css::extra_style::{NormalWordBreak, RadialGradientValue, RadiusValue, RepeatBoth, RepeatX};
+use css::extra_style::{RepeatY, RgbaColor, UrlImage};
 use css::node_style::StyledNode;
 use layout::context::LayoutContext;
 use layout::display_list_builder::{DisplayListBuilder, ExtraDisplayListData, ToGfxColor};
@@ -12,27 +20,43 @@ use layout::flow::FlowContext;
 use layout::model::{BoxModel, MaybeAuto};
 use layout::text;
 
+use std::ascii::StrAsciiExt;
 use std::cell::Cell;
 use std::cmp::ApproxEq;
+use std::float;
 use std::managed;
 use std::num::Zero;
 use geom::{Point2D, Rect, Size2D, SideOffsets2D};
 use gfx::display_list::{BaseDisplayItem, BorderDisplayItem, BorderDisplayItemClass};
+use gfx::display_list::{BorderRadii, BorderStyle, BorderStyleNone, BorderStyleHidden};
+use gfx::display_list::{BorderStyleSolid, BorderStyleDotted, BorderStyleDashed, BorderStyleDouble};
+use gfx::display_list::{BorderStyleGroove, BorderStyleRidge, BorderStyleInset, BorderStyleOutset};
 use gfx::display_list::{DisplayList, ImageDisplayItem, ImageDisplayItemClass};
 use gfx::display_list::{SolidColorDisplayItem, SolidColorDisplayItemClass, TextDisplayItem};
-use gfx::display_list::{TextDisplayItemClass};
-use gfx::font::{FontStyle, FontWeight300};
-use gfx::geometry::{Au, pt_to_px};
+use gfx::display_list::{TextDisplayItemClass, GradientStop, LinearGradientDisplayItem};
+use gfx::display_list::{LinearGradientDisplayItemClass, RadialGradientDisplayItem};
+use gfx::display_list::{RadialGradientDisplayItemClass, BoxShadowDisplayItem, TextDecorations};
+use gfx::display_list::{BoxShadowDisplayItemClass, TextShadow, BackgroundImageDisplayItem};
+use gfx::display_list::BackgroundImageDisplayItemClass;
+use gfx::font::{FontFeatures, FontStyle, FontWeight300};
+use gfx::geometry::{Au, pt_to_px, to_frac_px};
+use gfx::text::bidi;
+use gfx::text::line_break::{WordBreak, WordBreakBreakAll, WordBreakKeepAll, WordBreakNormal};
 use gfx::text::text_run::TextRun;
-use newcss::color::rgb;
+use newcss::color::{Color, rgb};
 use newcss::complete::CompleteStyle;
 use newcss::units::{Cursive, Em, Fantasy, Monospace, Pt, Px, SansSerif, Serif};
+use newcss::values::{CSSBorderStyle, CSSBorderStyleNone, CSSBorderStyleHidden};
+use newcss::values::{CSSBorderStyleDotted, CSSBorderStyleDashed, CSSBorderStyleSolid};
+use newcss::values::{CSSBorderStyleDouble, CSSBorderStyleGroove, CSSBorderStyleRidge};
+use newcss::values::{CSSBorderStyleInset, CSSBorderStyleOutset};
 use newcss::values::{CSSClearNone, CSSClearLeft, CSSClearRight, CSSClearBoth};
 use newcss::values::{CSSFontFamilyFamilyName, CSSFontFamilyGenericFamily};
 use newcss::values::{CSSFontSizeLength, CSSFontStyleItalic, CSSFontStyleNormal};
-use newcss::values::{CSSFontStyleOblique, CSSTextAlign, CSSTextDecoration, CSSLineHeight};
-use newcss::values::{CSSTextDecorationNone, CSSFloatNone, CSSPositionStatic};
-use newcss::values::{CSSDisplayInlineBlock, CSSDisplayInlineTable};
+use newcss::values::{CSSFontStyleOblique, CSSTextAlign, CSSLineHeight};
+use newcss::values::{CSSTextDecorationLineThrough, CSSTextDecorationOverline};
+use newcss::values::{CSSTextDecorationUnderline, CSSFloatNone, CSSPositionStatic};
+use newcss::values::{CSSDisplayInlineBlock, CSSDisplayInlineTable, CSSOverflowVisible};
 use script::dom::node::{AbstractNode, LayoutView};
 use servo_net::image::holder::ImageHolder;
 use servo_net::local_image_cache::LocalImageCache;
@@ -147,6 +171,87 @@ pub enum SplitBoxResult {
     SplitDidNotFit(Option<RenderBox>, Option<RenderBox>)
 }
 
+/// A gradient used as the background image of a box.
+pub enum BackgroundGradient {
+    /// A linear gradient whose gradient line points in the given direction, in degrees clockwise
+    /// from "to top".
+    LinearBackgroundGradient(float, ~[BackgroundGradientStop]),
+    /// An elliptical gradient with the given center, in the same coordinate system as the box's
+    /// bounds, and the given horizontal and vertical radii, at which its last stop is reached.
+    RadialBackgroundGradient(Point2D<Au>, Size2D<Au>, ~[BackgroundGradientStop]),
+}
+
+/// A color stop of a background gradient.
+pub struct BackgroundGradientStop {
+    color: Color,
+    /// The position of the stop along the gradient line, where fractions are of its length.
+    /// Stops without one are spaced evenly between their neighbors.
+    position: Option<BackgroundLength>,
+}
+
+/// An image used as the background of a box, with the properties that place it.
+pub struct BackgroundImage {
+    url: Url,
+    repeat: BackgroundRepeat,
+    /// Where the image sits in the padding box, as given by `background-position`.
+    position_x: BackgroundLength,
+    position_y: BackgroundLength,
+    size: BackgroundSize,
+}
+
+/// The axes along which a background image is repeated, as given by `background-repeat`.
+#[deriving(Clone, Eq)]
+pub enum BackgroundRepeat {
+    BackgroundRepeatBoth,
+    BackgroundRepeatX,
+    BackgroundRepeatY,
+    BackgroundNoRepeat,
+}
+
+/// A length used to place or size a background image.
+pub enum BackgroundLength {
+    BackgroundLengthAu(Au),
+    /// A fraction of the length it is resolved against, e.g. 0.5 for `50%`.
+    BackgroundFraction(float),
+}
+
+impl BackgroundLength {
+    pub fn resolve(&self, reference: Au) -> Au {
+        match *self {
+            BackgroundLengthAu(length) => length,
+            BackgroundFraction(fraction) => reference.scale_by(fraction),
+        }
+    }
+}
+
+/// The size a background image is drawn at, as given by `background-size`.
+pub enum BackgroundSize {
+    /// As large as possible while fitting within the padding box.
+    BackgroundSizeContain,
+    /// As small as possible while covering the padding box.
+    BackgroundSizeCover,
+    /// The given width and height, where `None` means `auto`.
+    BackgroundSizeLengths(Option<BackgroundLength>, Option<BackgroundLength>),
+}
+
+/// Whether a word too long for its line may be broken between any two characters, as given by
+/// `overflow-wrap`.
+#[deriving(Clone, Eq)]
+pub enum OverflowWrap {
+    OverflowWrapNormal,
+    OverflowWrapBreakWord,
+}
+
+/// A shadow cast by a box, as given by the `box-shadow` property.
+pub struct BoxShadow {
+    offset: Point2D<Au>,
+    blur_radius: Au,
+    spread_radius: Au,
+    color: Color,
+    /// Whether the shadow is cast inside the box's padding box instead of outside its border box.
+    inset: bool,
+}
+
 /// Data common to all render boxes.
 pub struct RenderBoxBase {
     /// The DOM node that this `RenderBox` originates from.
@@ -261,7 +366,7 @@ impl RenderBox {
     pub fn can_merge_with_box(&self, other: RenderBox) -> bool {
         match (self, &other) {
             (&UnscannedTextRenderBoxClass(*), &UnscannedTextRenderBoxClass(*)) => {
-                self.font_style() == other.font_style() && self.text_decoration() == other.text_decoration()
+                self.font_style() == other.font_style()
             },
             (&TextRenderBoxClass(text_box_a), &TextRenderBoxClass(text_box_b)) => {
                 managed::ptr_eq(text_box_a.run, text_box_b.run)
@@ -281,71 +386,76 @@ impl RenderBox {
             }
 
             TextRenderBoxClass(text_box) => {
-                let mut pieces_processed_count: uint = 0;
+                let run = text_box.run;
                 let mut remaining_width: Au = max_width;
                 let mut left_range = Range::new(text_box.range.begin(), 0);
                 let mut right_range: Option<Range> = None;
+                let mut did_fit = true;
 
                 debug!("split_to_width: splitting text box (strlen=%u, range=%?, avail_width=%?)",
-                       text_box.run.text.len(),
+                       run.text().len(),
                        text_box.range,
                        max_width);
 
-                for (glyphs, offset, slice_range) in text_box.run.iter_slices_for_range(&text_box.range) {
-                    debug!("split_to_width: considering slice (offset=%?, range=%?, remain_width=%?)",
-                           offset,
-                           slice_range,
-                           remaining_width);
+                let remainder_from = |begin: uint| -> Option<Range> {
+                    if begin < text_box.range.end() {
+                        Some(Range::new(begin, text_box.range.end() - begin))
+                    } else {
+                        None
+                    }
+                };
 
-                    let metrics = text_box.run.metrics_for_slice(glyphs, &slice_range);
-                    let advance = metrics.advance_width;
-                    let should_continue: bool;
+                let pieces = run.break_pieces_for_range(&text_box.range, self.word_break());
+                for &(content, whitespace) in pieces.iter() {
+                    debug!("split_to_width: considering piece (content=%?, whitespace=%?, \
+                            remain_width=%?)",
+                           content,
+                           whitespace,
+                           remaining_width);
 
-                    if advance <= remaining_width {
-                        should_continue = true;
+                    if starts_line && left_range.is_empty() && content.is_empty() {
+                        debug!("split_to_width: case=skipping leading trimmable whitespace");
+                        left_range = Range::new(whitespace.end(), 0);
+                        loop
+                    }
 
-                        if starts_line && pieces_processed_count == 0 && glyphs.is_whitespace() {
-                            debug!("split_to_width: case=skipping leading trimmable whitespace");
-                            left_range.shift_by(slice_range.length() as int);
+                    let content_width = run.metrics_for_range(&content).advance_width;
+                    if content_width > remaining_width {
+                        if !left_range.is_empty() {
+                            debug!("split_to_width: case=splitting remainder at break \
+                                    opportunity");
+                            right_range = remainder_from(content.begin());
+                        } else if !starts_line {
+                            debug!("split_to_width: case=nothing fits on this line");
+                            did_fit = false;
+                            right_range = remainder_from(content.begin());
+                        } else if self.overflow_wrap() == OverflowWrapBreakWord {
+                            debug!("split_to_width: case=breaking overlong word between \
+                                    clusters");
+                            let end = run.cluster_break_for_width(&content, remaining_width);
+                            left_range = Range::new(content.begin(), end - content.begin());
+                            right_range = remainder_from(end);
                         } else {
-                            debug!("split_to_width: case=enlarging span");
-                            remaining_width = remaining_width - advance;
-                            left_range.extend_by(slice_range.length() as int);
-                        }
-                    } else {    // The advance is more than the remaining width.
-                        should_continue = false;
-                        let slice_begin = offset + slice_range.begin();
-                        let slice_end = offset + slice_range.end();
-
-                        if glyphs.is_whitespace() {
-                            // If there are still things after the trimmable whitespace, create the
-                            // right chunk.
-                            if slice_end < text_box.range.end() {
-                                debug!("split_to_width: case=skipping trimmable trailing \
-                                        whitespace, then split remainder");
-                                let right_range_end =
-                                    text_box.range.end() - slice_end;
-                                right_range = Some(Range::new(slice_end, right_range_end));
-                            } else {
-                                debug!("split_to_width: case=skipping trimmable trailing \
-                                        whitespace");
-                            }
-                        } else if slice_begin < text_box.range.end() {
-                            // There are still some things left over at the end of the line. Create
-                            // the right chunk.
-                            let right_range_end =
-                                text_box.range.end() - slice_begin;
-                            right_range = Some(Range::new(slice_begin, right_range_end));
-                            debug!("split_to_width: case=splitting remainder with right range=%?",
-                                   right_range);
+                            debug!("split_to_width: case=overflowing with unbreakable text");
+                            did_fit = false;
+                            left_range = content;
+                            right_range = remainder_from(whitespace.end());
                         }
+                        break
                     }
 
-                    pieces_processed_count += 1;
+                    debug!("split_to_width: case=enlarging span");
+                    remaining_width = remaining_width - content_width;
+                    left_range.extend_to(content.end());
 
-                    if !should_continue {
+                    let whitespace_width = run.metrics_for_range(&whitespace).advance_width;
+                    if whitespace_width > remaining_width {
+                        debug!("split_to_width: case=skipping trimmable trailing whitespace");
+                        right_range = remainder_from(whitespace.end());
                         break
                     }
+                    remaining_width = remaining_width - whitespace_width;
+                    left_range.extend_to(whitespace.end());
                 }
 
                 let left_box = if left_range.length() > 0 {
@@ -364,10 +474,10 @@ impl RenderBox {
                     Some(TextRenderBoxClass(new_text_box))
                 };
 
-                if pieces_processed_count == 1 || left_box.is_none() {
-                    SplitDidNotFit(left_box, right_box)
-                } else {
+                if did_fit && left_box.is_some() {
                     SplitDidFit(left_box, right_box)
+                } else {
+                    SplitDidNotFit(left_box, right_box)
                 }
             }
         }
@@ -435,7 +545,7 @@ impl RenderBox {
             }
 
             TextRenderBoxClass(text_box) => {
-                text_box.run.min_width_for_range(&text_box.range)
+                text_box.run.min_width_for_range(&text_box.range, self.word_break())
             }
 
             UnscannedTextRenderBoxClass(*) => fail!(~"Shouldn't see unscanned boxes here.")
@@ -542,6 +652,12 @@ impl RenderBox {
         self.with_base(|base| base.node.style())
     }
 
+    /// A convenience function to access the computed values of the properties that the CSS
+    /// library does not support, for the DOM node that this render box represents.
+    pub fn extra_style(&self) -> @ExtraStyle {
+        self.with_base(|base| base.node.extra_style())
+    }
+
     /// A convenience function to access the DOM node that this render box represents.
     pub fn node(&self) -> AbstractNode<LayoutView> {
         self.with_base(|base| base.node)
@@ -575,6 +691,8 @@ impl RenderBox {
     /// * `dirty`: The dirty rectangle in the coordinate system of the owning flow.
     /// * `origin`: The total offset from the display list root flow to the owning flow of this
     ///   box.
+    /// * `clip`: The rect, in the coordinate system of the display list root, outside of which
+    ///   this box must not paint.
     /// * `list`: The display list to which items should be appended.
     ///
     /// TODO: To implement stacking contexts correctly, we need to create a set of display lists,
@@ -583,9 +701,10 @@ impl RenderBox {
     /// items, each box puts its display items into the correct stack layer according to CSS 2.1
     /// Appendix E. Finally, the builder flattens the list.
     pub fn build_display_list<E:ExtraDisplayListData>(&self,
-                                                  _: &DisplayListBuilder,
+                                                  builder: &DisplayListBuilder,
                                                   dirty: &Rect<Au>,
                                                   offset: &Point2D<Au>,
+                                                  clip: &Rect<Au>,
                                                   list: &Cell<DisplayList<E>>) {
         let box_bounds = self.position();
         let absolute_box_bounds = box_bounds.translate(offset);
@@ -593,7 +712,7 @@ impl RenderBox {
                box_bounds, absolute_box_bounds, self.debug_str());
         debug!("RenderBox::build_display_list: dirty=%?, offset=%?", dirty, offset);
 
-        if absolute_box_bounds.intersects(dirty) {
+        if absolute_box_bounds.intersects(dirty) && absolute_box_bounds.intersects(clip) {
             debug!("RenderBox::build_display_list: intersected. Adding display item...");
         } else {
             debug!("RenderBox::build_display_list: Did not intersect...");
@@ -611,12 +730,14 @@ impl RenderBox {
                     let text_display_item = ~TextDisplayItem {
                         base: BaseDisplayItem {
                             bounds: absolute_box_bounds,
+                            clip: *clip,
                             extra: ExtraDisplayListData::new(*self),
                         },
-                        // FIXME(pcwalton): Allocation? Why?!
-                        text_run: ~text_box.run.serialize(),
+                        text_run: text_box.run.sendable(),
                         range: text_box.range,
                         color: color,
+                        decorations: self.text_decorations(),
+                        shadows: self.text_shadows(),
                     };
 
                     list.append_item(TextDisplayItemClass(text_display_item))
@@ -636,10 +757,13 @@ impl RenderBox {
                         let border_display_item = ~BorderDisplayItem {
                             base: BaseDisplayItem {
                                 bounds: absolute_box_bounds,
+                                clip: *clip,
                                 extra: ExtraDisplayListData::new(*self),
                             },
                             border: debug_border,
-                            color: rgb(0, 0, 200).to_gfx_color(),
+                            color: SideOffsets2D::new_all_same(rgb(0, 0, 200).to_gfx_color()),
+                            style: SideOffsets2D::new_all_same(BorderStyleSolid),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -656,10 +780,13 @@ impl RenderBox {
                         let border_display_item = ~BorderDisplayItem {
                             base: BaseDisplayItem {
                                 bounds: baseline,
+                                clip: *clip,
                                 extra: ExtraDisplayListData::new(*self),
                             },
                             border: debug_border,
-                            color: rgb(0, 200, 0).to_gfx_color(),
+                            color: SideOffsets2D::new_all_same(rgb(0, 200, 0).to_gfx_color()),
+                            style: SideOffsets2D::new_all_same(BorderStyleSolid),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -669,8 +796,10 @@ impl RenderBox {
             },
             GenericRenderBoxClass(_) => {
 
-                // Add the background to the list, if applicable.
-                self.paint_background_if_applicable(list, &absolute_box_bounds);
+                // Add the background and shadows to the list, if applicable.
+                self.paint_box_shadows(list, &absolute_box_bounds, clip, false);
+                self.paint_background_if_applicable(builder, list, &absolute_box_bounds, clip);
+                self.paint_box_shadows(list, &absolute_box_bounds, clip, true);
 
                 // FIXME(pcwalton): This is a bit of an abuse of the logging infrastructure. We
                 // should have a real `SERVO_DEBUG` system.
@@ -684,10 +813,13 @@ impl RenderBox {
                         let border_display_item = ~BorderDisplayItem {
                             base: BaseDisplayItem {
                                 bounds: absolute_box_bounds,
+                                clip: *clip,
                                 extra: ExtraDisplayListData::new(*self),
                             },
                             border: debug_border,
-                            color: rgb(0, 0, 200).to_gfx_color(),
+                            color: SideOffsets2D::new_all_same(rgb(0, 0, 200).to_gfx_color()),
+                            style: SideOffsets2D::new_all_same(BorderStyleSolid),
+                            radii: BorderRadii::zero(),
                         };
                         list.append_item(BorderDisplayItemClass(border_display_item))
                     }
@@ -698,8 +830,10 @@ impl RenderBox {
             },
             ImageRenderBoxClass(image_box) => {
 
-                // Add the background to the list, if applicable.
-                self.paint_background_if_applicable(list, &absolute_box_bounds);
+                // Add the background and shadows to the list, if applicable.
+                self.paint_box_shadows(list, &absolute_box_bounds, clip, false);
+                self.paint_background_if_applicable(builder, list, &absolute_box_bounds, clip);
+                self.paint_box_shadows(list, &absolute_box_bounds, clip, true);
 
                 match image_box.image.get_image() {
                     Some(image) => {
@@ -710,9 +844,11 @@ impl RenderBox {
                             let image_display_item = ~ImageDisplayItem {
                                 base: BaseDisplayItem {
                                     bounds: absolute_box_bounds,
+                                    clip: *clip,
                                     extra: ExtraDisplayListData::new(*self),
                                 },
                                 image: image.clone(),
+                                url: image_box.image.url.clone(),
                             };
                             list.append_item(ImageDisplayItemClass(image_display_item))
                         }
@@ -730,14 +866,48 @@ impl RenderBox {
         // Add a border, if applicable.
         //
         // TODO: Outlines.
-        self.paint_borders_if_applicable(list, &absolute_box_bounds);
+        self.paint_borders_if_applicable(list, &absolute_box_bounds, clip);
+    }
+
+    /// Returns true if this box hides content that overflows it. Scrolling is not supported yet,
+    /// so `overflow: scroll` and `overflow: auto` clip as well.
+    pub fn clips_overflow(&self) -> bool {
+        match self.style().overflow() {
+            CSSOverflowVisible => false,
+            _ => true,
+        }
+    }
+
+    /// Returns the clip rect for the descendants of this box: `clip` itself, narrowed to this
+    /// box's padding box if it hides its overflow. `offset` is as in `build_display_list`.
+    ///
+    /// Iframes need no clip here: the frame's document is laid out and rendered by its own
+    /// pipeline into its own compositor layer, which the compositor clips to the frame's rect.
+    pub fn clip_for_descendants(&self, offset: &Point2D<Au>, clip: &Rect<Au>) -> Rect<Au> {
+        if !self.clips_overflow() {
+            return *clip
+        }
+
+        let border = do self.with_base |base| {
+            base.model.border
+        };
+        let bounds = self.position().translate(offset);
+        let padding_box = Rect(Point2D(bounds.origin.x + border.left, bounds.origin.y + border.top),
+                               Size2D(bounds.size.width - border.left - border.right,
+                                      bounds.size.height - border.top - border.bottom));
+        match padding_box.intersection(clip) {
+            Some(descendant_clip) => descendant_clip,
+            None => Au::zero_rect(),
+        }
     }
 
     /// Adds the display items necessary to paint the background of this render box to the display
     /// list if necessary.
     pub fn paint_background_if_applicable<E:ExtraDisplayListData>(&self,
+                                                              builder: &DisplayListBuilder,
                                                               list: &Cell<DisplayList<E>>,
-                                                              absolute_bounds: &Rect<Au>) {
+                                                              absolute_bounds: &Rect<Au>,
+                                                              clip: &Rect<Au>) {
         // FIXME: This causes a lot of background colors to be displayed when they are clearly not
         // needed. We could use display list optimization to clean this up, but it still seems
         // inefficient. What we really want is something like "nearest ancestor element that
@@ -745,19 +915,349 @@ impl RenderBox {
         let nearest_ancestor_element = self.nearest_ancestor_element();
 
         let background_color = nearest_ancestor_element.style().background_color();
+        let radii = self.border_radii(absolute_bounds);
         if !background_color.alpha.approx_eq(&0.0) {
             do list.with_mut_ref |list| {
                 let solid_color_display_item = ~SolidColorDisplayItem {
                     base: BaseDisplayItem {
                         bounds: *absolute_bounds,
+                        clip: *clip,
                         extra: ExtraDisplayListData::new(*self),
                     },
                     color: background_color.to_gfx_color(),
+                    radii: radii,
                 };
 
                 list.append_item(SolidColorDisplayItemClass(solid_color_display_item))
             }
         }
+
+        match self.background_gradient(absolute_bounds) {
+            Some(ref gradient) => {
+                self.paint_background_gradient(list, absolute_bounds, clip, &radii, gradient)
+            }
+            None => {}
+        }
+
+        match self.background_image() {
+            Some(ref background_image) => {
+                self.paint_background_image(builder,
+                                            list,
+                                            absolute_bounds,
+                                            clip,
+                                            &radii,
+                                            background_image)
+            }
+            None => {}
+        }
+    }
+
+    /// Returns the image that this box's element uses as its background, if any. Gradients are
+    /// returned by `background_gradient` instead.
+    pub fn background_image(&self) -> Option<BackgroundImage> {
+        let extra_style = self.extra_style();
+        let url = match extra_style.background_image {
+            Some(UrlImage(ref url)) => url.clone(),
+            _ => return None,
+        };
+        let position = &extra_style.background_position;
+        Some(BackgroundImage {
+            url: url,
+            repeat: match extra_style.background_repeat {
+                RepeatBoth => BackgroundRepeatBoth,
+                RepeatX => BackgroundRepeatX,
+                RepeatY => BackgroundRepeatY,
+                NoRepeat => BackgroundNoRepeat,
+            },
+            position_x: self.background_length(&position.x),
+            position_y: self.background_length(&position.y),
+            size: match extra_style.background_size {
+                ContainSize => BackgroundSizeContain,
+                CoverSize => BackgroundSizeCover,
+                LengthsSize(ref width, ref height) => {
+                    BackgroundSizeLengths(width.map(|width| self.background_length(width)),
+                                          height.map(|height| self.background_length(height)))
+                }
+            },
+        })
+    }
+
+    /// Adds a display item for a background image, painted across the given border box and
+    /// placed and sized relative to its padding box. Nothing is added until the image has loaded.
+    fn paint_background_image<E:ExtraDisplayListData>(&self,
+                                                      builder: &DisplayListBuilder,
+                                                      list: &Cell<DisplayList<E>>,
+                                                      absolute_bounds: &Rect<Au>,
+                                                      clip: &Rect<Au>,
+                                                      radii: &BorderRadii,
+                                                      background_image: &BackgroundImage) {
+        let mut holder = ImageHolder::new(background_image.url.clone(), builder.ctx.image_cache);
+        let image = match holder.get_image() {
+            Some(image) => image,
+            None => {
+                debug!("(building display list) background image not ready: %s",
+                       background_image.url.to_str());
+                return
+            }
+        };
+        let intrinsic_size = Size2D(Au::from_px(image.get().width as int),
+                                    Au::from_px(image.get().height as int));
+
+        let padding_box = self.padding_box(absolute_bounds);
+        let tile_size = background_image_size(&background_image.size,
+                                              &padding_box.size,
+                                              &intrinsic_size);
+        if tile_size.width <= Au(0) || tile_size.height <= Au(0) {
+            return
+        }
+
+        // Fractions place the same point of the image and of the padding box together, so they
+        // are resolved against the room left over around the image.
+        let x = background_image.position_x.resolve(padding_box.size.width - tile_size.width);
+        let y = background_image.position_y.resolve(padding_box.size.height - tile_size.height);
+        let tile_bounds = Rect(padding_box.origin + Point2D(x, y), tile_size);
+
+        let repeat = background_image.repeat;
+        do list.with_mut_ref |list| {
+            let background_image_display_item = ~BackgroundImageDisplayItem {
+                base: BaseDisplayItem {
+                    bounds: *absolute_bounds,
+                    clip: *clip,
+                    extra: ExtraDisplayListData::new(*self),
+                },
+                image: image.clone(),
+                url: background_image.url.clone(),
+                tile_bounds: tile_bounds,
+                repeat_x: repeat == BackgroundRepeatBoth || repeat == BackgroundRepeatX,
+                repeat_y: repeat == BackgroundRepeatBoth || repeat == BackgroundRepeatY,
+                radii: *radii,
+            };
+            list.append_item(BackgroundImageDisplayItemClass(background_image_display_item))
+        }
+    }
+
+    /// Returns the gradient that this box's element uses as its background image, if any, laid
+    /// out in the given border box.
+    pub fn background_gradient(&self, absolute_bounds: &Rect<Au>) -> Option<BackgroundGradient> {
+        let extra_style = self.extra_style();
+        let gradient = match extra_style.background_image {
+            Some(GradientImage(ref gradient)) => gradient,
+            _ => return None,
+        };
+
+        let width = to_frac_px(absolute_bounds.size.width);
+        let height = to_frac_px(absolute_bounds.size.height);
+        match *gradient {
+            LinearGradientValue(ref direction, ref stops) => {
+                let angle = match *direction {
+                    AngleDirection(angle) => angle,
+                    CornerDirection(right, bottom) => {
+                        // The gradient line is perpendicular to the diagonal between the two
+                        // corners next to the one it points to.
+                        let angle = height.atan2(&width) * 180.0 / float::consts::pi;
+                        match (right, bottom) {
+                            (true, false) => angle,
+                            (true, true) => 180.0 - angle,
+                            (false, true) => 180.0 + angle,
+                            (false, false) => 360.0 - angle,
+                        }
+                    }
+                };
+                Some(LinearBackgroundGradient(angle, self.background_gradient_stops(*stops)))
+            }
+            RadialGradientValue(shape, extent, ref position, ref stops) => {
+                let center = Point2D(self.length_to_au(&position.x, absolute_bounds.size.width),
+                                     self.length_to_au(&position.y, absolute_bounds.size.height));
+                let (x, y) = (to_frac_px(center.x), to_frac_px(center.y));
+                let (left, right) = (x.abs(), (width - x).abs());
+                let (top, bottom) = (y.abs(), (height - y).abs());
+                let (side_x, side_y) = match extent {
+                    ClosestSideExtent | ClosestCornerExtent => (left.min(&right), top.min(&bottom)),
+                    FarthestSideExtent | FarthestCornerExtent => {
+                        (left.max(&right), top.max(&bottom))
+                    }
+                };
+                let (radius_x, radius_y) = match (shape, extent) {
+                    (CircleShape, ClosestSideExtent) => {
+                        let radius = side_x.min(&side_y);
+                        (radius, radius)
+                    }
+                    (CircleShape, FarthestSideExtent) => {
+                        let radius = side_x.max(&side_y);
+                        (radius, radius)
+                    }
+                    (CircleShape, _) => {
+                        let radius = (side_x * side_x + side_y * side_y).sqrt();
+                        (radius, radius)
+                    }
+                    (EllipseShape, ClosestSideExtent) | (EllipseShape, FarthestSideExtent) => {
+                        (side_x, side_y)
+                    }
+                    // The ellipse keeps the proportions it has at the sides and passes through
+                    // the corner.
+                    (EllipseShape, _) => (side_x * (2.0f).sqrt(), side_y * (2.0f).sqrt()),
+                };
+                Some(RadialBackgroundGradient(absolute_bounds.origin + center,
+                                              Size2D(Au::from_frac_px(radius_x),
+                                                     Au::from_frac_px(radius_y)),
+                                              self.background_gradient_stops(*stops)))
+            }
+        }
+    }
+
+    fn background_gradient_stops(&self, stops: &[ColorStopValue]) -> ~[BackgroundGradientStop] {
+        do stops.map |stop| {
+            BackgroundGradientStop {
+                color: self.resolve_color(&stop.color),
+                position: stop.position.map(|position| self.background_length(position)),
+            }
+        }
+    }
+
+    /// Adds a display item for a background gradient covering the given bounds.
+    fn paint_background_gradient<E:ExtraDisplayListData>(&self,
+                                                         list: &Cell<DisplayList<E>>,
+                                                         absolute_bounds: &Rect<Au>,
+                                                         clip: &Rect<Au>,
+                                                         radii: &BorderRadii,
+                                                         gradient: &BackgroundGradient) {
+        let width = to_frac_px(absolute_bounds.size.width);
+        let height = to_frac_px(absolute_bounds.size.height);
+        let center = absolute_bounds.origin + Point2D(Au::from_frac_px(width / 2.0),
+                                                      Au::from_frac_px(height / 2.0));
+
+        do list.with_mut_ref |list| {
+            let base = BaseDisplayItem {
+                bounds: *absolute_bounds,
+                clip: *clip,
+                extra: ExtraDisplayListData::new(*self),
+            };
+            match *gradient {
+                LinearBackgroundGradient(angle, ref stops) => {
+                    // The gradient line passes through the center of the box and is just long
+                    // enough for the lines perpendicular to it at its ends to touch the corners.
+                    let radians = angle * float::consts::pi / 180.0;
+                    let half_length = (width * radians.sin().abs() +
+                                       height * radians.cos().abs()) / 2.0;
+                    let delta = Point2D(Au::from_frac_px(radians.sin() * half_length),
+                                        Au::from_frac_px(-radians.cos() * half_length));
+                    let gradient_display_item = ~LinearGradientDisplayItem {
+                        base: base,
+                        radii: *radii,
+                        start_point: center - delta,
+                        end_point: center + delta,
+                        stops: resolve_gradient_stops(*stops,
+                                                      Au::from_frac_px(half_length * 2.0)),
+                    };
+                    list.append_item(LinearGradientDisplayItemClass(gradient_display_item))
+                }
+                RadialBackgroundGradient(center, radius, ref stops) => {
+                    let gradient_display_item = ~RadialGradientDisplayItem {
+                        base: base,
+                        radii: *radii,
+                        center: center,
+                        radius: radius,
+                        stops: resolve_gradient_stops(*stops, radius.width),
+                    };
+                    list.append_item(RadialGradientDisplayItemClass(gradient_display_item))
+                }
+            }
+        }
+    }
+
+    /// Returns the opacity of this box's element, from 0 for invisible to 1 for opaque.
+    pub fn opacity(&self) -> float {
+        self.extra_style().opacity
+    }
+
+    /// Returns the `z-index` of this box's element, or `None` if it is `auto`.
+    pub fn z_index(&self) -> Option<int> {
+        self.extra_style().z_index
+    }
+
+    /// Returns the z-index of the stacking context this box's element forms, if it forms one.
+    /// Positioned elements with a `z-index` other than `auto` do, and so do elements that are not
+    /// fully opaque, at a z-index of 0.
+    pub fn stacking_context_z_index(&self) -> Option<int> {
+        match self.z_index() {
+            Some(z_index) if self.style().position() != CSSPositionStatic => Some(z_index),
+            _ if self.opacity() < 1.0 => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Returns the padding box of this box, given its border box.
+    pub fn padding_box(&self, absolute_bounds: &Rect<Au>) -> Rect<Au> {
+        let border = do self.with_base |base| {
+            base.model.border
+        };
+        Rect(Point2D(absolute_bounds.origin.x + border.left, absolute_bounds.origin.y + border.top),
+             Size2D(absolute_bounds.size.width - border.left - border.right,
+                    absolute_bounds.size.height - border.top - border.bottom))
+    }
+
+    /// Returns the shadows cast by this box, from the front-most to the back-most.
+    pub fn box_shadows(&self) -> ~[BoxShadow] {
+        do self.extra_style().box_shadow.map |shadow| {
+            BoxShadow {
+                offset: Point2D(self.length_to_au(&shadow.offset_x, Au(0)),
+                                self.length_to_au(&shadow.offset_y, Au(0))),
+                blur_radius: self.length_to_au(&shadow.blur_radius, Au(0)),
+                spread_radius: self.length_to_au(&shadow.spread_radius, Au(0)),
+                color: self.resolve_color(&shadow.color),
+                inset: shadow.inset,
+            }
+        }
+    }
+
+    /// Adds display items for this box's outer shadows, which are painted below its background,
+    /// or for its inset shadows, which are painted above its background and below its border.
+    pub fn paint_box_shadows<E:ExtraDisplayListData>(&self,
+                                                     list: &Cell<DisplayList<E>>,
+                                                     absolute_bounds: &Rect<Au>,
+                                                     clip: &Rect<Au>,
+                                                     inset: bool) {
+        let shadows = self.box_shadows();
+        if shadows.is_empty() {
+            return
+        }
+
+        // Inset shadows are cast inside the padding box, and outer ones around the border box.
+        let box_bounds = if inset {
+            self.padding_box(absolute_bounds)
+        } else {
+            *absolute_bounds
+        };
+
+        // The first shadow is on top, so paint them back to front.
+        for shadow in shadows.rev_iter().filter(|shadow| shadow.inset == inset) {
+            let bounds = if inset {
+                box_bounds
+            } else {
+                let extent = shadow.spread_radius + shadow.blur_radius;
+                let shadow_bounds = absolute_bounds.translate(&shadow.offset);
+                Rect(Point2D(shadow_bounds.origin.x - extent, shadow_bounds.origin.y - extent),
+                     Size2D(shadow_bounds.size.width + extent.scale_by(2.0),
+                            shadow_bounds.size.height + extent.scale_by(2.0)))
+            };
+
+            do list.with_mut_ref |list| {
+                let box_shadow_display_item = ~BoxShadowDisplayItem {
+                    base: BaseDisplayItem {
+                        bounds: bounds,
+                        clip: *clip,
+                        extra: ExtraDisplayListData::new(*self),
+                    },
+                    box_bounds: box_bounds,
+                    offset: shadow.offset,
+                    blur_radius: shadow.blur_radius,
+                    spread_radius: shadow.spread_radius,
+                    color: shadow.color.to_gfx_color(),
+                    inset: shadow.inset,
+                };
+                list.append_item(BoxShadowDisplayItemClass(box_shadow_display_item))
+            }
+        }
     }
 
     pub fn clear(&self) -> Option<ClearType> {
@@ -770,6 +1270,52 @@ impl RenderBox {
         }
     }
 
+    /// Returns the font size of the nearest ancestor-or-self element in pixels, which `em`
+    /// lengths are relative to.
+    pub fn font_size(&self) -> float {
+        match self.nearest_ancestor_element().style().font_size() {
+            CSSFontSizeLength(Px(length)) => length,
+            CSSFontSizeLength(Pt(length)) => pt_to_px(length),
+            // todo: this is based on a hard coded font size, should be the parent element's font size
+            CSSFontSizeLength(Em(length)) => length * 16f, 
+            _ => 16f // px units
+        }
+    }
+
+    /// Converts a length of the extra style to app units. Percentages are of `reference`.
+    pub fn length_to_au(&self, length: &LengthValue, reference: Au) -> Au {
+        match *length {
+            LengthPx(length) => Au::from_frac_px(length),
+            LengthEm(length) => Au::from_frac_px(length * self.font_size()),
+            LengthPercentage(percent) => reference.scale_by(percent / 100.0),
+        }
+    }
+
+    /// Converts a length of the extra style to one for placing backgrounds, which keeps
+    /// percentages until the length they are of is known.
+    pub fn background_length(&self, length: &LengthValue) -> BackgroundLength {
+        match *length {
+            LengthPercentage(percent) => BackgroundFraction(percent / 100.0),
+            _ => BackgroundLengthAu(self.length_to_au(length, Au(0))),
+        }
+    }
+
+    /// Converts a color of the extra style, where `currentColor` is the `color` of the nearest
+    /// ancestor-or-self element.
+    pub fn resolve_color(&self, color: &ColorValue) -> Color {
+        match *color {
+            CurrentColor => self.nearest_ancestor_element().style().color(),
+            RgbaColor(red, green, blue, alpha) => {
+                Color {
+                    red: red,
+                    green: green,
+                    blue: blue,
+                    alpha: alpha,
+                }
+            }
+        }
+    }
+
     /// Converts this node's computed style to a font style used for rendering.
     pub fn font_style(&self) -> FontStyle {
         let my_style = self.nearest_ancestor_element().style();
@@ -777,7 +1323,8 @@ impl RenderBox {
         debug!("(font style) start: %?", self.nearest_ancestor_element().type_id());
 
         // FIXME: Too much allocation here.
-        let font_families = do my_style.font_family().map |family| {
+        let families = my_style.font_family();
+        let font_families = do families.map |family| {
             match *family {
                 CSSFontFamilyFamilyName(ref family_str) => (*family_str).clone(),
                 CSSFontFamilyGenericFamily(Serif)       => ~"serif",
@@ -790,13 +1337,7 @@ impl RenderBox {
         let font_families = font_families.connect(", ");
         debug!("(font style) font families: `%s`", font_families);
 
-        let font_size = match my_style.font_size() {
-            CSSFontSizeLength(Px(length)) => length,
-            CSSFontSizeLength(Pt(length)) => pt_to_px(length),
-            // todo: this is based on a hard coded font size, should be the parent element's font size
-            CSSFontSizeLength(Em(length)) => length * 16f, 
-            _ => 16f // px units
-        };
+        let font_size = self.font_size();
         debug!("(font style) font size: `%fpx`", font_size);
 
         let (italic, oblique) = match my_style.font_style() {
@@ -805,12 +1346,24 @@ impl RenderBox {
             CSSFontStyleOblique => (false, true),
         };
 
+        // FIXME: The CSS library does not support `font-variant` or `font-feature-settings` yet,
+        // so the features keep their defaults, except that ligatures are turned off in monospace
+        // text, where they would break the alignment of columns.
+        let mut features = FontFeatures::default();
+        features.ligatures = !do families.iter().any |family| {
+            match *family {
+                CSSFontFamilyGenericFamily(Monospace) => true,
+                _ => false,
+            }
+        };
+
         FontStyle {
             pt_size: font_size,
             weight: FontWeight300,
             italic: italic,
             oblique: oblique,
             families: font_families,
+            features: features,
         }
     }
 
@@ -824,44 +1377,136 @@ impl RenderBox {
         self.nearest_ancestor_element().style().line_height()
     }
 
-    /// Returns the text decoration of the computed style of the nearest `Element` node
-    pub fn text_decoration(&self) -> CSSTextDecoration {
-        /// Computes the propagated value of text-decoration, as specified in CSS 2.1 § 16.3.1
-        /// TODO: make sure this works with anonymous box generation.
-        fn get_propagated_text_decoration(element: AbstractNode<LayoutView>) -> CSSTextDecoration {
-            //Skip over non-element nodes in the DOM
-            if(!element.is_element()){
-                return match element.parent_node() {
-                    None => CSSTextDecorationNone,
-                    Some(parent) => get_propagated_text_decoration(parent),
+    /// Returns where lines may break within the words of this box's text.
+    pub fn word_break(&self) -> WordBreak {
+        match self.nearest_ancestor_element().extra_style().word_break {
+            NormalWordBreak => WordBreakNormal,
+            BreakAllWordBreak => WordBreakBreakAll,
+            KeepAllWordBreak => WordBreakKeepAll,
+        }
+    }
+
+    /// Returns whether words too long for a line may be broken in this box's text.
+    pub fn overflow_wrap(&self) -> OverflowWrap {
+        match self.nearest_ancestor_element().extra_style().overflow_wrap {
+            NormalOverflowWrap => OverflowWrapNormal,
+            BreakWordOverflowWrap => OverflowWrapBreakWord,
+        }
+    }
+
+    /// Returns the bidirectional embedding level of the paragraph this box is in: 1 if the `dir`
+    /// attribute of the nearest ancestor-or-self element that has a valid one is "rtl", and 0
+    /// otherwise. If it is "auto", the level comes from the first strong character of `text`,
+    /// which must be the text of the whole paragraph.
+    ///
+    /// FIXME: The CSS library does not support `direction` or `unicode-bidi` yet, so only the
+    /// attribute is honoured.
+    pub fn base_bidi_level(&self, text: &str) -> u8 {
+        let mut node = self.nearest_ancestor_element();
+        loop {
+            if node.is_element() {
+                let level = do node.with_imm_element |element| {
+                    match element.get_attr("dir").map(|dir| dir.to_ascii_lower()) {
+                        Some(dir) => {
+                            match dir.as_slice() {
+                                "ltr" => Some(0u8),
+                                "rtl" => Some(1u8),
+                                "auto" => Some(bidi::paragraph_level(text).unwrap_or(0)),
+                                _ => None,
+                            }
+                        }
+                        None => None,
+                    }
                 };
+                if level.is_some() {
+                    return level.unwrap();
+                }
             }
+            match node.parent_node() {
+                Some(parent) => node = parent,
+                None => return 0,
+            }
+        }
+    }
 
-            //FIXME: is the root param on display() important?
-            let display_in_flow = match element.style().display(false) {
-                CSSDisplayInlineTable | CSSDisplayInlineBlock => false,
-                _ => true,
-            };
-
-            let position = element.style().position();
-            let float = element.style().float();
+    /// Returns the embedding level this box is displayed at. Text boxes are split wherever the
+    /// direction of their text changes, so each has a single level. Other boxes are at the level
+    /// of their paragraph, which is given.
+    pub fn bidi_level(&self, paragraph_level: u8) -> u8 {
+        match *self {
+            TextRenderBoxClass(text_box) => {
+                match text_box.run.level_ranges_for_range(&text_box.range).head_opt() {
+                    Some(&(level, _)) => level,
+                    None => paragraph_level,
+                }
+            }
+            _ => paragraph_level,
+        }
+    }
 
-            let in_flow = (position == CSSPositionStatic) && (float == CSSFloatNone) &&
-                display_in_flow;
+    /// Returns the lines to draw along this box's text. Following CSS 2.1 § 16.3.1, these come
+    /// from the `text-decoration` of the nearest ancestor element and of its ancestors up to the
+    /// nearest float, absolutely positioned box, inline block or inline table, each in the color
+    /// of the element that specifies it.
+    ///
+    /// TODO: make sure this works with anonymous box generation.
+    pub fn text_decorations(&self) -> TextDecorations {
+        let mut decorations = TextDecorations::none();
+        let mut node = Some(self.nearest_ancestor_element());
+        loop {
+            let element = match node {
+                None => break,
+                Some(element) => element,
+            };
 
-            let text_decoration = element.style().text_decoration();
+            // Skip over non-element nodes in the DOM.
+            node = element.parent_node();
+            if !element.is_element() {
+                loop
+            }
 
-            if(text_decoration == CSSTextDecorationNone && in_flow){
-                match element.parent_node() {
-                    None => CSSTextDecorationNone,
-                    Some(parent) => get_propagated_text_decoration(parent),
+            // Decorations specified closer to the text are drawn over the same lines from
+            // further out, so only the closest of each is kept.
+            let style = element.style();
+            let color = Some(style.color().to_gfx_color());
+            match style.text_decoration() {
+                CSSTextDecorationUnderline if decorations.underline.is_none() => {
+                    decorations.underline = color
+                }
+                CSSTextDecorationOverline if decorations.overline.is_none() => {
+                    decorations.overline = color
                 }
+                CSSTextDecorationLineThrough if decorations.line_through.is_none() => {
+                    decorations.line_through = color
+                }
+                _ => {}
             }
-            else {
-                text_decoration
+
+            // FIXME: is the root param on display() important?
+            let display_in_flow = match style.display(false) {
+                CSSDisplayInlineTable | CSSDisplayInlineBlock => false,
+                _ => true,
+            };
+            let in_flow = style.position() == CSSPositionStatic && style.float() == CSSFloatNone &&
+                display_in_flow;
+            if !in_flow {
+                break
+            }
+        }
+        decorations
+    }
+
+    /// Returns the shadows of this box's text, from the top one to the bottom one, as given by
+    /// the `text-shadow` of the nearest ancestor element.
+    pub fn text_shadows(&self) -> ~[TextShadow] {
+        do self.nearest_ancestor_element().extra_style().text_shadow.map |shadow| {
+            TextShadow {
+                offset: Point2D(self.length_to_au(&shadow.offset_x, Au(0)),
+                                self.length_to_au(&shadow.offset_y, Au(0))),
+                blur_radius: self.length_to_au(&shadow.blur_radius, Au(0)),
+                color: self.resolve_color(&shadow.color).to_gfx_color(),
             }
         }
-        get_propagated_text_decoration(self.nearest_ancestor_element())
     }
 
     /// Dumps this node, for debugging.
@@ -886,8 +1531,9 @@ impl RenderBox {
             GenericRenderBoxClass(*) => ~"GenericRenderBox",
             ImageRenderBoxClass(*) => ~"ImageRenderBox",
             TextRenderBoxClass(text_box) => {
-                fmt!("TextRenderBox(text=%s)", text_box.run.text.slice_chars(text_box.range.begin(),
-                                                                             text_box.range.end()))
+                let text = text_box.run.text().slice_chars(text_box.range.begin(),
+                                                           text_box.range.end());
+                fmt!("TextRenderBox(text=%s)", text)
             }
             UnscannedTextRenderBoxClass(text_box) => {
                 fmt!("UnscannedTextRenderBox(%s)", text_box.text)
@@ -901,11 +1547,29 @@ impl RenderBox {
     // Painting
     //
 
+    /// Returns the radii of the outer corners of this box's border, scaled down to fit the given
+    /// border box. Its background is clipped to them as well.
+    pub fn border_radii(&self, abs_bounds: &Rect<Au>) -> BorderRadii {
+        let extra_style = self.extra_style();
+        let radius = |radius: &RadiusValue| {
+            Size2D(self.length_to_au(&radius.horizontal, abs_bounds.size.width),
+                   self.length_to_au(&radius.vertical, abs_bounds.size.height))
+        };
+        let radii = BorderRadii {
+            top_left: radius(&extra_style.border_top_left_radius),
+            top_right: radius(&extra_style.border_top_right_radius),
+            bottom_right: radius(&extra_style.border_bottom_right_radius),
+            bottom_left: radius(&extra_style.border_bottom_left_radius),
+        };
+        radii.scaled_to_fit(&abs_bounds.size)
+    }
+
     /// Adds the display items necessary to paint the borders of this render box to a display list
     /// if necessary.
     pub fn paint_borders_if_applicable<E:ExtraDisplayListData>(&self,
                                                                list: &Cell<DisplayList<E>>,
-                                                               abs_bounds: &Rect<Au>) {
+                                                               abs_bounds: &Rect<Au>,
+                                                               clip: &Rect<Au>) {
         // Fast path.
         let border = do self.with_base |base| {
             base.model.border
@@ -914,15 +1578,23 @@ impl RenderBox {
             return
         }
 
-        // FIXME: all colors set to top color. this is obviously not right.
-        let top_color = self.style().border_top_color();
-        let color = top_color.to_gfx_color();
+        let style = self.style();
+        let color = SideOffsets2D::new(style.border_top_color().to_gfx_color(),
+                                       style.border_right_color().to_gfx_color(),
+                                       style.border_bottom_color().to_gfx_color(),
+                                       style.border_left_color().to_gfx_color());
+        let border_style = SideOffsets2D::new(to_gfx_border_style(style.border_top_style()),
+                                              to_gfx_border_style(style.border_right_style()),
+                                              to_gfx_border_style(style.border_bottom_style()),
+                                              to_gfx_border_style(style.border_left_style()));
+        let radii = self.border_radii(abs_bounds);
 
         // Append the border to the display list.
         do list.with_mut_ref |list| {
             let border_display_item = ~BorderDisplayItem {
                 base: BaseDisplayItem {
                     bounds: *abs_bounds,
+                    clip: *clip,
                     extra: ExtraDisplayListData::new(*self),
                 },
                 border: SideOffsets2D::new(border.top,
@@ -930,9 +1602,127 @@ impl RenderBox {
                                            border.bottom,
                                            border.left),
                 color: color,
+                style: border_style,
+                radii: radii,
             };
 
             list.append_item(BorderDisplayItemClass(border_display_item))
         }
     }
 }
+
+fn to_gfx_border_style(style: CSSBorderStyle) -> BorderStyle {
+    match style {
+        CSSBorderStyleNone => BorderStyleNone,
+        CSSBorderStyleHidden => BorderStyleHidden,
+        CSSBorderStyleDotted => BorderStyleDotted,
+        CSSBorderStyleDashed => BorderStyleDashed,
+        CSSBorderStyleSolid => BorderStyleSolid,
+        CSSBorderStyleDouble => BorderStyleDouble,
+        CSSBorderStyleGroove => BorderStyleGroove,
+        CSSBorderStyleRidge => BorderStyleRidge,
+        CSSBorderStyleInset => BorderStyleInset,
+        CSSBorderStyleOutset => BorderStyleOutset,
+    }
+}
+
+/// Returns the size a background image with the given intrinsic size is drawn at in a padding box
+/// of the given size. A missing width or height keeps the image's aspect ratio.
+fn background_image_size(size: &BackgroundSize, area: &Size2D<Au>, intrinsic: &Size2D<Au>)
+                         -> Size2D<Au> {
+    if intrinsic.width <= Au(0) || intrinsic.height <= Au(0) {
+        return Size2D(Au(0), Au(0))
+    }
+
+    let width_ratio = to_frac_px(area.width) / to_frac_px(intrinsic.width);
+    let height_ratio = to_frac_px(area.height) / to_frac_px(intrinsic.height);
+    match *size {
+        BackgroundSizeContain => {
+            let scale = width_ratio.min(&height_ratio);
+            Size2D(intrinsic.width.scale_by(scale), intrinsic.height.scale_by(scale))
+        }
+        BackgroundSizeCover => {
+            let scale = width_ratio.max(&height_ratio);
+            Size2D(intrinsic.width.scale_by(scale), intrinsic.height.scale_by(scale))
+        }
+        BackgroundSizeLengths(None, None) => *intrinsic,
+        BackgroundSizeLengths(Some(ref width), None) => {
+            let width = width.resolve(area.width);
+            let scale = to_frac_px(width) / to_frac_px(intrinsic.width);
+            Size2D(width, intrinsic.height.scale_by(scale))
+        }
+        BackgroundSizeLengths(None, Some(ref height)) => {
+            let height = height.resolve(area.height);
+            let scale = to_frac_px(height) / to_frac_px(intrinsic.height);
+            Size2D(intrinsic.width.scale_by(scale), height)
+        }
+        BackgroundSizeLengths(Some(ref width), Some(ref height)) => {
+            Size2D(width.resolve(area.width), height.resolve(area.height))
+        }
+    }
+}
+
+/// Gives every stop of a gradient a position, as a fraction of the length of the gradient line, as
+/// CSS requires: unpositioned first and last stops go at the ends of the gradient line, no stop may
+/// come before the one preceding it, and any other unpositioned stops are spaced evenly between
+/// their positioned neighbors.
+fn resolve_gradient_stops(stops: &[BackgroundGradientStop], line_length: Au) -> ~[GradientStop] {
+    if stops.is_empty() {
+        return ~[]
+    }
+
+    let mut positions = do stops.iter().map |stop| {
+        do stop.position.map |position| {
+            match *position {
+                BackgroundFraction(fraction) => fraction,
+                BackgroundLengthAu(_) if line_length <= Au(0) => 0.0,
+                BackgroundLengthAu(length) => to_frac_px(length) / to_frac_px(line_length),
+            }
+        }
+    };
+    let mut positions: ~[Option<float>] = positions.collect();
+    let last = positions.len() - 1;
+    if positions[0].is_none() {
+        positions[0] = Some(0.0);
+    }
+    if positions[last].is_none() {
+        positions[last] = Some(1.0);
+    }
+
+    let mut max_position = positions[0].unwrap();
+    for position in positions.mut_iter() {
+        match *position {
+            Some(value) => {
+                max_position = max_position.max(&value);
+                *position = Some(max_position);
+            }
+            None => {}
+        }
+    }
+
+    let mut i = 1;
+    while i < last {
+        if positions[i].is_none() {
+            let start = i - 1;
+            let mut end = i + 1;
+            while positions[end].is_none() {
+                end += 1;
+            }
+            let (from, to) = (positions[start].unwrap(), positions[end].unwrap());
+            for j in range(i, end) {
+                let fraction = ((j - start) as float) / ((end - start) as float);
+                positions[j] = Some(from + (to - from) * fraction);
+            }
+            i = end;
+        }
+        i += 1;
+    }
+
+    let mut gradient_stops = do stops.iter().zip(positions.iter()).map |(stop, position)| {
+        GradientStop {
+            offset: position.unwrap() as f32,
+            color: stop.color.to_gfx_color(),
+        }
+    };
+    gradient_stops.collect()
+}
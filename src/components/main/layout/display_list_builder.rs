@@ -6,8 +6,14 @@
 
 use layout::box::RenderBox;
 use layout::context::LayoutContext;
+use layout::flow::FlowContext;
 
+use std::cell::Cell;
+use geom::rect::Rect;
 use gfx;
+use gfx::display_list::{BaseDisplayItem, DisplayList, StackingContextDisplayItem};
+use gfx::display_list::{StackingContextDisplayItemClass};
+use gfx::geometry::Au;
 use newcss;
 
 /// Extra display list data is either nothing (if the display list is to be rendered) or the
@@ -42,6 +48,63 @@ pub struct DisplayListBuilder<'self> {
     ctx:  &'self LayoutContext,
 }
 
+impl<'self> DisplayListBuilder<'self> {
+    /// Builds the items of a flow that forms a stacking context, then those of its descendants,
+    /// into `list`, and puts the stacking contexts among them into painting order.
+    /// `build_own_items` adds the items of the flow itself to `list` and positions its children.
+    pub fn build_stacking_context_items<E:ExtraDisplayListData>(&self,
+                                                               flow: &FlowContext,
+                                                               dirty: &Rect<Au>,
+                                                               list: &Cell<DisplayList<E>>,
+                                                               build_own_items: &fn()) {
+        build_own_items();
+        let background_len = do list.with_ref |list| {
+            list.len()
+        };
+
+        for child in flow.children() {
+            do child.partially_traverse_preorder |descendant| {
+                descendant.build_display_list(self, dirty, list)
+            }
+        }
+
+        do list.with_mut_ref |list| {
+            list.sort_stacking_contexts(background_len)
+        }
+    }
+
+    /// Adds a stacking context formed by the given flow and its box to `list`, containing the
+    /// items of the flow and its descendants as `build_stacking_context_items` builds them.
+    /// `build_own` adds the items of the flow itself to the list it is given.
+    pub fn build_stacking_context<E:ExtraDisplayListData>(&self,
+                                                         flow: &FlowContext,
+                                                         box: RenderBox,
+                                                         z_index: int,
+                                                         dirty: &Rect<Au>,
+                                                         list: &Cell<DisplayList<E>>,
+                                                         build_own: &fn(&Cell<DisplayList<E>>)) {
+        let context_list = Cell::new(DisplayList::new());
+        self.build_stacking_context_items(flow, dirty, &context_list, || {
+            build_own(&context_list)
+        });
+
+        do list.with_mut_ref |list| {
+            let context_list = context_list.take();
+            let stacking_context_display_item = ~StackingContextDisplayItem {
+                base: BaseDisplayItem {
+                    bounds: context_list.bounds(),
+                    clip: Au::max_rect(),
+                    extra: ExtraDisplayListData::new(box),
+                },
+                z_index: z_index,
+                opacity: box.opacity() as f32,
+                list: context_list,
+            };
+            list.append_item(StackingContextDisplayItemClass(stacking_context_display_item))
+        }
+    }
+}
+
 //
 // Miscellaneous useful routines
 //
@@ -300,13 +300,41 @@ impl FloatFlowData {
             return false;
         }
 
+        match self.box {
+            Some(box) => match box.stacking_context_z_index() {
+                Some(z_index) => {
+                    let flow = FloatFlow(self);
+                    do builder.build_stacking_context(&flow, box, z_index, dirty, list) |list| {
+                        self.build_own_display_items(builder, dirty, list)
+                    }
+                    return false
+                }
+                None => {}
+            },
+            None => {}
+        }
+
+        self.build_own_display_items(builder, dirty, list);
+        true
+    }
 
+    // Adds the items of this float's box to the list and positions its children for building
+    // theirs.
+    fn build_own_display_items<E:ExtraDisplayListData>(@mut self,
+                                                       builder: &DisplayListBuilder,
+                                                       dirty: &Rect<Au>,
+                                                       list: &Cell<DisplayList<E>>) {
         let offset = self.common.abs_position + self.rel_pos;
         // add box that starts block context
+        let clip = self.common.clip;
         self.box.map(|&box| {
-            box.build_display_list(builder, dirty, &offset, list)
+            box.build_display_list(builder, dirty, &offset, &clip, list)
         });
 
+        let child_clip = match self.box {
+            Some(box) => box.clip_for_descendants(&offset, &clip),
+            None => clip,
+        };
 
         // TODO: handle any out-of-flow elements
 
@@ -315,10 +343,9 @@ impl FloatFlowData {
         for child in flow.children() {
             do child.with_mut_base |base| {
                 base.abs_position = offset + base.position.origin;
+                base.clip = child_clip;
             }
         }
-
-        true
     }
 }
 
@@ -230,6 +230,9 @@ pub struct FlowData {
     floats_out: FloatContext,
     num_floats: uint,
     abs_position: Point2D<Au>,
+    /// The rect outside of which the contents of this flow are not painted, in the coordinate
+    /// system of the display list root. Set by the parent flow when it builds its display list.
+    clip: Rect<Au>,
     is_inorder: bool,
 }
 
@@ -271,6 +274,7 @@ impl FlowData {
             floats_out: Invalid,
             num_floats: 0,
             abs_position: Point2D(Au(0), Au(0)),
+            clip: Au::max_rect(),
             is_inorder: false
         }
     }
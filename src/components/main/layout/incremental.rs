@@ -123,7 +123,8 @@ pub fn compute_damage(node: &AbstractNode<LayoutView>,
 
     add_if_not_equal!([ Repaint ],
         [ color, background_color, border_top_color, border_right_color,
-          border_bottom_color, border_left_color ]);
+          border_bottom_color, border_left_color, border_top_style, border_right_style,
+          border_bottom_style, border_left_style ]);
 
     add_if_not_equal!([ Repaint, BubbleWidths, Reflow ],
         [ border_top_width, border_right_width, border_bottom_width,
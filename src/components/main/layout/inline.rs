@@ -11,13 +11,16 @@ use layout::flow::{FlowContext, FlowData, InlineFlow};
 use layout::float_context::FloatContext;
 use layout::util::{ElementMapping};
 use layout::float_context::{PlacementInfo, FloatLeft};
+use layout::text;
 
 use std::u16;
 use std::util;
 use geom::{Point2D, Rect, Size2D};
 use gfx::display_list::DisplayList;
 use gfx::geometry::Au;
-use newcss::values::{CSSTextAlignLeft, CSSTextAlignCenter, CSSTextAlignRight, CSSTextAlignJustify};
+use gfx::text::bidi;
+use newcss::values::{CSSTextAlign, CSSTextAlignLeft, CSSTextAlignCenter, CSSTextAlignRight};
+use newcss::values::CSSTextAlignJustify;
 use newcss::units::{Em, Px, Pt};
 use newcss::values::{CSSLineHeightNormal, CSSLineHeightNumber, CSSLineHeightLength, CSSLineHeightPercentage};
 use servo_util::range::Range;
@@ -127,6 +130,7 @@ impl LineboxScanner {
                     debug!("LineboxScanner: Working with box from work list: b%d", box.id());
                     box
                 };
+                let cur_box = self.split_at_direction_change(cur_box);
 
                 let box_was_appended = self.try_append_to_line(cur_box);
                 if !box_was_appended {
@@ -162,6 +166,35 @@ impl LineboxScanner {
         util::swap(&mut inline.lines, &mut self.lines);
     }
 
+    /// Splits off the part of a text box before the first change in the direction of its text,
+    /// deferring the rest, so that every box on a line has a single embedding level by which it
+    /// can be reordered.
+    fn split_at_direction_change(&mut self, box: RenderBox) -> RenderBox {
+        match box {
+            TextRenderBoxClass(text_box) => {
+                let level_ranges = text_box.run.level_ranges_for_range(&text_box.range);
+                if level_ranges.len() <= 1 {
+                    return box;
+                }
+
+                let (_, first_range) = level_ranges[0];
+                let rest_range = Range::new(first_range.end(),
+                                            text_box.range.end() - first_range.end());
+                debug!("LineboxScanner: Splitting box b%d at change of direction: %? and %?",
+                       box.id(), first_range, rest_range);
+                let rest_box = text::adapt_textbox_with_range(text_box.base,
+                                                              text_box.run,
+                                                              rest_range);
+                self.work_list.push_front(TextRenderBoxClass(@mut rest_box));
+                let first_box = text::adapt_textbox_with_range(text_box.base,
+                                                               text_box.run,
+                                                               first_range);
+                TextRenderBoxClass(@mut first_box)
+            }
+            _ => box,
+        }
+    }
+
     fn flush_current_line(&mut self) {
         debug!("LineboxScanner: Flushing line %u: %?",
                self.lines.len(), self.pending_line);
@@ -477,7 +510,10 @@ pub struct InlineFlowData {
     // vec of ranges into boxes that represent elements. These ranges
     // must be well-nested, and are only related to the content of
     // boxes (not lines). Ranges are only kept for non-leaf elements.
-    elems: ElementMapping
+    elems: ElementMapping,
+    /// The bidirectional embedding level of the paragraph that the text of the flow forms. Set
+    /// when the text runs are made.
+    base_bidi_level: u8,
 }
 
 impl InlineFlowData {
@@ -487,6 +523,7 @@ impl InlineFlowData {
             boxes: ~[],
             lines: ~[],
             elems: ElementMapping::new(),
+            base_bidi_level: 0,
         }
     }
 
@@ -619,47 +656,30 @@ impl InlineFlowData {
             // Get the text alignment.
             // TODO(Issue #222): use 'text-align' property from InlineFlow's
             // block container, not from the style of the first box child.
-            let linebox_align;
-            if line.range.begin() < self.boxes.len() {
-                let first_box = self.boxes[line.range.begin()];
-                linebox_align = first_box.text_align();
+            let linebox_align = if line.range.begin() < self.boxes.len() {
+                self.boxes[line.range.begin()].text_align()
             } else {
                 // Nothing to lay out, so assume left alignment.
-                linebox_align = CSSTextAlignLeft;
-            }
+                CSSTextAlignLeft
+            };
 
             // Set the box x positions
-            let mut offset_x = line.bounds.origin.x;
-            match linebox_align {
-                // So sorry, but justified text is more complicated than shuffling linebox coordinates.
-                // TODO(Issue #213): implement `text-align: justify`
-                CSSTextAlignLeft | CSSTextAlignJustify => {
-                    for i in line.range.eachi() {
-                        do self.boxes[i].with_mut_base |base| {
-                            base.position.origin.x = offset_x;
-                            offset_x = offset_x + base.position.size.width;
-                        }
-                    }
+            let base_level = self.base_bidi_level;
+            let mut offset_x = line.bounds.origin.x + line_align_offset(linebox_align,
+                                                                        base_level,
+                                                                        slack_width);
+
+            // The boxes stay in logical order, but are placed from left to right in the order
+            // their embedding levels give (UAX #9 rule L2).
+            let levels: ~[u8] = do line.range.eachi().map |i| {
+                self.boxes[i].bidi_level(base_level)
+            }.collect();
+            for &j in bidi::visual_order(levels).iter() {
+                do self.boxes[line.range.begin() + j].with_mut_base |base| {
+                    base.position.origin.x = offset_x;
+                    offset_x = offset_x + base.position.size.width;
                 }
-                CSSTextAlignCenter => {
-                    offset_x = offset_x + slack_width.scale_by(0.5f);
-                    for i in line.range.eachi() {
-                        do self.boxes[i].with_mut_base |base| {
-                            base.position.origin.x = offset_x;
-                            offset_x = offset_x + base.position.size.width;
-                        }
-                    }
-                }
-                CSSTextAlignRight => {
-                    offset_x = offset_x + slack_width;
-                    for i in line.range.eachi() {
-                        do self.boxes[i].with_mut_base |base| {
-                            base.position.origin.x = offset_x;
-                            offset_x = offset_x + base.position.size.width;
-                        }
-                    }
-                }
-            };
+            }
 
 
             // Get the baseline offset, assuming that the tallest text box will determine
@@ -767,7 +787,11 @@ impl InlineFlowData {
                self.boxes.len());
 
         for box in self.boxes.iter() {
-            box.build_display_list(builder, dirty, &self.common.abs_position, list)
+            box.build_display_list(builder,
+                                   dirty,
+                                   &self.common.abs_position,
+                                   &self.common.clip,
+                                   list)
         }
 
         // TODO(#225): Should `inline-block` elements have flows as children of the inline flow or
@@ -778,3 +802,35 @@ impl InlineFlowData {
     }
 }
 
+
+/// Returns how far right of the start of the green zone the boxes of a line begin, given the
+/// line's text alignment, the bidirectional embedding level of its paragraph and the width it
+/// leaves unused.
+///
+/// FIXME: The CSS library does not support `text-align: start` or `end`, and reports the initial
+/// value as `left`, so `left` is taken to be the initial value and aligns right-to-left paragraphs
+/// to the right.
+pub fn line_align_offset(align: CSSTextAlign, base_level: u8, slack_width: Au) -> Au {
+    let rtl = base_level % 2 == 1;
+    match align {
+        // So sorry, but justified text is more complicated than shuffling linebox coordinates.
+        // TODO(Issue #213): implement `text-align: justify`
+        CSSTextAlignLeft | CSSTextAlignJustify if rtl => slack_width,
+        CSSTextAlignLeft | CSSTextAlignJustify => Au(0),
+        CSSTextAlignCenter => slack_width.scale_by(0.5f),
+        CSSTextAlignRight => slack_width,
+    }
+}
+
+#[test]
+fn test_line_align_offset() {
+    let slack = Au::from_px(40);
+    assert!(line_align_offset(CSSTextAlignLeft, 0, slack) == Au(0));
+    assert!(line_align_offset(CSSTextAlignJustify, 0, slack) == Au(0));
+    assert!(line_align_offset(CSSTextAlignLeft, 1, slack) == slack);
+    assert!(line_align_offset(CSSTextAlignJustify, 1, slack) == slack);
+    assert!(line_align_offset(CSSTextAlignLeft, 2, slack) == Au(0));
+    assert!(line_align_offset(CSSTextAlignCenter, 1, slack) == Au::from_px(20));
+    assert!(line_align_offset(CSSTextAlignRight, 0, slack) == slack);
+    assert!(line_align_offset(CSSTextAlignRight, 1, slack) == slack);
+}
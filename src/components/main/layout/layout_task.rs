@@ -5,24 +5,27 @@
 //! The layout task. Performs layout on the DOM, builds display lists and sends them to be
 /// rendered.
 
+use css::extra_select::ExtraSelectCtx;
 use css::matching::MatchMethods;
 use css::select::new_css_select_ctx;
 use layout::aux::{LayoutData, LayoutAuxMethods};
-use layout::box::RenderBox;
+use layout::box::{RenderBox, RenderBoxBase};
 use layout::box_builder::LayoutTreeBuilder;
 use layout::context::LayoutContext;
 use layout::display_list_builder::{DisplayListBuilder};
-use layout::flow::FlowContext;
+use layout::flow::{FlowContext, FlowData};
 use layout::incremental::{RestyleDamage, BubbleWidths};
 
 use std::cast::transmute;
 use std::cell::Cell;
-use std::comm::{Port};
+use std::comm::{Port, SharedChan, stream};
+use std::sys;
 use geom::point::Point2D;
 use geom::rect::Rect;
 use geom::size::Size2D;
 use gfx::display_list::DisplayList;
-use gfx::font_context::FontContext;
+use gfx::font::{FontFaceRule, ShapeCache, WebFontData};
+use gfx::font_context::{FontContext, can_load_web_font};
 use gfx::geometry::Au;
 use gfx::opts::Opts;
 use gfx::render_task::{RenderMsg, RenderChan, RenderLayer};
@@ -31,19 +34,24 @@ use newcss::stylesheet::Stylesheet;
 use newcss::types::OriginAuthor;
 use script::dom::event::ReflowEvent;
 use script::dom::node::{AbstractNode, LayoutView};
-use script::layout_interface::{AddStylesheetMsg, ContentBoxQuery};
+use script::html::cssparse::RawStylesheet;
+use script::layout_interface::{AddFontFaceMsg, AddRawStylesheetMsg, AddStylesheetMsg};
+use script::layout_interface::ContentBoxQuery;
 use script::layout_interface::{HitTestQuery, ContentBoxResponse, HitTestResponse};
 use script::layout_interface::{ContentBoxesQuery, ContentBoxesResponse, ExitMsg, LayoutQuery};
 use script::layout_interface::{MatchSelectorsDocumentDamage, Msg};
-use script::layout_interface::{QueryMsg, Reflow, ReflowDocumentDamage};
+use script::layout_interface::{QueryMsg, Reflow, ReflowDocumentDamage, ReportMemoryMsg};
 use script::layout_interface::{ReflowForDisplay, ReflowMsg};
 use script::script_task::{ReflowCompleteMsg, ScriptChan, SendEventMsg};
 use servo_msg::constellation_msg::{ConstellationChan, PipelineId};
 use servo_net::image_cache_task::{ImageCacheTask, ImageResponseMsg};
 use servo_net::local_image_cache::LocalImageCache;
+use servo_net::resource_task::{ResourceTask, load_whole_resource};
+use servo_util::memory::MemoryReport;
 use servo_util::tree::TreeNodeRef;
-use servo_util::time::{ProfilerChan, profile};
+use servo_util::time::{NavigationMsg, ProfilerChan, profile};
 use servo_util::time;
+use extra::arc::Arc;
 use extra::url::Url;
 
 struct LayoutTask {
@@ -54,15 +62,30 @@ struct LayoutTask {
     render_chan: RenderChan,
     image_cache_task: ImageCacheTask,
     local_image_cache: @mut LocalImageCache,
+    resource_task: ResourceTask,
     font_ctx: @mut FontContext,
+
+    /// Receives the fonts downloaded for `@font-face` rules, which are added to the font
+    /// context at the start of the next reflow.
+    web_font_port: Port<(FontFaceRule, WebFontData)>,
+    web_font_chan: SharedChan<(FontFaceRule, WebFontData)>,
+
     doc_url: Option<Url>,
     screen_size: Option<Size2D<Au>>,
 
     /// This is used to root reader data.
     layout_refs: ~[@mut LayoutData],
 
+    /// Measurements of the flow tree and display list built by the most recent reflow.
+    reflow_memory_reports: ~[MemoryReport],
+
     css_select_ctx: @mut SelectCtx,
+
+    /// The style rules, as written, for the properties that the CSS library does not support.
+    extra_select_ctx: ExtraSelectCtx,
+
     profiler_chan: ProfilerChan,
+    opts: Opts,
 }
 
 impl LayoutTask {
@@ -72,6 +95,8 @@ impl LayoutTask {
                   script_chan: ScriptChan,
                   render_chan: RenderChan,
                   img_cache_task: ImageCacheTask,
+                  resource_task: ResourceTask,
+                  shape_cache: ShapeCache,
                   opts: Opts,
                   profiler_chan: ProfilerChan) {
 
@@ -80,6 +105,8 @@ impl LayoutTask {
         let script_chan = Cell::new(script_chan);
         let render_chan = Cell::new(render_chan);
         let img_cache_task = Cell::new(img_cache_task);
+        let resource_task = Cell::new(resource_task);
+        let shape_cache = Cell::new(shape_cache);
         let profiler_chan = Cell::new(profiler_chan);
 
         do spawn {
@@ -89,6 +116,8 @@ impl LayoutTask {
                                              script_chan.take(),
                                              render_chan.take(),
                                              img_cache_task.take(),
+                                             resource_task.take(),
+                                             shape_cache.take(),
                                              &opts,
                                              profiler_chan.take());
             layout.start();
@@ -101,10 +130,17 @@ impl LayoutTask {
            script_chan: ScriptChan,
            render_chan: RenderChan, 
            image_cache_task: ImageCacheTask,
+           resource_task: ResourceTask,
+           shape_cache: ShapeCache,
            opts: &Opts,
            profiler_chan: ProfilerChan)
            -> LayoutTask {
-        let fctx = @mut FontContext::new(opts.render_backend, true, profiler_chan.clone());
+        let profiler_chan = profiler_chan.for_task("LayoutTask", Some(*id));
+        let fctx = @mut FontContext::new(opts.render_backend,
+                                         true,
+                                         shape_cache,
+                                         profiler_chan.clone());
+        let (web_font_port, web_font_chan) = stream();
 
         LayoutTask {
             id: id,
@@ -114,13 +150,19 @@ impl LayoutTask {
             render_chan: render_chan,
             image_cache_task: image_cache_task.clone(),
             local_image_cache: @mut LocalImageCache(image_cache_task),
+            resource_task: resource_task,
             font_ctx: fctx,
+            web_font_port: web_font_port,
+            web_font_chan: SharedChan::new(web_font_chan),
             doc_url: None,
             screen_size: None,
             
             layout_refs: ~[],
+            reflow_memory_reports: ~[],
             css_select_ctx: @mut new_css_select_ctx(),
+            extra_select_ctx: ExtraSelectCtx::new(),
             profiler_chan: profiler_chan,
+            opts: (*opts).clone(),
         }
     }
 
@@ -146,6 +188,8 @@ impl LayoutTask {
     fn handle_request(&mut self) -> bool {
         match self.port.recv() {
             AddStylesheetMsg(sheet) => self.handle_add_stylesheet(sheet),
+            AddRawStylesheetMsg(sheet) => self.handle_add_raw_stylesheet(sheet),
+            AddFontFaceMsg(rule) => self.load_web_font(rule),
             ReflowMsg(data) => {
                 let data = Cell::new(data);
 
@@ -159,6 +203,11 @@ impl LayoutTask {
                     self.handle_query(query.take());
                 }
             }
+            ReportMemoryMsg(response_chan) => {
+                let mut reports = self.reflow_memory_reports.clone();
+                reports.push_all_move(self.font_ctx.memory_reports());
+                response_chan.send(reports);
+            }
             ExitMsg => {
                 debug!("layout: ExitMsg received");
                 return false
@@ -173,6 +222,42 @@ impl LayoutTask {
         self.css_select_ctx.append_sheet(sheet.take(), OriginAuthor);
     }
 
+    fn handle_add_raw_stylesheet(&mut self, sheet: RawStylesheet) {
+        self.extra_select_ctx.append_sheet(sheet);
+    }
+
+    /// Downloads the first font file of the rule that loads and that the platform can read, in
+    /// the background. Sources in formats known to be unsupported are skipped without being
+    /// downloaded. Once a font has loaded, script is asked for a reflow, which adds the font to the
+    /// font context.
+    fn load_web_font(&self, rule: FontFaceRule) {
+        let rule = Cell::new(rule);
+        let resource_task = self.resource_task.clone();
+        let web_font_chan = self.web_font_chan.clone();
+        let script_chan = self.script_chan.clone();
+        let id = self.id.clone();
+
+        do spawn {
+            let rule = rule.take();
+            for source in rule.sources.iter().filter(|source| source.is_supported()) {
+                let url = source.url.clone();
+                match load_whole_resource(&resource_task, url.clone()) {
+                    Ok(data) if can_load_web_font(data) => {
+                        let data = WebFontData {
+                            url: url.to_str(),
+                            data: Arc::new(data),
+                        };
+                        web_font_chan.send((rule.clone(), data));
+                        script_chan.send(SendEventMsg(id.clone(), ReflowEvent));
+                        return;
+                    }
+                    Ok(_) => debug!("layout: cannot read web font from %s", url.to_str()),
+                    Err(()) => debug!("layout: failed to load web font from %s", url.to_str()),
+                }
+            }
+        }
+    }
+
     /// The high-level routine that performs layout tasks.
     fn handle_reflow(&mut self, data: &Reflow) {
         // FIXME: Isolate this transmutation into a "bridge" module.
@@ -191,6 +276,19 @@ impl LayoutTask {
         // Reset the image cache.
         self.local_image_cache.next_round(self.make_on_image_available_cb(script_chan));
 
+        // Add the web fonts that have loaded since the last reflow.
+        while self.web_font_port.peek() {
+            let (rule, data) = self.web_font_port.recv();
+            self.font_ctx.add_web_font(rule, data);
+        }
+
+        let navigated = match self.doc_url {
+            Some(ref url) => *url != doc_url,
+            None => true,
+        };
+        if navigated {
+            self.profiler_chan.send(NavigationMsg(*self.id, doc_url.to_str()));
+        }
         self.doc_url = Some(doc_url);
         let screen_size = Size2D(Au::from_px(data.window_size.width as int),
                                  Au::from_px(data.window_size.height as int));
@@ -213,7 +311,9 @@ impl LayoutTask {
             ReflowDocumentDamage => {}
             MatchSelectorsDocumentDamage => {
                 do profile(time::LayoutSelectorMatchCategory, self.profiler_chan.clone()) {
-                    node.restyle_subtree(self.css_select_ctx);
+                    node.restyle_subtree(self.css_select_ctx,
+                                         &self.extra_select_ctx,
+                                         self.doc_url.get_ref());
                 }
             }
         }
@@ -263,6 +363,19 @@ impl LayoutTask {
         debug!("layout: constructed Flow tree");
         debug!("%?", layout_root.dump());
 
+        let mut flow_count = 0;
+        let mut box_count = 0;
+        for flow in layout_root.traverse_preorder() {
+            flow_count += 1;
+            for _ in flow.iter_all_boxes() {
+                box_count += 1;
+            }
+        }
+        self.reflow_memory_reports = ~[
+            MemoryReport::new("flows", flow_count, flow_count * sys::size_of::<FlowData>()),
+            MemoryReport::new("boxes", box_count, box_count * sys::size_of::<RenderBoxBase>()),
+        ];
+
         // Perform the primary layout passes over the flow tree to compute the locations of all
         // the boxes.
         do profile(time::LayoutMainCategory, self.profiler_chan.clone()) {
@@ -303,8 +416,17 @@ impl LayoutTask {
                     base.position.size
                 };
 
+                let display_list = display_list.take();
+                if self.opts.dump_display_list {
+                    println(fmt!("Display list for pipeline %u:", *self.id));
+                    println(display_list.dump());
+                }
+                self.reflow_memory_reports.push(MemoryReport::new("display list items",
+                                                                  display_list.len(),
+                                                                  display_list.memory_size()));
+
                 let render_layer = RenderLayer {
-                    display_list: display_list.take(),
+                    display_list: Arc::new(display_list),
                     size: Size2D(root_size.width.to_px() as uint, root_size.height.to_px() as uint)
                 };
 
@@ -312,6 +434,8 @@ impl LayoutTask {
             } // time(layout: display list building)
         }
 
+        self.font_ctx.report_cache_stats();
+
         // Tell script that we're done.
         //
         // FIXME(pcwalton): This should probably be *one* channel, but we can't fix this without
@@ -412,21 +536,14 @@ impl LayoutTask {
                         }
                         let (x, y) = (Au::from_frac_px(point.x as float),
                                       Au::from_frac_px(point.y as float));
-                        let mut resp = Err(());
-                        let display_list = &display_list.take().list;
-                        // iterate in reverse to ensure we have the most recently painted render box
-                        for display_item in display_list.rev_iter() {
-                            let bounds = display_item.bounds();
-                            // TODO this check should really be performed by a method of DisplayItem
-                            if x <= bounds.origin.x + bounds.size.width &&
-                               bounds.origin.x <= x &&
-                               y < bounds.origin.y + bounds.size.height &&
-                               bounds.origin.y <  y {
-                                resp = Ok(HitTestResponse(display_item.base().extra.node()));
-                                break;
+                        let display_list = display_list.take();
+                        // the frontmost item is the most recently painted render box
+                        match display_list.item_at(&Point2D(x, y)) {
+                            Some(display_item) => {
+                                Ok(HitTestResponse(display_item.base().extra.node()))
                             }
+                            None => Err(()),
                         }
-                        resp
                     }
                 };
 
@@ -4,15 +4,13 @@
 
 //! Text layout.
 
-use std::vec;
-
+use gfx::text::bidi;
 use gfx::text::text_run::TextRun;
 use gfx::text::util::{CompressWhitespaceNewline, transform_text};
 use layout::box::{RenderBox, RenderBoxBase, TextRenderBox};
 use layout::box::{TextRenderBoxClass, UnscannedTextRenderBoxClass};
 use layout::context::LayoutContext;
 use layout::flow::FlowContext;
-use newcss::values::{CSSTextDecoration, CSSTextDecorationUnderline};
 use servo_util::range::Range;
 
 
@@ -23,7 +21,7 @@ pub fn adapt_textbox_with_range(mut base: RenderBoxBase, run: @TextRun, range: R
            run.char_len(),
            range.begin(),
            range.length(),
-           run.text,
+           run.text(),
            run.char_len());
 
     assert!(range.begin() < run.char_len());
@@ -54,15 +52,30 @@ impl UnscannedMethods for RenderBox {
     }
 }
 
+/// The character that stands for a box that is not text, such as an image, in the text of an
+/// inline flow. The bidirectional algorithm treats it as a neutral.
+static OBJECT_REPLACEMENT_CHARACTER: char = '\ufffc';
+
 /// A stack-allocated object for scanning an inline flow into `TextRun`-containing `TextBox`es.
 struct TextRunScanner {
     clump: Range,
+    /// The text of each box of the flow after whitespace compression, or `None` for boxes that
+    /// are not text.
+    transformed_texts: ~[Option<~str>],
+    /// The offset of the first character of each box in the text of the whole flow, in which
+    /// boxes that are not text are one character each.
+    char_offsets: ~[uint],
+    /// The bidirectional embedding level of each character of the text of the whole flow.
+    char_levels: ~[u8],
 }
 
 impl TextRunScanner {
     pub fn new() -> TextRunScanner {
         TextRunScanner {
             clump: Range::empty(),
+            transformed_texts: ~[],
+            char_offsets: ~[],
+            char_levels: ~[],
         }
     }
 
@@ -71,18 +84,19 @@ impl TextRunScanner {
         assert!(inline.boxes.len() > 0);
         debug!("TextRunScanner: scanning %u boxes for text runs...", inline.boxes.len());
 
-        let mut last_whitespace = true;
+        self.resolve_paragraph(flow);
+
         let mut out_boxes = ~[];
         for box_i in range(0, flow.inline().boxes.len()) {
             debug!("TextRunScanner: considering box: %?", flow.inline().boxes[box_i].debug_str());
             if box_i > 0 && !can_coalesce_text_nodes(flow.inline().boxes, box_i-1, box_i) {
-                last_whitespace = self.flush_clump_to_list(ctx, flow, last_whitespace, &mut out_boxes);
+                self.flush_clump_to_list(ctx, flow, &mut out_boxes);
             }
             self.clump.extend_by(1);
         }
         // handle remaining clumps
         if self.clump.length() > 0 {
-            self.flush_clump_to_list(ctx, flow, last_whitespace, &mut out_boxes);
+            self.flush_clump_to_list(ctx, flow, &mut out_boxes);
         }
 
         debug!("TextRunScanner: swapping out boxes.");
@@ -106,6 +120,52 @@ impl TextRunScanner {
         }
     }
 
+    /// Compresses the whitespace in the text of each box of the flow, and resolves the embedding
+    /// levels of the text of the whole flow at once. The flow is a single paragraph of the
+    /// bidirectional algorithm, so the level of each character can depend on text in other boxes,
+    /// and `dir="auto"` looks at the first strong character of the whole paragraph.
+    fn resolve_paragraph(&mut self, flow: FlowContext) {
+        // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
+        let compression = CompressWhitespaceNewline;
+
+        let inline = flow.inline();
+        let mut paragraph_text = ~"";
+        let mut char_count = 0;
+        let mut last_whitespace = true;
+        for box in inline.boxes.iter() {
+            self.char_offsets.push(char_count);
+            match *box {
+                UnscannedTextRenderBoxClass(*) => {
+                    // TODO(#113): Boxes starting and/or ending with whitespace are not compressed
+                    // correctly with respect to each other.
+                    let (text, whitespace) = transform_text(box.raw_text(),
+                                                            compression,
+                                                            last_whitespace);
+                    last_whitespace = whitespace;
+                    paragraph_text.push_str(text);
+                    char_count += text.char_len();
+                    self.transformed_texts.push(Some(text));
+                }
+                _ => {
+                    paragraph_text.push_char(OBJECT_REPLACEMENT_CHARACTER);
+                    char_count += 1;
+                    self.transformed_texts.push(None);
+                }
+            }
+        }
+
+        let base_level = inline.boxes[0].base_bidi_level(paragraph_text);
+        inline.base_bidi_level = base_level;
+        self.char_levels = bidi::resolve_levels(paragraph_text, base_level);
+    }
+
+    // Returns the embedding levels of the characters of the text boxes in the given range of the
+    // flow's boxes, which are all text.
+    fn char_levels_for_boxes<'a>(&'a self, boxes: &Range, char_count: uint) -> &'a [u8] {
+        let start = self.char_offsets[boxes.begin()];
+        self.char_levels.slice(start, start + char_count)
+    }
+
     /// A "clump" is a range of inline flow leaves that can be merged together into a single
     /// `RenderBox`. Adjacent text with the same style can be merged, and nothing else can.
     ///
@@ -119,18 +179,10 @@ impl TextRunScanner {
     pub fn flush_clump_to_list(&mut self,
                                ctx: &LayoutContext,
                                flow: FlowContext,
-                               last_whitespace: bool,
-                               out_boxes: &mut ~[RenderBox]) -> bool {
+                               out_boxes: &mut ~[RenderBox]) {
         let inline = &mut *flow.inline();
         let in_boxes = &inline.boxes;
 
-        fn has_underline(decoration: CSSTextDecoration) -> bool{
-            match decoration {
-                CSSTextDecorationUnderline => true,
-                _ => false
-            }
-        }
-
         assert!(self.clump.length() > 0);
 
         debug!("TextRunScanner: flushing boxes in range=%?", self.clump);
@@ -140,8 +192,6 @@ impl TextRunScanner {
             _ => false
         };
 
-        let mut new_whitespace = last_whitespace;
-
         match (is_singleton, is_text_clump) {
             (false, false) => {
                 fail!(~"WAT: can't coalesce non-text nodes in flush_clump_to_list()!")
@@ -152,24 +202,20 @@ impl TextRunScanner {
             },
             (true, true)  => {
                 let old_box = in_boxes[self.clump.begin()];
-                let text = old_box.raw_text();
                 let font_style = old_box.font_style();
-                let underline = has_underline(old_box.text_decoration());
-
-                // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
-                let compression = CompressWhitespaceNewline;
-
-                let (transformed_text, whitespace) = transform_text(text, compression, last_whitespace);
-                new_whitespace = whitespace;
+                let transformed_text = self.transformed_texts[self.clump.begin()].clone().unwrap();
 
                 if transformed_text.len() > 0 {
-                    // TODO(#177): Text run creation must account for the renderability of text by
-                    // font group fonts. This is probably achieved by creating the font group above
-                    // and then letting `FontGroup` decide which `Font` to stick into the text run.
                     let fontgroup = ctx.font_ctx.get_resolved_font_for_style(&font_style);
-                    let run = @fontgroup.create_textrun(transformed_text, underline);
+                    let char_levels = self.char_levels_for_boxes(&self.clump,
+                                                                 transformed_text.char_len());
+                    debug!("TextRunScanner: pushing single text box in range: %? (%?)",
+                           self.clump,
+                           transformed_text);
+                    let run = @fontgroup.create_textrun(ctx.font_ctx,
+                                                        transformed_text,
+                                                        char_levels);
 
-                    debug!("TextRunScanner: pushing single text box in range: %? (%?)", self.clump, text);
                     let new_box = do old_box.with_base |old_box_base| {
                         let range = Range::new(0, run.char_len());
                         @mut adapt_textbox_with_range(*old_box_base, run, range)
@@ -179,50 +225,29 @@ impl TextRunScanner {
                 }
             },
             (false, true) => {
-                // TODO(#115): Use the actual CSS `white-space` property of the relevant style.
-                let compression = CompressWhitespaceNewline;
-
-                // First, transform/compress text of all the nodes.
-                let mut last_whitespace_in_clump = new_whitespace;
-                let transformed_strs: ~[~str] = do vec::from_fn(self.clump.length()) |i| {
-                    // TODO(#113): We should be passing the compression context between calls to
-                    // `transform_text`, so that boxes starting and/or ending with whitespace can
-                    // be compressed correctly with respect to the text run.
-                    let idx = i + self.clump.begin();
-                    let (new_str, new_whitespace) = transform_text(in_boxes[idx].raw_text(),
-                                                                   compression,
-                                                                   last_whitespace_in_clump);
-                    last_whitespace_in_clump = new_whitespace;
-                    new_str
-                };
-                new_whitespace = last_whitespace_in_clump;
-
-                // Next, concatenate all of the transformed strings together, saving the new
-                // character indices.
+                // Concatenate the text of all the boxes, whose whitespace has already been
+                // compressed, saving the new character indices.
                 let mut run_str: ~str = ~"";
                 let mut new_ranges: ~[Range] = ~[];
                 let mut char_total = 0;
-                for i in range(0, transformed_strs.len()) {
-                    let added_chars = transformed_strs[i].char_len();
+                for i in self.clump.eachi() {
+                    let transformed_str = self.transformed_texts[i].get_ref().as_slice();
+                    let added_chars = transformed_str.char_len();
                     new_ranges.push(Range::new(char_total, added_chars));
-                    run_str.push_str(transformed_strs[i]);
+                    run_str.push_str(transformed_str);
                     char_total += added_chars;
                 }
 
                 // Now create the run.
-                //
-                // TODO(#177): Text run creation must account for the renderability of text by
-                // font group fonts. This is probably achieved by creating the font group above
-                // and then letting `FontGroup` decide which `Font` to stick into the text run.
                 let font_style = in_boxes[self.clump.begin()].font_style();
                 let fontgroup = ctx.font_ctx.get_resolved_font_for_style(&font_style);
-                let underline = has_underline(in_boxes[self.clump.begin()].text_decoration());
+                let char_levels = self.char_levels_for_boxes(&self.clump, char_total);
 
                 // TextRuns contain a cycle which is usually resolved by the teardown
                 // sequence. If no clump takes ownership, however, it will leak.
                 let clump = self.clump;
                 let run = if clump.length() != 0 && run_str.len() > 0 {
-                    Some(@TextRun::new(fontgroup.fonts[0], run_str, underline))
+                    Some(@fontgroup.create_textrun(ctx.font_ctx, run_str, char_levels))
                 } else {
                     None
                 };
@@ -266,7 +291,5 @@ impl TextRunScanner {
 
         let end = self.clump.end(); // FIXME: borrow checker workaround
         self.clump.reset(end, 0);
-
-        new_whitespace
     } // End of `flush_clump_to_list`.
 }
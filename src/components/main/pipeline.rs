@@ -7,15 +7,19 @@ use compositing::CompositorChan;
 use gfx::render_task::{RenderChan, RenderTask};
 use gfx::render_task::{PaintPermissionGranted, PaintPermissionRevoked};
 use gfx::render_task;
+use gfx::font::ShapeCache;
 use gfx::opts::Opts;
 use layout::layout_task::LayoutTask;
 use script::layout_interface::LayoutChan;
+use script::layout_interface;
 use script::script_task::{ExecuteMsg, LoadMsg};
 use servo_msg::constellation_msg::{ConstellationChan, NavigationType, PipelineId, SubpageId};
 use script::script_task::{AttachLayoutMsg, NewLayoutInfo, ScriptTask, ScriptChan};
 use script::script_task;
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
+use servo_util::memory::{MemoryProfilerChan, MemoryReporter, RegisterReporterMsg};
+use servo_util::memory::UnregisterReporterMsg;
 use servo_util::time::ProfilerChan;
 use geom::size::Size2D;
 use extra::future::Future;
@@ -29,6 +33,9 @@ pub struct Pipeline {
     script_chan: ScriptChan,
     layout_chan: LayoutChan,
     render_chan: RenderChan,
+    mem_profiler_chan: MemoryProfilerChan,
+    /// The tasks this pipeline registered memory reporters for
+    reporter_tasks: ~[&'static str],
     /// The most recently loaded url
     url: Option<Url>,
     navigation_type: Option<NavigationType>,
@@ -41,8 +48,11 @@ impl Pipeline {
                        constellation_chan: ConstellationChan,
                        compositor_chan: CompositorChan,
                        image_cache_task: ImageCacheTask,
+                       shape_cache: ShapeCache,
                        profiler_chan: ProfilerChan,
+                       mem_profiler_chan: MemoryProfilerChan,
                        opts: Opts,
+                       resource_task: ResourceTask,
                        script_pipeline: &Pipeline,
                        size_future: Future<Size2D<uint>>) -> Pipeline {
         
@@ -61,6 +71,8 @@ impl Pipeline {
                            script_pipeline.script_chan.clone(),
                            render_chan.clone(),
                            image_cache_task.clone(),
+                           resource_task,
+                           shape_cache,
                            opts.clone(),
                            profiler_chan);
 
@@ -73,12 +85,15 @@ impl Pipeline {
 
         script_pipeline.script_chan.send(AttachLayoutMsg(new_layout_info));
 
-        Pipeline::new(id,
-                      subpage_id,
-                      script_pipeline.script_chan.clone(),
-                      layout_chan,
-                      render_chan)
-
+        let mut pipeline = Pipeline::new(id,
+                                         subpage_id,
+                                         script_pipeline.script_chan.clone(),
+                                         layout_chan,
+                                         render_chan,
+                                         mem_profiler_chan);
+        // The script task belongs to `script_pipeline`, which already reports on it.
+        pipeline.register_layout_and_render_reporters();
+        pipeline
     }
 
     pub fn create(id: PipelineId,
@@ -86,8 +101,10 @@ impl Pipeline {
                   constellation_chan: ConstellationChan,
                   compositor_chan: CompositorChan,
                   image_cache_task: ImageCacheTask,
+                  shape_cache: ShapeCache,
                   resource_task: ResourceTask,
                   profiler_chan: ProfilerChan,
+                  mem_profiler_chan: MemoryProfilerChan,
                   opts: Opts,
                   size: Future<Size2D<uint>>) -> Pipeline {
 
@@ -101,7 +118,7 @@ impl Pipeline {
                            script_port,
                            script_chan.clone(),
                            constellation_chan.clone(),
-                           resource_task,
+                           resource_task.clone(),
                            image_cache_task.clone(),
                            size);
 
@@ -118,20 +135,28 @@ impl Pipeline {
                            script_chan.clone(),
                            render_chan.clone(),
                            image_cache_task,
+                           resource_task,
+                           shape_cache,
                            opts.clone(),
                            profiler_chan);
-        Pipeline::new(id,
-                      subpage_id,
-                      script_chan,
-                      layout_chan,
-                      render_chan)
+
+        let mut pipeline = Pipeline::new(id,
+                                         subpage_id,
+                                         script_chan,
+                                         layout_chan,
+                                         render_chan,
+                                         mem_profiler_chan);
+        pipeline.register_script_reporter();
+        pipeline.register_layout_and_render_reporters();
+        pipeline
     }
 
     pub fn new(id: PipelineId,
                subpage_id: Option<SubpageId>,
                script_chan: ScriptChan,
                layout_chan: LayoutChan,
-               render_chan: RenderChan)
+               render_chan: RenderChan,
+               mem_profiler_chan: MemoryProfilerChan)
                -> Pipeline {
         Pipeline {
             id: id,
@@ -139,11 +164,44 @@ impl Pipeline {
             script_chan: script_chan,
             layout_chan: layout_chan,
             render_chan: render_chan,
+            mem_profiler_chan: mem_profiler_chan,
+            reporter_tasks: ~[],
             url: None,
             navigation_type: None,
         }
     }
 
+    fn reporter_name(&self, task: &str) -> ~str {
+        fmt!("%s (pipeline %u)", task, *self.id)
+    }
+
+    fn register_reporter(&mut self, task: &'static str, reporter: MemoryReporter) {
+        self.mem_profiler_chan.send(RegisterReporterMsg(self.reporter_name(task), reporter));
+        self.reporter_tasks.push(task);
+    }
+
+    fn register_script_reporter(&mut self) {
+        let script_chan = self.script_chan.clone();
+        let reporter: MemoryReporter = |response_chan| {
+            script_chan.send(script_task::ReportMemoryMsg(response_chan));
+        };
+        self.register_reporter("script", reporter);
+    }
+
+    fn register_layout_and_render_reporters(&mut self) {
+        let layout_chan = self.layout_chan.clone();
+        let reporter: MemoryReporter = |response_chan| {
+            layout_chan.send(layout_interface::ReportMemoryMsg(response_chan));
+        };
+        self.register_reporter("layout", reporter);
+
+        let render_chan = self.render_chan.clone();
+        let reporter: MemoryReporter = |response_chan| {
+            render_chan.send(render_task::ReportMemoryMsg(response_chan));
+        };
+        self.register_reporter("render", reporter);
+    }
+
     pub fn load(&mut self, url: Url, navigation_type: Option<NavigationType>) {
         self.url = Some(url.clone());
         self.navigation_type = navigation_type;
@@ -171,6 +229,10 @@ impl Pipeline {
     }
 
     pub fn exit(&self) {
+        for task in self.reporter_tasks.iter() {
+            self.mem_profiler_chan.send(UnregisterReporterMsg(self.reporter_name(*task)));
+        }
+
         // Script task handles shutting down layout, as well
         self.script_chan.send(script_task::ExitMsg);
 
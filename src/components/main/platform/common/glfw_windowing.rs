@@ -8,7 +8,7 @@ use windowing::{ApplicationMethods, WindowEvent, WindowMethods};
 use windowing::{IdleWindowEvent, ResizeWindowEvent, LoadUrlWindowEvent, MouseWindowEventClass};
 use windowing::{ScrollWindowEvent, ZoomWindowEvent, NavigationWindowEvent, FinishedWindowEvent};
 use windowing::{QuitWindowEvent, MouseWindowClickEvent, MouseWindowMouseDownEvent, MouseWindowMouseUpEvent};
-use windowing::{Forward, Back};
+use windowing::{MemoryReportWindowEvent, Forward, Back};
 
 use alert::{Alert, AlertMethods};
 use std::libc::c_int;
@@ -206,6 +206,9 @@ impl Window {
             glfw::KEY_MINUS if mods & glfw::MOD_CONTROL != 0 => { // Ctrl--
                 self.event_queue.push(ZoomWindowEvent(0.90909090909));
             }
+            glfw::KEY_M if mods & glfw::MOD_CONTROL != 0 => { // Ctrl-M
+                self.event_queue.push(MemoryReportWindowEvent);
+            }
             glfw::KEY_BACKSPACE if mods & glfw::MOD_SHIFT != 0 => { // Shift-Backspace
                 self.event_queue.push(NavigationWindowEvent(Forward));
             }
@@ -40,6 +40,8 @@ pub enum WindowEvent {
     NavigationWindowEvent(WindowNavigateMsg),
     /// Sent when rendering is finished.
     FinishedWindowEvent,
+    /// Sent when the user asks for a memory report (i.e. ctrl-m).
+    MemoryReportWindowEvent,
     /// Sent when the user quits the application
     QuitWindowEvent,
 }
@@ -62,6 +62,9 @@ pub trait RenderListener {
     fn resize_layer(&self, PipelineId, Size2D<uint>);
     fn delete_layer(&self, PipelineId);
     fn paint(&self, id: PipelineId, layer_buffer_set: arc::Arc<LayerBufferSet>);
+    /// Tells the compositor that the tiles at the given page rects were requested but will not
+    /// be rendered, so that it can ask for them again.
+    fn tiles_dropped(&self, id: PipelineId, page_rects: ~[Rect<f32>]);
     fn set_render_state(&self, render_state: RenderState);
 }
 
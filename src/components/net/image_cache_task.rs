@@ -4,7 +4,8 @@
 
 use image::base::{Image, load_from_memory};
 use resource_task;
-use resource_task::ResourceTask;
+use resource_task::{ResourceTask, load_whole_resource};
+use servo_util::memory::MemoryReport;
 use servo_util::url::{UrlMap, url_map};
 
 use std::cell::Cell;
@@ -12,6 +13,7 @@ use std::comm::{Chan, Port, SharedChan, stream};
 use std::task::spawn;
 use std::to_str::ToStr;
 use std::util::replace;
+#[cfg(test)]
 use std::result;
 use extra::arc::Arc;
 use extra::url::Url;
@@ -38,6 +40,9 @@ pub enum Msg {
     /// Wait for an image to become available (or fail to load).
     WaitForImage(Url, Chan<ImageResponseMsg>),
 
+    /// Request measurements of the image data held by the cache.
+    ReportMemory(Chan<~[MemoryReport]>),
+
     /// For testing
     priv OnMsg(~fn(msg: &Msg)),
 
@@ -197,6 +202,7 @@ impl ImageCache {
                 WaitForImage(url, response) => {
                     self.wait_for_image(url, response)
                 }
+                ReportMemory(response) => response.send(self.memory_reports()),
                 OnMsg(handler) => msg_handlers.push(handler),
                 Exit(response) => {
                     assert!(self.need_exit.is_none());
@@ -232,6 +238,30 @@ impl ImageCache {
         }
     }
 
+    fn memory_reports(&self) -> ~[MemoryReport] {
+        let (mut prefetched_count, mut prefetched_bytes) = (0, 0);
+        let (mut decoded_count, mut decoded_bytes) = (0, 0);
+        for (_, state) in self.state_map.iter() {
+            match *state {
+                Prefetched(data_cell) => {
+                    prefetched_count += 1;
+                    if !data_cell.is_empty() {
+                        prefetched_bytes += data_cell.with_ref(|data| data.len());
+                    }
+                }
+                Decoded(image) => {
+                    decoded_count += 1;
+                    decoded_bytes += image.get().data.len();
+                }
+                Init | Prefetching(*) | Decoding | Failed => ()
+            }
+        }
+        ~[
+            MemoryReport::new("prefetched image data", prefetched_count, prefetched_bytes),
+            MemoryReport::new("decoded images", decoded_count, decoded_bytes),
+        ]
+    }
+
     fn get_state(&self, url: Url) -> ImageState {
         match self.state_map.find(&url) {
             Some(state) => *state,
@@ -254,7 +284,7 @@ impl ImageCache {
                     let url = url_cell.take();
                     debug!("image_cache_task: started fetch for %s", url.to_str());
 
-                    let image = load_image_data(url.clone(), resource_task.clone());
+                    let image = load_whole_resource(&resource_task, url.clone());
 
                     let result = if image.is_ok() {
                         Ok(Cell::new(image.unwrap()))
@@ -436,27 +466,6 @@ impl ImageCacheTaskClient for ImageCacheTask {
     }
 }
 
-fn load_image_data(url: Url, resource_task: ResourceTask) -> Result<~[u8], ()> {
-    let (response_port, response_chan) = stream();
-    resource_task.send(resource_task::Load(url, response_chan));
-
-    let mut image_data = ~[];
-
-    loop {
-        match response_port.recv() {
-            resource_task::Payload(data) => {
-                image_data.push_all(data);
-            }
-            resource_task::Done(result::Ok(*)) => {
-                return Ok(image_data);
-            }
-            resource_task::Done(result::Err(*)) => {
-                return Err(());
-            }
-        }
-    }
-}
-
 fn default_decoder_factory() -> ~fn(&[u8]) -> Option<Image> {
     let foo: ~fn(&[u8]) -> Option<Image> = |data: &[u8]| { load_from_memory(data) };
     foo
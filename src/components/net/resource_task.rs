@@ -8,7 +8,7 @@ use file_loader;
 //use http_loader;
 
 use std::cell::Cell;
-use std::comm::{Chan, Port, SharedChan};
+use std::comm::{Chan, Port, SharedChan, stream};
 use extra::url::Url;
 use util::spawn_listener;
 
@@ -60,6 +60,21 @@ fn create_resource_task_with_loaders(loaders: ~[(~str, LoaderTaskFactory)]) -> R
     SharedChan::new(chan)
 }
 
+/// Loads the whole of the resource at the given URL, waiting until it is done.
+pub fn load_whole_resource(resource_task: &ResourceTask, url: Url) -> Result<~[u8], ()> {
+    let (response_port, response_chan) = stream();
+    resource_task.send(Load(url, response_chan));
+
+    let mut data = ~[];
+    loop {
+        match response_port.recv() {
+            Payload(payload) => data.push_all(payload),
+            Done(Ok(())) => return Ok(data),
+            Done(Err(())) => return Err(()),
+        }
+    }
+}
+
 pub struct ResourceManager {
     from_client: Port<ControlMsg>,
     /// Per-scheme resource loaders
@@ -153,3 +168,25 @@ fn should_delegate_to_scheme_loader() {
     assert!(progress.recv() == Done(Ok(())));
     resource_task.send(Exit);
 }
+
+#[test]
+fn test_load_whole_resource() {
+    use std::from_str::FromStr;
+
+    let loader_factory: LoaderTaskFactory = || {
+        let loader: LoaderTask = |_url: Url, progress_chan: Chan<ProgressMsg>| {
+            progress_chan.send(Payload(~[1, 2]));
+            progress_chan.send(Payload(~[3]));
+            progress_chan.send(Done(Ok(())));
+        };
+        loader
+    };
+    let resource_task = create_resource_task_with_loaders(~[(~"snicklefritz", loader_factory)]);
+
+    let url: Url = FromStr::from_str("snicklefritz://heya").unwrap();
+    assert!(load_whole_resource(&resource_task, url) == Ok(~[1u8, 2, 3]));
+    let url: Url = FromStr::from_str("bogus://whatever").unwrap();
+    assert!(load_whole_resource(&resource_task, url).is_err());
+
+    resource_task.send(Exit);
+}
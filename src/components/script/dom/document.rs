@@ -337,11 +337,17 @@ impl Document {
         }
     }
 
+    /// The direction of the document is that of its root element.
     pub fn Dir(&self) -> DOMString {
-        null_string
+        do self.root.with_imm_element |root| {
+            root.get_dir()
+        }
     }
 
-    pub fn SetDir(&self, _dir: &DOMString) {
+    pub fn SetDir(&self, dir: &DOMString) {
+        do self.root.as_mut_element |root| {
+            root.set_attr(&str(~"dir"), dir);
+        }
     }
 
     pub fn GetDefaultView(&self) -> Option<@mut WindowProxy> {
@@ -44,6 +44,7 @@ use newcss::stylesheet::Stylesheet;
 
 use js::jsapi::{JSContext, JSObject};
 
+use std::ascii::StrAsciiExt;
 use std::cell::Cell;
 use std::comm;
 use std::str::eq_slice;
@@ -261,6 +262,20 @@ impl<'self> Element {
         return None;
     }
 
+    /// Returns the `dir` attribute as the `dir` IDL attribute reflects it: "ltr", "rtl" or "auto"
+    /// in lowercase, or the empty string if it is missing or has another value.
+    pub fn get_dir(&self) -> DOMString {
+        match self.get_attr("dir").map(|dir| dir.to_ascii_lower()) {
+            Some(dir) => {
+                match dir.as_slice() {
+                    "ltr" | "rtl" | "auto" => str(dir.clone()),
+                    _ => str(~""),
+                }
+            }
+            None => str(~""),
+        }
+    }
+
     pub fn set_attr(&mut self, name: &DOMString, value: &DOMString) {
         let name = name.to_str();
         let value_cell = Cell::new(value.to_str());
@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use dom::bindings::codegen::HTMLElementBinding;
-use dom::bindings::utils::{DOMString, null_string, ErrorResult};
+use dom::bindings::utils::{DOMString, null_string, str, ErrorResult};
 use dom::bindings::utils::{CacheableWrapper, BindingObject, WrapperCache};
 use dom::element::{Element, ElementTypeId};
 use dom::node::{AbstractNode, ScriptView};
@@ -38,10 +38,11 @@ impl HTMLElement {
     }
 
     pub fn Dir(&self) -> DOMString {
-        null_string
+        self.parent.get_dir()
     }
 
-    pub fn SetDir(&mut self, _dir: &DOMString, _rv: &mut ErrorResult) {
+    pub fn SetDir(&mut self, dir: &DOMString, _rv: &mut ErrorResult) {
+        self.parent.set_attr(&str(~"dir"), dir);
     }
 
     pub fn GetItemValue(&self, _cx: *JSContext, _rv: &mut ErrorResult) -> JSVal {
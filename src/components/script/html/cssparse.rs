@@ -4,13 +4,19 @@
 
 /// Some little helpers for hooking up the HTML parser with the CSS parser.
 
+use std::ascii::StrAsciiExt;
 use std::cell::Cell;
 use std::comm;
 use std::comm::Port;
+use std::str;
 use std::task;
+use gfx::font::{CSSFontWeight, FontFaceRule, FontWeight100, FontWeight200, FontWeight300};
+use gfx::font::{FontWeight400, FontWeight500, FontWeight600, FontWeight700, FontWeight800};
+use gfx::font::{FontFaceSource, FontWeight900};
 use newcss::stylesheet::Stylesheet;
 use newcss::util::DataStream;
 use servo_net::resource_task::{ResourceTask, ProgressMsg, Load, Payload, Done};
+use servo_util::url::make_url;
 use extra::url::Url;
 
 /// Where a style sheet comes from.
@@ -19,9 +25,33 @@ pub enum StylesheetProvenance {
     InlineProvenance(Url, ~str),
 }
 
+/// The style rules of a style sheet as written, from which layout reads the properties that the
+/// CSS library does not support.
+pub struct RawStylesheet {
+    /// The URL that relative URLs in the rules are resolved against.
+    url: Url,
+    rules: ~[StyleRule],
+}
+
+/// A style rule as written: its selector list and its declarations, in order.
+pub struct StyleRule {
+    selectors: ~str,
+    declarations: ~[Declaration],
+}
+
+/// A declaration as written, with its property name in lowercase and its value trimmed and
+/// without the `!important` flag.
+pub struct Declaration {
+    name: ~str,
+    value: ~str,
+    important: bool,
+}
+
+/// Parses the style sheet in another task. The port receives the sheet along with its
+/// `@font-face` rules and its style rules as written.
 pub fn spawn_css_parser(provenance: StylesheetProvenance,
                         resource_task: ResourceTask)
-                     -> Port<Stylesheet> {
+                     -> Port<(Stylesheet, ~[FontFaceRule], RawStylesheet)> {
     let (result_port, result_chan) = comm::stream();
 
     let provenance_cell = Cell::new(provenance);
@@ -33,9 +63,30 @@ pub fn spawn_css_parser(provenance: StylesheetProvenance,
             }
         };
 
-        let sheet = Stylesheet::new(url, data_stream(provenance_cell.take(),
-                                                     resource_task.clone()));
-        result_chan.send(sheet);
+        // The CSS library does not expose `@font-face` rules or the properties it does not
+        // support, so they are found in a copy of the source instead.
+        let source = @mut ~[];
+        let stream = data_stream(provenance_cell.take(), resource_task.clone());
+        let copying_stream: DataStream = || {
+            let data = stream();
+            for data in data.iter() {
+                source.push_all(*data);
+            }
+            data
+        };
+
+        let sheet = Stylesheet::new(url.clone(), copying_stream);
+        let (font_faces, rules) = if str::is_utf8(*source) {
+            let source = str::from_utf8(*source);
+            (parse_font_face_rules(source, &url), parse_style_rules(source))
+        } else {
+            (~[], ~[])
+        };
+        let raw_sheet = RawStylesheet {
+            url: url,
+            rules: rules,
+        };
+        result_chan.send((sheet, font_faces, raw_sheet));
     }
 
     return result_port;
@@ -77,3 +128,414 @@ fn data_to_data_stream(data: ~str) -> DataStream {
     }
 }
 
+
+/// Finds the `@font-face` rules in the source of a style sheet. Relative URLs are resolved
+/// against the style sheet's URL. Rules without a family or a `url()` source are dropped. Text
+/// inside quoted strings never starts a rule, a comment or a new declaration, and never ends one.
+pub fn parse_font_face_rules(css: &str, base_url: &Url) -> ~[FontFaceRule] {
+    let css = strip_comments(css);
+    // ASCII lowercasing keeps byte offsets, so positions found in one apply to the other.
+    let lowercase_css = css.to_ascii_lower();
+
+    let mut rules = ~[];
+    let mut position = 0;
+    loop {
+        let start = match find_unquoted(lowercase_css, position, "@font-face") {
+            Some(start) => start,
+            None => break,
+        };
+        let open = match find_unquoted(css, start, "{") {
+            Some(open) => open + 1,
+            None => break,
+        };
+        let close = match find_unquoted(css, open, "}") {
+            Some(close) => close,
+            None => css.len(),
+        };
+
+        match parse_font_face_block(css.slice(open, close), base_url) {
+            Some(rule) => rules.push(rule),
+            None => debug!("cssparse: dropping incomplete @font-face rule"),
+        }
+        if close >= css.len() {
+            break
+        }
+        position = close + 1;
+    }
+    rules
+}
+
+/// Finds the style rules at the top level of the source of a style sheet, in order. At-rules,
+/// including the rules inside `@media` blocks, are skipped.
+pub fn parse_style_rules(css: &str) -> ~[StyleRule] {
+    let css = strip_comments(css);
+
+    let mut rules = ~[];
+    let mut position = 0;
+    loop {
+        let open = match find_unquoted(css, position, "{") {
+            Some(open) => open,
+            None => break,
+        };
+        let prelude = css.slice(position, open).trim_left();
+        if prelude.starts_with("@") {
+            // A statement at-rule such as `@import` ends at a semicolon before the next block.
+            match find_unquoted(css, position, ";") {
+                Some(semicolon) if semicolon < open => {
+                    position = semicolon + 1;
+                    loop
+                }
+                _ => {}
+            }
+        }
+
+        let close = find_block_end(css, open);
+        if !prelude.starts_with("@") {
+            rules.push(StyleRule {
+                selectors: prelude.trim().to_owned(),
+                declarations: parse_declarations(css.slice(open + 1, close)),
+            });
+        }
+        if close >= css.len() {
+            break
+        }
+        position = close + 1;
+    }
+    rules
+}
+
+/// Parses the declarations of a block or a `style` attribute. Declarations without a colon are
+/// dropped.
+pub fn parse_declarations(block: &str) -> ~[Declaration] {
+    let mut declarations = ~[];
+    for declaration in split_unquoted(block, ";").iter() {
+        let colon = match declaration.find(':') {
+            Some(colon) => colon,
+            None => loop,
+        };
+        let name = declaration.slice_to(colon).trim().to_ascii_lower();
+        let mut value = declaration.slice_from(colon + 1).trim();
+        let mut important = false;
+        match value.rfind('!') {
+            Some(bang) if value.slice_from(bang + 1).trim().to_ascii_lower() == ~"important" => {
+                value = value.slice_to(bang).trim();
+                important = true;
+            }
+            _ => {}
+        }
+        declarations.push(Declaration {
+            name: name,
+            value: value.to_owned(),
+            important: important,
+        });
+    }
+    declarations
+}
+
+fn parse_font_face_block(block: &str, base_url: &Url) -> Option<FontFaceRule> {
+    let mut family = None;
+    let mut sources = ~[];
+    let mut weight = FontWeight400;
+    let mut italic = false;
+
+    for declaration in parse_declarations(block).iter() {
+        let value = declaration.value.as_slice();
+        match declaration.name.as_slice() {
+            "font-family" => family = Some(unquote(value).to_owned()),
+            "src" => sources = parse_font_face_sources(value, base_url),
+            "font-weight" => weight = parse_font_weight(value),
+            "font-style" => italic = value != "normal",
+            _ => debug!("cssparse: ignoring @font-face descriptor %s", declaration.name),
+        }
+    }
+
+    if sources.is_empty() {
+        return None
+    }
+    match family {
+        Some(family) => {
+            Some(FontFaceRule {
+                family: family,
+                sources: sources,
+                weight: weight,
+                italic: italic,
+            })
+        }
+        None => None,
+    }
+}
+
+// Returns the `url()` sources, in order, with their `format()` hints. `local()` sources are
+// skipped.
+fn parse_font_face_sources(value: &str, base_url: &Url) -> ~[FontFaceSource] {
+    let mut sources = ~[];
+    for source in split_unquoted(value, ",").iter() {
+        let lowercase_source = source.to_ascii_lower();
+        let url = match function_argument(*source, lowercase_source, "url(") {
+            Some(url) => url,
+            None => loop,
+        };
+        let format = do function_argument(*source, lowercase_source, "format(").map |format| {
+            format.to_ascii_lower()
+        };
+        sources.push(FontFaceSource {
+            url: make_url(url, Some(base_url.clone())),
+            format: format,
+        });
+    }
+    sources
+}
+
+// Returns the unquoted argument of the first call to the given function in `value`, which
+// `lowercase_value` is the ASCII lowercase copy of.
+fn function_argument(value: &str, lowercase_value: &str, function: &str) -> Option<~str> {
+    let start = match lowercase_value.find_str(function) {
+        Some(offset) => offset + function.len(),
+        None => return None,
+    };
+    let end = match find_unquoted(value, start, ")") {
+        Some(end) => end,
+        None => return None,
+    };
+    Some(unquote(value.slice(start, end).trim()).to_owned())
+}
+
+fn parse_font_weight(value: &str) -> CSSFontWeight {
+    match value {
+        "100" => FontWeight100,
+        "200" => FontWeight200,
+        "300" => FontWeight300,
+        "500" => FontWeight500,
+        "600" => FontWeight600,
+        "bold" | "700" => FontWeight700,
+        "800" => FontWeight800,
+        "900" => FontWeight900,
+        _ => FontWeight400,
+    }
+}
+
+fn unquote<'a>(value: &'a str) -> &'a str {
+    value.trim_chars(&'"').trim_chars(&'\'')
+}
+
+// Returns the offset of the first occurrence of `target` at or after `position` that is not inside
+// a quoted string. `position` must not be inside a string. `target` must be ASCII, so that the
+// bytes of multibyte characters never match it and the offset is a character boundary.
+fn find_unquoted(value: &str, position: uint, target: &str) -> Option<uint> {
+    let bytes = value.as_bytes();
+    let target = target.as_bytes();
+    let mut quote = None;
+    let mut i = position;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        match quote {
+            Some(quote_byte) => {
+                if byte == '\\' as u8 {
+                    // Skip the escaped byte, which may be the quote.
+                    i += 1;
+                } else if byte == quote_byte {
+                    quote = None;
+                }
+            }
+            None => {
+                if i + target.len() <= bytes.len() && bytes.slice(i, i + target.len()) == target {
+                    return Some(i)
+                }
+                if byte == '"' as u8 || byte == '\'' as u8 {
+                    quote = Some(byte);
+                }
+            }
+        }
+        i += 1;
+    }
+    None
+}
+
+// Returns the offset of the `}` that closes the block opened by the `{` at `open`, or the length of
+// `css` if the block is not closed. Blocks nested in it, as in `@media`, are skipped.
+fn find_block_end(css: &str, open: uint) -> uint {
+    let mut depth = 0;
+    let mut position = open + 1;
+    loop {
+        let next_open = find_unquoted(css, position, "{");
+        let close = match find_unquoted(css, position, "}") {
+            Some(close) => close,
+            None => return css.len(),
+        };
+        match next_open {
+            Some(next_open) if next_open < close => {
+                depth += 1;
+                position = next_open + 1;
+            }
+            _ if depth == 0 => return close,
+            _ => {
+                depth -= 1;
+                position = close + 1;
+            }
+        }
+    }
+}
+
+// Splits `value` at each `separator` that is not inside a quoted string.
+fn split_unquoted<'a>(value: &'a str, separator: &str) -> ~[&'a str] {
+    let mut pieces = ~[];
+    let mut position = 0;
+    loop {
+        match find_unquoted(value, position, separator) {
+            Some(end) => {
+                pieces.push(value.slice(position, end));
+                position = end + separator.len();
+            }
+            None => {
+                pieces.push(value.slice_from(position));
+                return pieces
+            }
+        }
+    }
+}
+
+fn strip_comments(css: &str) -> ~str {
+    let mut result = ~"";
+    let mut rest = css;
+    loop {
+        let start = match find_unquoted(rest, 0, "/*") {
+            Some(start) => start,
+            None => break,
+        };
+        result.push_str(rest.slice_to(start));
+        match rest.slice_from(start + 2).find_str("*/") {
+            Some(end) => rest = rest.slice_from(start + 2 + end + 2),
+            None => return result,
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+mod font_face_tests {
+    use super::{parse_declarations, parse_font_face_rules, parse_style_rules};
+    use gfx::font::{FontFaceSource, FontWeight400, FontWeight700};
+    use servo_util::url::make_url;
+    use extra::url::Url;
+
+    fn base_url() -> Url {
+        make_url(~"http://example.com/css/style.css", None)
+    }
+
+    #[test]
+    fn test_comments() {
+        let css = "/* @font-face { font-family: Hidden; src: url(hidden.ttf) } */\n\
+                   @font-face { /* } */ font-family: /* ; */ Shown; src: url(shown.ttf) }";
+        let rules = parse_font_face_rules(css, &base_url());
+        assert!(rules.len() == 1);
+        assert!(rules[0].family == ~"Shown");
+        assert!(rules[0].sources[0].url.path == ~"/css/shown.ttf");
+    }
+
+    #[test]
+    fn test_quoted_and_unquoted_urls() {
+        let css = "@font-face { font-family: 'A'; src: url(\"a.ttf\") }\n\
+                   @font-face { font-family: \"B\"; src: url('b.ttf') }\n\
+                   @font-face { font-family: C; src: url( c.ttf ) }";
+        let rules = parse_font_face_rules(css, &base_url());
+        assert!(rules.len() == 3);
+        assert!(rules[0].family == ~"A");
+        assert!(rules[0].sources[0].url.path == ~"/css/a.ttf");
+        assert!(rules[1].family == ~"B");
+        assert!(rules[1].sources[0].url.path == ~"/css/b.ttf");
+        assert!(rules[2].family == ~"C");
+        assert!(rules[2].sources[0].url.path == ~"/css/c.ttf");
+    }
+
+    #[test]
+    fn test_multiple_sources() {
+        let css = "@font-face {\n\
+                       font-family: Multi;\n\
+                       src: local(Multi), url(multi.woff2) format(\"WOFF2\"),\n\
+                            local('Multi Regular'), url(multi.ttf) format('truetype'), url(multi.otf);\n\
+                       font-weight: bold;\n\
+                   }";
+        let rules = parse_font_face_rules(css, &base_url());
+        assert!(rules.len() == 1);
+        let sources: &[FontFaceSource] = rules[0].sources;
+        assert!(sources.len() == 3);
+        assert!(sources[0].url.path == ~"/css/multi.woff2");
+        assert!(sources[0].format == Some(~"woff2"));
+        assert!(!sources[0].is_supported());
+        assert!(sources[1].url.path == ~"/css/multi.ttf");
+        assert!(sources[1].format == Some(~"truetype"));
+        assert!(sources[1].is_supported());
+        assert!(sources[2].url.path == ~"/css/multi.otf");
+        assert!(sources[2].format == None);
+        assert!(rules[0].weight == FontWeight700);
+    }
+
+    #[test]
+    fn test_relative_url_resolution() {
+        let css = "@font-face { font-family: R; \
+                   src: url(/fonts/root.ttf), url(../up.ttf), url(//cdn.example.org/cdn.ttf), \
+                   url(http://other.example.net/absolute.ttf) }";
+        let rules = parse_font_face_rules(css, &base_url());
+        assert!(rules.len() == 1);
+        let sources: &[FontFaceSource] = rules[0].sources;
+        assert!(sources.len() == 4);
+        assert!(sources[0].url.host == ~"example.com");
+        assert!(sources[0].url.path == ~"/fonts/root.ttf");
+        assert!(sources[1].url.host == ~"example.com");
+        assert!(sources[1].url.path == ~"/css/../up.ttf");
+        assert!(sources[2].url.scheme == ~"http");
+        assert!(sources[2].url.host == ~"cdn.example.org");
+        assert!(sources[3].url.host == ~"other.example.net");
+        assert!(sources[3].url.path == ~"/absolute.ttf");
+        assert!(rules[0].weight == FontWeight400);
+    }
+
+    #[test]
+    fn test_strings() {
+        let css = "p::before { content: \"@font-face { font-family: Fake; src: url(fake.ttf) }\" }\n\
+                   @font-face { font-family: 'Brace } Face'; src: url(\"a}b;c,d)e.ttf\"), url(f.ttf) }\n\
+                   @font-face { font-family: Next; src: url(next.ttf) }";
+        let rules = parse_font_face_rules(css, &base_url());
+        assert!(rules.len() == 2);
+        assert!(rules[0].family == ~"Brace } Face");
+        assert!(rules[0].sources.len() == 2);
+        assert!(rules[0].sources[0].url.path.ends_with("e.ttf"));
+        assert!(rules[0].sources[1].url.path == ~"/css/f.ttf");
+        assert!(rules[1].family == ~"Next");
+    }
+
+    #[test]
+    fn test_style_rules() {
+        let css = "@import url(\"a.css\");\n\
+                   /* p { color: red } */\n\
+                   div.a > p, #b { box-shadow: 1px 1px red; content: \"}\" }\n\
+                   @media print { p { opacity: 0.5 } }\n\
+                   @font-face { font-family: F; src: url(f.ttf) }\n\
+                   span { opacity: 0.5 !important; z-index : 2 }";
+        let rules = parse_style_rules(css);
+        assert!(rules.len() == 2);
+        assert!(rules[0].selectors == ~"div.a > p, #b");
+        assert!(rules[0].declarations.len() == 2);
+        assert!(rules[0].declarations[0].name == ~"box-shadow");
+        assert!(rules[0].declarations[0].value == ~"1px 1px red");
+        assert!(rules[0].declarations[1].value == ~"\"}\"");
+        assert!(rules[1].selectors == ~"span");
+        assert!(rules[1].declarations[0].value == ~"0.5");
+        assert!(rules[1].declarations[0].important);
+        assert!(rules[1].declarations[1].name == ~"z-index");
+        assert!(!rules[1].declarations[1].important);
+    }
+
+    #[test]
+    fn test_declarations() {
+        let css = "Opacity: .5 ! IMPORTANT; junk; background: url(\"a;b\")";
+        let declarations = parse_declarations(css);
+        assert!(declarations.len() == 2);
+        assert!(declarations[0].name == ~"opacity");
+        assert!(declarations[0].value == ~".5");
+        assert!(declarations[0].important);
+        assert!(declarations[1].name == ~"background");
+        assert!(declarations[1].value == ~"url(\"a;b\")");
+        assert!(!declarations[1].important);
+    }
+}
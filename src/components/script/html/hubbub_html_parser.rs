@@ -47,7 +47,9 @@ use dom::htmlelement::HTMLElement;
 use dom::node::{AbstractNode, Comment, Doctype, ElementNodeTypeId, Node, ScriptView};
 use dom::node::{Text};
 use dom::bindings::utils::str;
-use html::cssparse::{InlineProvenance, StylesheetProvenance, UrlProvenance, spawn_css_parser};
+use html::cssparse::{InlineProvenance, RawStylesheet, StylesheetProvenance, UrlProvenance};
+use html::cssparse::spawn_css_parser;
+use gfx::font::FontFaceRule;
 use js::jsapi::JSContext;
 use newcss::stylesheet::Stylesheet;
 
@@ -109,7 +111,7 @@ enum JSMessage {
 
 /// Messages generated by the HTML parser upon discovery of additional resources
 pub enum HtmlDiscoveryMessage {
-    HtmlDiscoveredStyle(Stylesheet),
+    HtmlDiscoveredStyle(Stylesheet, ~[FontFaceRule], RawStylesheet),
     HtmlDiscoveredIFrame((Url, SubpageId, Future<Size2D<uint>>)),
     HtmlDiscoveredScript(JSResult)
 }
@@ -167,7 +169,8 @@ fn css_link_listener(to_parent: SharedChan<HtmlDiscoveryMessage>,
     // Send the sheets back in order
     // FIXME: Shouldn't wait until after we've recieved CSSTaskExit to start sending these
     for port in result_vec.iter() {
-        to_parent.send(HtmlDiscoveredStyle(port.recv()));
+        let (sheet, font_faces, raw_sheet) = port.recv();
+        to_parent.send(HtmlDiscoveredStyle(sheet, font_faces, raw_sheet));
     }
 }
 
@@ -12,8 +12,11 @@ use std::comm::{Chan, SharedChan};
 use geom::rect::Rect;
 use geom::size::Size2D;
 use geom::point::Point2D;
+use gfx::font::FontFaceRule;
 use gfx::geometry::Au;
+use html::cssparse::RawStylesheet;
 use newcss::stylesheet::Stylesheet;
+use servo_util::memory::MemoryReport;
 use extra::url::Url;
 
 /// Asynchronous messages that script can send to layout.
@@ -23,6 +26,13 @@ pub enum Msg {
     /// Adds the given stylesheet to the document.
     AddStylesheetMsg(Stylesheet),
 
+    /// Adds the style rules of the stylesheet last added, for the properties that the CSS library
+    /// does not support.
+    AddRawStylesheetMsg(RawStylesheet),
+
+    /// Downloads the font of the given `@font-face` rule for the document.
+    AddFontFaceMsg(FontFaceRule),
+
     /// Requests a reflow.
     ReflowMsg(~Reflow),
 
@@ -31,6 +41,9 @@ pub enum Msg {
     /// FIXME(pcwalton): As noted below, this isn't very type safe.
     QueryMsg(LayoutQuery),
 
+    /// Requests measurements of the memory the layout task is using.
+    ReportMemoryMsg(Chan<~[MemoryReport]>),
+
     /// Requests that the layout task shut down and exit.
     ExitMsg,
 }
@@ -12,9 +12,9 @@ use dom::document::AbstractDocument;
 use dom::element::Element;
 use dom::event::{Event_, ResizeEvent, ReflowEvent, ClickEvent, MouseDownEvent, MouseUpEvent};
 use dom::htmldocument::HTMLDocument;
-use dom::node::{define_bindings};
+use dom::node::{define_bindings, Node, ScriptView, Text};
 use dom::window::Window;
-use layout_interface::{AddStylesheetMsg, DocumentDamage};
+use layout_interface::{AddFontFaceMsg, AddRawStylesheetMsg, AddStylesheetMsg, DocumentDamage};
 use layout_interface::{DocumentDamageLevel, HitTestQuery, HitTestResponse, LayoutQuery};
 use layout_interface::{LayoutChan, MatchSelectorsDocumentDamage, QueryMsg, Reflow};
 use layout_interface::{ReflowDocumentDamage, ReflowForDisplay, ReflowGoal};
@@ -30,6 +30,7 @@ use std::comm;
 use std::comm::{Port, SharedChan};
 use std::io::read_whole_file;
 use std::ptr::null;
+use std::sys;
 use std::task::{SingleThreaded, task};
 use std::util::replace;
 use dom::window::TimerData;
@@ -46,6 +47,7 @@ use js::rust::{Compartment, Cx};
 use js;
 use servo_net::image_cache_task::ImageCacheTask;
 use servo_net::resource_task::ResourceTask;
+use servo_util::memory::MemoryReport;
 use servo_util::tree::TreeNodeRef;
 use servo_util::url::make_url;
 use extra::url::Url;
@@ -69,6 +71,8 @@ pub enum ScriptMsg {
     ReflowCompleteMsg(PipelineId),
     /// Notifies script that window has been resized but to not take immediate action.
     ResizeInactiveMsg(Size2D<uint>),
+    /// Requests measurements of the DOM nodes held by the script task.
+    ReportMemoryMsg(comm::Chan<~[MemoryReport]>),
     /// Exits the constellation.
     ExitMsg,
 }
@@ -455,6 +459,7 @@ impl ScriptTask {
             NavigateMsg(direction) => self.handle_navigate_msg(direction),
             ReflowCompleteMsg(id) => self.handle_reflow_complete_msg(id),
             ResizeInactiveMsg(new_size) => self.handle_resize_inactive_msg(new_size),
+            ReportMemoryMsg(response_chan) => response_chan.send(self.memory_reports()),
             ExitMsg => {
                 self.handle_exit_msg();
                 return false
@@ -551,6 +556,41 @@ impl ScriptTask {
         }
     }
 
+    /// Measures the DOM nodes of every page that has a document. Elements and text nodes are
+    /// counted separately since they make up nearly all of a typical DOM.
+    fn memory_reports(&mut self) -> ~[MemoryReport] {
+        let (mut element_count, mut element_bytes) = (0, 0);
+        let (mut text_count, mut text_bytes) = (0, 0);
+        let mut other_count = 0;
+        for page in self.page_tree.iter() {
+            let root = match page.frame {
+                Some(ref frame) => frame.document.with_base(|doc| doc.root),
+                None => loop,
+            };
+            for node in root.traverse_preorder() {
+                if node.is_element() {
+                    element_count += 1;
+                    element_bytes += sys::size_of::<Element>();
+                } else if node.is_text() {
+                    text_count += 1;
+                    text_bytes += sys::size_of::<Text>();
+                    do node.with_imm_text |text| {
+                        text_bytes += text.parent.data.to_str().len();
+                    }
+                } else {
+                    other_count += 1;
+                }
+            }
+        }
+        ~[
+            MemoryReport::new("DOM elements", element_count, element_bytes),
+            MemoryReport::new("DOM text nodes", text_count, text_bytes),
+            MemoryReport::new("other DOM nodes",
+                              other_count,
+                              other_count * sys::size_of::<Node<ScriptView>>()),
+        ]
+    }
+
     /// Handles a request to exit the script task and shut down layout.
     fn handle_exit_msg(&mut self) {
         for page in self.page_tree.iter() {
@@ -642,8 +682,12 @@ impl ScriptTask {
                     assert!(js_scripts.is_none());
                     js_scripts = Some(scripts);
                 }
-                Some(HtmlDiscoveredStyle(sheet)) => {
+                Some(HtmlDiscoveredStyle(sheet, font_faces, raw_sheet)) => {
                     page.layout_chan.send(AddStylesheetMsg(sheet));
+                    page.layout_chan.send(AddRawStylesheetMsg(raw_sheet));
+                    for rule in font_faces.move_iter() {
+                        page.layout_chan.send(AddFontFaceMsg(rule));
+                    }
                 }
                 Some(HtmlDiscoveredIFrame((iframe_url, subpage_id, size_future))) => {
                     page.next_subpage_id = SubpageId(*subpage_id + 1);
@@ -2,75 +2,148 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use extra::arc::Arc;
 use std::hashmap::HashMap;
+use std::util::replace;
+
+/// Lookup counters kept by every cache, so that cache effectiveness can be reported through the
+/// profiler.
+#[deriving(Clone, Eq)]
+pub struct CacheStats {
+    hits: uint,
+    misses: uint,
+    evictions: uint,
+}
+
+impl CacheStats {
+    pub fn new() -> CacheStats {
+        CacheStats {
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn lookups(&self) -> uint {
+        self.hits + self.misses
+    }
+
+    /// The fraction of lookups that were hits, or zero if there were no lookups.
+    pub fn hit_ratio(&self) -> float {
+        let lookups = self.lookups();
+        if lookups == 0 {
+            0f
+        } else {
+            (self.hits as float) / (lookups as float)
+        }
+    }
+}
 
 pub trait Cache<K: Eq, V: Clone> {
     fn insert(&mut self, key: K, value: V);
     fn find(&mut self, key: &K) -> Option<V>;
     fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V;
     fn evict_all(&mut self);
+    fn stats(&self) -> CacheStats;
+}
+
+/// Values stored in a `WeightedLRUCache` report how much of the cache's budget they use. This is
+/// usually an approximation of their size in bytes.
+pub trait CacheWeight {
+    fn cache_weight(&self) -> uint;
+}
+
+impl<T: Freeze + Send + CacheWeight> CacheWeight for Arc<T> {
+    fn cache_weight(&self) -> uint {
+        self.get().cache_weight()
+    }
 }
 
+/// A cache holding a single entry.
 pub struct MonoCache<K, V> {
     entry: Option<(K,V)>,
+    stats: CacheStats,
 }
 
 impl<K: Clone + Eq, V: Clone> MonoCache<K,V> {
-    pub fn new(_size: uint) -> MonoCache<K,V> {
-        MonoCache { entry: None }
+    pub fn new() -> MonoCache<K,V> {
+        MonoCache {
+            entry: None,
+            stats: CacheStats::new(),
+        }
     }
 }
 
 impl<K: Clone + Eq, V: Clone> Cache<K,V> for MonoCache<K,V> {
     fn insert(&mut self, key: K, value: V) {
+        if self.entry.is_some() {
+            self.stats.evictions += 1;
+        }
         self.entry = Some((key, value));
     }
 
     fn find(&mut self, key: &K) -> Option<V> {
-        match self.entry {
+        let result = match self.entry {
             None => None,
             Some((ref k, ref v)) => if *k == *key { Some(v.clone()) } else { None }
+        };
+        if result.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
         }
+        result
     }
 
     fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V {
-        match self.entry {
-            None => { 
+        match self.find(key) {
+            Some(value) => value,
+            None => {
                 let value = blk(key);
-                self.entry = Some((key.clone(), value.clone()));
+                self.insert(key.clone(), value.clone());
                 value
-            },
-            Some((ref _k, ref v)) => v.clone()
+            }
         }
     }
 
     fn evict_all(&mut self) {
         self.entry = None;
     }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
 }
 
 #[test]
 fn test_monocache() {
-    let cache = MonoCache::new(10);
+    let mut cache = MonoCache::new();
     let one = @"one";
     let two = @"two";
-    cache.insert(&1, one);
+    cache.insert(1, one);
 
     assert!(cache.find(&1).is_some());
     assert!(cache.find(&2).is_none());
     cache.find_or_create(&2, |_v| { two });
     assert!(cache.find(&2).is_some());
     assert!(cache.find(&1).is_none());
+
+    // A miss must not hand back the value cached under another key.
+    assert!(cache.find_or_create(&1, |_v| { one }) == one);
+    assert!(cache.stats().hits == 2);
+    assert!(cache.stats().misses == 3);
 }
 
 pub struct HashCache<K, V> {
     entries: HashMap<K, V>,
+    stats: CacheStats,
 }
 
 impl<K: Clone + Eq + Hash, V: Clone> HashCache<K,V> {
     pub fn new() -> HashCache<K, V> {
         HashCache {
           entries: HashMap::new(),
+          stats: CacheStats::new(),
         }
     }
 }
@@ -82,27 +155,40 @@ impl<K: Clone + Eq + Hash, V: Clone> Cache<K,V> for HashCache<K,V> {
 
     fn find(&mut self, key: &K) -> Option<V> {
         match self.entries.find(key) {
-            Some(v) => Some(v.clone()),
-            None    => None,
+            Some(v) => {
+                self.stats.hits += 1;
+                Some(v.clone())
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
         }
     }
 
     fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V {
-        self.entries.find_or_insert_with(key.clone(), blk).clone()
+        match self.find(key) {
+            Some(value) => value,
+            None => self.entries.find_or_insert_with(key.clone(), blk).clone(),
+        }
     }
 
     fn evict_all(&mut self) {
         self.entries.clear();
     }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
 }
 
 #[test]
 fn test_hashcache() {
-    let cache = HashCache::new();
+    let mut cache = HashCache::new();
     let one = @"one";
     let two = @"two";
 
-    cache.insert(&1, one);
+    cache.insert(1, one);
     assert!(cache.find(&1).is_some());
     assert!(cache.find(&2).is_none());
 
@@ -111,47 +197,211 @@ fn test_hashcache() {
     assert!(cache.find(&2).is_some());
 }
 
+struct LRUEntry<K, V> {
+    key: K,
+    value: V,
+    weight: uint,
+    prev: Option<uint>,
+    next: Option<uint>,
+}
+
+/// The storage shared by the LRU caches: a doubly-linked list threaded through a vector of slots,
+/// ordered from least to most recently used, plus a hash index from keys to slots. Lookup,
+/// insertion, promotion and eviction are all O(1).
+struct LRUList<K, V> {
+    index: HashMap<K, uint>,
+    slots: ~[Option<LRUEntry<K, V>>],
+    free_slots: ~[uint],
+    // Least recently used entry.
+    head: Option<uint>,
+    // Most recently used entry.
+    tail: Option<uint>,
+    total_weight: uint,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LRUList<K,V> {
+    fn new() -> LRUList<K,V> {
+        LRUList {
+            index: HashMap::new(),
+            slots: ~[],
+            free_slots: ~[],
+            head: None,
+            tail: None,
+            total_weight: 0,
+        }
+    }
+
+    fn len(&self) -> uint {
+        self.index.len()
+    }
+
+    fn find_slot(&self, key: &K) -> Option<uint> {
+        match self.index.find(key) {
+            Some(&slot) => Some(slot),
+            None => None,
+        }
+    }
+
+    fn unlink(&mut self, slot: uint) {
+        let (prev, next) = {
+            let entry = self.slots[slot].get_ref();
+            (entry.prev, entry.next)
+        };
+        match prev {
+            Some(prev) => self.slots[prev].get_mut_ref().next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.slots[next].get_mut_ref().prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    fn link_at_tail(&mut self, slot: uint) {
+        let tail = self.tail;
+        {
+            let entry = self.slots[slot].get_mut_ref();
+            entry.prev = tail;
+            entry.next = None;
+        }
+        match tail {
+            Some(tail) => self.slots[tail].get_mut_ref().next = Some(slot),
+            None => self.head = Some(slot),
+        }
+        self.tail = Some(slot);
+    }
+
+    /// Marks the entry in `slot` as the most recently used one and returns its value.
+    fn touch(&mut self, slot: uint) -> V {
+        if self.tail != Some(slot) {
+            self.unlink(slot);
+            self.link_at_tail(slot);
+        }
+        self.slots[slot].get_ref().value.clone()
+    }
+
+    /// Adds an entry as the most recently used one, replacing any entry with the same key.
+    fn push(&mut self, key: K, value: V, weight: uint) {
+        match self.find_slot(&key) {
+            Some(slot) => { self.remove_slot(slot); }
+            None => {}
+        }
+
+        let entry = LRUEntry {
+            key: key.clone(),
+            value: value,
+            weight: weight,
+            prev: None,
+            next: None,
+        };
+        let slot = match self.free_slots.pop_opt() {
+            Some(slot) => {
+                self.slots[slot] = Some(entry);
+                slot
+            }
+            None => {
+                self.slots.push(Some(entry));
+                self.slots.len() - 1
+            }
+        };
+
+        self.index.insert(key, slot);
+        self.total_weight += weight;
+        self.link_at_tail(slot);
+    }
+
+    fn remove_slot(&mut self, slot: uint) -> LRUEntry<K,V> {
+        self.unlink(slot);
+        let entry = replace(&mut self.slots[slot], None).unwrap();
+        self.free_slots.push(slot);
+        self.index.remove(&entry.key);
+        self.total_weight -= entry.weight;
+        entry
+    }
+
+    /// Removes the least recently used entry.
+    fn pop_lru(&mut self) -> Option<LRUEntry<K,V>> {
+        match self.head {
+            Some(slot) => Some(self.remove_slot(slot)),
+            None => None,
+        }
+    }
+
+    fn values(&self) -> ~[V] {
+        self.slots.iter().filter_map(|slot| slot.map(|entry| entry.value.clone())).collect()
+    }
+
+    fn clear(&mut self) {
+        self.index.clear();
+        self.slots = ~[];
+        self.free_slots = ~[];
+        self.head = None;
+        self.tail = None;
+        self.total_weight = 0;
+    }
+}
+
+/// A cache holding at most `cache_size` entries, evicting the least recently used one first.
 pub struct LRUCache<K, V> {
-    entries: ~[(K, V)],
+    priv entries: LRUList<K, V>,
     cache_size: uint,
+    stats: CacheStats,
 }
 
-impl<K: Clone + Eq, V: Clone> LRUCache<K,V> {
+impl<K: Clone + Eq + Hash, V: Clone> LRUCache<K,V> {
     pub fn new(size: uint) -> LRUCache<K, V> {
         LRUCache {
-          entries: ~[],
+          entries: LRUList::new(),
           cache_size: size,
+          stats: CacheStats::new(),
         }
     }
 
-    pub fn touch(&mut self, pos: uint) -> V {
-        let last_index = self.entries.len() - 1;
-        if pos != last_index {
-            let entry = self.entries.remove(pos);
-            self.entries.push(entry);
+    pub fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    /// The values currently held by the cache, in no particular order.
+    pub fn values(&self) -> ~[V] {
+        self.entries.values()
+    }
+
+    /// Grows the cache, if need be, so that it holds at least `size` entries.
+    pub fn reserve(&mut self, size: uint) {
+        if size > self.cache_size {
+            self.cache_size = size;
         }
-        self.entries[last_index].second_ref().clone()
     }
 }
 
-impl<K: Clone + Eq, V: Clone> Cache<K,V> for LRUCache<K,V> {
+impl<K: Clone + Eq + Hash, V: Clone> Cache<K,V> for LRUCache<K,V> {
     fn insert(&mut self, key: K, val: V) {
-        if self.entries.len() == self.cache_size {
-            self.entries.remove(0);
+        if self.cache_size == 0 {
+            return;
+        }
+        if self.entries.find_slot(&key).is_none() && self.entries.len() == self.cache_size {
+            self.entries.pop_lru();
+            self.stats.evictions += 1;
         }
-        self.entries.push((key, val));
+        self.entries.push(key, val, 1);
     }
 
     fn find(&mut self, key: &K) -> Option<V> {
-        match self.entries.iter().position(|&(ref k, _)| *k == *key) {
-            Some(pos) => Some(self.touch(pos)),
-            None      => None,
+        match self.entries.find_slot(key) {
+            Some(slot) => {
+                self.stats.hits += 1;
+                Some(self.entries.touch(slot))
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
         }
     }
 
     fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V {
-        match self.entries.iter().position(|&(ref k, _)| *k == *key) {
-            Some(pos) => self.touch(pos),
+        match self.find(key) {
+            Some(val) => val,
             None => {
                 let val = blk(key);
                 self.insert(key.clone(), val.clone());
@@ -163,6 +413,10 @@ impl<K: Clone + Eq, V: Clone> Cache<K,V> for LRUCache<K,V> {
     fn evict_all(&mut self) {
         self.entries.clear();
     }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
 }
 
 #[test]
@@ -173,17 +427,17 @@ fn test_lru_cache() {
     let four = @"four";
 
     // Test normal insertion.
-    let cache = LRUCache::new(2); // (_, _) (cache is empty)
-    cache.insert(&1, one);    // (1, _)
-    cache.insert(&2, two);    // (1, 2)
-    cache.insert(&3, three);  // (2, 3)
+    let mut cache = LRUCache::new(2); // (_, _) (cache is empty)
+    cache.insert(1, one);    // (1, _)
+    cache.insert(2, two);    // (1, 2)
+    cache.insert(3, three);  // (2, 3)
 
     assert!(cache.find(&1).is_none());  // (2, 3) (no change)
     assert!(cache.find(&3).is_some());  // (2, 3)
     assert!(cache.find(&2).is_some());  // (3, 2)
 
     // Test that LRU works (this insertion should replace 3, not 2).
-    cache.insert(&4, four); // (2, 4)
+    cache.insert(4, four); // (2, 4)
 
     assert!(cache.find(&1).is_none());  // (2, 4) (no change)
     assert!(cache.find(&2).is_some());  // (4, 2)
@@ -191,10 +445,147 @@ fn test_lru_cache() {
     assert!(cache.find(&4).is_some());  // (2, 4) (no change)
 
     // Test find_or_create.
-    do cache.find_or_create(&1) |_| { one } // (4, 1)
+    do cache.find_or_create(&1) |_| { one }; // (4, 1)
 
     assert!(cache.find(&1).is_some()); // (4, 1) (no change)
     assert!(cache.find(&2).is_none()); // (4, 1) (no change)
     assert!(cache.find(&3).is_none()); // (4, 1) (no change)
     assert!(cache.find(&4).is_some()); // (1, 4)
+
+    // Reinserting an existing key must not evict anything.
+    cache.insert(4, four); // (1, 4)
+    assert!(cache.len() == 2);
+    assert!(cache.find(&1).is_some());
+    assert!(cache.values().len() == 2);
+
+    assert!(cache.stats().evictions == 3);
+
+    // Reserving room keeps what would otherwise be evicted, and never shrinks the cache.
+    cache.reserve(3);
+    cache.reserve(1);
+    cache.insert(2, two); // (1, 4, 2)
+    assert!(cache.find(&1).is_some());
+    assert!(cache.len() == 3);
+    assert!(cache.stats().evictions == 3);
+}
+
+/// A cache that evicts least recently used entries once the total weight of its values exceeds
+/// `budget`. Values heavier than the whole budget are never cached.
+pub struct WeightedLRUCache<K, V> {
+    priv entries: LRUList<K, V>,
+    budget: uint,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + CacheWeight> WeightedLRUCache<K,V> {
+    pub fn new(budget: uint) -> WeightedLRUCache<K, V> {
+        WeightedLRUCache {
+            entries: LRUList::new(),
+            budget: budget,
+            stats: CacheStats::new(),
+        }
+    }
+
+    pub fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    /// The total weight of the values currently held by the cache.
+    pub fn weight(&self) -> uint {
+        self.entries.total_weight
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + CacheWeight> Cache<K,V> for WeightedLRUCache<K,V> {
+    fn insert(&mut self, key: K, val: V) {
+        let weight = val.cache_weight();
+        if weight > self.budget {
+            // The value can't be kept, but neither can the one it replaces.
+            match self.entries.find_slot(&key) {
+                Some(slot) => { self.entries.remove_slot(slot); }
+                None => {}
+            }
+            return;
+        }
+
+        self.entries.push(key, val, weight);
+        while self.entries.total_weight > self.budget {
+            self.entries.pop_lru();
+            self.stats.evictions += 1;
+        }
+    }
+
+    fn find(&mut self, key: &K) -> Option<V> {
+        match self.entries.find_slot(key) {
+            Some(slot) => {
+                self.stats.hits += 1;
+                Some(self.entries.touch(slot))
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn find_or_create(&mut self, key: &K, blk: &fn(&K) -> V) -> V {
+        match self.find(key) {
+            Some(val) => val,
+            None => {
+                let val = blk(key);
+                self.insert(key.clone(), val.clone());
+                val
+            }
+        }
+    }
+
+    fn evict_all(&mut self) {
+        self.entries.clear();
+    }
+
+    fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+impl CacheWeight for ~str {
+    fn cache_weight(&self) -> uint {
+        self.len()
+    }
+}
+
+#[test]
+fn test_weighted_lru_cache() {
+    let mut cache = WeightedLRUCache::new(10);
+    cache.insert(1, ~"aaaa");   // (1) weight 4
+    cache.insert(2, ~"bbbb");   // (1, 2) weight 8
+    assert!(cache.find(&1).is_some()); // (2, 1)
+
+    // Going over budget evicts the least recently used entry.
+    cache.insert(3, ~"cccc");   // (1, 3) weight 8
+    assert!(cache.find(&2).is_none());
+    assert!(cache.find(&1).is_some());
+    assert!(cache.find(&3).is_some());
+    assert!(cache.weight() == 8);
+
+    // A heavy entry may evict several light ones.
+    cache.insert(4, ~"dddddddddd"); // (4) weight 10
+    assert!(cache.len() == 1);
+    assert!(cache.find(&4).is_some());
+
+    // Entries heavier than the budget are not cached at all.
+    cache.insert(5, ~"eeeeeeeeeee");
+    assert!(cache.find(&5).is_none());
+    assert!(cache.find(&4).is_some());
+
+    // Replacing an entry with one heavier than the budget drops the old value too.
+    cache.insert(4, ~"ffffffffffff");
+    assert!(cache.find(&4).is_none());
+    assert!(cache.weight() == 0);
+
+    cache.insert(6, ~"gg");
+    cache.evict_all();
+    assert!(cache.len() == 0);
+    assert!(cache.weight() == 0);
 }
@@ -0,0 +1,144 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Memory reporting. Tasks register reporters with the memory profiler; when a report is
+//! requested, the profiler asks every reporter for its current measurements and prints them.
+
+use std::cell::Cell;
+use std::comm::{Chan, Port, SharedChan, stream};
+
+/// One measurement from a reporter: the number of things of some kind that a task holds and the
+/// memory they use.
+#[deriving(Clone)]
+pub struct MemoryReport {
+    name: ~str,
+    count: uint,
+    bytes: uint,
+}
+
+impl MemoryReport {
+    pub fn new(name: &str, count: uint, bytes: uint) -> MemoryReport {
+        MemoryReport {
+            name: name.to_owned(),
+            count: count,
+            bytes: bytes,
+        }
+    }
+}
+
+/// A reporter is called with a channel on which it must send its measurements exactly once.
+/// Reporters usually just forward the channel to the task whose memory they measure.
+pub type MemoryReporter = ~fn(Chan<~[MemoryReport]>);
+
+// front-end representation of the memory profiler used to communicate with it
+#[deriving(Clone)]
+pub struct MemoryProfilerChan {
+    chan: SharedChan<MemoryProfilerMsg>,
+}
+
+impl MemoryProfilerChan {
+    pub fn new(chan: Chan<MemoryProfilerMsg>) -> MemoryProfilerChan {
+        MemoryProfilerChan {
+            chan: SharedChan::new(chan),
+        }
+    }
+
+    pub fn send(&self, msg: MemoryProfilerMsg) {
+        self.chan.send(msg);
+    }
+}
+
+pub enum MemoryProfilerMsg {
+    // Message used to add a reporter, replacing any previous reporter with the same name
+    RegisterReporterMsg(~str, MemoryReporter),
+    // Message used to remove a reporter, usually because its task is shutting down
+    UnregisterReporterMsg(~str),
+    // Message used to collect and print the reports of all registered reporters
+    PrintMsg,
+}
+
+// back end of the memory profiler that collects and prints reports
+pub struct MemoryProfiler {
+    port: Port<MemoryProfilerMsg>,
+    reporters: ~[(~str, MemoryReporter)],
+}
+
+impl MemoryProfiler {
+    pub fn create(port: Port<MemoryProfilerMsg>) {
+        let port = Cell::new(port);
+        do spawn {
+            let mut profiler = MemoryProfiler::new(port.take());
+            profiler.start();
+        }
+    }
+
+    pub fn new(port: Port<MemoryProfilerMsg>) -> MemoryProfiler {
+        MemoryProfiler {
+            port: port,
+            reporters: ~[],
+        }
+    }
+
+    pub fn start(&mut self) {
+        loop {
+            let msg = self.port.recv();
+            self.handle_msg(msg);
+        }
+    }
+
+    fn handle_msg(&mut self, msg: MemoryProfilerMsg) {
+        match msg {
+            RegisterReporterMsg(name, reporter) => {
+                self.unregister(name.as_slice());
+                self.reporters.push((name, reporter));
+            }
+            UnregisterReporterMsg(name) => self.unregister(name.as_slice()),
+            PrintMsg => self.print_reports(),
+        }
+    }
+
+    fn unregister(&mut self, name: &str) {
+        let position = self.reporters.iter().position(|&(ref reporter_name, _)| {
+            reporter_name.as_slice() == name
+        });
+        match position {
+            Some(index) => { self.reporters.remove(index); }
+            None => {}
+        }
+    }
+
+    fn print_reports(&self) {
+        // Ask every reporter first so that they measure concurrently.
+        let mut ports = ~[];
+        for &(ref name, ref reporter) in self.reporters.iter() {
+            let (port, chan) = stream();
+            (*reporter)(chan);
+            ports.push((name.clone(), port));
+        }
+
+        println(fmt!("%31s %15s %15s", "_memory_", "_count_", "_size (MB)_"));
+        let mut total_bytes = 0;
+        for &(ref name, ref port) in ports.iter() {
+            println(fmt!("%s:", *name));
+
+            // A task that has exited without unregistering drops the channel unanswered.
+            match port.try_recv() {
+                Some(reports) => {
+                    for report in reports.iter() {
+                        println(fmt!("%-30s: %15u %15.2f", fmt!(" - %s", report.name),
+                                     report.count, bytes_to_mb(report.bytes)));
+                        total_bytes += report.bytes;
+                    }
+                }
+                None => println(" - not responding"),
+            }
+        }
+        println(fmt!("%-30s: %15s %15.2f", "total", "", bytes_to_mb(total_bytes)));
+        println("");
+    }
+}
+
+fn bytes_to_mb(bytes: uint) -> float {
+    (bytes as float) / (1024f * 1024f)
+}
@@ -15,7 +15,7 @@ enum RangeRelation {
     EntirelyAfter
 }
 
-#[deriving(Clone)]
+#[deriving(Clone, Eq)]
 pub struct Range {
     priv off: uint,
     priv len: uint
@@ -3,9 +3,14 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 // Timing functions.
+use cache::CacheStats;
 use extra::time::precise_time_ns;
 use std::cell::Cell;
+use std::cmp::min;
 use std::comm::{Port, SharedChan};
+use std::hashmap::HashMap;
+use std::io;
+use extra::json;
 use extra::sort::tim_sort;
 use std::iterator::AdditiveIterator;
 
@@ -13,20 +18,86 @@ use std::iterator::AdditiveIterator;
 #[deriving(Clone)]
 pub struct ProfilerChan {
     chan: SharedChan<ProfilerMsg>,
+    // the task that spans sent through this channel are attributed to
+    identity: TaskIdentity,
 }
 
 impl ProfilerChan {
     pub fn new(chan: Chan<ProfilerMsg>) -> ProfilerChan {
         ProfilerChan {
             chan: SharedChan::new(chan),
+            identity: TaskIdentity::new("Main", None),
         }
     }
+
+    /// Returns a channel whose spans are attributed to the given task and pipeline.
+    pub fn for_task(&self, name: &'static str, pipeline_id: Option<uint>) -> ProfilerChan {
+        ProfilerChan {
+            chan: self.chan.clone(),
+            identity: TaskIdentity::new(name, pipeline_id),
+        }
+    }
+
+    /// Returns a channel whose spans are attributed to one of several tasks of the same name and
+    /// pipeline, such as the workers of a pool, told apart by the given index.
+    pub fn for_worker(&self, index: uint) -> ProfilerChan {
+        ProfilerChan {
+            chan: self.chan.clone(),
+            identity: TaskIdentity {
+                index: Some(index),
+                .. self.identity
+            },
+        }
+    }
+
     pub fn send(&self, msg: ProfilerMsg) {
         self.chan.send(msg);
     }
 }
 
-#[deriving(Eq, Clone)]
+/// Names the task, and the pipeline if there is one, that a span was recorded on.
+#[deriving(Clone, Eq)]
+pub struct TaskIdentity {
+    name: &'static str,
+    pipeline_id: Option<uint>,
+    // tells apart tasks that share a name and pipeline
+    index: Option<uint>,
+}
+
+impl TaskIdentity {
+    pub fn new(name: &'static str, pipeline_id: Option<uint>) -> TaskIdentity {
+        TaskIdentity {
+            name: name,
+            pipeline_id: pipeline_id,
+            index: None,
+        }
+    }
+
+    /// Returns the name of the task, followed by its index if it has one.
+    pub fn label(&self) -> ~str {
+        match self.index {
+            Some(index) => fmt!("%s %u", self.name, index),
+            None => self.name.to_owned(),
+        }
+    }
+}
+
+/// A single timed section of work, as recorded by `profile`.
+#[deriving(Clone)]
+pub struct ProfilerSpan {
+    category: ProfilerCategory,
+    start_ns: u64,
+    end_ns: u64,
+    task: TaskIdentity,
+}
+
+impl ProfilerSpan {
+    pub fn duration_ms(&self) -> float {
+        (self.end_ns - self.start_ns) as float / 1000000f
+    }
+}
+
+#[deriving(Eq, Clone, IterBytes)]
 pub enum ProfilerCategory {
     CompositingCategory,
     LayoutQueryCategory,
@@ -39,92 +110,221 @@ pub enum ProfilerCategory {
     LayoutDispListBuildCategory,
     GfxRegenAvailableFontsCategory,
     RenderingDrawingCategory,
+    RenderingTextCategory,
     RenderingPrepBuffCategory,
     RenderingCategory,
-    // hackish but helps prevent errors when adding new categories
-    NUM_BUCKETS,
+    RenderingWorkerCategory,
 }
-// FIXME(#5873) this should be initialized by a NUM_BUCKETS cast,
-static BUCKETS: uint = 13;
-type ProfilerBuckets = [(ProfilerCategory, ~[float]), ..BUCKETS];
 
 pub enum ProfilerMsg {
     // Normal message used for reporting time
     TimeMsg(ProfilerCategory, float),
+    // Message used for reporting a timed span along with where it ran
+    SpanMsg(ProfilerSpan),
+    // Message used to report the current counters of a named cache
+    CacheStatsMsg(~str, CacheStats),
+    // Message used to add to a named counter, such as the number of display items culled
+    CounterMsg(~str, uint),
+    // Message used to tell the profiler which URL a pipeline has navigated to
+    NavigationMsg(uint, ~str),
+    // Message used to discard the metrics gathered so far; the constellation sends it when the
+    // root pipeline navigates, if asked to
+    ResetMsg,
     // Message used to force print the profiling metrics
     PrintMsg,
+    // Message used to write out any recorded trace; acknowledged once the trace is written
+    FlushMsg(Chan<()>),
+}
+
+/// How the profiler prints its metrics.
+#[deriving(Eq, Clone)]
+pub enum ProfilerOutputFormat {
+    // aligned columns, meant to be read by people
+    TextOutput,
+    // one comma-separated row per bucket
+    CsvOutput,
+    // one JSON object per printout
+    JsonOutput,
+}
+
+impl ProfilerOutputFormat {
+    pub fn from_str(name: &str) -> Option<ProfilerOutputFormat> {
+        match name {
+            "text" => Some(TextOutput),
+            "csv" => Some(CsvOutput),
+            "json" => Some(JsonOutput),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for the profiler task, usually taken from the command line.
+#[deriving(Clone)]
+pub struct ProfilerConfig {
+    output_format: ProfilerOutputFormat,
+    // percentiles to print for each bucket, between 0 and 100
+    percentiles: ~[float],
+    // where to write the Chrome trace, if tracing is enabled
+    trace_file: Option<~str>,
+}
+
+impl ProfilerConfig {
+    pub fn new() -> ProfilerConfig {
+        ProfilerConfig {
+            output_format: TextOutput,
+            percentiles: ~[90f, 99f],
+            trace_file: None,
+        }
+    }
+}
+
+// Samples are grouped by the URL of the pipeline that recorded them as well as by category, and
+// by worker for tasks that belong to a pool. Samples that can't be attributed to a pipeline have
+// an empty URL.
+#[deriving(Eq, Clone, IterBytes)]
+struct BucketKey {
+    url: ~str,
+    category: ProfilerCategory,
+    worker: Option<uint>,
+}
+
+impl BucketKey {
+    // the category, followed by the worker if there is one
+    fn label(&self) -> ~str {
+        match self.worker {
+            Some(worker) => fmt!("%s (worker %u)", self.category.format(), worker),
+            None => self.category.format(),
+        }
+    }
+}
+
+// sort by URL, then in category declaration order, then by worker
+impl Ord for BucketKey {
+    fn lt(&self, other: &BucketKey) -> bool {
+        if self.url != other.url {
+            self.url < other.url
+        } else if self.category != other.category {
+            (self.category as uint) < (other.category as uint)
+        } else {
+            self.worker < other.worker
+        }
+    }
+    fn le(&self, other: &BucketKey) -> bool { !other.lt(self) }
+    fn gt(&self, other: &BucketKey) -> bool { other.lt(self) }
+    fn ge(&self, other: &BucketKey) -> bool { !self.lt(other) }
+}
+
+/// Summary statistics for the samples in one bucket, in milliseconds.
+#[deriving(Clone)]
+pub struct BucketStats {
+    count: uint,
+    mean: float,
+    median: float,
+    min: float,
+    max: float,
+    // (percentile, value) pairs, in the order they were requested
+    percentiles: ~[(float, float)],
+}
+
+impl BucketStats {
+    /// Summarizes `data`, which must be sorted and non-empty.
+    pub fn from_sorted(data: &[float], percentiles: &[float]) -> BucketStats {
+        let data_len = data.len();
+        BucketStats {
+            count: data_len,
+            mean: data.iter().map(|&x|x).sum() / (data_len as float),
+            median: data[data_len / 2],
+            min: data[0],
+            max: data[data_len - 1],
+            percentiles: percentiles.iter().map(|&p| (p, percentile(data, p))).collect(),
+        }
+    }
+}
+
+/// Returns the nearest-rank percentile `p`, between 0 and 100, of `data`, which must be sorted
+/// and non-empty.
+pub fn percentile(data: &[float], p: float) -> float {
+    assert!(p >= 0f && p <= 100f);
+    let rank = (p / 100f * (data.len() as float)).ceil() as uint;
+    let index = if rank == 0 { 0 } else { rank - 1 };
+    data[min(index, data.len() - 1)]
+}
+
+#[test]
+fn test_percentile() {
+    let data = [1f, 2f, 3f, 4f, 5f, 6f, 7f, 8f, 9f, 10f];
+    assert!(percentile(data, 0f) == 1f);
+    assert!(percentile(data, 50f) == 5f);
+    assert!(percentile(data, 90f) == 9f);
+    assert!(percentile(data, 99f) == 10f);
+    assert!(percentile(data, 100f) == 10f);
+
+    let stats = BucketStats::from_sorted(data, [90f, 99f]);
+    assert!(stats.count == 10);
+    assert!(stats.mean == 5.5f);
+    assert!(stats.min == 1f && stats.max == 10f);
+    assert!(stats.percentiles == ~[(90f, 9f), (99f, 10f)]);
 }
 
 // back end of the profiler that handles data aggregation and performance metrics
 pub struct Profiler {
     port: Port<ProfilerMsg>,
-    buckets: ProfilerBuckets,
+    config: ProfilerConfig,
+    buckets: HashMap<BucketKey, ~[float]>,
+    // the URL each pipeline most recently navigated to
+    pipeline_urls: HashMap<uint, ~str>,
+    cache_stats: HashMap<~str, CacheStats>,
+    counters: HashMap<~str, uint>,
+    spans: ~[ProfilerSpan],
     last_msg: Option<ProfilerMsg>,
 }
 
 impl ProfilerCategory {
-    // convenience function to not have to cast every time
-    pub fn num_buckets() -> uint {
-        NUM_BUCKETS as uint
-    }
-
-    // enumeration of all ProfilerCategory types
-    // TODO(tkuehn): is there a better way to ensure proper order of categories?
-    fn empty_buckets() -> ProfilerBuckets {
-        let buckets = [
-            (CompositingCategory, ~[]),
-            (LayoutQueryCategory, ~[]),
-            (LayoutPerformCategory, ~[]),
-            (LayoutAuxInitCategory, ~[]),
-            (LayoutSelectorMatchCategory, ~[]),
-            (LayoutTreeBuilderCategory, ~[]),
-            (LayoutMainCategory, ~[]),
-            (LayoutShapingCategory, ~[]),
-            (LayoutDispListBuildCategory, ~[]),
-            (GfxRegenAvailableFontsCategory, ~[]),
-            (RenderingDrawingCategory, ~[]),
-            (RenderingPrepBuffCategory, ~[]),
-            (RenderingCategory, ~[]),
-        ];
-
-        ProfilerCategory::check_order(&buckets);
-        buckets
-    }
-
-    // ensure that the order of the buckets matches the order of the enum categories
-    fn check_order(vec: &ProfilerBuckets) {
-        for &(category, _) in vec.iter() {
-            if category != vec[category as uint].first() {
-                fail!("Enum category does not match bucket index. This is a bug.");
-            }
-        }
-    }
-
-    // some categories are subcategories of LayoutPerformCategory
+    // some categories are subcategories of LayoutPerformCategory or RenderingDrawingCategory
     // and should be printed to indicate this
     pub fn format(self) -> ~str {
         let padding = match self {
             LayoutAuxInitCategory | LayoutSelectorMatchCategory | LayoutTreeBuilderCategory |
-            LayoutMainCategory | LayoutDispListBuildCategory | LayoutShapingCategory=> " - ",
+            LayoutMainCategory | LayoutDispListBuildCategory | LayoutShapingCategory |
+            RenderingTextCategory => " - ",
             _ => ""
         };
         fmt!("%s%?", padding, self)
     }
+
+    // the coarse grouping used for the "cat" field of trace events
+    fn trace_group(self) -> &'static str {
+        match self {
+            CompositingCategory => "compositing",
+            LayoutQueryCategory | LayoutPerformCategory | LayoutAuxInitCategory |
+            LayoutSelectorMatchCategory | LayoutTreeBuilderCategory | LayoutMainCategory |
+            LayoutShapingCategory | LayoutDispListBuildCategory => "layout",
+            GfxRegenAvailableFontsCategory => "gfx",
+            RenderingDrawingCategory | RenderingTextCategory | RenderingPrepBuffCategory |
+            RenderingCategory | RenderingWorkerCategory => "rendering",
+        }
+    }
 }
 
 impl Profiler {
-    pub fn create(port: Port<ProfilerMsg>) {
+    pub fn create(port: Port<ProfilerMsg>, config: ProfilerConfig) {
         let port = Cell::new(port);
+        let config = Cell::new(config);
         do spawn {
-            let mut profiler = Profiler::new(port.take());
+            let mut profiler = Profiler::new(port.take(), config.take());
             profiler.start();
         }
     }
 
-    pub fn new(port: Port<ProfilerMsg>) -> Profiler {
+    pub fn new(port: Port<ProfilerMsg>, config: ProfilerConfig) -> Profiler {
         Profiler {
             port: port,
-            buckets: ProfilerCategory::empty_buckets(),
+            config: config,
+            buckets: HashMap::new(),
+            pipeline_urls: HashMap::new(),
+            cache_stats: HashMap::new(),
+            counters: HashMap::new(),
+            spans: ~[],
             last_msg: None,
         }
     }
@@ -138,44 +338,316 @@ impl Profiler {
 
     fn handle_msg(&mut self, msg: ProfilerMsg) {
         match msg {
-            TimeMsg(category, t) => match self.buckets[category as uint] {
-                //TODO(tkuehn): would be nice to have tuple.second_mut()
-                (_, ref mut data) => data.push(t),
-            },
+            TimeMsg(category, t) => self.record_time(None, None, category, t),
+            SpanMsg(span) => {
+                self.record_time(span.task.pipeline_id, span.task.index, span.category,
+                                 span.duration_ms());
+                if self.config.trace_file.is_some() {
+                    self.spans.push(span);
+                }
+            }
+            CacheStatsMsg(ref name, stats) => {
+                self.cache_stats.insert(name.clone(), stats);
+            }
+            CounterMsg(ref name, value) => {
+                *self.counters.find_or_insert(name.clone(), 0) += value;
+            }
+            NavigationMsg(pipeline_id, ref url) => {
+                self.pipeline_urls.insert(pipeline_id, url.clone());
+            }
+            ResetMsg => self.reset(),
             PrintMsg => match self.last_msg {
                 // only print if more data has arrived since the last printout
-                Some(TimeMsg(*)) => self.print_buckets(),
+                Some(TimeMsg(*)) | Some(SpanMsg(*)) | Some(CacheStatsMsg(*)) |
+                Some(CounterMsg(*)) => {
+                    self.print_buckets()
+                }
                 _ => {}
             },
+            FlushMsg(ref response_chan) => {
+                self.write_trace();
+                response_chan.send(());
+            }
         };
         self.last_msg = Some(msg);
     }
 
+    fn record_time(&mut self,
+                   pipeline_id: Option<uint>,
+                   worker: Option<uint>,
+                   category: ProfilerCategory,
+                   t: float) {
+        let url = match pipeline_id {
+            Some(id) => match self.pipeline_urls.find(&id) {
+                Some(url) => url.clone(),
+                None => ~"",
+            },
+            None => ~"",
+        };
+        let key = BucketKey {
+            url: url,
+            category: category,
+            worker: worker,
+        };
+        self.buckets.find_or_insert_with(key, |_| ~[]).push(t);
+    }
+
+    // The trace is a timeline rather than a summary, so it is kept across resets.
+    fn reset(&mut self) {
+        self.buckets.clear();
+        self.cache_stats.clear();
+        self.counters.clear();
+    }
+
+    fn write_trace(&self) {
+        let path = match self.config.trace_file {
+            Some(ref trace_file) => Path(*trace_file),
+            None => return,
+        };
+        match io::file_writer(&path, [io::Create, io::Truncate]) {
+            Ok(writer) => writer.write_str(chrome_trace_json(self.spans)),
+            Err(e) => error!("profiler: couldn't write trace to %s: %s", path.to_str(), e),
+        }
+    }
+
     fn print_buckets(&mut self) {
-        println(fmt!("%31s %15s %15s %15s %15s %15s",
-                         "_category_", "_mean (ms)_", "_median (ms)_",
-                         "_min (ms)_", "_max (ms)_", "_bucket size_"));
-        for bucket in self.buckets.mut_iter() {
-            let (category, data) = match *bucket {
-                (category, ref mut data) => (category, data),
-            };
+        let mut keys: ~[BucketKey] = self.buckets.iter().map(|(key, _)| key.clone()).collect();
+        tim_sort(keys);
+
+        let mut rows = ~[];
+        for key in keys.iter() {
+            let data = self.buckets.find_mut(key).unwrap();
             tim_sort(*data);
-            let data_len = data.len();
-            if data_len > 0 {
-                let (mean, median, &min, &max) =
-                    (data.iter().map(|&x|x).sum() / (data_len as float),
-                     data[data_len / 2],
-                     data.iter().min().unwrap(),
-                     data.iter().max().unwrap());
-                println(fmt!("%-30s: %15.4f %15.4f %15.4f %15.4f %15u",
-                             category.format(), mean, median, min, max, data_len));
+            rows.push((key.clone(), BucketStats::from_sorted(*data, self.config.percentiles)));
+        }
+
+        match self.config.output_format {
+            TextOutput => self.print_text(rows),
+            CsvOutput => self.print_csv(rows),
+            JsonOutput => self.print_json(rows),
+        }
+    }
+
+    fn print_text(&self, rows: &[(BucketKey, BucketStats)]) {
+        let mut header = fmt!("%31s %15s %15s %15s %15s",
+                              "_category_", "_mean (ms)_", "_median (ms)_",
+                              "_min (ms)_", "_max (ms)_");
+        for p in self.config.percentiles.iter() {
+            header.push_str(fmt!(" %15s", fmt!("_p%s (ms)_", p.to_str())));
+        }
+        header.push_str(fmt!(" %15s", "_bucket size_"));
+
+        let mut current_url = None;
+        for &(ref key, ref stats) in rows.iter() {
+            if current_url != Some(key.url.as_slice()) {
+                if !key.url.is_empty() {
+                    println(fmt!("%s:", key.url));
+                }
+                println(header);
+                current_url = Some(key.url.as_slice());
+            }
+
+            let mut row = fmt!("%-30s: %15.4f %15.4f %15.4f %15.4f",
+                               key.label(), stats.mean, stats.median,
+                               stats.min, stats.max);
+            for &(_, value) in stats.percentiles.iter() {
+                row.push_str(fmt!(" %15.4f", value));
             }
+            row.push_str(fmt!(" %15u", stats.count));
+            println(row);
         }
         println("");
+
+        if self.cache_stats.len() > 0 {
+            println(fmt!("%31s %15s %15s %15s %15s",
+                         "_cache_", "_hits_", "_misses_", "_evictions_", "_hit ratio_"));
+            for (name, stats) in self.cache_stats.iter() {
+                println(fmt!("%-30s: %15u %15u %15u %15.4f",
+                             *name, stats.hits, stats.misses, stats.evictions,
+                             stats.hit_ratio()));
+            }
+            println("");
+        }
+
+        if self.counters.len() > 0 {
+            println(fmt!("%31s %15s", "_counter_", "_total_"));
+            for (name, total) in self.counters.iter() {
+                println(fmt!("%-30s: %15u", *name, *total));
+            }
+            println("");
+        }
+    }
+
+    fn print_csv(&self, rows: &[(BucketKey, BucketStats)]) {
+        let mut header = ~"url,category,worker,count,mean_ms,median_ms,min_ms,max_ms";
+        for p in self.config.percentiles.iter() {
+            header.push_str(fmt!(",p%s_ms", p.to_str()));
+        }
+        println(header);
+
+        for &(ref key, ref stats) in rows.iter() {
+            let worker = match key.worker {
+                Some(worker) => worker.to_str(),
+                None => ~"",
+            };
+            let mut row = fmt!("\"%s\",%?,%s,%u,%.4f,%.4f,%.4f,%.4f",
+                               key.url.replace("\"", "\"\""), key.category, worker, stats.count,
+                               stats.mean, stats.median, stats.min, stats.max);
+            for &(_, value) in stats.percentiles.iter() {
+                row.push_str(fmt!(",%.4f", value));
+            }
+            println(row);
+        }
+    }
+
+    fn print_json(&self, rows: &[(BucketKey, BucketStats)]) {
+        let mut buckets = do rows.iter().map |&(ref key, ref stats)| {
+            let mut percentiles = do stats.percentiles.iter().map |&(p, value)| {
+                fmt!("\"p%s\":%.4f", p.to_str(), value)
+            };
+            let percentiles: ~[~str] = percentiles.collect();
+            let worker = match key.worker {
+                Some(worker) => worker.to_str(),
+                None => ~"null",
+            };
+            fmt!("{\"url\":%s,\"category\":\"%?\",\"worker\":%s,\"count\":%u,\"mean_ms\":%.4f,\
+                  \"median_ms\":%.4f,\"min_ms\":%.4f,\"max_ms\":%.4f,\"percentiles_ms\":{%s}}",
+                 json::String(key.url.clone()).to_str(), key.category, worker, stats.count,
+                 stats.mean, stats.median, stats.min, stats.max, percentiles.connect(","))
+        };
+        let buckets: ~[~str] = buckets.collect();
+
+        let mut caches = do self.cache_stats.iter().map |(name, stats)| {
+            fmt!("{\"name\":%s,\"hits\":%u,\"misses\":%u,\"evictions\":%u}",
+                 json::String(name.clone()).to_str(), stats.hits, stats.misses, stats.evictions)
+        };
+        let caches: ~[~str] = caches.collect();
+
+        let mut counters = do self.counters.iter().map |(name, total)| {
+            fmt!("%s:%u", json::String(name.clone()).to_str(), *total)
+        };
+        let counters: ~[~str] = counters.collect();
+
+        println(fmt!("{\"buckets\":[%s],\"caches\":[%s],\"counters\":{%s}}",
+                     buckets.connect(","), caches.connect(","), counters.connect(",")));
     }
 }
 
 
+/// Formats spans as a Chrome trace event file, which can be loaded into `chrome://tracing`.
+/// Each pipeline is shown as a process and each task as a thread within it; spans recorded
+/// outside of any pipeline are grouped under a "Servo" process.
+pub fn chrome_trace_json(spans: &[ProfilerSpan]) -> ~str {
+    let mut tasks: ~[TaskIdentity] = ~[];
+    let mut events = ~[];
+
+    for span in spans.iter() {
+        let tid = match tasks.iter().position(|task| *task == span.task) {
+            Some(tid) => tid,
+            None => {
+                tasks.push(span.task);
+                tasks.len() - 1
+            }
+        };
+        events.push(fmt!("{\"name\":\"%?\",\"cat\":\"%s\",\"ph\":\"X\",\"ts\":%.3f,\
+                          \"dur\":%.3f,\"pid\":%u,\"tid\":%u}",
+                         span.category,
+                         span.category.trace_group(),
+                         span.start_ns as float / 1000f,
+                         (span.end_ns - span.start_ns) as float / 1000f,
+                         trace_pid(span.task.pipeline_id),
+                         tid));
+    }
+
+    for (tid, task) in tasks.iter().enumerate() {
+        let process_name = match task.pipeline_id {
+            Some(id) => fmt!("Pipeline %u", id),
+            None => ~"Servo",
+        };
+        events.push(fmt!("{\"name\":\"process_name\",\"ph\":\"M\",\"pid\":%u,\
+                          \"args\":{\"name\":\"%s\"}}",
+                         trace_pid(task.pipeline_id), process_name));
+        events.push(fmt!("{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":%u,\"tid\":%u,\
+                          \"args\":{\"name\":\"%s\"}}",
+                         trace_pid(task.pipeline_id), tid, task.label()));
+    }
+
+    fmt!("[%s]\n", events.connect(",\n"))
+}
+
+// pid 0 is reserved for spans that don't belong to a pipeline
+fn trace_pid(pipeline_id: Option<uint>) -> uint {
+    match pipeline_id {
+        Some(id) => id + 1,
+        None => 0,
+    }
+}
+
+#[test]
+fn test_chrome_trace_json() {
+    let spans = [
+        ProfilerSpan {
+            category: LayoutMainCategory,
+            start_ns: 1000,
+            end_ns: 3500,
+            task: TaskIdentity::new("LayoutTask", Some(0)),
+        },
+        ProfilerSpan {
+            category: CompositingCategory,
+            start_ns: 2000,
+            end_ns: 4000,
+            task: TaskIdentity::new("Compositor", None),
+        },
+        ProfilerSpan {
+            category: RenderingWorkerCategory,
+            start_ns: 2500,
+            end_ns: 3000,
+            task: TaskIdentity {
+                index: Some(1),
+                .. TaskIdentity::new("RenderWorker", Some(0))
+            },
+        },
+    ];
+    let json = chrome_trace_json(spans);
+    assert!(json.starts_with("["));
+    assert!(json.contains("{\"name\":\"LayoutMainCategory\",\"cat\":\"layout\",\"ph\":\"X\",\
+                           \"ts\":1.000,\"dur\":2.500,\"pid\":1,\"tid\":0}"));
+    assert!(json.contains("\"pid\":0,\"tid\":1,\"args\":{\"name\":\"Compositor\"}"));
+    assert!(json.contains("\"args\":{\"name\":\"Pipeline 0\"}"));
+    assert!(json.contains("\"pid\":1,\"tid\":2,\"args\":{\"name\":\"RenderWorker 1\"}"));
+}
+
+#[test]
+fn test_worker_buckets() {
+    let (port, _chan) = ::std::comm::stream();
+    let mut profiler = Profiler::new(port, ProfilerConfig::new());
+    profiler.handle_msg(NavigationMsg(0, ~"http://example.com/"));
+    for &index in [0u, 1u, 1u].iter() {
+        profiler.handle_msg(SpanMsg(ProfilerSpan {
+            category: RenderingWorkerCategory,
+            start_ns: 0,
+            end_ns: 1000000,
+            task: TaskIdentity {
+                index: Some(index),
+                .. TaskIdentity::new("RenderWorker", Some(0))
+            },
+        }));
+    }
+    profiler.handle_msg(TimeMsg(RenderingWorkerCategory, 1f));
+
+    let key = |worker: Option<uint>| BucketKey {
+        url: if worker.is_some() { ~"http://example.com/" } else { ~"" },
+        category: RenderingWorkerCategory,
+        worker: worker,
+    };
+    assert!(profiler.buckets.len() == 3);
+    assert!(profiler.buckets.find(&key(Some(0))).unwrap().len() == 1);
+    assert!(profiler.buckets.find(&key(Some(1))).unwrap().len() == 2);
+    assert!(profiler.buckets.find(&key(None)).unwrap().len() == 1);
+    assert!(key(Some(1)).label() == ~"RenderingWorkerCategory (worker 1)");
+    assert!(key(Some(0)) < key(Some(1)));
+}
+
 pub fn profile<T>(category: ProfilerCategory, 
                   profiler_chan: ProfilerChan,
                   callback: &fn() -> T)
@@ -183,8 +655,12 @@ pub fn profile<T>(category: ProfilerCategory,
     let start_time = precise_time_ns();
     let val = callback();
     let end_time = precise_time_ns();
-    let ms = ((end_time - start_time) as float / 1000000f);
-    profiler_chan.send(TimeMsg(category, ms));
+    profiler_chan.send(SpanMsg(ProfilerSpan {
+        category: category,
+        start_ns: start_time,
+        end_ns: end_time,
+        task: profiler_chan.identity,
+    }));
     return val;
 }
 
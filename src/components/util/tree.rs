@@ -36,6 +36,99 @@ impl<Node, Ref: TreeNodeRef<Node>> Iterator<Ref> for ChildIterator<Ref> {
     }
 }
 
+pub struct AncestorIterator<Ref> {
+    priv current: Option<Ref>,
+}
+
+impl<Node, Ref: TreeNodeRef<Node>> Iterator<Ref> for AncestorIterator<Ref> {
+    fn next(&mut self) -> Option<Ref> {
+        let x = match self.current.take() {
+            None => return None,
+            Some(x) => x,
+        };
+        self.current = x.with_base(|n| TreeNodeRef::parent_node::<Node, Ref>(n));
+        Some(x)
+    }
+}
+
+pub struct SiblingIterator<Ref> {
+    priv current: Option<Ref>,
+    priv reverse: bool,
+}
+
+impl<Node, Ref: TreeNodeRef<Node>> Iterator<Ref> for SiblingIterator<Ref> {
+    fn next(&mut self) -> Option<Ref> {
+        let x = match self.current.take() {
+            None => return None,
+            Some(x) => x,
+        };
+        self.current = if self.reverse {
+            x.with_base(|n| TreeNodeRef::prev_sibling::<Node, Ref>(n))
+        } else {
+            x.with_base(|n| TreeNodeRef::next_sibling::<Node, Ref>(n))
+        };
+        Some(x)
+    }
+}
+
+/// Walks the tree in tree order (or reverse tree order) one node at a time, so unlike
+/// TreeIterator it does not need to gather the nodes up front.
+pub struct TreeOrderIterator<Ref> {
+    priv current: Option<Ref>,
+    priv reverse: bool,
+}
+
+impl<Node, Ref: TreeNodeRef<Node>> Iterator<Ref> for TreeOrderIterator<Ref> {
+    fn next(&mut self) -> Option<Ref> {
+        let x = match self.current.take() {
+            None => return None,
+            Some(x) => x,
+        };
+        self.current = if self.reverse {
+            preceding_node::<Node, Ref>(&x)
+        } else {
+            following_node::<Node, Ref>(&x)
+        };
+        Some(x)
+    }
+}
+
+/// Returns the node after `node` in tree order: its first child, or else the next sibling of the
+/// nearest inclusive ancestor that has one.
+fn following_node<Node, Ref: TreeNodeRef<Node>>(node: &Ref) -> Option<Ref> {
+    match node.with_base(|n| TreeNodeRef::first_child::<Node, Ref>(n)) {
+        Some(first_child) => return Some(first_child),
+        None => {}
+    }
+
+    let mut current = node.clone();
+    loop {
+        match current.with_base(|n| TreeNodeRef::next_sibling::<Node, Ref>(n)) {
+            Some(next_sibling) => return Some(next_sibling),
+            None => {}
+        }
+        match current.with_base(|n| TreeNodeRef::parent_node::<Node, Ref>(n)) {
+            Some(parent) => current = parent,
+            None => return None,
+        }
+    }
+}
+
+/// Returns the node before `node` in tree order: the last descendant of its previous sibling, or
+/// else its parent.
+fn preceding_node<Node, Ref: TreeNodeRef<Node>>(node: &Ref) -> Option<Ref> {
+    let mut current = match node.with_base(|n| TreeNodeRef::prev_sibling::<Node, Ref>(n)) {
+        Some(prev_sibling) => prev_sibling,
+        None => return node.with_base(|n| TreeNodeRef::parent_node::<Node, Ref>(n)),
+    };
+    loop {
+        match current.with_base(|n| TreeNodeRef::last_child::<Node, Ref>(n)) {
+            Some(last_child) => current = last_child,
+            None => return Some(current),
+        }
+    }
+}
+
 // FIXME: Do this without precomputing a vector of refs.
 // Easy for preorder; harder for postorder.
 pub struct TreeIterator<Ref> {
@@ -150,6 +243,62 @@ pub trait TreeNodeRef<Node>: Clone {
         }
     }
 
+    /// Inserts a new child before `reference_child` in this node's list of children, or at the end
+    /// if `reference_child` is `None`.
+    ///
+    /// Fails unless `new_child` is disconnected from the tree. `reference_child` must be a child of
+    /// this node. (FIXME: This is not yet checked.)
+    fn insert_before(&self, new_child: Self, reference_child: Option<Self>) {
+        let reference_child = match reference_child {
+            None => return self.add_child(new_child),
+            Some(reference_child) => reference_child,
+        };
+
+        do self.with_mut_base |this_node| {
+            do new_child.with_mut_base |new_child_node| {
+                assert!((get!(new_child_node, parent_node)).is_none());
+                assert!((get!(new_child_node, prev_sibling)).is_none());
+                assert!((get!(new_child_node, next_sibling)).is_none());
+
+                do reference_child.with_mut_base |reference_child_node| {
+                    match get!(reference_child_node, prev_sibling) {
+                        None => set!(this_node, set_first_child, Some(new_child.clone())),
+                        Some(prev_sibling) => {
+                            do prev_sibling.with_mut_base |prev_sibling_node| {
+                                set!(prev_sibling_node, set_next_sibling, Some(new_child.clone()));
+                            }
+                            set!(new_child_node, set_prev_sibling, Some(prev_sibling.clone()));
+                        }
+                    }
+
+                    set!(reference_child_node, set_prev_sibling, Some(new_child.clone()));
+                    set!(new_child_node, set_next_sibling, Some(reference_child.clone()));
+                }
+
+                set!(new_child_node, set_parent_node, Some((*self).clone()));
+            }
+        }
+    }
+
+    /// Puts `new_child` in the place of `old_child` in this node's list of children. `old_child`
+    /// ends up disconnected from the tree.
+    ///
+    /// Fails unless `new_child` is disconnected from the tree and `old_child` is a child of this
+    /// node. (FIXME: The latter is not yet checked.)
+    fn replace_child(&self, new_child: Self, old_child: Self) {
+        self.insert_before(new_child, Some(old_child.clone()));
+        self.remove_child(old_child);
+    }
+
+    /// Removes this node from its parent's list of children, if it has a parent. Its descendants
+    /// stay attached to it.
+    fn detach(&self) {
+        match self.with_base(|this_node| get!(this_node, parent_node)) {
+            None => {}
+            Some(parent) => parent.remove_child(self.clone()),
+        }
+    }
+
     /// Removes the given child from this node's list of children.
     ///
     /// Fails unless `child` is a child of this node. (FIXME: This is not yet checked.)
@@ -190,6 +339,47 @@ pub trait TreeNodeRef<Node>: Clone {
         }
     }
 
+    /// Iterates over the ancestors of this node, starting with its parent.
+    fn ancestors(&self) -> AncestorIterator<Self> {
+        AncestorIterator {
+            current: self.with_base(|n| get!(n, parent_node)),
+        }
+    }
+
+    /// Iterates over the siblings after this node, nearest first.
+    fn following_siblings(&self) -> SiblingIterator<Self> {
+        SiblingIterator {
+            current: self.with_base(|n| get!(n, next_sibling)),
+            reverse: false,
+        }
+    }
+
+    /// Iterates over the siblings before this node, nearest first.
+    fn preceding_siblings(&self) -> SiblingIterator<Self> {
+        SiblingIterator {
+            current: self.with_base(|n| get!(n, prev_sibling)),
+            reverse: true,
+        }
+    }
+
+    /// Iterates over every node after this one in tree order, including its descendants, up to
+    /// the end of the tree.
+    fn following(&self) -> TreeOrderIterator<Self> {
+        TreeOrderIterator {
+            current: following_node(self),
+            reverse: false,
+        }
+    }
+
+    /// Iterates over every node before this one in reverse tree order, including its ancestors,
+    /// back to the root of the tree.
+    fn preceding(&self) -> TreeOrderIterator<Self> {
+        TreeOrderIterator {
+            current: preceding_node(self),
+            reverse: true,
+        }
+    }
+
     /// Iterates over this node and all its descendants, in preorder.
     fn traverse_preorder(&self) -> TreeIterator<Self> {
         self.traverse_preorder_prune(|_| false)
@@ -294,3 +484,155 @@ pub trait TreeNode<Ref: TreeNodeRef<Self>> {
         TreeNodeRef::set_next_sibling::<Self,Ref>(self, new_next_sibling)
     }
 }
+
+#[cfg(test)]
+struct TestNode {
+    value: uint,
+    parent: Option<@mut TestNode>,
+    first_child: Option<@mut TestNode>,
+    last_child: Option<@mut TestNode>,
+    prev_sibling: Option<@mut TestNode>,
+    next_sibling: Option<@mut TestNode>,
+}
+
+#[cfg(test)]
+impl TreeNodeRef<TestNode> for @mut TestNode {
+    fn with_base<R>(&self, callback: &fn(&TestNode) -> R) -> R {
+        callback(&**self)
+    }
+    fn with_mut_base<R>(&self, callback: &fn(&mut TestNode) -> R) -> R {
+        callback(&mut **self)
+    }
+
+    fn parent_node(node: &TestNode) -> Option<@mut TestNode> {
+        node.parent
+    }
+    fn first_child(node: &TestNode) -> Option<@mut TestNode> {
+        node.first_child
+    }
+    fn last_child(node: &TestNode) -> Option<@mut TestNode> {
+        node.last_child
+    }
+    fn prev_sibling(node: &TestNode) -> Option<@mut TestNode> {
+        node.prev_sibling
+    }
+    fn next_sibling(node: &TestNode) -> Option<@mut TestNode> {
+        node.next_sibling
+    }
+
+    fn set_parent_node(node: &mut TestNode, new_parent_node: Option<@mut TestNode>) {
+        node.parent = new_parent_node
+    }
+    fn set_first_child(node: &mut TestNode, new_first_child: Option<@mut TestNode>) {
+        node.first_child = new_first_child
+    }
+    fn set_last_child(node: &mut TestNode, new_last_child: Option<@mut TestNode>) {
+        node.last_child = new_last_child
+    }
+    fn set_prev_sibling(node: &mut TestNode, new_prev_sibling: Option<@mut TestNode>) {
+        node.prev_sibling = new_prev_sibling
+    }
+    fn set_next_sibling(node: &mut TestNode, new_next_sibling: Option<@mut TestNode>) {
+        node.next_sibling = new_next_sibling
+    }
+}
+
+#[cfg(test)]
+fn test_node(value: uint) -> @mut TestNode {
+    @mut TestNode {
+        value: value,
+        parent: None,
+        first_child: None,
+        last_child: None,
+        prev_sibling: None,
+        next_sibling: None,
+    }
+}
+
+#[cfg(test)]
+fn values<I: Iterator<@mut TestNode>>(iter: I) -> ~[uint] {
+    let mut iter = iter.map(|node| node.value);
+    iter.collect()
+}
+
+// Builds the tree 0(1(3, 4), 2(5)) and returns its nodes indexed by value.
+#[cfg(test)]
+fn test_tree() -> ~[@mut TestNode] {
+    let nodes = do ::std::vec::from_fn(6) |i| { test_node(i) };
+    nodes[0].add_child(nodes[1]);
+    nodes[0].add_child(nodes[2]);
+    nodes[1].add_child(nodes[3]);
+    nodes[1].add_child(nodes[4]);
+    nodes[2].add_child(nodes[5]);
+    nodes
+}
+
+#[test]
+fn test_traversal() {
+    let nodes = test_tree();
+    assert_eq!(values(nodes[0].traverse_preorder()), ~[0, 1, 3, 4, 2, 5]);
+    assert_eq!(values(nodes[0].traverse_postorder()), ~[3, 4, 1, 5, 2, 0]);
+    assert_eq!(values(nodes[0].children()), ~[1, 2]);
+}
+
+#[test]
+fn test_insert_before() {
+    let nodes = test_tree();
+    nodes[1].insert_before(test_node(6), Some(nodes[3]));
+    nodes[1].insert_before(test_node(7), Some(nodes[4]));
+    nodes[1].insert_before(test_node(8), None);
+    assert_eq!(values(nodes[1].children()), ~[6, 3, 7, 4, 8]);
+    assert_eq!(nodes[1].first_child.get_ref().value, 6);
+    assert_eq!(nodes[1].last_child.get_ref().value, 8);
+    assert_eq!(values(nodes[4].preceding_siblings()), ~[7, 3, 6]);
+}
+
+#[test]
+fn test_replace_child() {
+    let nodes = test_tree();
+    nodes[0].replace_child(test_node(6), nodes[1]);
+    assert_eq!(values(nodes[0].traverse_preorder()), ~[0, 6, 2, 5]);
+    assert!(nodes[1].parent.is_none());
+    assert!(nodes[1].next_sibling.is_none());
+    assert_eq!(values(nodes[1].children()), ~[3, 4]);
+
+    nodes[2].replace_child(nodes[1], nodes[5]);
+    assert_eq!(values(nodes[0].traverse_preorder()), ~[0, 6, 2, 1, 3, 4]);
+}
+
+#[test]
+fn test_detach() {
+    let nodes = test_tree();
+    nodes[3].detach();
+    assert_eq!(values(nodes[0].traverse_preorder()), ~[0, 1, 4, 2, 5]);
+    assert!(nodes[3].parent.is_none());
+
+    nodes[2].detach();
+    assert_eq!(values(nodes[0].traverse_preorder()), ~[0, 1, 4]);
+    assert_eq!(values(nodes[2].traverse_preorder()), ~[2, 5]);
+
+    // Detaching a root does nothing.
+    nodes[2].detach();
+    assert_eq!(values(nodes[2].traverse_preorder()), ~[2, 5]);
+}
+
+#[test]
+fn test_ancestors_and_siblings() {
+    let nodes = test_tree();
+    assert_eq!(values(nodes[4].ancestors()), ~[1, 0]);
+    assert_eq!(values(nodes[0].ancestors()), ~[]);
+    assert_eq!(values(nodes[3].following_siblings()), ~[4]);
+    assert_eq!(values(nodes[3].preceding_siblings()), ~[]);
+    assert_eq!(values(nodes[2].preceding_siblings()), ~[1]);
+}
+
+#[test]
+fn test_following_and_preceding() {
+    let nodes = test_tree();
+    assert_eq!(values(nodes[0].following()), ~[1, 3, 4, 2, 5]);
+    assert_eq!(values(nodes[4].following()), ~[2, 5]);
+    assert_eq!(values(nodes[5].following()), ~[]);
+    assert_eq!(values(nodes[5].preceding()), ~[2, 4, 3, 1, 0]);
+    assert_eq!(values(nodes[2].preceding()), ~[4, 3, 1, 0]);
+    assert_eq!(values(nodes[0].preceding()), ~[]);
+}